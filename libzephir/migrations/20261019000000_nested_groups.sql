@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS group_group (
+    group_id VARCHAR(255) NOT NULL REFERENCES "group" (id) ON DELETE CASCADE,
+    member_group_id VARCHAR(255) NOT NULL REFERENCES "group" (id) ON DELETE CASCADE,
+    PRIMARY KEY (group_id, member_group_id),
+    CHECK (group_id <> member_group_id)
+);
+
+CREATE INDEX IF NOT EXISTS group_group_member_group_id_idx ON group_group (member_group_id);
@@ -0,0 +1,27 @@
+CREATE EXTENSION IF NOT EXISTS pgcrypto;
+
+CREATE TABLE IF NOT EXISTS role (
+    id VARCHAR(255) PRIMARY KEY,
+    policy_id VARCHAR(255) NULL REFERENCES policy (id) ON DELETE SET NULL,
+    trusted_identities JSON NOT NULL DEFAULT '[]',
+    trusted_groups JSON NOT NULL DEFAULT '[]'
+);
+
+CREATE TABLE IF NOT EXISTS role_policy (
+    role_id VARCHAR(255) NOT NULL REFERENCES role (id) ON DELETE CASCADE,
+    policy_id VARCHAR(255) NOT NULL REFERENCES policy (id) ON DELETE CASCADE,
+    PRIMARY KEY (role_id, policy_id)
+);
+
+CREATE TABLE IF NOT EXISTS role_session (
+    id VARCHAR(36) PRIMARY KEY DEFAULT gen_random_uuid()::text,
+    role_id VARCHAR(255) NOT NULL REFERENCES role (id) ON DELETE CASCADE,
+    identity_id VARCHAR(255) NOT NULL REFERENCES identity (id) ON DELETE CASCADE,
+    policy_version INTEGER NULL,
+    policy_effect BOOLEAN NULL,
+    policy_actions JSON NULL,
+    policy_resources JSON NULL,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS role_session_expires_at_idx ON role_session (expires_at);
@@ -0,0 +1,5 @@
+ALTER TABLE identity
+    ADD COLUMN IF NOT EXISTS boundary_policy_id VARCHAR(255) NULL REFERENCES policy (id) ON DELETE RESTRICT;
+
+ALTER TABLE "group"
+    ADD COLUMN IF NOT EXISTS boundary_policy_id VARCHAR(255) NULL REFERENCES policy (id) ON DELETE RESTRICT;
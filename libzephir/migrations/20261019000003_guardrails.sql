@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS guardrail (
+    id VARCHAR(255) PRIMARY KEY,
+    version INTEGER NOT NULL,
+    effect BOOLEAN NOT NULL,
+    actions JSON NOT NULL,
+    resources JSON NOT NULL
+);
@@ -0,0 +1,8 @@
+ALTER TABLE identity_policy
+    ADD COLUMN IF NOT EXISTS priority INTEGER NOT NULL DEFAULT 0;
+
+ALTER TABLE group_policy
+    ADD COLUMN IF NOT EXISTS priority INTEGER NOT NULL DEFAULT 0;
+
+ALTER TABLE role_policy
+    ADD COLUMN IF NOT EXISTS priority INTEGER NOT NULL DEFAULT 0;
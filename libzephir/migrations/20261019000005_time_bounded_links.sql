@@ -0,0 +1,15 @@
+ALTER TABLE identity_policy
+    ADD COLUMN IF NOT EXISTS not_before TIMESTAMPTZ NULL,
+    ADD COLUMN IF NOT EXISTS not_after TIMESTAMPTZ NULL;
+
+ALTER TABLE group_policy
+    ADD COLUMN IF NOT EXISTS not_before TIMESTAMPTZ NULL,
+    ADD COLUMN IF NOT EXISTS not_after TIMESTAMPTZ NULL;
+
+ALTER TABLE group_identity
+    ADD COLUMN IF NOT EXISTS not_before TIMESTAMPTZ NULL,
+    ADD COLUMN IF NOT EXISTS not_after TIMESTAMPTZ NULL;
+
+CREATE INDEX IF NOT EXISTS identity_policy_not_after_idx ON identity_policy (not_after) WHERE not_after IS NOT NULL;
+CREATE INDEX IF NOT EXISTS group_policy_not_after_idx ON group_policy (not_after) WHERE not_after IS NOT NULL;
+CREATE INDEX IF NOT EXISTS group_identity_not_after_idx ON group_identity (not_after) WHERE not_after IS NOT NULL;
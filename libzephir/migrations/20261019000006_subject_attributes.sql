@@ -0,0 +1,11 @@
+ALTER TABLE identity
+    ADD COLUMN IF NOT EXISTS attributes JSONB NOT NULL DEFAULT '{}';
+
+ALTER TABLE "group"
+    ADD COLUMN IF NOT EXISTS attributes JSONB NOT NULL DEFAULT '{}';
+
+ALTER TABLE policy
+    ADD COLUMN IF NOT EXISTS conditions JSONB NULL;
+
+ALTER TABLE guardrail
+    ADD COLUMN IF NOT EXISTS conditions JSONB NULL;
@@ -0,0 +1,4 @@
+ALTER TABLE "group"
+    ADD COLUMN IF NOT EXISTS membership_rule TEXT NULL;
+
+CREATE INDEX IF NOT EXISTS group_membership_rule_idx ON "group" (id) WHERE membership_rule IS NOT NULL;
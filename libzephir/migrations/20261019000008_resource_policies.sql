@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS resource_policy (
+    id VARCHAR(255) PRIMARY KEY,
+    version INTEGER NOT NULL,
+    effect BOOLEAN NOT NULL,
+    actions JSON NOT NULL,
+    resources JSON NOT NULL,
+    conditions JSONB NULL,
+    principal_identities JSON NOT NULL,
+    principal_groups JSON NOT NULL,
+    principal_roles JSON NOT NULL
+);
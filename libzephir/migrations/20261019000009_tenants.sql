@@ -0,0 +1,73 @@
+-- Every entity belongs to a tenant: the existing ones are moved to the default tenant.
+ALTER TABLE policy ADD COLUMN IF NOT EXISTS tenant_id VARCHAR(255) NOT NULL DEFAULT 'default';
+ALTER TABLE identity ADD COLUMN IF NOT EXISTS tenant_id VARCHAR(255) NOT NULL DEFAULT 'default';
+ALTER TABLE "group" ADD COLUMN IF NOT EXISTS tenant_id VARCHAR(255) NOT NULL DEFAULT 'default';
+ALTER TABLE role ADD COLUMN IF NOT EXISTS tenant_id VARCHAR(255) NOT NULL DEFAULT 'default';
+ALTER TABLE guardrail ADD COLUMN IF NOT EXISTS tenant_id VARCHAR(255) NOT NULL DEFAULT 'default';
+ALTER TABLE resource_policy ADD COLUMN IF NOT EXISTS tenant_id VARCHAR(255) NOT NULL DEFAULT 'default';
+ALTER TABLE identity_policy ADD COLUMN IF NOT EXISTS tenant_id VARCHAR(255) NOT NULL DEFAULT 'default';
+ALTER TABLE group_policy ADD COLUMN IF NOT EXISTS tenant_id VARCHAR(255) NOT NULL DEFAULT 'default';
+ALTER TABLE group_identity ADD COLUMN IF NOT EXISTS tenant_id VARCHAR(255) NOT NULL DEFAULT 'default';
+ALTER TABLE group_group ADD COLUMN IF NOT EXISTS tenant_id VARCHAR(255) NOT NULL DEFAULT 'default';
+ALTER TABLE role_policy ADD COLUMN IF NOT EXISTS tenant_id VARCHAR(255) NOT NULL DEFAULT 'default';
+ALTER TABLE role_session ADD COLUMN IF NOT EXISTS tenant_id VARCHAR(255) NOT NULL DEFAULT 'default';
+
+-- Ids are unique per tenant only: primary keys (and the foreign
+-- keys referencing them) are recreated including the tenant.
+ALTER TABLE policy DROP CONSTRAINT IF EXISTS policy_pkey CASCADE;
+ALTER TABLE identity DROP CONSTRAINT IF EXISTS identity_pkey CASCADE;
+ALTER TABLE "group" DROP CONSTRAINT IF EXISTS group_pkey CASCADE;
+ALTER TABLE role DROP CONSTRAINT IF EXISTS role_pkey CASCADE;
+ALTER TABLE guardrail DROP CONSTRAINT IF EXISTS guardrail_pkey CASCADE;
+ALTER TABLE resource_policy DROP CONSTRAINT IF EXISTS resource_policy_pkey CASCADE;
+ALTER TABLE identity_policy DROP CONSTRAINT IF EXISTS identity_policy_pkey;
+ALTER TABLE group_policy DROP CONSTRAINT IF EXISTS group_policy_pkey;
+ALTER TABLE group_identity DROP CONSTRAINT IF EXISTS group_identity_pkey;
+ALTER TABLE group_group DROP CONSTRAINT IF EXISTS group_group_pkey;
+ALTER TABLE role_policy DROP CONSTRAINT IF EXISTS role_policy_pkey;
+
+ALTER TABLE policy ADD PRIMARY KEY (tenant_id, id);
+ALTER TABLE identity ADD PRIMARY KEY (tenant_id, id);
+ALTER TABLE "group" ADD PRIMARY KEY (tenant_id, id);
+ALTER TABLE role ADD PRIMARY KEY (tenant_id, id);
+ALTER TABLE guardrail ADD PRIMARY KEY (tenant_id, id);
+ALTER TABLE resource_policy ADD PRIMARY KEY (tenant_id, id);
+ALTER TABLE identity_policy ADD PRIMARY KEY (tenant_id, identity_id, policy_id);
+ALTER TABLE group_policy ADD PRIMARY KEY (tenant_id, group_id, policy_id);
+ALTER TABLE group_identity ADD PRIMARY KEY (tenant_id, group_id, identity_id);
+ALTER TABLE group_group ADD PRIMARY KEY (tenant_id, group_id, member_group_id);
+ALTER TABLE role_policy ADD PRIMARY KEY (tenant_id, role_id, policy_id);
+
+-- Inline policies are replaced (deleted and re-inserted) while saving their
+-- subject: the references are checked at the end of the transaction.
+ALTER TABLE identity
+    ADD FOREIGN KEY (tenant_id, policy_id) REFERENCES policy (tenant_id, id) DEFERRABLE INITIALLY DEFERRED,
+    ADD FOREIGN KEY (tenant_id, boundary_policy_id) REFERENCES policy (tenant_id, id) ON DELETE RESTRICT;
+ALTER TABLE "group"
+    ADD FOREIGN KEY (tenant_id, policy_id) REFERENCES policy (tenant_id, id) DEFERRABLE INITIALLY DEFERRED,
+    ADD FOREIGN KEY (tenant_id, boundary_policy_id) REFERENCES policy (tenant_id, id) ON DELETE RESTRICT;
+ALTER TABLE role
+    ADD FOREIGN KEY (tenant_id, policy_id) REFERENCES policy (tenant_id, id) DEFERRABLE INITIALLY DEFERRED;
+
+ALTER TABLE identity_policy
+    ADD FOREIGN KEY (tenant_id, identity_id) REFERENCES identity (tenant_id, id) ON DELETE CASCADE,
+    ADD FOREIGN KEY (tenant_id, policy_id) REFERENCES policy (tenant_id, id) ON DELETE CASCADE;
+ALTER TABLE group_policy
+    ADD FOREIGN KEY (tenant_id, group_id) REFERENCES "group" (tenant_id, id) ON DELETE CASCADE,
+    ADD FOREIGN KEY (tenant_id, policy_id) REFERENCES policy (tenant_id, id) ON DELETE CASCADE;
+ALTER TABLE group_identity
+    ADD FOREIGN KEY (tenant_id, group_id) REFERENCES "group" (tenant_id, id) ON DELETE CASCADE,
+    ADD FOREIGN KEY (tenant_id, identity_id) REFERENCES identity (tenant_id, id) ON DELETE CASCADE;
+ALTER TABLE group_group
+    ADD FOREIGN KEY (tenant_id, group_id) REFERENCES "group" (tenant_id, id) ON DELETE CASCADE,
+    ADD FOREIGN KEY (tenant_id, member_group_id) REFERENCES "group" (tenant_id, id) ON DELETE CASCADE;
+ALTER TABLE role_policy
+    ADD FOREIGN KEY (tenant_id, role_id) REFERENCES role (tenant_id, id) ON DELETE CASCADE,
+    ADD FOREIGN KEY (tenant_id, policy_id) REFERENCES policy (tenant_id, id) ON DELETE CASCADE;
+ALTER TABLE role_session
+    ADD FOREIGN KEY (tenant_id, role_id) REFERENCES role (tenant_id, id) ON DELETE CASCADE,
+    ADD FOREIGN KEY (tenant_id, identity_id) REFERENCES identity (tenant_id, id) ON DELETE CASCADE;
+
+DROP INDEX IF EXISTS group_group_member_group_id_idx;
+CREATE INDEX IF NOT EXISTS group_group_member_group_id_idx ON group_group (tenant_id, member_group_id);
+CREATE INDEX IF NOT EXISTS group_identity_identity_id_idx ON group_identity (tenant_id, identity_id);
@@ -0,0 +1,12 @@
+-- Every write is recorded in the change log, read by the change feed consumers.
+-- Sequence numbers are assigned in commit order (see StorageManager::_record_change).
+CREATE TABLE IF NOT EXISTS change_log (
+    seq BIGSERIAL PRIMARY KEY,
+    tenant_id VARCHAR(255) NOT NULL,
+    entity_type VARCHAR(32) NOT NULL,
+    entity_id VARCHAR(255) NOT NULL,
+    operation VARCHAR(16) NOT NULL,
+    changed_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS change_log_tenant_id_seq_idx ON change_log (tenant_id, seq);
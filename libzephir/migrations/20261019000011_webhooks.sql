@@ -0,0 +1,39 @@
+-- Webhooks notified of the changes to the authorization data of their tenant.
+-- Events are glob patterns matched against the event names (e.g. "group.*").
+CREATE TABLE IF NOT EXISTS webhook (
+    tenant_id VARCHAR(255) NOT NULL,
+    id VARCHAR(255) NOT NULL,
+    url TEXT NOT NULL,
+    events JSONB NOT NULL,
+    secret VARCHAR(255) NOT NULL,
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    PRIMARY KEY (tenant_id, id)
+);
+
+-- Pending deliveries, queued in the same transaction as the change they notify.
+CREATE TABLE IF NOT EXISTS webhook_delivery (
+    id BIGSERIAL PRIMARY KEY,
+    tenant_id VARCHAR(255) NOT NULL,
+    webhook_id VARCHAR(255) NOT NULL,
+    event VARCHAR(64) NOT NULL,
+    payload JSONB NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    last_error TEXT,
+    FOREIGN KEY (tenant_id, webhook_id) REFERENCES webhook (tenant_id, id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS webhook_delivery_next_attempt_at_idx ON webhook_delivery (next_attempt_at);
+
+-- Deliveries which failed too many times, kept until redelivered.
+CREATE TABLE IF NOT EXISTS webhook_dead_letter (
+    id BIGINT PRIMARY KEY,
+    tenant_id VARCHAR(255) NOT NULL,
+    webhook_id VARCHAR(255) NOT NULL,
+    event VARCHAR(64) NOT NULL,
+    payload JSONB NOT NULL,
+    attempts INTEGER NOT NULL,
+    last_error TEXT,
+    failed_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    FOREIGN KEY (tenant_id, webhook_id) REFERENCES webhook (tenant_id, id) ON DELETE CASCADE
+);
@@ -0,0 +1,18 @@
+-- Every saved version of a policy, numbered from 1, to be compared with the others.
+CREATE TABLE IF NOT EXISTS policy_revision (
+    tenant_id VARCHAR(255) NOT NULL,
+    policy_id VARCHAR(255) NOT NULL,
+    revision INTEGER NOT NULL,
+    version INT NOT NULL,
+    effect BOOLEAN NOT NULL,
+    actions JSONB NOT NULL,
+    resources JSONB NOT NULL,
+    conditions JSONB NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (tenant_id, policy_id, revision)
+);
+
+-- The current policies become their first revision.
+INSERT INTO policy_revision (tenant_id, policy_id, revision, version, effect, actions, resources, conditions)
+SELECT tenant_id, id, 1, version, effect, actions, resources, conditions FROM policy
+ON CONFLICT DO NOTHING;
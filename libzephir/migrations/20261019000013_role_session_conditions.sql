@@ -0,0 +1,3 @@
+-- The subject conditions of the session policies.
+ALTER TABLE role_session
+    ADD COLUMN IF NOT EXISTS policy_conditions JSONB NULL;
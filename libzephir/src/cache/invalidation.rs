@@ -0,0 +1,303 @@
+use crate::cache::membership_rule_cache::MEMBERSHIP_RULE_CACHE;
+use crate::cache::subject_cache::SUBJECT_CACHE;
+use crate::cache::tenant_cache::{GUARDRAIL_CACHE, RESOURCE_POLICY_CACHE};
+use crate::compiler::compiler::cache;
+use crate::err::Error;
+use log::{debug, trace, warn};
+use sqlx::postgres::PgListener;
+use sqlx::{Pool, Postgres};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Name of the channel (redis) or of the notification channel (postgres)
+/// used to broadcast the invalidation events.
+pub const CHANNEL: &str = "zephir_invalidation";
+
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// An event signaling that the locally cached copies of an entity
+/// are not valid anymore.
+///
+/// Entity ids are scoped to their tenant (see `tenant::scoped_id`).
+#[derive(Clone, Debug, PartialEq)]
+pub enum InvalidationEvent {
+    /// A policy has been updated or removed.
+    Policy(String),
+
+    /// An identity (or its policy links) has been updated.
+    Identity(String),
+
+    /// A group (its policy links or its members) has been updated.
+    Group(String),
+
+    /// The guardrails of the given tenant have been updated.
+    Guardrails(String),
+
+    /// The resource policies of the given tenant have been updated.
+    ResourcePolicies(String),
+
+    /// A dynamic group rule has been updated: the memberships
+    /// of any identity could have changed.
+    DynamicGroups,
+
+    /// Some events could have been lost: all local caches must be flushed.
+    All,
+}
+
+impl InvalidationEvent {
+    /// Serializes the event into the string sent on the channel.
+    pub fn to_payload(&self) -> String {
+        match self {
+            InvalidationEvent::Policy(id) => format!("policy:{}", id),
+            InvalidationEvent::Identity(id) => format!("identity:{}", id),
+            InvalidationEvent::Group(id) => format!("group:{}", id),
+            InvalidationEvent::Guardrails(tenant) => format!("guardrails:{}", tenant),
+            InvalidationEvent::ResourcePolicies(tenant) => format!("resource_policies:{}", tenant),
+            InvalidationEvent::DynamicGroups => "dynamic_groups".to_string(),
+            InvalidationEvent::All => "all".to_string(),
+        }
+    }
+
+    /// Parses an event received from the channel.
+    pub fn from_payload(payload: &str) -> Option<Self> {
+        match payload {
+            "all" => return Option::Some(InvalidationEvent::All),
+            "dynamic_groups" => return Option::Some(InvalidationEvent::DynamicGroups),
+            _ => {}
+        }
+
+        let mut parts = payload.splitn(2, ':');
+        match (parts.next(), parts.next()) {
+            (Option::Some("policy"), Option::Some(id)) => {
+                Option::Some(InvalidationEvent::Policy(id.to_string()))
+            }
+            (Option::Some("identity"), Option::Some(id)) => {
+                Option::Some(InvalidationEvent::Identity(id.to_string()))
+            }
+            (Option::Some("group"), Option::Some(id)) => {
+                Option::Some(InvalidationEvent::Group(id.to_string()))
+            }
+            (Option::Some("guardrails"), Option::Some(tenant)) => {
+                Option::Some(InvalidationEvent::Guardrails(tenant.to_string()))
+            }
+            (Option::Some("resource_policies"), Option::Some(tenant)) => {
+                Option::Some(InvalidationEvent::ResourcePolicies(tenant.to_string()))
+            }
+            _ => Option::None,
+        }
+    }
+
+    /// Evicts the entries invalidated by this event from the local caches.
+    pub fn apply(&self) {
+        trace!("Applying invalidation event {:?}", self);
+        match self {
+            InvalidationEvent::Policy(id) => cache::flush_policy(id),
+            InvalidationEvent::Guardrails(tenant) => GUARDRAIL_CACHE.invalidate(tenant),
+            InvalidationEvent::ResourcePolicies(tenant) => RESOURCE_POLICY_CACHE.invalidate(tenant),
+            InvalidationEvent::DynamicGroups => MEMBERSHIP_RULE_CACHE.clear(),
+            InvalidationEvent::All => {
+                cache::flush_all();
+                GUARDRAIL_CACHE.clear();
+                MEMBERSHIP_RULE_CACHE.clear();
+                RESOURCE_POLICY_CACHE.clear();
+            }
+            _ => {}
+        }
+
+        SUBJECT_CACHE.invalidate(self);
+    }
+}
+
+/// Broadcasts the invalidation events to all the zephir instances
+/// sharing the same database.
+///
+/// Redis pub/sub is used if REDIS_DSN is set, postgres LISTEN/NOTIFY otherwise.
+/// Events are published on a redis connection shared by all the clones
+/// of the invalidator, opened on first use and re-opened after a failure.
+#[derive(Clone)]
+pub enum Invalidator {
+    Redis(redis::Client, Arc<Mutex<Option<redis::Connection>>>),
+    Postgres(Pool<Postgres>),
+}
+
+impl Invalidator {
+    /// Creates the invalidator from the environment variables.
+    pub fn from_env(pool: Pool<Postgres>) -> Self {
+        let redis_dsn = std::env::var("REDIS_DSN").unwrap_or_default();
+        if redis_dsn.is_empty() {
+            return Invalidator::Postgres(pool);
+        }
+
+        Invalidator::Redis(redis::Client::open(redis_dsn.as_str()).unwrap(), Arc::new(Mutex::new(Option::None)))
+    }
+
+    /// Applies the events locally and publishes them to the other instances.
+    ///
+    /// Must be called *after* the transaction modifying the entities has
+    /// been committed, otherwise another instance could reload and cache
+    /// the old data after having received the event. As the changes are
+    /// already saved, a publishing failure is logged and not returned: the
+    /// other instances will flush their caches when they reconnect.
+    pub async fn publish(&self, events: &[InvalidationEvent]) {
+        for event in events {
+            event.apply();
+        }
+
+        let payloads: Vec<String> = events.iter().map(InvalidationEvent::to_payload).collect();
+        debug!("Publishing invalidation events {:?}", payloads);
+
+        let result = match self {
+            Invalidator::Redis(client, connection) => {
+                let client = client.clone();
+                let connection = connection.clone();
+                async_std::task::spawn_blocking(move || publish_redis(&client, &connection, &payloads)).await
+            }
+            Invalidator::Postgres(pool) => publish_postgres(pool, &payloads).await,
+        };
+
+        if let Err(e) = result {
+            warn!("Cannot publish the invalidation events: {}", e);
+        }
+    }
+
+    /// Subscribes to the invalidation channel and applies the received events.
+    ///
+    /// The subscription is handled in background and restarted on failure.
+    /// Every time the connection is (re-)established all the local caches
+    /// are flushed, as some events could have been missed.
+    pub fn listen(self) {
+        match self {
+            Invalidator::Redis(client, _) => {
+                std::thread::spawn(move || loop {
+                    if let Err(e) = listen_redis(&client) {
+                        warn!("Invalidation channel subscription failed: {}", e);
+                    }
+
+                    std::thread::sleep(RETRY_DELAY);
+                });
+            }
+            Invalidator::Postgres(pool) => {
+                async_std::task::spawn(async move {
+                    loop {
+                        if let Err(e) = listen_postgres(&pool).await {
+                            warn!("Invalidation channel subscription failed: {}", e);
+                        }
+
+                        async_std::task::sleep(RETRY_DELAY).await;
+                    }
+                });
+            }
+        }
+    }
+}
+
+fn publish_redis(client: &redis::Client, connection: &Mutex<Option<redis::Connection>>, payloads: &[String]) -> Result<(), Error> {
+    let mut connection = connection.lock().unwrap();
+    if connection.is_none() {
+        *connection = Option::Some(client.get_connection()?);
+    }
+
+    for payload in payloads {
+        let result = redis::cmd("PUBLISH")
+            .arg(CHANNEL)
+            .arg(payload.as_str())
+            .query::<i64>(connection.as_ref().unwrap());
+
+        if let Err(e) = result {
+            // The connection could be broken: open a new one next time.
+            *connection = Option::None;
+            return Err(e.into());
+        }
+    }
+
+    Ok(())
+}
+
+async fn publish_postgres(pool: &Pool<Postgres>, payloads: &[String]) -> Result<(), Error> {
+    for payload in payloads {
+        sqlx::query("SELECT pg_notify($1, $2)")
+            .bind(CHANNEL)
+            .bind(payload.as_str())
+            .execute(pool)
+            .await?;
+    }
+
+    Ok(())
+}
+
+fn listen_redis(client: &redis::Client) -> Result<(), Error> {
+    let mut connection = client.get_connection()?;
+    let mut pubsub = connection.as_pubsub();
+    pubsub.subscribe(CHANNEL)?;
+    InvalidationEvent::All.apply();
+
+    loop {
+        let payload: String = pubsub.get_message()?.get_payload()?;
+        apply_payload(&payload);
+    }
+}
+
+async fn listen_postgres(pool: &Pool<Postgres>) -> Result<(), Error> {
+    let mut listener = PgListener::connect_with(pool).await?;
+    listener.listen(CHANNEL).await?;
+
+    loop {
+        InvalidationEvent::All.apply();
+        while let Some(notification) = listener.try_recv().await? {
+            apply_payload(notification.payload());
+        }
+
+        warn!("Invalidation channel connection lost. Reconnecting...");
+    }
+}
+
+fn apply_payload(payload: &str) {
+    match InvalidationEvent::from_payload(payload) {
+        Option::None => warn!("Unknown invalidation event received: {}", payload),
+        Option::Some(event) => event.apply(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cache::invalidation::{InvalidationEvent, Invalidator};
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn events_should_be_serializable() {
+        let events = vec![
+            InvalidationEvent::Policy("default/p1".to_string()),
+            InvalidationEvent::Policy("default/urn:policy:with:colons".to_string()),
+            InvalidationEvent::Identity("acme/i1".to_string()),
+            InvalidationEvent::Group("acme/g1".to_string()),
+            InvalidationEvent::Guardrails("acme".to_string()),
+            InvalidationEvent::ResourcePolicies("acme".to_string()),
+            InvalidationEvent::DynamicGroups,
+            InvalidationEvent::All,
+        ];
+
+        for event in events {
+            assert_eq!(
+                InvalidationEvent::from_payload(&event.to_payload()),
+                Option::Some(event)
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_events_should_not_be_parsed() {
+        assert_eq!(InvalidationEvent::from_payload("foo:bar"), Option::None);
+        assert_eq!(InvalidationEvent::from_payload("policy"), Option::None);
+        assert_eq!(InvalidationEvent::from_payload("guardrails"), Option::None);
+    }
+
+    #[test]
+    fn publishing_failures_should_not_be_returned() {
+        let connection = Arc::new(Mutex::new(Option::None));
+        let client = redis::Client::open("redis://127.0.0.1:1/").unwrap();
+        let invalidator = Invalidator::Redis(client, connection.clone());
+
+        async_std::task::block_on(invalidator.publish(&[InvalidationEvent::Guardrails("acme".to_string())]));
+        assert!(connection.lock().unwrap().is_none());
+    }
+}
@@ -0,0 +1,273 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+struct Entry<V> {
+    value: V,
+    tick: u64,
+    inserted_at: Instant,
+    /// Expiration time of the single entry, regardless of the cache TTL.
+    deadline: Option<Instant>,
+}
+
+struct Inner<V> {
+    entries: HashMap<String, Entry<V>>,
+    /// Access order: the entry with the lowest tick is the least recently used.
+    order: BTreeMap<u64, String>,
+    tick: u64,
+}
+
+impl<V> Inner<V> {
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    fn remove(&mut self, key: &str) -> Option<Entry<V>> {
+        let entry = self.entries.remove(key)?;
+        self.order.remove(&entry.tick);
+
+        Some(entry)
+    }
+}
+
+/// A bounded, thread-safe, least-recently-used in-memory cache.
+///
+/// When the capacity is reached, the least recently used entry is evicted
+/// to make room for the new one. If a TTL is given, entries older than the
+/// TTL are considered expired and removed on access.
+///
+/// `uluru` is not used here: its capacity is a compile-time array size
+/// (the capacity is read from CACHE_CAPACITY), its lookups are linear,
+/// and it can neither remove a single key nor report the evicted entries.
+pub struct LruCache<V: Clone> {
+    capacity: usize,
+    ttl: Option<Duration>,
+    inner: Mutex<Inner<V>>,
+
+    evictions: AtomicU64,
+    expirations: AtomicU64,
+}
+
+impl<V: Clone> LruCache<V> {
+    /// Creates a new LRU cache holding at most `capacity` entries.
+    /// A zero capacity is treated as one.
+    pub fn new(capacity: usize, ttl: Option<Duration>) -> Self {
+        LruCache {
+            capacity: capacity.max(1),
+            ttl,
+            inner: Mutex::new(Inner {
+                entries: HashMap::new(),
+                order: BTreeMap::new(),
+                tick: 0,
+            }),
+            evictions: AtomicU64::new(0),
+            expirations: AtomicU64::new(0),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn ttl(&self) -> Option<Duration> {
+        self.ttl
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of entries removed to respect the cache capacity.
+    pub fn evictions(&self) -> u64 {
+        self.evictions.load(Ordering::Relaxed)
+    }
+
+    /// Number of entries removed because their TTL elapsed.
+    pub fn expirations(&self) -> u64 {
+        self.expirations.load(Ordering::Relaxed)
+    }
+
+    /// Gets a copy of the value stored under the given key, marking it
+    /// as the most recently used entry.
+    pub fn get(&self, key: &str) -> Option<V> {
+        let mut inner = self.inner.lock().unwrap();
+        let expired = match inner.entries.get(key) {
+            None => return None,
+            Some(entry) => self.is_expired(entry),
+        };
+
+        if expired {
+            inner.remove(key);
+            self.expirations.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+
+        let tick = inner.next_tick();
+        let entry = inner.entries.get_mut(key).unwrap();
+        let old_tick = entry.tick;
+        entry.tick = tick;
+
+        let value = entry.value.clone();
+        inner.order.remove(&old_tick);
+        inner.order.insert(tick, key.to_string());
+
+        Some(value)
+    }
+
+    /// Stores a value, evicting the least recently used entry
+    /// if the cache is full.
+    ///
+    /// # Returns
+    ///
+    /// The keys of the evicted entries
+    pub fn insert(&self, key: &str, value: V) -> Vec<String> {
+        self.insert_until(key, value, None)
+    }
+
+    /// Stores a value which expires at the given deadline
+    /// (or when the cache TTL elapses, whichever comes first).
+    ///
+    /// # Returns
+    ///
+    /// The keys of the evicted entries
+    pub fn insert_until(&self, key: &str, value: V, deadline: Option<Instant>) -> Vec<String> {
+        let mut inner = self.inner.lock().unwrap();
+        let mut evicted = vec![];
+        inner.remove(key);
+
+        while inner.entries.len() >= self.capacity {
+            let lru = inner.order.iter().next().map(|(_, k)| k.clone());
+            match lru {
+                None => break,
+                Some(lru) => {
+                    inner.remove(&lru);
+                    self.evictions.fetch_add(1, Ordering::Relaxed);
+                    evicted.push(lru);
+                }
+            }
+        }
+
+        let tick = inner.next_tick();
+        inner.order.insert(tick, key.to_string());
+        inner.entries.insert(
+            key.to_string(),
+            Entry {
+                value,
+                tick,
+                inserted_at: Instant::now(),
+                deadline,
+            },
+        );
+
+        evicted
+    }
+
+    /// Whether any entry, expired or not, holds the given value.
+    pub fn contains_value(&self, value: &V) -> bool
+    where
+        V: PartialEq,
+    {
+        self.inner.lock().unwrap().entries.values().any(|e| e.value == *value)
+    }
+
+    /// Removes an entry from the cache.
+    pub fn remove(&self, key: &str) {
+        self.inner.lock().unwrap().remove(key);
+    }
+
+    /// Removes all the entries from the cache.
+    pub fn clear(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.entries.clear();
+        inner.order.clear();
+    }
+
+    fn is_expired(&self, entry: &Entry<V>) -> bool {
+        if matches!(entry.deadline, Some(d) if Instant::now() >= d) {
+            return true;
+        }
+
+        match self.ttl {
+            None => false,
+            Some(ttl) => entry.inserted_at.elapsed() >= ttl,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cache::lru_cache::LruCache;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn should_store_and_retrieve_values() {
+        let cache = LruCache::new(2, None);
+        cache.insert("a", 1);
+
+        assert_eq!(cache.get("a"), Some(1));
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.len(), 1);
+
+        cache.remove("a");
+        assert_eq!(cache.get("a"), None);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn should_evict_least_recently_used_entry() {
+        let cache = LruCache::new(2, None);
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        cache.get("a");
+
+        assert_eq!(cache.insert("c", 3), vec!["b".to_string()]);
+        assert_eq!(cache.get("a"), Some(1));
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.get("c"), Some(3));
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.evictions(), 1);
+    }
+
+    #[test]
+    fn replacing_a_value_should_not_evict() {
+        let cache = LruCache::new(2, None);
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        cache.insert("a", 3);
+
+        assert_eq!(cache.get("a"), Some(3));
+        assert_eq!(cache.get("b"), Some(2));
+        assert_eq!(cache.evictions(), 0);
+    }
+
+    #[test]
+    fn expired_entries_should_be_removed() {
+        let cache = LruCache::new(2, Some(Duration::from_millis(10)));
+        cache.insert("a", 1);
+        assert_eq!(cache.get("a"), Some(1));
+
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(cache.get("a"), None);
+        assert_eq!(cache.expirations(), 1);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn entries_should_expire_at_their_deadline() {
+        let cache = LruCache::new(2, None);
+        cache.insert_until("a", 1, Some(Instant::now() + Duration::from_millis(10)));
+        cache.insert("b", 2);
+        assert_eq!(cache.get("a"), Some(1));
+
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(cache.get("a"), None);
+        assert_eq!(cache.get("b"), Some(2));
+        assert_eq!(cache.expirations(), 1);
+    }
+}
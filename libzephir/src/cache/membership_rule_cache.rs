@@ -0,0 +1,74 @@
+use crate::identity::membership_rule::MembershipRule;
+use std::collections::HashMap;
+use std::lazy::SyncLazy;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+pub(crate) static MEMBERSHIP_RULE_CACHE: SyncLazy<MembershipRuleCache> = SyncLazy::new(MembershipRuleCache::new);
+
+/// Holds the parsed membership rules of the dynamic groups, keyed by
+/// tenant, as they are evaluated whenever the groups of an identity are loaded.
+pub(crate) struct MembershipRuleCache {
+    rules: Mutex<HashMap<String, Vec<(String, MembershipRule)>>>,
+
+    /// Incremented on every invalidation.
+    generation: AtomicU64,
+}
+
+impl MembershipRuleCache {
+    pub(crate) fn new() -> Self {
+        MembershipRuleCache {
+            rules: Mutex::new(HashMap::new()),
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    /// Gets the current cache generation.
+    /// See `SubjectCache::generation` for details.
+    pub(crate) fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    /// Gets the ids of the dynamic groups of the tenant, along with their rules.
+    pub(crate) fn get(&self, tenant: &str) -> Option<Vec<(String, MembershipRule)>> {
+        self.rules.lock().unwrap().get(tenant).cloned()
+    }
+
+    pub(crate) fn insert(&self, tenant: &str, rules: &[(String, MembershipRule)], generation: u64) {
+        let mut cached = self.rules.lock().unwrap();
+        if generation == self.generation() {
+            cached.insert(tenant.to_string(), rules.to_vec());
+        }
+    }
+
+    /// Rule updates are not scoped to a tenant: all the rules are evicted.
+    pub(crate) fn clear(&self) {
+        let mut cached = self.rules.lock().unwrap();
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        cached.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cache::membership_rule_cache::MembershipRuleCache;
+    use crate::identity::membership_rule::MembershipRule;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn stale_rules_should_not_be_cached() {
+        let cache = MembershipRuleCache::new();
+        let rules = vec![(String::from("finance"), MembershipRule::try_from(r#"department == "finance""#).unwrap())];
+
+        let generation = cache.generation();
+        cache.insert("acme", &rules, generation);
+        assert_eq!(cache.get("acme").unwrap().len(), 1);
+        assert!(cache.get("default").is_none());
+
+        cache.clear();
+        assert!(cache.get("acme").is_none());
+
+        cache.insert("acme", &rules, generation);
+        assert!(cache.get("acme").is_none());
+    }
+}
@@ -0,0 +1,226 @@
+pub mod invalidation;
+mod lru_cache;
+pub(crate) mod membership_rule_cache;
+pub(crate) mod subject_cache;
+pub(crate) mod tenant_cache;
+
+pub use lru_cache::LruCache;
+
+use mouscache::Cacheable;
+use redis::parse_redis_url;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Default number of entries held by the in-memory cache.
+pub const DEFAULT_CAPACITY: usize = 1024;
+
+enum Backend<V: Cacheable + Clone + 'static> {
+    Memory(LruCache<V>),
+    Redis(mouscache::Cache),
+}
+
+/// Cache statistics.
+///
+/// Evictions, expirations, entries and capacity are only tracked
+/// by the in-memory backend: redis handles them on its own.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    pub expirations: u64,
+    pub entries: Option<usize>,
+    pub capacity: Option<usize>,
+}
+
+/// A cache backed by a bounded in-memory LRU or by redis.
+pub struct Cache<V: Cacheable + Clone + 'static> {
+    backend: Backend<V>,
+    ttl: Option<Duration>,
+
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl<V: Cacheable + Clone + 'static> Cache<V> {
+    /// Creates a new in-memory cache holding at most `capacity` entries.
+    pub fn memory(capacity: usize, ttl: Option<Duration>) -> Self {
+        Self::with_backend(Backend::Memory(LruCache::new(capacity, ttl)), ttl)
+    }
+
+    /// Creates a new cache storing the values on redis.
+    pub fn redis(cache: mouscache::Cache, ttl: Option<Duration>) -> Self {
+        Self::with_backend(Backend::Redis(cache), ttl)
+    }
+
+    fn with_backend(backend: Backend<V>, ttl: Option<Duration>) -> Self {
+        Cache {
+            backend,
+            ttl,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Gets the name of the backend ("memory" or "redis").
+    pub fn backend_name(&self) -> &'static str {
+        match self.backend {
+            Backend::Memory(_) => "memory",
+            Backend::Redis(_) => "redis",
+        }
+    }
+
+    pub fn ttl(&self) -> Option<Duration> {
+        self.ttl
+    }
+
+    /// Gets a value from the cache.
+    /// Backend errors are treated as cache misses.
+    pub fn get(&self, key: &str) -> Option<V> {
+        let value = match &self.backend {
+            Backend::Memory(c) => c.get(key),
+            Backend::Redis(c) => c.get::<_, V>(key).unwrap_or(None),
+        };
+
+        let counter = if value.is_some() { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+
+        value
+    }
+
+    /// Stores a value into the cache.
+    ///
+    /// # Returns
+    ///
+    /// Whether the value has been successfully stored
+    pub fn insert(&self, key: &str, value: V) -> bool {
+        match &self.backend {
+            Backend::Memory(c) => {
+                c.insert(key, value);
+                true
+            }
+            Backend::Redis(c) => c
+                .insert_with(key, value, self.ttl.map(|ttl| ttl.as_secs() as usize))
+                .is_ok(),
+        }
+    }
+
+    /// Removes a value from the cache.
+    pub fn remove(&self, key: &str) {
+        match &self.backend {
+            Backend::Memory(c) => c.remove(key),
+            Backend::Redis(c) => {
+                let _ = c.remove::<_, V>(key);
+            }
+        }
+    }
+
+    /// Removes all the values from an in-memory cache.
+    /// Redis caches are shared between instances and are left untouched.
+    pub fn clear(&self) {
+        if let Backend::Memory(c) = &self.backend {
+            c.clear();
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let mut stats = CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            ..CacheStats::default()
+        };
+
+        if let Backend::Memory(c) = &self.backend {
+            stats.evictions = c.evictions();
+            stats.expirations = c.expirations();
+            stats.entries = Some(c.len());
+            stats.capacity = Some(c.capacity());
+        }
+
+        stats
+    }
+}
+
+pub(crate) fn get_env_number(name: &str) -> Option<u64> {
+    match std::env::var(name) {
+        Result::Err(_) => Option::None,
+        Result::Ok(value) => value.parse().ok(),
+    }
+}
+
+/// Reads a number of seconds from the given env var.
+/// Zero or invalid values are treated as no duration.
+pub(crate) fn get_env_duration(name: &str) -> Option<Duration> {
+    match get_env_number(name) {
+        Option::None | Option::Some(0) => Option::None,
+        Option::Some(secs) => Option::Some(Duration::from_secs(secs)),
+    }
+}
+
+/// Creates the cache from the environment variables.
+///
+/// If REDIS_DSN is set to a non-empty value, a redis cache is created.
+/// Otherwise an in-memory LRU cache holding CACHE_CAPACITY entries
+/// (default: 1024) is returned.
+/// CACHE_TTL represents the number of seconds after which an entry
+/// expires (default: 0, entries never expire).
+pub fn create_cache<V: Cacheable + Clone + 'static>() -> Cache<V> {
+    let ttl = get_env_duration("CACHE_TTL");
+    let redis_dsn = std::env::var("REDIS_DSN").unwrap_or_default();
+    if redis_dsn.is_empty() {
+        let capacity = get_env_number("CACHE_CAPACITY")
+            .map(|c| c as usize)
+            .unwrap_or(DEFAULT_CAPACITY);
+
+        return Cache::memory(capacity, ttl);
+    }
+
+    let redis_url = parse_redis_url(redis_dsn.as_str()).unwrap();
+
+    Cache::redis(
+        mouscache::redis(
+            redis_url.host_str().unwrap(),
+            redis_url.password(),
+            Option::None,
+        )
+        .unwrap(),
+        ttl,
+    )
+}
+
+/// Gets the statistics of the compiled policies cache.
+pub fn stats() -> CacheStats {
+    crate::compiler::compiler::Compiler::get_instance().cache_stats()
+}
+
+/// Gets the name of the compiled policies cache backend.
+pub fn backend_name() -> &'static str {
+    crate::compiler::compiler::Compiler::get_instance().cache_backend_name()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cache::{Cache, CacheStats};
+    use crate::compiler::compiled_policy::CompiledPolicy;
+
+    #[test]
+    fn memory_cache_should_count_hits_and_misses() {
+        let cache: Cache<CompiledPolicy> = Cache::memory(1, Option::None);
+        cache.insert("a", CompiledPolicy::new(vec![], vec![]));
+        cache.get("a");
+        cache.get("b");
+        cache.insert("b", CompiledPolicy::new(vec![], vec![]));
+
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                hits: 1,
+                misses: 1,
+                evictions: 1,
+                expirations: 0,
+                entries: Some(1),
+                capacity: Some(1),
+            }
+        );
+    }
+}
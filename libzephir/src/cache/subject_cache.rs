@@ -0,0 +1,314 @@
+use crate::cache::invalidation::InvalidationEvent;
+use crate::cache::{get_env_duration, get_env_number, LruCache, DEFAULT_CAPACITY};
+use crate::identity::group::Group;
+use crate::identity::identity::Identity;
+use crate::identity::subject::Subject;
+use crate::policy::validity::unix_now;
+use crate::tenant::scoped_id;
+use log::trace;
+use std::collections::{HashMap, HashSet};
+use std::lazy::SyncLazy;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+pub(crate) static SUBJECT_CACHE: SyncLazy<SubjectCache> = SyncLazy::new(|| {
+    SubjectCache::new(
+        get_env_number("SUBJECT_CACHE_CAPACITY")
+            .map(|c| c as usize)
+            .unwrap_or(DEFAULT_CAPACITY),
+        get_env_duration("SUBJECT_CACHE_TTL"),
+    )
+});
+
+/// Keeps track of the entities each cached identity has been built from.
+#[derive(Default)]
+struct DependencyIndex {
+    /// Entity -> identities depending on it
+    dependents: HashMap<String, HashSet<String>>,
+    /// Identity -> entities it depends on
+    dependencies: HashMap<String, HashSet<String>>,
+}
+
+impl DependencyIndex {
+    fn add(&mut self, identity_id: &str, dependencies: Vec<String>) {
+        for dependency in dependencies {
+            self.dependents
+                .entry(dependency.clone())
+                .or_default()
+                .insert(identity_id.to_string());
+            self.dependencies
+                .entry(identity_id.to_string())
+                .or_default()
+                .insert(dependency);
+        }
+    }
+
+    fn remove(&mut self, identity_id: &str) {
+        for dependency in self.dependencies.remove(identity_id).unwrap_or_default() {
+            if let Some(dependents) = self.dependents.get_mut(&dependency) {
+                dependents.remove(identity_id);
+                if dependents.is_empty() {
+                    self.dependents.remove(&dependency);
+                }
+            }
+        }
+    }
+
+    fn dependents(&self, dependency: &str) -> Vec<String> {
+        self.dependents
+            .get(dependency)
+            .map(|d| d.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+fn subject_dependencies<T: Subject>(tenant: &str, subject: &T) -> Vec<String> {
+    subject
+        .get_inline_policy()
+        .into_iter()
+        .chain(subject.get_boundary())
+        .chain(subject.linked_policies())
+        .map(|p| InvalidationEvent::Policy(scoped_id(tenant, &p.id)).to_payload())
+        .collect()
+}
+
+/// Caches the subjects loaded from the storage (identities and
+/// the groups an identity belongs to), keyed by tenant and identity id.
+///
+/// Every cached entry records the policies, groups and identities
+/// it has been built from, so that an invalidation event evicts
+/// exactly the identities affected by the change.
+pub(crate) struct SubjectCache {
+    identities: LruCache<Identity>,
+    groups: LruCache<Vec<Group>>,
+    index: Mutex<DependencyIndex>,
+
+    /// Incremented on every invalidation.
+    generation: AtomicU64,
+}
+
+impl SubjectCache {
+    pub(crate) fn new(capacity: usize, ttl: Option<Duration>) -> Self {
+        SubjectCache {
+            identities: LruCache::new(capacity, ttl),
+            groups: LruCache::new(capacity, ttl),
+            index: Mutex::new(DependencyIndex::default()),
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    /// Gets the current cache generation.
+    ///
+    /// Must be read *before* loading the data from the storage and passed
+    /// to the insert functions: if an invalidation happened meanwhile the
+    /// loaded data could be stale and will not be cached.
+    pub(crate) fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn get_identity(&self, tenant: &str, id: &str) -> Option<Identity> {
+        self.identities.get(&scoped_id(tenant, id))
+    }
+
+    pub(crate) fn insert_identity(&self, tenant: &str, identity: &Identity, generation: u64) {
+        let key = scoped_id(tenant, &identity.id);
+        let mut dependencies = subject_dependencies(tenant, identity);
+        dependencies.push(InvalidationEvent::Identity(key.clone()).to_payload());
+
+        self.insert(&key, dependencies, generation, |cache| {
+            cache.identities.insert(&key, identity.clone())
+        });
+    }
+
+    /// Gets the groups the given identity belongs to.
+    pub(crate) fn get_groups(&self, tenant: &str, identity_id: &str) -> Option<Vec<Group>> {
+        self.groups.get(&scoped_id(tenant, identity_id))
+    }
+
+    /// Caches the groups of the given identity. If the memberships change
+    /// at a known time (`valid_until`, as unix timestamp) the entry expires then.
+    pub(crate) fn insert_groups(&self, tenant: &str, identity_id: &str, groups: &[Group], valid_until: Option<i64>, generation: u64) {
+        let key = scoped_id(tenant, identity_id);
+        let mut dependencies = vec![
+            InvalidationEvent::Identity(key.clone()).to_payload(),
+            InvalidationEvent::DynamicGroups.to_payload(),
+        ];
+        for group in groups {
+            dependencies.push(InvalidationEvent::Group(scoped_id(tenant, &group.name)).to_payload());
+            dependencies.append(&mut subject_dependencies(tenant, group));
+        }
+
+        self.insert(&key, dependencies, generation, |cache| {
+            let deadline = valid_until.map(|t| {
+                let remaining = (t - unix_now()).max(0) as u64;
+                Instant::now() + Duration::from_secs(remaining)
+            });
+
+            cache.groups.insert_until(&key, groups.to_vec(), deadline)
+        });
+    }
+
+    fn insert<F>(&self, identity_id: &str, dependencies: Vec<String>, generation: u64, insert: F)
+    where
+        F: FnOnce(&Self) -> Vec<String>,
+    {
+        let mut index = self.index.lock().unwrap();
+        if generation != self.generation() {
+            trace!(r#"Subject "{}" changed while loading. Skipping cache."#, identity_id);
+            return;
+        }
+
+        index.add(identity_id, dependencies);
+        for evicted in insert(self) {
+            self.forget(&mut index, &evicted);
+        }
+    }
+
+    /// Evicts all the identities depending on the entity targeted by the event.
+    pub(crate) fn invalidate(&self, event: &InvalidationEvent) {
+        let mut index = self.index.lock().unwrap();
+        self.generation.fetch_add(1, Ordering::SeqCst);
+
+        if *event == InvalidationEvent::All {
+            self.identities.clear();
+            self.groups.clear();
+            *index = DependencyIndex::default();
+            return;
+        }
+
+        for identity_id in index.dependents(&event.to_payload()) {
+            trace!(r#"Evicting subject "{}" from cache"#, identity_id);
+            self.forget(&mut index, &identity_id);
+        }
+    }
+
+    fn forget(&self, index: &mut DependencyIndex, identity_id: &str) {
+        self.identities.remove(identity_id);
+        self.groups.remove(identity_id);
+        index.remove(identity_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cache::invalidation::InvalidationEvent;
+    use crate::cache::subject_cache::SubjectCache;
+    use crate::identity::group::Group;
+    use crate::identity::identity::Identity;
+    use crate::policy::policy_set::PolicySetTrait;
+    use crate::policy::{PolicyEffect, PolicyVersion};
+    use crate::zephir_policy;
+
+    fn identity(id: &str, policy: &str) -> Identity {
+        Identity::new(id, Option::None).add_policy(
+            zephir_policy!(
+                policy,
+                PolicyVersion::Version1,
+                PolicyEffect::Allow,
+                vec!["action"]
+            )
+            .unwrap(),
+        )
+    }
+
+    fn group(name: &str, policy: &str) -> Group {
+        Group::new(name, Option::None).add_policy(
+            zephir_policy!(
+                policy,
+                PolicyVersion::Version1,
+                PolicyEffect::Allow,
+                vec!["action"]
+            )
+            .unwrap(),
+        )
+    }
+
+    #[test]
+    fn policy_change_should_evict_only_dependent_identities() {
+        let cache = SubjectCache::new(10, Option::None);
+        let generation = cache.generation();
+        cache.insert_identity("t1", &identity("i1", "p1"), generation);
+        cache.insert_identity("t1", &identity("i2", "p2"), generation);
+
+        cache.invalidate(&InvalidationEvent::Policy("t1/p1".to_string()));
+
+        assert!(cache.get_identity("t1", "i1").is_none());
+        assert!(cache.get_identity("t1", "i2").is_some());
+    }
+
+    #[test]
+    fn group_change_should_evict_members() {
+        let cache = SubjectCache::new(10, Option::None);
+        let generation = cache.generation();
+        cache.insert_identity("t1", &identity("i1", "p1"), generation);
+        cache.insert_groups("t1", "i1", &[group("g1", "gp1")], Option::None, generation);
+        cache.insert_groups("t1", "i2", &[group("g2", "gp2")], Option::None, generation);
+
+        cache.invalidate(&InvalidationEvent::Policy("t1/gp1".to_string()));
+        assert!(cache.get_identity("t1", "i1").is_none());
+        assert!(cache.get_groups("t1", "i1").is_none());
+        assert!(cache.get_groups("t1", "i2").is_some());
+
+        cache.invalidate(&InvalidationEvent::Group("t1/g2".to_string()));
+        assert!(cache.get_groups("t1", "i2").is_none());
+    }
+
+    #[test]
+    fn identity_change_should_evict_its_groups() {
+        let cache = SubjectCache::new(10, Option::None);
+        let generation = cache.generation();
+        cache.insert_groups("t1", "i1", &[group("g1", "gp1")], Option::None, generation);
+
+        cache.invalidate(&InvalidationEvent::Identity("t1/i1".to_string()));
+        assert!(cache.get_groups("t1", "i1").is_none());
+    }
+
+    #[test]
+    fn dynamic_groups_change_should_evict_all_groups() {
+        let cache = SubjectCache::new(10, Option::None);
+        let generation = cache.generation();
+        cache.insert_groups("t1", "i1", &[group("g1", "gp1")], Option::None, generation);
+        cache.insert_groups("t1", "i2", &[], Option::None, generation);
+
+        cache.invalidate(&InvalidationEvent::DynamicGroups);
+        assert!(cache.get_groups("t1", "i1").is_none());
+        assert!(cache.get_groups("t1", "i2").is_none());
+    }
+
+    #[test]
+    fn events_should_only_evict_entries_of_the_same_tenant() {
+        let cache = SubjectCache::new(10, Option::None);
+        let generation = cache.generation();
+        cache.insert_identity("t1", &identity("i1", "p1"), generation);
+        cache.insert_identity("t2", &identity("i1", "p1"), generation);
+        cache.insert_groups("t2", "i1", &[group("g1", "gp1")], Option::None, generation);
+
+        cache.invalidate(&InvalidationEvent::Policy("t1/p1".to_string()));
+        assert!(cache.get_identity("t1", "i1").is_none());
+        assert!(cache.get_identity("t2", "i1").is_some());
+
+        cache.invalidate(&InvalidationEvent::Group("t1/g1".to_string()));
+        assert!(cache.get_groups("t2", "i1").is_some());
+    }
+
+    #[test]
+    fn stale_data_should_not_be_cached() {
+        let cache = SubjectCache::new(10, Option::None);
+        let generation = cache.generation();
+        cache.invalidate(&InvalidationEvent::Policy("t1/p2".to_string()));
+        cache.insert_identity("t1", &identity("i1", "p1"), generation);
+
+        assert!(cache.get_identity("t1", "i1").is_none());
+    }
+
+    #[test]
+    fn all_event_should_clear_the_cache() {
+        let cache = SubjectCache::new(10, Option::None);
+        let generation = cache.generation();
+        cache.insert_identity("t1", &identity("i1", "p1"), generation);
+
+        cache.invalidate(&InvalidationEvent::All);
+        assert!(cache.get_identity("t1", "i1").is_none());
+    }
+}
@@ -0,0 +1,91 @@
+use crate::policy::guardrails::Guardrails;
+use crate::policy::resource_policy::ResourcePolicies;
+use std::collections::HashMap;
+use std::lazy::SyncLazy;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Holds the guardrails, as they are needed to evaluate every single request.
+pub(crate) static GUARDRAIL_CACHE: SyncLazy<TenantCache<Guardrails>> = SyncLazy::new(TenantCache::new);
+
+/// Holds the resource policies, as they are needed to evaluate every request targeting a resource.
+pub(crate) static RESOURCE_POLICY_CACHE: SyncLazy<TenantCache<ResourcePolicies>> = SyncLazy::new(TenantCache::new);
+
+/// Holds a value loaded from the storage for each tenant.
+pub(crate) struct TenantCache<V: Clone> {
+    values: Mutex<HashMap<String, V>>,
+
+    /// Incremented on every invalidation.
+    generation: AtomicU64,
+}
+
+impl<V: Clone> TenantCache<V> {
+    pub(crate) fn new() -> Self {
+        TenantCache {
+            values: Mutex::new(HashMap::new()),
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    /// Gets the current cache generation.
+    /// See `SubjectCache::generation` for details.
+    pub(crate) fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn get(&self, tenant: &str) -> Option<V> {
+        self.values.lock().unwrap().get(tenant).cloned()
+    }
+
+    pub(crate) fn insert(&self, tenant: &str, value: &V, generation: u64) {
+        let mut cached = self.values.lock().unwrap();
+        if generation == self.generation() {
+            cached.insert(tenant.to_string(), value.clone());
+        }
+    }
+
+    pub(crate) fn invalidate(&self, tenant: &str) {
+        let mut cached = self.values.lock().unwrap();
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        cached.remove(tenant);
+    }
+
+    pub(crate) fn clear(&self) {
+        let mut cached = self.values.lock().unwrap();
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        cached.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cache::tenant_cache::TenantCache;
+    use crate::policy::guardrails::Guardrails;
+
+    #[test]
+    fn stale_values_should_not_be_cached() {
+        let cache: TenantCache<Guardrails> = TenantCache::new();
+        let generation = cache.generation();
+        cache.insert("default", &Guardrails::default(), generation);
+        assert!(cache.get("default").is_some());
+
+        cache.invalidate("default");
+        assert!(cache.get("default").is_none());
+
+        cache.insert("default", &Guardrails::default(), generation);
+        assert!(cache.get("default").is_none());
+    }
+
+    #[test]
+    fn values_should_be_cached_per_tenant() {
+        let cache: TenantCache<Guardrails> = TenantCache::new();
+        cache.insert("acme", &Guardrails::default(), cache.generation());
+        assert!(cache.get("acme").is_some());
+        assert!(cache.get("default").is_none());
+
+        cache.insert("default", &Guardrails::default(), cache.generation());
+        cache.invalidate("acme");
+        assert!(cache.get("acme").is_none());
+        assert!(cache.get("default").is_some());
+    }
+}
@@ -1,37 +1,80 @@
-use crate::cache::create_cache;
+use crate::cache::{create_cache, Cache, CacheStats, LruCache, DEFAULT_CAPACITY};
 use crate::compiler::compiled_policy::CompiledPolicy;
 use crate::utils::glob_to_regex;
 use log::{debug, log_enabled, trace, Level};
-use mouscache::{Cache, CacheError};
 use std::lazy::SyncLazy;
 use std::ops::Deref;
 
 static COMPILER: SyncLazy<Compiler> = SyncLazy::new(|| Compiler::new(create_cache()));
 
 pub(crate) mod cache {
-    use crate::compiler::compiled_policy::CompiledPolicy;
     use crate::compiler::compiler::COMPILER;
 
-    /// Removes a compiled policy from the cache.
+    /// Removes the last compiled version of a policy from the cache.
     /// Should be called from the storage manager, when a policy is updated or removed.
+    ///
+    /// Cache keys depend only on the policy content, so this is not needed
+    /// to avoid serving stale regexes: it just frees the entry which is
+    /// not referenced by the updated policy anymore.
     pub fn flush_policy(id: &str) {
-        let _ = COMPILER.cache.remove::<_, CompiledPolicy>(id);
+        COMPILER.flush_policy(id);
+    }
+
+    /// Removes all the compiled policies from the local cache.
+    pub fn flush_all() {
+        COMPILER.keys.clear();
+        COMPILER.cache.clear();
     }
 }
 
+const FNV_OFFSET_BASIS: u128 = 0x6c62272e07bb014262b821756295c58d;
+const FNV_PRIME: u128 = 0x0000000001000000000000000000013b;
+
+/// Computes the cache key for the given actions and resources.
+///
+/// The key is a FNV-1a 128-bit hash of the two pattern lists, each
+/// pattern prefixed by its length so that different splits of the same
+/// characters cannot produce the same key.
+/// The hash is stable across processes, as the key could be shared
+/// between different instances through a redis cache.
+pub(crate) fn cache_key(actions: &[String], resources: &[String]) -> String {
+    let mut hash = FNV_OFFSET_BASIS;
+    let mut write = |bytes: &[u8]| {
+        for b in bytes {
+            hash ^= *b as u128;
+            hash = hash.wrapping_mul(FNV_PRIME);
+        }
+    };
+
+    for list in &[actions, resources] {
+        write(&(list.len() as u64).to_le_bytes());
+        for pattern in list.iter() {
+            write(&(pattern.len() as u64).to_le_bytes());
+            write(pattern.as_bytes());
+        }
+    }
+
+    format!("{:032x}", hash)
+}
+
 pub struct Compiler {
-    cache: Cache,
+    cache: Cache<CompiledPolicy>,
+
+    /// Maps the policy ids to the key of their last compiled version.
+    /// Bounded as the in-memory cache is: the least recently compiled
+    /// policies are forgotten, leaving their entry to the cache eviction.
+    keys: LruCache<String>,
 }
 
 impl Default for Compiler {
-    /// Creates a new compiler with the default *in-memory* cache.
+    /// Creates a new compiler with the default *in-memory* LRU cache.
     /// Should not be used if more than one instance of zephir is in execution
     /// and a redis cache should be preferred in case.
     ///
     /// However, is small deployments, in memory cache is more than enough
     /// and avoids an expensive redis (or redis-cluster) deployment.
     fn default() -> Self {
-        Self::new(mouscache::memory())
+        Self::new(Cache::memory(DEFAULT_CAPACITY, Option::None))
     }
 }
 
@@ -41,8 +84,12 @@ impl Compiler {
     /// The cache will be used to store copies of CompiledPolicy objects:
     /// glob to regex operation is in fact very expensive, while "allowed"
     /// operation should be very fast in order to be usable.
-    fn new(cache: Cache) -> Self {
-        Compiler { cache }
+    fn new(cache: Cache<CompiledPolicy>) -> Self {
+        let capacity = cache.stats().capacity.unwrap_or(DEFAULT_CAPACITY);
+        Compiler {
+            cache,
+            keys: LruCache::new(capacity, Option::None),
+        }
     }
 
     /// Gets a reference to the compiler singleton.
@@ -53,14 +100,45 @@ impl Compiler {
         COMPILER.deref()
     }
 
+    /// Gets the hit/miss/eviction counters of the compiled policies cache.
+    pub fn cache_stats(&self) -> CacheStats {
+        self.cache.stats()
+    }
+
+    /// Gets the name of the cache backend in use.
+    pub fn cache_backend_name(&self) -> &'static str {
+        self.cache.backend_name()
+    }
+
+    /// Forgets the last compiled version of a policy, removing its entry
+    /// from the cache unless another tracked policy has the same content.
+    ///
+    /// Policies without id (or forgotten by the bounded key tracker) are not
+    /// taken into account: their entry could still be removed, and they will
+    /// be compiled again on their next evaluation.
+    fn flush_policy(&self, id: &str) {
+        if let Some(key) = self.keys.get(id) {
+            self.keys.remove(id);
+            if !self.keys.contains_value(&key) {
+                self.cache.remove(&key);
+            }
+        }
+    }
+
     /// Compiles a policy
     ///
     /// This function will convert policy components (actions, resources) into
     /// regexes that could be easily matched against the strings present in
     /// an "allowed" request.
     ///
-    /// The id field must be unique and represents the identifier of the policy.
-    /// The field will be used as a cache key to avoid glob-to-regex recalculation.
+    /// The compiled policy is cached under a hash of its actions and resources
+    /// to avoid glob-to-regex recalculation: policies with identical patterns
+    /// share the same compiled object, whatever their id is (embedded policies
+    /// could even have an empty id) and an edited policy can never be matched
+    /// against the regexes of its previous version.
+    ///
+    /// The id is only used to keep track of the last compiled version of the policy,
+    /// so that it could be removed from the cache when the policy is updated.
     ///
     /// # Returns
     ///
@@ -71,10 +149,14 @@ impl Compiler {
         actions: &[String],
         resources: &[String],
     ) -> CompiledPolicy {
-        let item = if id.is_empty() { Err(CacheError::Other("".to_string())) } else { self.cache.get(id) };
-        if (&item).is_ok() && (&item).as_ref().unwrap().is_some() {
-            debug!("Compiled policy {} found in cache.", id);
-            return item.unwrap().unwrap();
+        let key = cache_key(actions, resources);
+        if !id.is_empty() {
+            self.keys.insert(id, key.clone());
+        }
+
+        if let Some(item) = self.cache.get(&key) {
+            debug!("Compiled policy {} found in cache ({}).", id, key);
+            return item;
         }
 
         let compiled_actions = actions
@@ -94,11 +176,12 @@ impl Compiler {
 
         let cp = CompiledPolicy::new(compiled_actions, compiled_resources);
 
-        let cache_insert_result = self.cache.insert(id, cp.clone());
+        let cache_insert_result = self.cache.insert(&key, cp.clone());
         trace!(
-            "Compiled policy {} stored in cache: {}",
+            "Compiled policy {} stored in cache ({}): {}",
             id,
-            if cache_insert_result.is_ok() {
+            key,
+            if cache_insert_result {
                 "SUCCESS"
             } else {
                 "FAIL"
@@ -113,3 +196,108 @@ impl Compiler {
         cp
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::cache::Cache;
+    use crate::compiler::compiler::{cache_key, Compiler, COMPILER};
+    use crate::policy::policy::CompletePolicy;
+    use crate::policy::{PolicyEffect, PolicyVersion};
+    use crate::tenant::DEFAULT_TENANT;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn cache_key_should_depend_only_on_content() {
+        let key = cache_key(&strings(&["core:*"]), &strings(&["urn:*"]));
+
+        assert_eq!(key, cache_key(&strings(&["core:*"]), &strings(&["urn:*"])));
+        assert_ne!(key, cache_key(&strings(&["core:*"]), &strings(&["urn:**"])));
+        assert_ne!(key, cache_key(&strings(&["core:*", "urn:*"]), &[]));
+        assert_ne!(
+            cache_key(&strings(&["ab", "c"]), &[]),
+            cache_key(&strings(&["a", "bc"]), &[])
+        );
+    }
+
+    #[test]
+    fn policies_without_id_should_be_cached() {
+        let compiler = Compiler::default();
+        let actions = strings(&["core:GetVersion"]);
+        let resources = strings(&["*"]);
+
+        compiler.compile("", &actions, &resources);
+        let cached = compiler.cache.get(&cache_key(&actions, &resources));
+
+        assert!(cached.is_some());
+        assert!(compiler.keys.is_empty());
+    }
+
+    #[test]
+    fn compile_should_track_last_key_of_the_policy() {
+        let compiler = Compiler::default();
+        let resources = strings(&["*"]);
+
+        compiler.compile("CompilerTestPolicy", &strings(&["core:GetVersion"]), &resources);
+        compiler.compile("CompilerTestPolicy", &strings(&["core:*"]), &resources);
+
+        assert_eq!(
+            compiler.keys.get("CompilerTestPolicy"),
+            Option::Some(cache_key(&strings(&["core:*"]), &resources))
+        );
+    }
+
+    #[test]
+    fn tracked_keys_should_be_bounded_by_the_cache_capacity() {
+        let compiler = Compiler::new(Cache::memory(2, Option::None));
+        let resources = strings(&["*"]);
+        for id in &["First", "Second", "Third"] {
+            compiler.compile(id, &strings(&[&format!("core:{}", id)]), &resources);
+        }
+
+        assert_eq!(compiler.keys.len(), 2);
+        assert!(compiler.keys.get("First").is_none());
+        assert!(compiler.keys.get("Third").is_some());
+    }
+
+    #[test]
+    fn flushing_a_policy_should_keep_entries_shared_with_other_policies() {
+        let compiler = Compiler::default();
+        let actions = strings(&["core:GetVersion"]);
+        let resources = strings(&["*"]);
+        let key = cache_key(&actions, &resources);
+
+        compiler.compile("First", &actions, &resources);
+        compiler.compile("Second", &actions, &resources);
+
+        compiler.flush_policy("First");
+        assert!(compiler.keys.get("First").is_none());
+        assert!(compiler.cache.get(&key).is_some());
+
+        compiler.flush_policy("Second");
+        assert!(compiler.cache.get(&key).is_none());
+    }
+
+    #[test]
+    fn untracked_policies_should_not_replace_the_tracked_key() {
+        let policy = |actions: Vec<&str>, tracked: bool| {
+            let id = String::from("UntrackedTestPolicy");
+            if tracked {
+                CompletePolicy::new_in_tenant(DEFAULT_TENANT, id, PolicyVersion::Version1, PolicyEffect::Allow, actions, vec!["*"])
+            } else {
+                CompletePolicy::new_untracked(id, PolicyVersion::Version1, PolicyEffect::Allow, actions, vec!["*"])
+            }
+            .unwrap()
+        };
+
+        policy(vec!["core:GetVersion"], true);
+        policy(vec!["core:*"], false);
+
+        assert_eq!(
+            COMPILER.keys.get("default/UntrackedTestPolicy"),
+            Option::Some(cache_key(&strings(&["core:GetVersion"]), &strings(&["*"])))
+        );
+    }
+}
@@ -15,6 +15,29 @@ pub enum ErrorKind {
     /// Raised when trying to unwrap an Option::None value.
     UnwrapNoneValueError = 3,
 
+    /// Raised when saving a group membership which would make a group
+    /// (directly or indirectly) a member of itself.
+    GroupCycleError = 4,
+
+    /// Raised when the requested combining algorithm is not known.
+    UnknownCombiningAlgorithmError = 5,
+
+    /// Raised when a dynamic group membership rule could not be parsed.
+    InvalidMembershipRuleError = 6,
+
+    /// Raised when a tenant id is empty or contains invalid characters.
+    InvalidTenantError = 7,
+
+    /// Raised when the policy files could not be loaded into a consistent snapshot.
+    InvalidSnapshotError = 8,
+
+    /// Raised when a policy bundle is malformed, has been tampered
+    /// with or is not signed by the expected key.
+    InvalidBundleError = 9,
+
+    /// Raised when a webhook has an invalid url or subscribes to unknown events.
+    InvalidWebhookError = 10,
+
     /// Represents any other error including the one not raised by this library
     /// and wrapped into a Error object exposed from this crate.
     UnknownError = -1,
@@ -66,6 +89,52 @@ impl Error {
             UnknownPolicyVersionError { version },
         )
     }
+
+    pub fn group_cycle(path: Vec<String>) -> Self {
+        Self::new(ErrorKind::GroupCycleError, GroupCycleError { path })
+    }
+
+    pub fn unknown_combining_algorithm<S: ToString>(name: S) -> Self {
+        Self::new(
+            ErrorKind::UnknownCombiningAlgorithmError,
+            format!("Unknown combining algorithm \"{}\"", name.to_string()),
+        )
+    }
+
+    pub fn invalid_membership_rule<S: ToString, R: ToString>(rule: S, reason: R) -> Self {
+        Self::new(
+            ErrorKind::InvalidMembershipRuleError,
+            format!("Invalid membership rule \"{}\": {}", rule.to_string(), reason.to_string()),
+        )
+    }
+
+    pub fn invalid_tenant<S: ToString>(tenant: S) -> Self {
+        Self::new(
+            ErrorKind::InvalidTenantError,
+            format!("Invalid tenant \"{}\"", tenant.to_string()),
+        )
+    }
+
+    pub fn invalid_snapshot<S: ToString>(reason: S) -> Self {
+        Self::new(
+            ErrorKind::InvalidSnapshotError,
+            format!("Invalid policy snapshot: {}", reason.to_string()),
+        )
+    }
+
+    pub fn invalid_bundle<S: ToString>(reason: S) -> Self {
+        Self::new(
+            ErrorKind::InvalidBundleError,
+            format!("Invalid policy bundle: {}", reason.to_string()),
+        )
+    }
+
+    pub fn invalid_webhook<S: ToString>(reason: S) -> Self {
+        Self::new(
+            ErrorKind::InvalidWebhookError,
+            format!("Invalid webhook: {}", reason.to_string()),
+        )
+    }
 }
 
 impl Display for Error {
@@ -96,6 +165,19 @@ impl fmt::Display for UnknownPolicyVersionError {
 
 impl std::error::Error for UnknownPolicyVersionError {}
 
+#[derive(Debug)]
+struct GroupCycleError {
+    path: Vec<String>,
+}
+
+impl fmt::Display for GroupCycleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Group membership would create a cycle: {}", self.path.join(" -> "))
+    }
+}
+
+impl std::error::Error for GroupCycleError {}
+
 #[derive(Debug)]
 pub struct NoneError {}
 
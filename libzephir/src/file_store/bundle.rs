@@ -0,0 +1,325 @@
+use crate::err::Error;
+use crate::file_store::document::Document;
+use crate::policy::policy::ToJson;
+use crate::storage::{TenantExport, EXPORT_SECTIONS};
+use ed25519_dalek::{Keypair, PublicKey, SecretKey, Signature, Signer, Verifier};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::io::Read;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The name of the bundle manifest, listing the content hashes of the data files.
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// The name of the file holding the (hex-encoded) signature of the manifest.
+pub const SIGNATURE_FILE: &str = "manifest.sig";
+
+const BUNDLE_VERSION: i32 = 1;
+
+fn sha256(content: &[u8]) -> String {
+    hex::encode(Sha256::digest(content))
+}
+
+/// Generates a new signing key.
+///
+/// # Returns
+///
+/// The hex-encoded secret and public keys
+pub fn generate_keys() -> (String, String) {
+    let keypair = Keypair::generate(&mut rand::rngs::OsRng);
+    (hex::encode(keypair.secret.as_bytes()), hex::encode(keypair.public.as_bytes()))
+}
+
+/// Parses an hex-encoded Ed25519 secret key, as generated by `generate_keys`.
+pub fn parse_secret_key(key: &str) -> Result<Keypair, Error> {
+    let bytes = hex::decode(key.trim()).map_err(|e| Error::invalid_bundle(format!("invalid secret key: {}", e)))?;
+    let secret = SecretKey::from_bytes(&bytes).map_err(|e| Error::invalid_bundle(format!("invalid secret key: {}", e)))?;
+    let public = PublicKey::from(&secret);
+
+    Ok(Keypair { secret, public })
+}
+
+/// Parses an hex-encoded Ed25519 public key, as generated by `generate_keys`.
+pub fn parse_public_key(key: &str) -> Result<PublicKey, Error> {
+    let bytes = hex::decode(key.trim()).map_err(|e| Error::invalid_bundle(format!("invalid public key: {}", e)))?;
+    PublicKey::from_bytes(&bytes).map_err(|e| Error::invalid_bundle(format!("invalid public key: {}", e)))
+}
+
+/// Describes the content of a bundle.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Manifest {
+    pub version: i32,
+    pub tenant: String,
+    pub created_at: i64,
+
+    /// The SHA-256 hash of every data file, by file name.
+    pub files: BTreeMap<String, String>,
+}
+
+impl Manifest {
+    /// Checks whether the bundle could replace the loaded one: it must
+    /// hold the same tenant, and must not be older (a stale or replayed
+    /// bundle could otherwise restore revoked permissions).
+    pub fn check_replaces(&self, loaded: &Manifest) -> Result<(), Error> {
+        if self.tenant != loaded.tenant {
+            return Err(Error::invalid_bundle(format!(
+                "bundle of tenant \"{}\" cannot replace the one of tenant \"{}\"",
+                self.tenant, loaded.tenant
+            )));
+        }
+
+        if self.created_at < loaded.created_at {
+            return Err(Error::invalid_bundle(format!(
+                "bundle created at {} is older than the loaded one, created at {}",
+                self.created_at, loaded.created_at
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// A signed, self-contained copy of the entities of a tenant, used to
+/// distribute the policies to the instances without database access.
+///
+/// A bundle is a tar.gz archive holding a JSON data file per section of
+/// the tenant export, a manifest with the content hashes of these files
+/// and the Ed25519 signature of the manifest: verifying the signature
+/// and then the hashes guarantees that no file has been altered.
+pub struct Bundle {
+    manifest: Manifest,
+    files: BTreeMap<String, Vec<u8>>,
+}
+
+impl Bundle {
+    /// Creates a bundle holding the entities of the given tenant export.
+    pub fn from_export(export: &TenantExport) -> Result<Self, Error> {
+        let created_at = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+        let mut json = export.to_json();
+        let mut files = BTreeMap::new();
+        let mut hashes = BTreeMap::new();
+
+        // Each section of the export is stored in its own data file.
+        for section in EXPORT_SECTIONS {
+            let mut content = Map::new();
+            content.insert(section.to_string(), json.remove(*section).unwrap_or_else(|| Value::Array(vec![])));
+
+            let name = format!("{}.json", section);
+            let content = serde_json::to_vec_pretty(&content)?;
+            hashes.insert(name.clone(), sha256(&content));
+            files.insert(name, content);
+        }
+
+        Ok(Bundle {
+            manifest: Manifest {
+                version: BUNDLE_VERSION,
+                tenant: export.tenant.clone(),
+                created_at,
+                files: hashes,
+            },
+            files,
+        })
+    }
+
+    pub fn get_manifest(&self) -> &Manifest {
+        &self.manifest
+    }
+
+    /// Signs the bundle with the given key and writes it as a tar.gz archive.
+    pub fn write(&self, keypair: &Keypair) -> Result<Vec<u8>, Error> {
+        let manifest = serde_json::to_vec_pretty(&self.manifest)?;
+        let signature = hex::encode(keypair.sign(&manifest).to_bytes());
+
+        let mut builder = tar::Builder::new(GzEncoder::new(vec![], Compression::default()));
+        let mut append = |name: &str, content: &[u8]| -> Result<(), Error> {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_mtime(self.manifest.created_at as u64);
+            header.set_cksum();
+
+            Ok(builder.append_data(&mut header, name, content)?)
+        };
+
+        append(MANIFEST_FILE, &manifest)?;
+        append(SIGNATURE_FILE, signature.as_bytes())?;
+        for (name, content) in &self.files {
+            append(name, content)?;
+        }
+
+        Ok(builder.into_inner()?.finish()?)
+    }
+
+    /// Reads a bundle archive, verifying its signature against the given
+    /// public key and the hashes of all its files.
+    /// Fails if the archive contains any file not listed in the manifest.
+    pub fn read(archive: &[u8], public_key: &PublicKey) -> Result<Self, Error> {
+        let mut files = BTreeMap::new();
+        for entry in tar::Archive::new(GzDecoder::new(archive)).entries()? {
+            let mut entry = entry?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+
+            let name = entry.path()?.to_string_lossy().to_string();
+            let mut content = vec![];
+            entry.read_to_end(&mut content)?;
+            if files.insert(name.clone(), content).is_some() {
+                return Err(Error::invalid_bundle(format!("duplicated file \"{}\"", name)));
+            }
+        }
+
+        let manifest = files
+            .remove(MANIFEST_FILE)
+            .ok_or_else(|| Error::invalid_bundle("missing manifest"))?;
+        let signature = files
+            .remove(SIGNATURE_FILE)
+            .ok_or_else(|| Error::invalid_bundle("missing signature"))?;
+
+        let signature = hex::decode(String::from_utf8_lossy(&signature).trim())
+            .ok()
+            .and_then(|bytes| Signature::try_from(bytes.as_slice()).ok())
+            .ok_or_else(|| Error::invalid_bundle("malformed signature"))?;
+        public_key
+            .verify(&manifest, &signature)
+            .map_err(|_| Error::invalid_bundle("signature verification failed"))?;
+
+        let manifest: Manifest =
+            serde_json::from_slice(&manifest).map_err(|e| Error::invalid_bundle(format!("malformed manifest: {}", e)))?;
+        if manifest.version != BUNDLE_VERSION {
+            return Err(Error::invalid_bundle(format!("unsupported version {}", manifest.version)));
+        }
+
+        for (name, hash) in &manifest.files {
+            match files.get(name) {
+                Some(content) if &sha256(content) == hash => (),
+                Some(_) => return Err(Error::invalid_bundle(format!("hash mismatch for \"{}\"", name))),
+                None => return Err(Error::invalid_bundle(format!("missing file \"{}\"", name))),
+            }
+        }
+
+        if let Some(name) = files.keys().find(|name| !manifest.files.contains_key(*name)) {
+            return Err(Error::invalid_bundle(format!("unexpected file \"{}\"", name)));
+        }
+
+        Ok(Bundle { manifest, files })
+    }
+
+    /// Parses the data files into a single document.
+    pub(super) fn document(&self) -> Result<Document, Error> {
+        let mut document = Document::default();
+        for (name, content) in &self.files {
+            let loaded = serde_json::from_slice(content)
+                .map_err(|e| Error::invalid_bundle(format!("{}: {}", name, e)))?;
+            document.merge(loaded);
+        }
+
+        Ok(document)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::file_store::bundle::{generate_keys, parse_public_key, parse_secret_key, Bundle, Manifest, MANIFEST_FILE};
+    use crate::policy::policy::CompletePolicy;
+    use crate::policy::{PolicyEffect, PolicyVersion};
+    use crate::storage::TenantExport;
+    use flate2::read::GzDecoder;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Read;
+
+    fn export() -> TenantExport {
+        TenantExport {
+            tenant: String::from("acme"),
+            policies: vec![CompletePolicy::new(
+                String::from("TestPolicy"),
+                PolicyVersion::Version1,
+                PolicyEffect::Allow,
+                vec!["get_*"],
+                vec!["urn:resource:*"],
+            )
+            .unwrap()],
+            ..TenantExport::default()
+        }
+    }
+
+    /// Rewrites the archive, replacing the content of the given file.
+    fn tamper(archive: &[u8], file: &str, content: &[u8]) -> Vec<u8> {
+        let mut reader = tar::Archive::new(GzDecoder::new(archive));
+        let mut builder = tar::Builder::new(GzEncoder::new(vec![], Compression::default()));
+        for entry in reader.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let name = entry.path().unwrap().to_string_lossy().to_string();
+            let mut data = vec![];
+            entry.read_to_end(&mut data).unwrap();
+            if name == file {
+                data = content.to_vec();
+            }
+
+            let mut header = entry.header().clone();
+            header.set_size(data.len() as u64);
+            header.set_cksum();
+            builder.append_data(&mut header, name, data.as_slice()).unwrap();
+        }
+
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    #[test]
+    fn bundle_should_be_signed_and_verified() {
+        let (secret, public) = generate_keys();
+        let keypair = parse_secret_key(&secret).unwrap();
+        let public_key = parse_public_key(&public).unwrap();
+
+        let bundle = Bundle::from_export(&export()).unwrap();
+        let archive = bundle.write(&keypair).unwrap();
+
+        let read = Bundle::read(&archive, &public_key).unwrap();
+        assert_eq!(read.get_manifest(), bundle.get_manifest());
+        assert_eq!(read.get_manifest().tenant, "acme");
+        assert_eq!(read.get_manifest().files.len(), 6);
+
+        let document = read.document().unwrap();
+        assert_eq!(document.policies.len(), 1);
+        assert_eq!(document.policies[0].id, "TestPolicy");
+    }
+
+    #[test]
+    fn tampered_bundles_should_be_rejected() {
+        let (secret, _) = generate_keys();
+        let (_, other_public) = generate_keys();
+        let keypair = parse_secret_key(&secret).unwrap();
+
+        let archive = Bundle::from_export(&export()).unwrap().write(&keypair).unwrap();
+        assert!(Bundle::read(&archive, &parse_public_key(&other_public).unwrap()).is_err());
+
+        let tampered = tamper(&archive, "policies.json", br#"{"policies":[]}"#);
+        assert!(Bundle::read(&tampered, &keypair.public).is_err());
+
+        let tampered = tamper(&archive, MANIFEST_FILE, br#"{"version":1,"tenant":"acme","created_at":0,"files":{}}"#);
+        assert!(Bundle::read(&tampered, &keypair.public).is_err());
+        assert!(Bundle::read(&archive, &keypair.public).is_ok());
+    }
+
+    #[test]
+    fn older_or_foreign_bundles_should_not_replace_the_loaded_one() {
+        let loaded = Bundle::from_export(&export()).unwrap().get_manifest().clone();
+        let manifest = |tenant: &str, created_at: i64| Manifest {
+            tenant: String::from(tenant),
+            created_at,
+            ..loaded.clone()
+        };
+
+        assert!(manifest("acme", loaded.created_at).check_replaces(&loaded).is_ok());
+        assert!(manifest("acme", loaded.created_at + 1).check_replaces(&loaded).is_ok());
+        assert!(manifest("acme", loaded.created_at - 1).check_replaces(&loaded).is_err());
+        assert!(manifest("globex", loaded.created_at + 1).check_replaces(&loaded).is_err());
+    }
+}
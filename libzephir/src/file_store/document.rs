@@ -0,0 +1,138 @@
+use crate::policy::condition::Attributes;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+fn default_version() -> i32 {
+    1
+}
+
+/// The content of a policy file. Every section is optional: entities
+/// could be split across any number of files.
+///
+/// The format is the same produced by the tenant export, so that
+/// an export could be used as it is to feed a file-based instance.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(super) struct Document {
+    /// The export format version, checked on imports only.
+    pub(super) version: Option<i32>,
+    pub(super) policies: Vec<PolicyDocument>,
+    pub(super) identities: Vec<IdentityDocument>,
+    pub(super) groups: Vec<GroupDocument>,
+    pub(super) roles: Vec<RoleDocument>,
+    pub(super) guardrails: Vec<PolicyDocument>,
+    pub(super) resource_policies: Vec<ResourcePolicyDocument>,
+}
+
+impl Document {
+    /// Appends the entities of another document to this one.
+    pub(super) fn merge(&mut self, other: Document) {
+        self.policies.extend(other.policies);
+        self.identities.extend(other.identities);
+        self.groups.extend(other.groups);
+        self.roles.extend(other.roles);
+        self.guardrails.extend(other.guardrails);
+        self.resource_policies.extend(other.resource_policies);
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub(super) struct PolicyDocument {
+    #[serde(default)]
+    pub(super) id: String,
+    #[serde(default = "default_version")]
+    pub(super) version: i32,
+    pub(super) effect: String,
+    pub(super) actions: Vec<String>,
+    #[serde(default)]
+    pub(super) resources: Vec<String>,
+    pub(super) subject: Option<Attributes>,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(default)]
+pub(super) struct ValidityDocument {
+    pub(super) not_before: Option<i64>,
+    pub(super) not_after: Option<i64>,
+}
+
+/// The policies linked to a subject, with their priorities and validity.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(super) struct LinksDocument {
+    pub(super) linked_policies: Vec<String>,
+    pub(super) linked_policy_priorities: HashMap<String, i32>,
+    pub(super) linked_policy_validity: HashMap<String, ValidityDocument>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(super) struct IdentityDocument {
+    pub(super) id: String,
+    pub(super) inline_policy: Option<PolicyDocument>,
+    #[serde(flatten)]
+    pub(super) links: LinksDocument,
+    pub(super) boundary_policy: Option<String>,
+    #[serde(default)]
+    pub(super) attributes: Attributes,
+}
+
+/// A group member: its id, or its id along with the membership validity.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub(super) enum MemberDocument {
+    Id(String),
+    WithValidity {
+        id: String,
+        #[serde(flatten)]
+        validity: ValidityDocument,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+pub(super) struct GroupDocument {
+    pub(super) id: String,
+    pub(super) inline_policy: Option<PolicyDocument>,
+    #[serde(flatten)]
+    pub(super) links: LinksDocument,
+    pub(super) boundary_policy: Option<String>,
+    #[serde(default)]
+    pub(super) attributes: Attributes,
+    pub(super) membership_rule: Option<String>,
+    #[serde(default)]
+    pub(super) identities: Vec<MemberDocument>,
+    #[serde(default)]
+    pub(super) groups: Vec<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(super) struct TrustPolicyDocument {
+    pub(super) identities: Vec<String>,
+    pub(super) groups: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(super) struct RoleDocument {
+    pub(super) id: String,
+    pub(super) inline_policy: Option<PolicyDocument>,
+    #[serde(flatten)]
+    pub(super) links: LinksDocument,
+    #[serde(default)]
+    pub(super) trust_policy: TrustPolicyDocument,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(super) struct PrincipalDocument {
+    pub(super) identities: Vec<String>,
+    pub(super) groups: Vec<String>,
+    pub(super) roles: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(super) struct ResourcePolicyDocument {
+    #[serde(flatten)]
+    pub(super) policy: PolicyDocument,
+    #[serde(default)]
+    pub(super) principal: PrincipalDocument,
+}
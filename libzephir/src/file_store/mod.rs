@@ -0,0 +1,266 @@
+pub mod bundle;
+mod document;
+pub mod simulation;
+mod snapshot;
+
+pub use snapshot::Snapshot;
+
+use crate::err::Error;
+use crate::policy::policy::CompletePolicy;
+use crate::storage::{TenantExport, EXPORT_VERSION};
+use bundle::{Bundle, Manifest};
+use document::{Document, PolicyDocument};
+use ed25519_dalek::PublicKey;
+use log::{debug, info, warn};
+use notify::{DebouncedEvent, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::channel;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+/// Delay used to coalesce the filesystem events: editors usually
+/// write a file in more than one step.
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Reads a policy file, parsing it as YAML or JSON depending on its extension.
+fn read_document(path: &Path) -> Result<Option<Document>, Error> {
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or_default();
+    let content = match extension {
+        "json" | "yaml" | "yml" => std::fs::read_to_string(path)?,
+        _ => return Ok(Option::None),
+    };
+
+    let document = if extension == "json" {
+        serde_json::from_str(&content).map_err(|e| e.to_string())
+    } else {
+        serde_yaml::from_str(&content).map_err(|e| e.to_string())
+    };
+
+    document
+        .map(Option::Some)
+        .map_err(|e| Error::invalid_snapshot(format!("{}: {}", path.display(), e)))
+}
+
+/// Reads a single policy from a JSON or YAML file, written
+/// as the policies in the policy files are.
+pub fn read_policy<P: AsRef<Path>>(path: P) -> Result<CompletePolicy, Error> {
+    let path = path.as_ref();
+    let content = std::fs::read_to_string(path)?;
+    let document: Result<PolicyDocument, String> = if path.extension().and_then(|e| e.to_str()) == Some("json") {
+        serde_json::from_str(&content).map_err(|e| e.to_string())
+    } else {
+        serde_yaml::from_str(&content).map_err(|e| e.to_string())
+    };
+
+    let document = document.map_err(|e| Error::invalid_snapshot(format!("{}: {}", path.display(), e)))?;
+    snapshot::build_policy(&document)
+}
+
+/// Parses and validates an export document (as produced by `StorageManager::export_tenant`)
+/// before importing it into the given tenant.
+///
+/// The document is validated as a whole, as the policy files are: any dangling
+/// reference or invalid entity rejects the import.
+pub fn parse_export<S: ToString>(content: &[u8], tenant: S) -> Result<TenantExport, Error> {
+    let document: Document = serde_json::from_slice(content).map_err(|e| Error::invalid_snapshot(e.to_string()))?;
+    match document.version {
+        Option::Some(version) if version != EXPORT_VERSION => {
+            Err(Error::invalid_snapshot(format!("unsupported export version {}", version)))
+        }
+        _ => Ok(Snapshot::build(document)?.into_export(tenant)),
+    }
+}
+
+/// Lists the files in the given directory (and its subdirectories), sorted by path.
+fn list_files(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut result = vec![];
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let hidden = matches!(path.file_name().and_then(|n| n.to_str()), Some(n) if n.starts_with('.'));
+        if hidden {
+            continue;
+        }
+
+        if path.is_dir() {
+            result.append(&mut list_files(&path)?);
+        } else {
+            result.push(path);
+        }
+    }
+
+    result.sort();
+    Ok(result)
+}
+
+/// Where the policy files are read from.
+enum Source {
+    /// A directory of JSON/YAML files.
+    Directory(PathBuf),
+
+    /// A bundle archive, which must be signed by the given key.
+    Bundle(PathBuf, PublicKey),
+}
+
+impl Source {
+    fn path(&self) -> &Path {
+        match self {
+            Source::Directory(path) | Source::Bundle(path, _) => path,
+        }
+    }
+
+    /// Loads the document, along with the manifest if the source is a bundle.
+    fn load(&self) -> Result<(Document, Option<Manifest>), Error> {
+        match self {
+            Source::Directory(dir) => {
+                let mut document = Document::default();
+                for path in list_files(dir)? {
+                    if let Some(loaded) = read_document(&path)? {
+                        debug!("Loaded policy file {}", path.display());
+                        document.merge(loaded);
+                    }
+                }
+
+                Ok((document, Option::None))
+            }
+            Source::Bundle(path, public_key) => {
+                let bundle = Bundle::read(&std::fs::read(path)?, public_key)?;
+                debug!(
+                    "Loaded bundle {} of tenant \"{}\", created at {}",
+                    path.display(),
+                    bundle.get_manifest().tenant,
+                    bundle.get_manifest().created_at
+                );
+
+                Ok((bundle.document()?, Option::Some(bundle.get_manifest().clone())))
+            }
+        }
+    }
+}
+
+/// Serves the policies, identities and groups defined in a directory
+/// of JSON/YAML files (or in a signed bundle), allowing zephir to run
+/// without a database.
+///
+/// The files are loaded into an immutable snapshot, which is atomically
+/// replaced when the directory changes. A snapshot failing the validation
+/// is rejected, and the previous one keeps serving the requests.
+pub struct FileStore {
+    source: Source,
+    snapshot: RwLock<Arc<Snapshot>>,
+
+    /// The manifest of the loaded bundle, if the source is a bundle.
+    manifest: Mutex<Option<Manifest>>,
+}
+
+impl FileStore {
+    /// Loads the files in the given directory.
+    /// Fails if the initial snapshot is not valid.
+    pub fn load<P: AsRef<Path>>(dir: P) -> Result<Self, Error> {
+        Self::from_source(Source::Directory(dir.as_ref().to_path_buf()))
+    }
+
+    /// Loads the given bundle, after verifying it has been signed by the given key.
+    /// Fails if the bundle or the initial snapshot are not valid.
+    pub fn load_bundle<P: AsRef<Path>>(path: P, public_key: PublicKey) -> Result<Self, Error> {
+        Self::from_source(Source::Bundle(path.as_ref().to_path_buf(), public_key))
+    }
+
+    fn from_source(source: Source) -> Result<Self, Error> {
+        let (document, manifest) = source.load()?;
+        let snapshot = Snapshot::build(document)?;
+
+        Ok(FileStore {
+            source,
+            snapshot: RwLock::new(Arc::new(snapshot)),
+            manifest: Mutex::new(manifest),
+        })
+    }
+
+    /// Gets the current snapshot.
+    ///
+    /// The snapshot is never modified: a request holding it is
+    /// evaluated consistently even if a reload happens meanwhile.
+    pub fn snapshot(&self) -> Arc<Snapshot> {
+        self.snapshot.read().unwrap().clone()
+    }
+
+    /// Reloads the files, replacing the current snapshot only if the new one is valid.
+    /// A bundle is rejected if it is older than the loaded one, or if it holds another tenant.
+    pub fn reload(&self) -> Result<(), Error> {
+        let path = self.source.path();
+        let mut loaded = self.manifest.lock().unwrap();
+        let result = self.source.load().and_then(|(document, manifest)| {
+            if let (Some(manifest), Some(loaded)) = (&manifest, &*loaded) {
+                manifest.check_replaces(loaded)?;
+            }
+
+            Ok((Snapshot::build(document)?, manifest))
+        });
+
+        match result {
+            Ok((snapshot, manifest)) => {
+                *self.snapshot.write().unwrap() = Arc::new(snapshot);
+                *loaded = manifest;
+                info!("Policy files in {} reloaded", path.display());
+
+                Ok(())
+            }
+            Err(e) => {
+                warn!("Policy files in {} rejected, keeping the previous snapshot: {}", path.display(), e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Watches the directory (through inotify on linux) and
+    /// reloads the snapshot every time a file changes.
+    ///
+    /// Bundles are usually replaced by renaming a new file over
+    /// the old one: their parent directory is watched instead.
+    pub fn watch(self: Arc<Self>) -> Result<(), Error> {
+        let (sender, receiver) = channel();
+        let mut watcher = notify::watcher(sender, WATCH_DEBOUNCE)?;
+        match &self.source {
+            Source::Directory(dir) => watcher.watch(dir, RecursiveMode::Recursive)?,
+            Source::Bundle(path, _) => {
+                let parent = path.parent().filter(|p| !p.as_os_str().is_empty());
+                watcher.watch(parent.unwrap_or_else(|| Path::new(".")), RecursiveMode::NonRecursive)?
+            }
+        };
+
+        std::thread::spawn(move || {
+            // The watcher stops as soon as it is dropped.
+            let _watcher = watcher;
+            for event in receiver {
+                match event {
+                    DebouncedEvent::NoticeWrite(_) | DebouncedEvent::NoticeRemove(_) => continue,
+                    DebouncedEvent::Error(e, _) => warn!("Policy files watch error: {}", e),
+                    event => {
+                        if self.concerns(&event) {
+                            let _ = self.reload();
+                        }
+                    }
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Whether the given event could change the snapshot.
+    fn concerns(&self, event: &DebouncedEvent) -> bool {
+        let bundle = match &self.source {
+            Source::Directory(_) => return true,
+            Source::Bundle(path, _) => path.file_name(),
+        };
+
+        match event {
+            DebouncedEvent::Create(path)
+            | DebouncedEvent::Write(path)
+            | DebouncedEvent::Chmod(path)
+            | DebouncedEvent::Remove(path) => path.file_name() == bundle,
+            DebouncedEvent::Rename(from, to) => from.file_name() == bundle || to.file_name() == bundle,
+            _ => true,
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use crate::err::Error;
+use crate::file_store::Snapshot;
+use crate::policy::policy::ToJson;
+use crate::storage::{TenantExport, EXPORT_SECTIONS};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+
+/// Hypothetical changes to the entities of a tenant.
+///
+/// Entities are written in the export format, grouped by section
+/// (e.g. "policies", "identities" or "groups"): each of them replaces
+/// the current entity with the same id, or is added if there is none.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ProposedChanges {
+    /// Ids of the entities to be deleted, by section.
+    pub deleted: BTreeMap<String, Vec<String>>,
+
+    /// Entities to be created or replaced, by section.
+    #[serde(flatten)]
+    pub upserts: Map<String, Value>,
+}
+
+fn check_section(section: &str) -> Result<(), Error> {
+    if EXPORT_SECTIONS.contains(&section) {
+        Ok(())
+    } else {
+        Err(Error::invalid_snapshot(format!("unknown section \"{}\"", section)))
+    }
+}
+
+fn entity_id(entity: &Value) -> Option<&str> {
+    entity.get("id").and_then(Value::as_str)
+}
+
+impl ProposedChanges {
+    /// Applies the changes to an export document.
+    fn apply(&self, document: &mut Map<String, Value>) -> Result<(), Error> {
+        for (section, ids) in &self.deleted {
+            check_section(section)?;
+            let entities = match document.get_mut(section.as_str()).and_then(Value::as_array_mut) {
+                Option::Some(entities) => entities,
+                Option::None => continue,
+            };
+
+            for id in ids {
+                let position = entities.iter().position(|e| entity_id(e) == Option::Some(id.as_str()));
+                match position {
+                    Option::Some(position) => entities.remove(position),
+                    Option::None => {
+                        return Err(Error::invalid_snapshot(format!("cannot delete unknown entity \"{}\" from {}", id, section)))
+                    }
+                };
+            }
+        }
+
+        for (section, proposed) in &self.upserts {
+            check_section(section)?;
+            let proposed = proposed
+                .as_array()
+                .ok_or_else(|| Error::invalid_snapshot(format!("{} must be a list", section)))?;
+            let entities = document
+                .entry(section.as_str())
+                .or_insert_with(|| Value::Array(vec![]))
+                .as_array_mut()
+                .unwrap();
+
+            for entity in proposed {
+                let id = entity_id(entity)
+                    .ok_or_else(|| Error::invalid_snapshot(format!("an entity in {} has no id", section)))?;
+                match entities.iter().position(|e| entity_id(e) == Option::Some(id)) {
+                    Option::Some(position) => entities[position] = entity.clone(),
+                    Option::None => entities.push(entity.clone()),
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn build(document: Map<String, Value>) -> Result<Snapshot, Error> {
+    let document = serde_json::from_value(Value::Object(document)).map_err(|e| Error::invalid_snapshot(e.to_string()))?;
+    Snapshot::build(document)
+}
+
+/// Builds the snapshots of the current entities of a tenant and of the
+/// entities as they would be after the proposed changes, so that requests
+/// could be evaluated against both without touching the storage.
+///
+/// The proposed data is validated as a whole, as an import is: changes leaving
+/// a dangling reference (e.g. deleting a policy still linked) are rejected.
+///
+/// # Returns
+///
+/// The current and the proposed snapshots
+pub fn simulate(current: &TenantExport, changes: &ProposedChanges) -> Result<(Snapshot, Snapshot), Error> {
+    let document = current.to_json();
+    let mut proposed = document.clone();
+    changes.apply(&mut proposed)?;
+
+    Ok((build(document)?, build(proposed)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::file_store::simulation::{simulate, ProposedChanges};
+    use crate::identity::identity::Identity;
+    use crate::identity::role::Role;
+    use crate::policy::policy::CompletePolicy;
+    use crate::policy::policy_set::PolicySetTrait;
+    use crate::policy::{PolicyEffect, PolicyVersion};
+    use crate::storage::TenantExport;
+    use serde_json::json;
+
+    fn export() -> TenantExport {
+        let policy = CompletePolicy::new(
+            String::from("ReadPolicy"),
+            PolicyVersion::Version1,
+            PolicyEffect::Allow,
+            vec!["get_*"],
+            vec!["urn:resource:*"],
+        )
+        .unwrap();
+
+        TenantExport {
+            tenant: String::from("acme"),
+            identities: vec![Identity::new("alice", Option::None).add_policy(policy.clone())],
+            policies: vec![policy],
+            ..TenantExport::default()
+        }
+    }
+
+    fn changes(value: serde_json::Value) -> ProposedChanges {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn proposed_changes_should_be_applied_to_a_copy() {
+        let proposed_changes = changes(json!({
+            "policies": [
+                { "id": "ReadPolicy", "effect": "DENY", "actions": ["get_*"], "resources": ["urn:resource:*"] },
+                { "id": "WritePolicy", "effect": "ALLOW", "actions": ["put_*"] }
+            ],
+            "identities": [{ "id": "bob", "linked_policies": ["WritePolicy"] }]
+        }));
+
+        let (current, proposed) = simulate(&export(), &proposed_changes).unwrap();
+        assert_eq!(current.find_policy("ReadPolicy").unwrap().effect, PolicyEffect::Allow);
+        assert!(current.find_identity("bob").is_none());
+
+        assert_eq!(proposed.find_policy("ReadPolicy").unwrap().effect, PolicyEffect::Deny);
+        assert!(proposed.find_policy("WritePolicy").is_some());
+        assert_eq!(proposed.find_identity("bob").unwrap().linked_policies().len(), 1);
+        assert_eq!(proposed.find_identity("alice").unwrap().linked_policies().len(), 1);
+    }
+
+    #[test]
+    fn invalid_proposed_changes_should_be_rejected() {
+        let deleted_identity = changes(json!({ "deleted": { "identities": ["alice"] } }));
+        let (_, proposed) = simulate(&export(), &deleted_identity).unwrap();
+        assert!(proposed.find_identity("alice").is_none());
+
+        let dangling = changes(json!({ "deleted": { "policies": ["ReadPolicy"] } }));
+        assert!(simulate(&export(), &dangling).is_err());
+
+        let unknown = changes(json!({ "deleted": { "identities": ["bob"] } }));
+        assert!(simulate(&export(), &unknown).is_err());
+
+        let unknown_section = changes(json!({ "sessions": [] }));
+        assert!(simulate(&export(), &unknown_section).is_err());
+
+        let missing_id = changes(json!({ "identities": [{ "linked_policies": [] }] }));
+        assert!(simulate(&export(), &missing_id).is_err());
+    }
+}
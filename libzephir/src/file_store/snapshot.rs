@@ -0,0 +1,394 @@
+use crate::err::Error;
+use crate::file_store::document::{Document, LinksDocument, MemberDocument, PolicyDocument};
+use crate::identity::assumable_role::{AssumableRole, TrustPolicy};
+use crate::identity::group::{find_cycle, Group};
+use crate::identity::identity::Identity;
+use crate::identity::membership_rule::MembershipRule;
+use crate::policy::condition::SubjectConditions;
+use crate::policy::guardrails::Guardrails;
+use crate::policy::policy::CompletePolicy;
+use crate::policy::policy_set::{PolicyLink, PolicySetTrait};
+use crate::policy::resource_policy::{Principals, ResourcePolicies, ResourcePolicy};
+use crate::policy::validity::{unix_now, Validity};
+use crate::policy::{PolicyEffect, PolicyVersion};
+use crate::storage::TenantExport;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+
+/// An immutable, validated copy of all the entities defined in the policy files.
+///
+/// Snapshots are built all at once: any dangling reference, duplicated
+/// id or invalid policy rejects the whole snapshot.
+#[derive(Default)]
+pub struct Snapshot {
+    policies: HashMap<String, CompletePolicy>,
+    identities: HashMap<String, Identity>,
+    groups: HashMap<String, Group>,
+    roles: HashMap<String, AssumableRole>,
+    guardrails: Guardrails,
+    resource_policies: ResourcePolicies,
+
+    /// Identity -> groups it directly belongs to, with the membership validity.
+    memberships: HashMap<String, Vec<(String, Validity)>>,
+    /// Group -> groups it is directly nested into.
+    parents: HashMap<String, Vec<String>>,
+}
+
+/// Builds a policy of a snapshot. Snapshots are never invalidated policy by
+/// policy (and could hold a simulated version of a stored policy): their
+/// compiled policies are not tracked.
+pub(super) fn build_policy(document: &PolicyDocument) -> Result<CompletePolicy, Error> {
+    let policy = CompletePolicy::new_untracked(
+        document.id.clone(),
+        PolicyVersion::try_from(document.version)?,
+        PolicyEffect::try_from(&document.effect)
+            .map_err(|_| Error::invalid_snapshot(format!(r#"policy "{}" has an invalid effect"#, document.id)))?,
+        document.actions.clone(),
+        document.resources.clone(),
+    )?;
+
+    Ok(match &document.subject {
+        Option::None => policy,
+        Option::Some(conditions) => policy.with_subject_conditions(SubjectConditions::new(conditions.clone())),
+    })
+}
+
+/// Builds an inline policy, naming it as the storage does if the id is missing.
+fn build_inline_policy(document: &Option<PolicyDocument>, owner_type: &str, owner_id: &str) -> Result<Option<CompletePolicy>, Error> {
+    match document {
+        Option::None => Ok(Option::None),
+        Option::Some(document) => {
+            let mut document = document.clone();
+            if document.id.is_empty() {
+                document.id = format!("__embedded_policy_{}_{}__", owner_type, owner_id);
+            }
+
+            Ok(Option::Some(build_policy(&document)?))
+        }
+    }
+}
+
+fn sorted_by_id<T>(entities: HashMap<String, T>) -> Vec<T> {
+    let mut entities: Vec<(String, T)> = entities.into_iter().collect();
+    entities.sort_by(|(a, _), (b, _)| a.cmp(b));
+    entities.into_iter().map(|(_, e)| e).collect()
+}
+
+fn insert_unique<T>(map: &mut HashMap<String, T>, kind: &str, id: String, value: T) -> Result<(), Error> {
+    if map.contains_key(&id) {
+        return Err(Error::invalid_snapshot(format!(r#"{} "{}" is defined more than once"#, kind, id)));
+    }
+
+    map.insert(id, value);
+    Ok(())
+}
+
+impl Snapshot {
+    pub(super) fn build(document: Document) -> Result<Self, Error> {
+        let mut snapshot = Snapshot::default();
+        for policy in &document.policies {
+            insert_unique(&mut snapshot.policies, "Policy", policy.id.clone(), build_policy(policy)?)?;
+        }
+
+        for identity in document.identities {
+            let inline_policy = build_inline_policy(&identity.inline_policy, "identity", &identity.id)?;
+            let mut result = Identity::new(&identity.id, inline_policy).set_attributes(identity.attributes);
+            if let Some(boundary) = &identity.boundary_policy {
+                result = result.set_boundary(snapshot.policy(boundary)?);
+            }
+
+            for (policy, link) in snapshot.links(&identity.links)? {
+                result = result.add_policy_link(policy, link);
+            }
+
+            insert_unique(&mut snapshot.identities, "Identity", identity.id, result)?;
+        }
+
+        let mut edges = HashMap::new();
+        for group in document.groups {
+            let inline_policy = build_inline_policy(&group.inline_policy, "group", &group.id)?;
+            let mut result = Group::new(&group.id, inline_policy).set_attributes(group.attributes);
+            if let Some(boundary) = &group.boundary_policy {
+                result = result.set_boundary(snapshot.policy(boundary)?);
+            }
+
+            if let Some(rule) = &group.membership_rule {
+                result = result.set_membership_rule(MembershipRule::try_from(rule.as_str())?);
+            }
+
+            for (policy, link) in snapshot.links(&group.links)? {
+                result = result.add_policy_link(policy, link);
+            }
+
+            for member in group.identities {
+                let (id, validity) = match member {
+                    MemberDocument::Id(id) => (id, Validity::default()),
+                    MemberDocument::WithValidity { id, validity } => (id, Validity::new(validity.not_before, validity.not_after)),
+                };
+
+                if !snapshot.identities.contains_key(&id) {
+                    return Err(Error::invalid_snapshot(format!(r#"group "{}" references unknown identity "{}""#, group.id, id)));
+                }
+
+                snapshot.memberships.entry(id).or_default().push((group.id.clone(), validity));
+            }
+
+            for nested in &group.groups {
+                result = result.add_group(nested);
+                snapshot.parents.entry(nested.clone()).or_default().push(group.id.clone());
+            }
+
+            edges.insert(group.id.clone(), group.groups);
+            insert_unique(&mut snapshot.groups, "Group", group.id, result)?;
+        }
+
+        for (group, nested) in &edges {
+            if let Some(unknown) = nested.iter().find(|n| !snapshot.groups.contains_key(*n)) {
+                return Err(Error::invalid_snapshot(format!(r#"group "{}" references unknown group "{}""#, group, unknown)));
+            }
+
+            if let Some(cycle) = find_cycle(&edges, group) {
+                return Err(Error::group_cycle(cycle));
+            }
+        }
+
+        for role in document.roles {
+            let inline_policy = build_inline_policy(&role.inline_policy, "role", &role.id)?;
+            let trust_policy = TrustPolicy {
+                identities: role.trust_policy.identities,
+                groups: role.trust_policy.groups,
+            };
+
+            let mut result = AssumableRole::new(&role.id, inline_policy, trust_policy);
+            for (policy, link) in snapshot.links(&role.links)? {
+                result = result.add_policy_link(policy, link);
+            }
+
+            insert_unique(&mut snapshot.roles, "Role", role.id, result)?;
+        }
+
+        let mut ids = HashSet::new();
+        let mut guardrails = vec![];
+        for guardrail in &document.guardrails {
+            if !ids.insert(guardrail.id.clone()) {
+                return Err(Error::invalid_snapshot(format!(r#"Guardrail "{}" is defined more than once"#, guardrail.id)));
+            }
+
+            guardrails.push(build_policy(guardrail)?);
+        }
+
+        let mut ids = HashSet::new();
+        let mut resource_policies = vec![];
+        for resource_policy in document.resource_policies {
+            if !ids.insert(resource_policy.policy.id.clone()) {
+                return Err(Error::invalid_snapshot(format!(r#"Resource policy "{}" is defined more than once"#, resource_policy.policy.id)));
+            }
+
+            let principal = resource_policy.principal;
+            resource_policies.push(ResourcePolicy::new(
+                build_policy(&resource_policy.policy)?,
+                Principals::new(principal.identities, principal.groups, principal.roles),
+            ));
+        }
+
+        snapshot.guardrails = Guardrails::new(guardrails);
+        snapshot.resource_policies = ResourcePolicies::new(resource_policies);
+
+        Ok(snapshot)
+    }
+
+    fn policy(&self, id: &str) -> Result<CompletePolicy, Error> {
+        self.policies
+            .get(id)
+            .cloned()
+            .ok_or_else(|| Error::invalid_snapshot(format!(r#"unknown policy "{}""#, id)))
+    }
+
+    fn links(&self, links: &LinksDocument) -> Result<Vec<(CompletePolicy, PolicyLink)>, Error> {
+        let mut result = vec![];
+        for id in &links.linked_policies {
+            let priority = links.linked_policy_priorities.get(id).copied().unwrap_or_default();
+            let validity = links.linked_policy_validity.get(id).copied().unwrap_or_default();
+            result.push((
+                self.policy(id)?,
+                PolicyLink::new(priority, Validity::new(validity.not_before, validity.not_after)),
+            ));
+        }
+
+        Ok(result)
+    }
+
+    pub fn find_policy(&self, id: &str) -> Option<&CompletePolicy> {
+        self.policies.get(id)
+    }
+
+    pub fn find_identity(&self, id: &str) -> Option<&Identity> {
+        self.identities.get(id)
+    }
+
+    pub fn find_group(&self, id: &str) -> Option<&Group> {
+        self.groups.get(id)
+    }
+
+    pub fn find_role(&self, id: &str) -> Option<&AssumableRole> {
+        self.roles.get(id)
+    }
+
+    pub fn get_guardrails(&self) -> &Guardrails {
+        &self.guardrails
+    }
+
+    pub fn get_resource_policies(&self) -> &ResourcePolicies {
+        &self.resource_policies
+    }
+
+    /// Converts the snapshot into a tenant export, attaching
+    /// the direct members to their groups. Entities are sorted by id.
+    pub fn into_export<S: ToString>(self, tenant: S) -> TenantExport {
+        let mut groups: Vec<Group> = self.groups.into_values().collect();
+        groups.sort_by(|a, b| a.name.cmp(&b.name));
+        for (identity, memberships) in &self.memberships {
+            for (group, validity) in memberships {
+                if let Some(index) = groups.iter().position(|g| &g.name == group) {
+                    let member = self.identities[identity].clone();
+                    groups[index] = groups[index].clone().add_identity_with_validity(member, *validity);
+                }
+            }
+        }
+
+        TenantExport {
+            tenant: tenant.to_string(),
+            policies: sorted_by_id(self.policies),
+            identities: sorted_by_id(self.identities),
+            groups,
+            roles: sorted_by_id(self.roles),
+            guardrails: self.guardrails.get_policies().clone(),
+            resource_policies: self.resource_policies.get_policies().clone(),
+        }
+    }
+
+    /// Gets an identity and all the groups it currently belongs to, directly,
+    /// through a dynamic group rule or through nested groups.
+    ///
+    /// Groups are returned along with the shortest path through which the
+    /// identity inherits them, ordered by the length of the path, as the
+    /// storage does.
+    pub fn find_subject(&self, id: &str) -> Option<(Identity, Vec<Group>)> {
+        let identity = self.identities.get(id)?;
+        let now = unix_now();
+
+        let mut queue: Vec<Vec<String>> = self
+            .memberships
+            .get(id)
+            .into_iter()
+            .flatten()
+            .filter(|(_, validity)| validity.is_active_at(now))
+            .map(|(group, _)| vec![group.clone()])
+            .collect();
+
+        let mut dynamic_groups: Vec<&Group> = self
+            .groups
+            .values()
+            .filter(|g| matches!(g.get_membership_rule(), Some(rule) if rule.matches(identity.get_attributes())))
+            .collect();
+        dynamic_groups.sort_by(|a, b| a.name.cmp(&b.name));
+        queue.extend(dynamic_groups.into_iter().map(|g| vec![g.name.clone()]));
+
+        let mut paths: HashMap<&str, Vec<String>> = HashMap::new();
+        while !queue.is_empty() {
+            let mut next = vec![];
+            queue.sort();
+            for path in queue {
+                let group = path.last().unwrap();
+                let group = match self.groups.get_key_value(group) {
+                    Option::Some((key, _)) if !paths.contains_key(key.as_str()) => key.as_str(),
+                    _ => continue,
+                };
+
+                for parent in self.parents.get(group).into_iter().flatten() {
+                    let mut parent_path = path.clone();
+                    parent_path.push(parent.clone());
+                    next.push(parent_path);
+                }
+
+                paths.insert(group, path);
+            }
+
+            queue = next;
+        }
+
+        let mut groups: Vec<Group> = paths
+            .into_iter()
+            .map(|(group, path)| self.groups[group].clone().with_inheritance_path(path))
+            .collect();
+
+        groups.sort_by(|a, b| {
+            a.inheritance_path
+                .len()
+                .cmp(&b.inheritance_path.len())
+                .then_with(|| a.name.cmp(&b.name))
+        });
+
+        Option::Some((identity.clone(), groups))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::file_store::document::Document;
+    use crate::file_store::snapshot::Snapshot;
+
+    fn document(yaml: &str) -> Document {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn subjects_should_inherit_nested_and_dynamic_groups() {
+        let snapshot = Snapshot::build(document(
+            r#"
+policies:
+  - { id: ReadOnly, effect: ALLOW, actions: ["core:Get*"] }
+identities:
+  - { id: alice, linked_policies: [ReadOnly], attributes: { department: finance } }
+  - { id: bob }
+groups:
+  - { id: accountants, identities: [alice, { id: bob, not_after: 1 }] }
+  - { id: employees, groups: [accountants, finance] }
+  - { id: finance, membership_rule: 'department == "finance"' }
+"#,
+        ))
+        .unwrap();
+
+        let (identity, groups) = snapshot.find_subject("alice").unwrap();
+        assert_eq!(identity.get_id(), "alice");
+        assert_eq!(
+            groups.iter().map(|g| g.get_inheritance_path().clone()).collect::<Vec<_>>(),
+            vec![
+                vec!["accountants".to_string()],
+                vec!["finance".to_string()],
+                vec!["accountants".to_string(), "employees".to_string()],
+            ]
+        );
+
+        let (_, groups) = snapshot.find_subject("bob").unwrap();
+        assert!(groups.is_empty());
+        assert!(snapshot.find_subject("carol").is_none());
+    }
+
+    #[test]
+    fn inconsistent_snapshots_should_be_rejected() {
+        let invalid = vec![
+            "identities: [{ id: alice, linked_policies: [Unknown] }]",
+            "identities: [{ id: alice }, { id: alice }]",
+            "groups: [{ id: g1, identities: [alice] }]",
+            "groups: [{ id: g1, groups: [g2] }, { id: g2, groups: [g1] }]",
+            "groups: [{ id: g1, membership_rule: 'department =' }]",
+            "policies: [{ id: p1, effect: MAYBE, actions: [core] }]",
+            "policies: [{ id: p1, effect: ALLOW, actions: [] }]",
+        ];
+
+        for yaml in invalid {
+            assert!(Snapshot::build(document(yaml)).is_err(), "{}", yaml);
+        }
+    }
+}
@@ -0,0 +1,319 @@
+use crate::identity::group::Group;
+use crate::identity::identity::Identity;
+use crate::identity::role::{allowed, allowed_with, Role};
+use crate::identity::subject::{Subject, SubjectIterator};
+use crate::policy::allowed_result::AllowedResult;
+use crate::policy::combining_algorithm::CombiningAlgorithm;
+use crate::policy::condition::Attributes;
+use crate::policy::policy::{CompletePolicy, ToJson};
+use crate::policy::policy_set::{PolicyLink, PolicySet, PolicySetHelper, PolicySetTrait};
+use crate::policy::resource_policy::{Principal, ResourcePolicies};
+use serde_json::{Map, Value};
+use std::fmt::{Debug, Display};
+
+/// Names the identities and the groups allowed to assume a role.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TrustPolicy {
+    pub identities: Vec<String>,
+    pub groups: Vec<String>,
+}
+
+impl TrustPolicy {
+    /// Checks whether the given identity (which belongs to the given
+    /// groups, nested ones included) is trusted by this policy.
+    pub fn trusts(&self, identity: &Identity, groups: &[Group]) -> bool {
+        self.identities.contains(&identity.id)
+            || groups.iter().any(|g| self.groups.contains(&g.name))
+    }
+}
+
+impl ToJson for TrustPolicy {
+    fn to_json(&self) -> Map<String, Value> {
+        let mut map = Map::new();
+        map.insert(String::from("identities"), Value::from(self.identities.clone()));
+        map.insert(String::from("groups"), Value::from(self.groups.clone()));
+
+        map
+    }
+}
+
+/// A role which can be assumed by the identities named in its trust policy.
+///
+/// While the role is assumed, the permissions are evaluated against
+/// the role policies instead of the identity ones.
+#[derive(Clone, Debug)]
+pub struct AssumableRole {
+    pub(crate) id: String,
+    pub(crate) trust_policy: TrustPolicy,
+
+    pub(crate) inline_policy: Option<CompletePolicy>,
+    pub(crate) linked_policies: PolicySet<CompletePolicy>,
+}
+
+impl AssumableRole {
+    pub fn new<T: ToString>(id: T, policy: Option<CompletePolicy>, trust_policy: TrustPolicy) -> Self {
+        AssumableRole {
+            id: id.to_string(),
+            trust_policy,
+            inline_policy: policy,
+            linked_policies: PolicySet::new(),
+        }
+    }
+
+    pub fn get_id(&self) -> &String {
+        &self.id
+    }
+
+    pub fn get_trust_policy(&self) -> &TrustPolicy {
+        &self.trust_policy
+    }
+
+    pub fn clear_inline_policy(mut self) -> Self {
+        self.inline_policy = Option::None;
+        self
+    }
+
+    pub fn set_inline_policy(mut self, policy: CompletePolicy) -> Self {
+        let mut policy = policy;
+        policy.id = "__embedded_policy_role_".to_owned() + self.id.as_str() + "__";
+
+        self.inline_policy = Option::Some(policy);
+        self
+    }
+
+    /// Evaluates the role permissions (and the resource policies naming
+    /// the role) with the given combining algorithm, restricted by the
+    /// session policy if any. Subject conditions are checked against
+    /// the attributes of the identity owning the session.
+    pub fn allowed_in_session<T, S>(
+        &self,
+        algorithm: CombiningAlgorithm,
+        attributes: &Attributes,
+        resource_policies: &ResourcePolicies,
+        session_policy: Option<&CompletePolicy>,
+        action: Option<T>,
+        resource: Option<S>,
+    ) -> AllowedResult
+    where
+        T: ToString + Display,
+        S: ToString + Display + Debug,
+    {
+        let mut result = self.allowed_with(algorithm, attributes, action.as_ref(), resource.as_ref());
+        if !algorithm.is_final(&result) {
+            let principal = Principal::role(self.id.as_str());
+            algorithm.merge(
+                &mut result,
+                resource_policies.allowed_for(&principal, attributes, algorithm, action.as_ref(), resource.as_ref()),
+            );
+        }
+
+        if let Some(session_policy) = session_policy {
+            result.intersect(allowed_with(std::iter::once(session_policy), action, resource, CombiningAlgorithm::default(), attributes));
+        }
+
+        result
+    }
+}
+
+impl Subject for AssumableRole {
+    fn get_inline_policy(&self) -> Option<&CompletePolicy> {
+        self.inline_policy.as_ref()
+    }
+}
+
+impl ToJson for AssumableRole {
+    fn to_json(&self) -> Map<String, Value> {
+        let linked_policies = &self.linked_policies;
+        let mut map = Map::new();
+        map.insert(String::from("id"), Value::from(self.id.as_str()));
+        map.insert(
+            String::from("inline_policy"),
+            match self.inline_policy.as_ref() {
+                Option::None => Value::Null,
+                Option::Some(policy) => Value::from(policy.to_json()),
+            },
+        );
+        map.insert(
+            String::from("linked_policies"),
+            Value::from(
+                linked_policies
+                    .into_iter()
+                    .map(|p| p.id.as_str())
+                    .collect::<Vec<&str>>(),
+            ),
+        );
+        map.insert(String::from("linked_policy_priorities"), linked_policies.priorities_to_value());
+        map.insert(String::from("linked_policy_validity"), linked_policies.validity_to_value());
+        map.insert(String::from("trust_policy"), self.trust_policy.to_value());
+
+        map
+    }
+}
+
+impl From<AssumableRole> for Value {
+    fn from(role: AssumableRole) -> Self {
+        Value::Object(role.to_json())
+    }
+}
+
+impl PolicySetTrait<CompletePolicy> for AssumableRole {
+    fn add_policy_link(mut self, policy: CompletePolicy, link: PolicyLink) -> Self {
+        self.linked_policies = PolicySetHelper::link_policy(self.linked_policies, policy, link);
+        self
+    }
+
+    fn remove_policy<S: ToString>(mut self, id: S) -> Self {
+        self.linked_policies = PolicySetHelper::unlink_policy(self.linked_policies, id);
+        self
+    }
+}
+
+impl Role for AssumableRole {
+    fn linked_policies(&self) -> &PolicySet<CompletePolicy> {
+        &self.linked_policies
+    }
+
+    fn allowed<T, S>(&self, action: Option<T>, resource: Option<S>) -> AllowedResult
+    where
+        T: ToString + Display,
+        S: ToString + Display + Debug {
+        allowed(SubjectIterator::new(self), action, resource)
+    }
+}
+
+/// A session opened by an identity assuming a role.
+#[derive(Clone, Debug)]
+pub struct RoleSession {
+    pub(crate) id: String,
+    pub(crate) role_id: String,
+    pub(crate) identity_id: String,
+    pub(crate) policy: Option<CompletePolicy>,
+
+    /// Expiration time, as a unix timestamp.
+    pub(crate) expires_at: i64,
+}
+
+impl RoleSession {
+    pub fn get_id(&self) -> &String {
+        &self.id
+    }
+
+    pub fn get_role_id(&self) -> &String {
+        &self.role_id
+    }
+
+    pub fn get_identity_id(&self) -> &String {
+        &self.identity_id
+    }
+
+    pub fn get_policy(&self) -> Option<&CompletePolicy> {
+        self.policy.as_ref()
+    }
+
+    pub fn get_expires_at(&self) -> i64 {
+        self.expires_at
+    }
+}
+
+impl ToJson for RoleSession {
+    fn to_json(&self) -> Map<String, Value> {
+        let mut map = Map::new();
+        map.insert(String::from("id"), Value::from(self.id.as_str()));
+        map.insert(String::from("role"), Value::from(self.role_id.as_str()));
+        map.insert(String::from("identity"), Value::from(self.identity_id.as_str()));
+        map.insert(
+            String::from("session_policy"),
+            match self.policy.as_ref() {
+                Option::None => Value::Null,
+                Option::Some(policy) => Value::from(policy.to_json()),
+            },
+        );
+        map.insert(String::from("expires_at"), Value::from(self.expires_at));
+
+        map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::identity::assumable_role::{AssumableRole, TrustPolicy};
+    use crate::identity::group::Group;
+    use crate::identity::identity::Identity;
+    use crate::policy::allowed_result::AllowedOutcome;
+    use crate::policy::combining_algorithm::CombiningAlgorithm;
+    use crate::policy::condition::Attributes;
+    use crate::policy::policy_set::PolicySetTrait;
+    use crate::policy::resource_policy::ResourcePolicies;
+    use crate::policy::{PolicyEffect, PolicyVersion};
+    use crate::zephir_policy;
+
+    #[test]
+    fn trust_policy_should_check_identities_and_groups() {
+        let trust_policy = TrustPolicy {
+            identities: vec!["alice".to_string()],
+            groups: vec!["engineering".to_string()],
+        };
+
+        let alice = Identity::new("alice", Option::None);
+        let bob = Identity::new("bob", Option::None);
+
+        assert!(trust_policy.trusts(&alice, &[]));
+        assert!(!trust_policy.trusts(&bob, &[]));
+        assert!(!trust_policy.trusts(&bob, &[Group::new("sales", Option::None)]));
+        assert!(trust_policy.trusts(&bob, &[Group::new("engineering", Option::None)]));
+    }
+
+    #[test]
+    fn session_policy_should_restrict_role_permissions() {
+        let role = AssumableRole::new("deployer", Option::None, TrustPolicy::default()).add_policy(
+            zephir_policy!(
+                "DeployerPolicy",
+                PolicyVersion::Version1,
+                PolicyEffect::Allow,
+                vec!["deploy:*"]
+            )
+            .unwrap(),
+        );
+
+        let session_policy = zephir_policy!(
+            "",
+            PolicyVersion::Version1,
+            PolicyEffect::Allow,
+            vec!["deploy:staging"]
+        )
+        .unwrap();
+
+        let result = role.allowed_in_session::<&str, String>(CombiningAlgorithm::default(), &Attributes::new(), &ResourcePolicies::default(), Option::None, Option::Some("deploy:production"), Option::None);
+        assert_eq!(result.outcome(), AllowedOutcome::Allowed);
+
+        let result = role.allowed_in_session::<&str, String>(
+            CombiningAlgorithm::default(),
+            &Attributes::new(),
+            &ResourcePolicies::default(),
+            Option::Some(&session_policy),
+            Option::Some("deploy:staging"),
+            Option::None,
+        );
+        assert_eq!(result.outcome(), AllowedOutcome::Allowed);
+
+        let result = role.allowed_in_session::<&str, String>(
+            CombiningAlgorithm::default(),
+            &Attributes::new(),
+            &ResourcePolicies::default(),
+            Option::Some(&session_policy),
+            Option::Some("deploy:production"),
+            Option::None,
+        );
+        assert_eq!(result.outcome(), AllowedOutcome::Denied);
+
+        let result = role.allowed_in_session::<&str, String>(
+            CombiningAlgorithm::default(),
+            &Attributes::new(),
+            &ResourcePolicies::default(),
+            Option::Some(&session_policy),
+            Option::Some("iam:create"),
+            Option::None,
+        );
+        assert_eq!(result.outcome(), AllowedOutcome::Denied);
+    }
+}
@@ -1,16 +1,24 @@
 use crate::identity::identity::{Identity, ToIdentityId};
+use crate::identity::membership_rule::MembershipRule;
 use crate::identity::role::{Role, allowed};
 use crate::identity::subject::{Subject, SubjectIterator};
+use crate::policy::condition::Attributes;
 use crate::policy::policy::{CompletePolicy, ToJson};
-use crate::policy::policy_set::{PolicySet, PolicySetHelper, PolicySetTrait};
+use crate::policy::validity::Validity;
+use crate::policy::policy_set::{PolicyLink, PolicySet, PolicySetHelper, PolicySetTrait};
 use serde_json::{Map, Value};
 use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
 use std::slice::Iter;
 use crate::policy::allowed_result::AllowedResult;
 use std::fmt::{Display, Debug};
 
+#[derive(Clone)]
 pub struct IdentitySet {
     identities: Vec<Identity>,
+
+    /// Validity of the time-bounded memberships, keyed by identity id.
+    validities: HashMap<String, Validity>,
 }
 
 impl<'a> IntoIterator for &'a IdentitySet {
@@ -24,7 +32,10 @@ impl<'a> IntoIterator for &'a IdentitySet {
 
 impl IdentitySet {
     fn new() -> Self {
-        IdentitySet { identities: vec![] }
+        IdentitySet {
+            identities: vec![],
+            validities: HashMap::new(),
+        }
     }
 
     pub fn len(&self) -> usize {
@@ -42,14 +53,31 @@ impl IdentitySet {
         }
     }
 
-    pub fn add_identity(mut self, identity: Identity) -> Self {
+    pub fn add_identity(self, identity: Identity) -> Self {
+        self.add_identity_with_validity(identity, Validity::default())
+    }
+
+    pub fn add_identity_with_validity(mut self, identity: Identity, validity: Validity) -> Self {
+        if validity.is_bounded() && !self.identities.iter().any(|i| i.id == identity.id) {
+            self.validities.insert(identity.id.clone(), validity);
+        }
+
         Self::insert_if_missing(self.identities.as_mut(), identity);
 
         self
     }
 
+    /// Gets the validity of the membership of the given identity.
+    pub fn get_validity<T: ToIdentityId>(&self, identity: T) -> Validity {
+        self.validities
+            .get(identity.to_identity_id())
+            .copied()
+            .unwrap_or_default()
+    }
+
     pub fn remove_identity<T: ToIdentityId>(mut self, identity: T) -> Self {
         let identity_id = identity.to_identity_id();
+        self.validities.remove(identity_id);
         self.identities = self
             .identities
             .into_iter()
@@ -60,12 +88,28 @@ impl IdentitySet {
     }
 }
 
+#[derive(Clone)]
 pub struct Group {
     pub(crate) name: String,
     pub(crate) identities: IdentitySet,
+    pub(crate) groups: Vec<String>,
+
+    /// Names of the groups the subject this group has been loaded for
+    /// inherits it through, ending with this group name.
+    /// Empty if the group has not been loaded as part of a hierarchy.
+    pub(crate) inheritance_path: Vec<String>,
 
     pub(crate) inline_policy: Option<CompletePolicy>,
     pub(crate) linked_policies: PolicySet<CompletePolicy>,
+
+    /// Caps the permissions granted to the subject.
+    pub(crate) boundary: Option<CompletePolicy>,
+
+    /// Attributes inherited by the group members.
+    pub(crate) attributes: Attributes,
+
+    /// The rule defining the members of a dynamic group.
+    pub(crate) membership_rule: Option<MembershipRule>,
 }
 
 impl Group {
@@ -76,8 +120,13 @@ impl Group {
         Group {
             name: name.to_string(),
             identities: IdentitySet::new(),
+            groups: vec![],
+            inheritance_path: vec![],
             inline_policy: policy,
             linked_policies: PolicySet::new(),
+            boundary: Option::None,
+            attributes: Attributes::new(),
+            membership_rule: Option::None,
         }
     }
 
@@ -98,6 +147,43 @@ impl Group {
         self
     }
 
+    /// Sets the permission boundary: the group will grant its members
+    /// only what is allowed by both the group policies and the boundary.
+    pub fn set_boundary(mut self, policy: CompletePolicy) -> Self {
+        self.boundary = Option::Some(policy);
+        self
+    }
+
+    pub fn clear_boundary(mut self) -> Self {
+        self.boundary = Option::None;
+        self
+    }
+
+    pub fn get_attributes(&self) -> &Attributes {
+        &self.attributes
+    }
+
+    pub fn set_attributes(mut self, attributes: Attributes) -> Self {
+        self.attributes = attributes;
+        self
+    }
+
+    /// Makes the group dynamic: all the identities whose attributes
+    /// match the rule are members, along with the explicitly added ones.
+    pub fn set_membership_rule(mut self, rule: MembershipRule) -> Self {
+        self.membership_rule = Option::Some(rule);
+        self
+    }
+
+    pub fn clear_membership_rule(mut self) -> Self {
+        self.membership_rule = Option::None;
+        self
+    }
+
+    pub fn get_membership_rule(&self) -> Option<&MembershipRule> {
+        self.membership_rule.as_ref()
+    }
+
     pub fn get_identities(&self) -> &Vec<Identity> {
         self.identities.identities.as_ref()
     }
@@ -107,15 +193,87 @@ impl Group {
         self
     }
 
+    /// Adds a member effective only in the given time window.
+    pub fn add_identity_with_validity(mut self, identity: Identity, validity: Validity) -> Self {
+        self.identities = self.identities.add_identity_with_validity(identity, validity);
+        self
+    }
+
+    pub fn get_membership_validity<T: ToIdentityId>(&self, identity: T) -> Validity {
+        self.identities.get_validity(identity)
+    }
+
     pub fn remove_identity<T: ToIdentityId>(mut self, identity: T) -> Self {
         self.identities = self.identities.remove_identity(identity);
         self
     }
+
+    /// Gets the names of the groups directly nested into this group.
+    pub fn get_groups(&self) -> &Vec<String> {
+        &self.groups
+    }
+
+    pub fn add_group<T: ToString>(mut self, group: T) -> Self {
+        let group = group.to_string();
+        if !self.groups.contains(&group) {
+            self.groups.push(group);
+        }
+
+        self
+    }
+
+    pub fn remove_group<T: ToString>(mut self, group: T) -> Self {
+        let group = group.to_string();
+        self.groups.retain(|g| *g != group);
+        self
+    }
+
+    pub fn get_inheritance_path(&self) -> &Vec<String> {
+        &self.inheritance_path
+    }
+
+    pub(crate) fn with_inheritance_path(mut self, path: Vec<String>) -> Self {
+        self.inheritance_path = path;
+        self
+    }
+}
+
+/// Searches the membership graph (group -> nested groups) for a path
+/// going from the given group back to itself.
+///
+/// # Returns
+///
+/// The shortest cycle, starting and ending with the given group, if any
+pub(crate) fn find_cycle(edges: &HashMap<String, Vec<String>>, start: &str) -> Option<Vec<String>> {
+    let mut visited = HashSet::new();
+    let mut queue = vec![vec![start.to_string()]];
+
+    while !queue.is_empty() {
+        let mut next = vec![];
+        for path in queue {
+            let last = path.last().unwrap();
+            for member in edges.get(last).into_iter().flatten() {
+                let mut member_path = path.clone();
+                member_path.push(member.clone());
+                if member == start {
+                    return Option::Some(member_path);
+                }
+
+                if visited.insert(member.clone()) {
+                    next.push(member_path);
+                }
+            }
+        }
+
+        queue = next;
+    }
+
+    Option::None
 }
 
 impl PolicySetTrait<CompletePolicy> for Group {
-    fn add_policy(mut self, policy: CompletePolicy) -> Self {
-        self.linked_policies = PolicySetHelper::link_policy(self.linked_policies, policy);
+    fn add_policy_link(mut self, policy: CompletePolicy, link: PolicyLink) -> Self {
+        self.linked_policies = PolicySetHelper::link_policy(self.linked_policies, policy, link);
         self
     }
 
@@ -154,6 +312,23 @@ impl ToJson for Group {
                     .collect::<Vec<&str>>(),
             ),
         );
+        map.insert(String::from("linked_policy_priorities"), linked_policies.priorities_to_value());
+        map.insert(String::from("linked_policy_validity"), linked_policies.validity_to_value());
+        map.insert(
+            String::from("boundary_policy"),
+            match self.boundary.as_ref() {
+                Option::None => Value::Null,
+                Option::Some(policy) => Value::from(policy.id.as_str()),
+            },
+        );
+        map.insert(String::from("attributes"), Value::Object(self.attributes.clone()));
+        map.insert(
+            String::from("membership_rule"),
+            match self.membership_rule.as_ref() {
+                Option::None => Value::Null,
+                Option::Some(rule) => Value::from(rule.as_str()),
+            },
+        );
 
         map
     }
@@ -163,6 +338,10 @@ impl Subject for Group {
     fn get_inline_policy(&self) -> Option<&CompletePolicy> {
         self.inline_policy.as_ref()
     }
+
+    fn get_boundary(&self) -> Option<&CompletePolicy> {
+        self.boundary.as_ref()
+    }
 }
 
 impl Role for Group {
@@ -180,10 +359,17 @@ impl Role for Group {
 
 #[cfg(test)]
 mod tests {
-    use crate::identity::group::Group;
+    use crate::identity::group::{find_cycle, Group};
     use crate::identity::identity::Identity;
+    use crate::identity::role::Role;
+    use crate::identity::subject::Subject;
+    use crate::policy::allowed_result::{AllowedOutcome, AllowedResult};
+    use crate::policy::combining_algorithm::CombiningAlgorithm;
+    use crate::policy::condition::Attributes;
+    use crate::policy::policy_set::PolicySetTrait;
     use crate::policy::{PolicyEffect, PolicyVersion};
     use crate::zephir_policy;
+    use std::collections::HashMap;
 
     #[test]
     fn group_could_be_created() {
@@ -242,4 +428,84 @@ mod tests {
 
         assert_eq!(g.identities.len(), 0);
     }
+
+    #[test]
+    fn groups_can_be_nested() {
+        let g = Group::new("engineering", Option::None)
+            .add_group("backend")
+            .add_group("backend")
+            .add_group("frontend");
+        assert_eq!(g.get_groups(), &vec!["backend".to_string(), "frontend".to_string()]);
+
+        let g = g.remove_group("backend");
+        assert_eq!(g.get_groups(), &vec!["frontend".to_string()]);
+    }
+
+    #[test]
+    fn find_cycle_should_return_the_shortest_cycle() {
+        let mut edges = HashMap::new();
+        edges.insert("engineering".to_string(), vec!["backend".to_string()]);
+        edges.insert("backend".to_string(), vec!["payments".to_string(), "api".to_string()]);
+        edges.insert("api".to_string(), vec!["payments".to_string()]);
+        assert_eq!(find_cycle(&edges, "engineering"), Option::None);
+
+        edges.insert("payments".to_string(), vec!["engineering".to_string()]);
+        assert_eq!(
+            find_cycle(&edges, "engineering"),
+            Option::Some(vec![
+                "engineering".to_string(),
+                "backend".to_string(),
+                "payments".to_string(),
+                "engineering".to_string(),
+            ])
+        );
+
+        edges.insert("api".to_string(), vec!["api".to_string()]);
+        assert_eq!(
+            find_cycle(&edges, "api"),
+            Option::Some(vec!["api".to_string(), "api".to_string()])
+        );
+    }
+
+    #[test]
+    fn group_boundary_should_only_restrict_what_the_group_grants() {
+        let g = Group::new("contractors", Option::None)
+            .add_policy(zephir_policy!("AllowAll", PolicyVersion::Version1, PolicyEffect::Allow, vec!["test:*", "other:*"]).unwrap())
+            .add_policy(zephir_policy!("DenyDelete", PolicyVersion::Version1, PolicyEffect::Deny, vec!["test:delete"]).unwrap())
+            .set_boundary(
+                zephir_policy!("Boundary", PolicyVersion::Version1, PolicyEffect::Allow, vec!["test:*"], vec!["urn:test-resource:*"]).unwrap(),
+            );
+
+        let evaluate = |action: &str, resource: Option<&str>| {
+            g.apply_boundary_to_grants(g.allowed(Option::Some(action), resource), &Attributes::new(), Option::Some(action), resource)
+        };
+
+        // Granted by the group and covered by the boundary.
+        let result = evaluate("test:get", Option::Some("urn:test-resource:id"));
+        assert_eq!(result.outcome(), AllowedOutcome::Allowed);
+
+        // Not covered by the boundary: the grant is dropped, but nothing is denied.
+        let result = evaluate("other:get", Option::Some("urn:test-resource:id"));
+        assert_eq!(result.outcome(), AllowedOutcome::Denied);
+        let mut identity_result = AllowedResult::new(AllowedOutcome::Allowed, vec![]);
+        CombiningAlgorithm::DenyOverrides.merge(&mut identity_result, result);
+        assert_eq!(identity_result.outcome(), AllowedOutcome::Allowed);
+
+        // Not matched by the group policies at all.
+        let result = evaluate("unknown:get", Option::Some("urn:test-resource:id"));
+        let mut identity_result = AllowedResult::new(AllowedOutcome::Allowed, vec![]);
+        CombiningAlgorithm::DenyOverrides.merge(&mut identity_result, result);
+        assert_eq!(identity_result.outcome(), AllowedOutcome::Allowed);
+
+        // Denies of the group are not affected by the boundary.
+        let result = evaluate("test:delete", Option::Some("urn:other-resource:id"));
+        let mut identity_result = AllowedResult::new(AllowedOutcome::Allowed, vec![]);
+        CombiningAlgorithm::DenyOverrides.merge(&mut identity_result, result);
+        assert_eq!(identity_result.outcome(), AllowedOutcome::Denied);
+
+        // Conditional grants are intersected with the boundary.
+        let result = evaluate("test:get", Option::None);
+        assert_eq!(result.outcome(), AllowedOutcome::Abstain);
+        assert_eq!(result.get_partials().len(), 1);
+    }
 }
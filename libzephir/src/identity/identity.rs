@@ -1,16 +1,23 @@
+use crate::identity::group::Group;
 use crate::identity::role::{allowed, Role};
 use crate::identity::subject::{Subject, SubjectIterator};
 use crate::policy::allowed_result::AllowedResult;
+use crate::policy::condition::Attributes;
 use crate::policy::policy::{CompletePolicy, ToJson};
-use crate::policy::policy_set::{PolicySet, PolicySetHelper, PolicySetTrait};
+use crate::policy::policy_set::{PolicyLink, PolicySet, PolicySetHelper, PolicySetTrait};
 use serde_json::{Map, Value};
 use std::fmt::{Debug, Display};
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Identity {
     pub(crate) id: String,
     pub(crate) inline_policy: Option<CompletePolicy>,
     pub(crate) linked_policies: PolicySet<CompletePolicy>,
+
+    /// Caps the permissions granted to the subject.
+    pub(crate) boundary: Option<CompletePolicy>,
+
+    pub(crate) attributes: Attributes,
 }
 
 impl Identity {
@@ -19,6 +26,8 @@ impl Identity {
             id: id.to_string(),
             inline_policy: policy,
             linked_policies: PolicySet::new(),
+            boundary: Option::None,
+            attributes: Attributes::new(),
         }
     }
 
@@ -38,6 +47,45 @@ impl Identity {
         self.inline_policy = Option::Some(policy);
         self
     }
+
+    /// Sets the permission boundary: the identity will be allowed only what
+    /// is allowed by both the boundary and its own (and its groups) policies.
+    pub fn set_boundary(mut self, policy: CompletePolicy) -> Self {
+        self.boundary = Option::Some(policy);
+        self
+    }
+
+    pub fn clear_boundary(mut self) -> Self {
+        self.boundary = Option::None;
+        self
+    }
+
+    pub fn get_attributes(&self) -> &Attributes {
+        &self.attributes
+    }
+
+    pub fn set_attributes(mut self, attributes: Attributes) -> Self {
+        self.attributes = attributes;
+        self
+    }
+
+    /// Computes the attributes the identity is evaluated with: attributes
+    /// are inherited from the given groups (closest groups first, as returned
+    /// by the storage) and the identity own attributes take precedence.
+    pub fn effective_attributes(&self, groups: &[Group]) -> Attributes {
+        let mut attributes = Attributes::new();
+        for group in groups.iter().rev() {
+            for (name, value) in group.get_attributes() {
+                attributes.insert(name.clone(), value.clone());
+            }
+        }
+
+        for (name, value) in &self.attributes {
+            attributes.insert(name.clone(), value.clone());
+        }
+
+        attributes
+    }
 }
 
 pub trait ToIdentityId {
@@ -66,6 +114,10 @@ impl Subject for Identity {
     fn get_inline_policy(&self) -> Option<&CompletePolicy> {
         self.inline_policy.as_ref()
     }
+
+    fn get_boundary(&self) -> Option<&CompletePolicy> {
+        self.boundary.as_ref()
+    }
 }
 
 impl ToJson for Identity {
@@ -90,6 +142,16 @@ impl ToJson for Identity {
                     .collect::<Vec<&str>>(),
             ),
         );
+        map.insert(String::from("linked_policy_priorities"), linked_policies.priorities_to_value());
+        map.insert(String::from("linked_policy_validity"), linked_policies.validity_to_value());
+        map.insert(
+            String::from("boundary_policy"),
+            match self.boundary.as_ref() {
+                Option::None => Value::Null,
+                Option::Some(policy) => Value::from(policy.id.as_str()),
+            },
+        );
+        map.insert(String::from("attributes"), Value::Object(self.attributes.clone()));
 
         map
     }
@@ -102,8 +164,8 @@ impl Into<Value> for Identity {
 }
 
 impl PolicySetTrait<CompletePolicy> for Identity {
-    fn add_policy(mut self, policy: CompletePolicy) -> Self {
-        self.linked_policies = PolicySetHelper::link_policy(self.linked_policies, policy);
+    fn add_policy_link(mut self, policy: CompletePolicy, link: PolicyLink) -> Self {
+        self.linked_policies = PolicySetHelper::link_policy(self.linked_policies, policy, link);
         self
     }
 
@@ -128,12 +190,18 @@ impl Role for Identity {
 
 #[cfg(test)]
 mod tests {
+    use crate::identity::group::Group;
     use crate::identity::identity::Identity;
     use crate::identity::role::Role;
+    use crate::identity::subject::Subject;
     use crate::policy::allowed_result::AllowedOutcome;
-    use crate::policy::policy_set::PolicySetTrait;
+    use crate::policy::combining_algorithm::CombiningAlgorithm;
+    use crate::policy::condition::{Attributes, SubjectConditions};
+    use crate::policy::policy_set::{PolicyLink, PolicySetTrait};
+    use crate::policy::validity::{unix_now, Validity};
     use crate::policy::{PolicyEffect, PolicyVersion};
     use crate::zephir_policy;
+    use serde_json::{json, Value};
 
     #[test]
     fn can_be_created() {
@@ -216,4 +284,94 @@ mod tests {
         );
         assert_eq!(result.outcome(), AllowedOutcome::Allowed);
     }
+
+    #[test]
+    fn boundary_should_cap_the_permissions() {
+        let i = Identity::new("IdentityTestBoundary", Option::None)
+            .add_policy(
+                zephir_policy!(
+                    "TestLinkedPolicyOnIdentity",
+                    PolicyVersion::Version1,
+                    PolicyEffect::Allow,
+                    vec!["*"]
+                )
+                .unwrap(),
+            )
+            .set_boundary(
+                zephir_policy!(
+                    "TestBoundaryPolicy",
+                    PolicyVersion::Version1,
+                    PolicyEffect::Allow,
+                    vec!["test:*"],
+                    vec!["urn:test-resource:*"]
+                )
+                .unwrap(),
+            );
+
+        let action = Option::Some("test:identity");
+        let result = i.apply_boundary(i.allowed(action, Option::Some("urn:test-resource:id")), &Attributes::new(), action, Option::Some("urn:test-resource:id"));
+        assert_eq!(result.outcome(), AllowedOutcome::Allowed);
+
+        let result = i.apply_boundary(i.allowed(action, Option::Some("urn:other-resource:id")), &Attributes::new(), action, Option::Some("urn:other-resource:id"));
+        assert_eq!(result.outcome(), AllowedOutcome::Denied);
+
+        let action = Option::Some("other:identity");
+        let result = i.apply_boundary(i.allowed(action, Option::Some("urn:test-resource:id")), &Attributes::new(), action, Option::Some("urn:test-resource:id"));
+        assert_eq!(result.outcome(), AllowedOutcome::Denied);
+
+        let action = Option::Some("test:identity");
+        let result = i.apply_boundary(i.allowed(action, Option::None::<String>), &Attributes::new(), action, Option::None::<String>);
+        assert_eq!(result.outcome(), AllowedOutcome::Abstain);
+        assert_eq!(result.get_partials().len(), 1);
+    }
+
+    #[test]
+    fn links_out_of_their_time_window_should_be_ignored() {
+        let policy = |id: &str| zephir_policy!(id, PolicyVersion::Version1, PolicyEffect::Allow, vec!["test:*"]).unwrap();
+        let now = unix_now();
+
+        let expired = Identity::new("IdentityTestExpiredLink", Option::None).add_policy_link(
+            policy("TestExpiredPolicy"),
+            PolicyLink::new(0, Validity::new(Option::None, Option::Some(now - 10))),
+        );
+        let pending = Identity::new("IdentityTestPendingLink", Option::None).add_policy_link(
+            policy("TestPendingPolicy"),
+            PolicyLink::new(0, Validity::new(Option::Some(now + 3600), Option::None)),
+        );
+        let active = Identity::new("IdentityTestActiveLink", Option::None).add_policy_link(
+            policy("TestActivePolicy"),
+            PolicyLink::new(0, Validity::new(Option::Some(now - 10), Option::Some(now + 3600))),
+        );
+
+        let action = Option::Some("test:identity");
+        assert_eq!(expired.allowed(action, Option::None::<String>).outcome(), AllowedOutcome::Denied);
+        assert_eq!(pending.allowed(action, Option::None::<String>).outcome(), AllowedOutcome::Denied);
+        assert_eq!(active.allowed(action, Option::None::<String>).outcome(), AllowedOutcome::Allowed);
+    }
+
+    #[test]
+    fn subject_conditions_should_be_checked_against_the_effective_attributes() {
+        let attributes = |value: serde_json::Value| value.as_object().unwrap().clone();
+        let policy = zephir_policy!("TestFinancePolicy", PolicyVersion::Version1, PolicyEffect::Allow, vec!["ledger:*"])
+            .unwrap()
+            .with_subject_conditions(SubjectConditions::new(attributes(json!({ "department": "finance", "level": [3, 4] }))));
+
+        let i = Identity::new("IdentityTestAttributes", Option::None)
+            .add_policy(policy)
+            .set_attributes(attributes(json!({ "level": 3 })));
+        let groups = vec![
+            Group::new("finance", Option::None).set_attributes(attributes(json!({ "department": "finance", "level": 1 }))),
+            Group::new("staff", Option::None).set_attributes(attributes(json!({ "department": "none" }))),
+        ];
+
+        let effective = i.effective_attributes(&groups);
+        assert_eq!(Value::Object(effective.clone()), json!({ "department": "finance", "level": 3 }));
+
+        let action = Option::Some("ledger:read");
+        let result = i.allowed_with(CombiningAlgorithm::default(), &effective, action, Option::None::<String>);
+        assert_eq!(result.outcome(), AllowedOutcome::Allowed);
+
+        let result = i.allowed_with(CombiningAlgorithm::default(), &Attributes::new(), action, Option::None::<String>);
+        assert_eq!(result.outcome(), AllowedOutcome::Denied);
+    }
 }
@@ -0,0 +1,357 @@
+use crate::err::Error;
+use crate::policy::condition::Attributes;
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// The maximum number of tokens in a rule.
+const MAX_TOKENS: usize = 1024;
+
+/// The maximum nesting of parentheses and negations in a rule.
+const MAX_DEPTH: usize = 32;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Operator {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Identifier(String),
+    Literal(Value),
+    Operator(Operator),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+#[derive(Clone, Debug)]
+enum Expression {
+    Compare(String, Operator, Value),
+    Not(Box<Expression>),
+    And(Box<Expression>, Box<Expression>),
+    Or(Box<Expression>, Box<Expression>),
+}
+
+/// The rule defining the members of a dynamic group, evaluated
+/// against the attributes of the identities.
+///
+/// Rules are made of comparisons between an attribute and a literal
+/// (string, number or boolean) through `==`, `!=`, `<`, `<=`, `>`, `>=`,
+/// combined with `&&`, `||`, `!` and parentheses:
+///
+/// `department == "finance" && (level >= 3 || manager == true)`
+///
+/// Comparisons on missing attributes never match. Multi-valued (array)
+/// attributes match if any of their values does.
+#[derive(Clone, Debug)]
+pub struct MembershipRule {
+    source: String,
+    expression: Expression,
+}
+
+impl TryFrom<&str> for MembershipRule {
+    type Error = Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let tokens = tokenize(value).map_err(|e| Error::invalid_membership_rule(value, e))?;
+        if tokens.len() > MAX_TOKENS {
+            return Err(Error::invalid_membership_rule(value, format!("too long (at most {} tokens allowed)", MAX_TOKENS)));
+        }
+
+        let mut parser = Parser { tokens, position: 0, depth: 0 };
+
+        let expression = parser.parse_or().map_err(|e| Error::invalid_membership_rule(value, e))?;
+        if let Some(token) = parser.tokens.get(parser.position) {
+            return Err(Error::invalid_membership_rule(value, format!("unexpected {:?}", token)));
+        }
+
+        Ok(MembershipRule {
+            source: value.to_string(),
+            expression,
+        })
+    }
+}
+
+impl MembershipRule {
+    pub fn as_str(&self) -> &str {
+        self.source.as_str()
+    }
+
+    /// Checks whether an identity with the given attributes is a member.
+    pub fn matches(&self, attributes: &Attributes) -> bool {
+        evaluate(&self.expression, attributes)
+    }
+}
+
+fn evaluate(expression: &Expression, attributes: &Attributes) -> bool {
+    match expression {
+        Expression::Compare(name, operator, expected) => match attributes.get(name) {
+            Option::None | Option::Some(Value::Null) => false,
+            Option::Some(Value::Array(values)) => values.iter().any(|v| compare(v, *operator, expected)),
+            Option::Some(value) => compare(value, *operator, expected),
+        },
+        Expression::Not(inner) => !evaluate(inner, attributes),
+        Expression::And(left, right) => evaluate(left, attributes) && evaluate(right, attributes),
+        Expression::Or(left, right) => evaluate(left, attributes) || evaluate(right, attributes),
+    }
+}
+
+fn compare(value: &Value, operator: Operator, expected: &Value) -> bool {
+    let ordering = match (value, expected) {
+        (Value::Number(a), Value::Number(b)) => a.as_f64().zip(b.as_f64()).and_then(|(a, b)| a.partial_cmp(&b)),
+        (Value::String(a), Value::String(b)) => Option::Some(a.cmp(b)),
+        (Value::Bool(a), Value::Bool(b)) => Option::Some(a.cmp(b)),
+        _ => Option::None,
+    };
+
+    match (operator, ordering) {
+        (_, Option::None) => false,
+        (Operator::Eq, Option::Some(o)) => o == Ordering::Equal,
+        (Operator::Ne, Option::Some(o)) => o != Ordering::Equal,
+        (Operator::Lt, Option::Some(o)) => o == Ordering::Less,
+        (Operator::Le, Option::Some(o)) => o != Ordering::Greater,
+        (Operator::Gt, Option::Some(o)) => o == Ordering::Greater,
+        (Operator::Ge, Option::Some(o)) => o != Ordering::Less,
+    }
+}
+
+fn tokenize(rule: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut chars = rule.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        let token = match c {
+            _ if c.is_whitespace() => {
+                chars.next();
+                continue;
+            }
+            '(' | ')' => {
+                chars.next();
+                if c == '(' { Token::Open } else { Token::Close }
+            }
+            '&' | '|' => {
+                chars.next();
+                if chars.next() != Option::Some(c) {
+                    return Err(format!("expected \"{}{}\"", c, c));
+                }
+
+                if c == '&' { Token::And } else { Token::Or }
+            }
+            '!' | '=' | '<' | '>' => {
+                chars.next();
+                let followed_by_eq = chars.peek() == Option::Some(&'=');
+                if followed_by_eq {
+                    chars.next();
+                }
+
+                match (c, followed_by_eq) {
+                    ('!', false) => Token::Not,
+                    ('!', true) => Token::Operator(Operator::Ne),
+                    ('=', true) => Token::Operator(Operator::Eq),
+                    ('<', false) => Token::Operator(Operator::Lt),
+                    ('<', true) => Token::Operator(Operator::Le),
+                    ('>', false) => Token::Operator(Operator::Gt),
+                    ('>', true) => Token::Operator(Operator::Ge),
+                    _ => return Err(String::from("expected \"==\"")),
+                }
+            }
+            '"' => Token::Literal(Value::String(read_string(&mut chars)?)),
+            _ if c == '-' || c.is_ascii_digit() => Token::Literal(read_number(&mut chars)?),
+            _ if c == '_' || c.is_alphabetic() => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if !(c.is_alphanumeric() || c == '_' || c == '-' || c == '.') {
+                        break;
+                    }
+
+                    word.push(c);
+                    chars.next();
+                }
+
+                match word.as_str() {
+                    "true" => Token::Literal(Value::Bool(true)),
+                    "false" => Token::Literal(Value::Bool(false)),
+                    _ => Token::Identifier(word),
+                }
+            }
+            _ => return Err(format!("unexpected character '{}'", c)),
+        };
+
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+fn read_string(chars: &mut Peekable<Chars>) -> Result<String, String> {
+    chars.next();
+
+    let mut result = String::new();
+    loop {
+        match chars.next() {
+            Option::None => return Err(String::from("unterminated string")),
+            Option::Some('"') => return Ok(result),
+            Option::Some('\\') => match chars.next() {
+                Option::Some(c) => result.push(c),
+                Option::None => return Err(String::from("unterminated string")),
+            },
+            Option::Some(c) => result.push(c),
+        }
+    }
+}
+
+fn read_number(chars: &mut Peekable<Chars>) -> Result<Value, String> {
+    let mut number = String::new();
+    while let Some(&c) = chars.peek() {
+        if !(c.is_ascii_digit() || c == '.' || (c == '-' && number.is_empty())) {
+            break;
+        }
+
+        number.push(c);
+        chars.next();
+    }
+
+    if let Ok(n) = number.parse::<i64>() {
+        return Ok(Value::from(n));
+    }
+
+    number
+        .parse::<f64>()
+        .map(Value::from)
+        .map_err(|_| format!("invalid number \"{}\"", number))
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+
+    /// The current nesting of parentheses and negations.
+    depth: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+
+        token
+    }
+
+    fn accept(&mut self, token: &Token) -> bool {
+        if self.tokens.get(self.position) == Option::Some(token) {
+            self.position += 1;
+            return true;
+        }
+
+        false
+    }
+
+    fn parse_or(&mut self) -> Result<Expression, String> {
+        let mut expression = self.parse_and()?;
+        while self.accept(&Token::Or) {
+            expression = Expression::Or(Box::new(expression), Box::new(self.parse_and()?));
+        }
+
+        Ok(expression)
+    }
+
+    fn parse_and(&mut self) -> Result<Expression, String> {
+        let mut expression = self.parse_unary()?;
+        while self.accept(&Token::And) {
+            expression = Expression::And(Box::new(expression), Box::new(self.parse_unary()?));
+        }
+
+        Ok(expression)
+    }
+
+    /// Parses a nested expression, refusing to recurse too deep.
+    fn parse_nested<F>(&mut self, parse: F) -> Result<Expression, String>
+    where
+        F: FnOnce(&mut Self) -> Result<Expression, String>,
+    {
+        if self.depth >= MAX_DEPTH {
+            return Err(format!("too deeply nested (at most {} levels allowed)", MAX_DEPTH));
+        }
+
+        self.depth += 1;
+        let expression = parse(self);
+        self.depth -= 1;
+
+        expression
+    }
+
+    fn parse_unary(&mut self) -> Result<Expression, String> {
+        match self.next() {
+            Option::Some(Token::Not) => Ok(Expression::Not(Box::new(self.parse_nested(Self::parse_unary)?))),
+            Option::Some(Token::Open) => {
+                let expression = self.parse_nested(Self::parse_or)?;
+                if !self.accept(&Token::Close) {
+                    return Err(String::from("expected \")\""));
+                }
+
+                Ok(expression)
+            }
+            Option::Some(Token::Identifier(name)) => match (self.next(), self.next()) {
+                (Option::Some(Token::Operator(operator)), Option::Some(Token::Literal(value))) => {
+                    Ok(Expression::Compare(name, operator, value))
+                }
+                _ => Err(format!("expected a comparison after \"{}\"", name)),
+            },
+            Option::Some(token) => Err(format!("unexpected {:?}", token)),
+            Option::None => Err(String::from("unexpected end of rule")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::identity::membership_rule::MembershipRule;
+    use serde_json::json;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn rules_should_be_evaluated_against_attributes() {
+        let rule = MembershipRule::try_from(r#"department == "finance" && (level >= 3 || !contractor == false)"#).unwrap();
+        let attributes = |value: serde_json::Value| value.as_object().unwrap().clone();
+
+        assert!(rule.matches(&attributes(json!({ "department": "finance", "level": 3, "contractor": false }))));
+        assert!(rule.matches(&attributes(json!({ "department": "finance", "level": 1, "contractor": true }))));
+        assert!(!rule.matches(&attributes(json!({ "department": "finance", "level": 1, "contractor": false }))));
+        assert!(!rule.matches(&attributes(json!({ "department": "sales", "level": 5 }))));
+        assert!(!rule.matches(&attributes(json!({ "level": 5 }))));
+
+        let rule = MembershipRule::try_from(r#"tags == "admin" || score > -1.5"#).unwrap();
+        assert!(rule.matches(&attributes(json!({ "tags": ["dev", "admin"] }))));
+        assert!(rule.matches(&attributes(json!({ "score": -1 }))));
+        assert!(!rule.matches(&attributes(json!({ "tags": ["dev"], "score": "high" }))));
+        assert_eq!(rule.as_str(), r#"tags == "admin" || score > -1.5"#);
+    }
+
+    #[test]
+    fn invalid_rules_should_be_rejected() {
+        for rule in vec!["", "department", r#"department = "finance""#, r#"level >= 3 &&"#, r#"(level >= 3"#, r#"name == "unterminated"#, "level >= 3 level"] {
+            assert!(MembershipRule::try_from(rule).is_err(), "{}", rule);
+        }
+    }
+
+    #[test]
+    fn deeply_nested_or_too_long_rules_should_be_rejected() {
+        let nested = |depth: usize| format!("{}level >= 3{}", "(".repeat(depth), ")".repeat(depth));
+        assert!(MembershipRule::try_from(nested(32).as_str()).is_ok());
+        assert!(MembershipRule::try_from(nested(100_000).as_str()).is_err());
+        assert!(MembershipRule::try_from(format!("{}level >= 3", "!".repeat(100_000)).as_str()).is_err());
+
+        let long = vec!["level >= 3"; 1000].join(" && ");
+        assert!(MembershipRule::try_from(long.as_str()).is_err());
+    }
+}
@@ -1,5 +1,7 @@
 pub mod role;
 pub mod subject;
 
+pub mod assumable_role;
 pub mod group;
 pub mod identity;
+pub mod membership_rule;
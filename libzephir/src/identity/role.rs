@@ -1,11 +1,14 @@
 use crate::policy::allowed_result::{AllowedOutcome, AllowedResult};
+use crate::policy::combining_algorithm::CombiningAlgorithm;
+use crate::policy::condition::Attributes;
 use crate::policy::policy::{CompletePolicy, MatchablePolicy};
 use crate::policy::policy_set::PolicySet;
+use crate::policy::validity::unix_now;
 use crate::policy::PolicyEffect;
 use serde_json::Value;
 use std::fmt::{Debug, Display};
 
-pub(super) fn allowed<'a, T, S, I>(
+pub(crate) fn allowed<'a, T, S, I>(
     policies: I,
     action: Option<T>,
     resource: Option<S>,
@@ -15,28 +18,46 @@ where
     S: ToString + Display + Debug,
     I: Iterator<Item = &'a CompletePolicy>,
 {
-    let mut outcome: AllowedOutcome = AllowedOutcome::Abstain;
-    let mut partials = vec![];
+    allowed_with(policies, action, resource, CombiningAlgorithm::default(), &Attributes::new())
+}
+
+/// Evaluates the policies in order, combining their results with the
+/// given algorithm. Subject conditions are checked against the given attributes.
+pub(crate) fn allowed_with<'a, T, S, I>(
+    policies: I,
+    action: Option<T>,
+    resource: Option<S>,
+    algorithm: CombiningAlgorithm,
+    attributes: &Attributes,
+) -> AllowedResult
+where
+    T: ToString + Display,
+    S: ToString + Display + Debug,
+    I: Iterator<Item = &'a CompletePolicy>,
+{
+    let mut outcome = AllowedResult::new(AllowedOutcome::Abstain, vec![]);
 
     for p in policies {
-        let result = p.matching(action.as_ref(), resource.as_ref());
+        let result = p.matching_subject(action.as_ref(), resource.as_ref(), attributes);
         if !result.is_match() {
             continue;
         }
 
-        if result.is_full() {
-            if p.effect == PolicyEffect::Deny {
-                return AllowedResult::new(AllowedOutcome::Denied, vec![]);
-            }
+        let policy_result = if !result.is_full() {
+            AllowedResult::new(AllowedOutcome::Abstain, vec![result.get_partial()])
+        } else if p.effect == PolicyEffect::Deny {
+            AllowedResult::new(AllowedOutcome::Denied, vec![])
+        } else {
+            AllowedResult::new(AllowedOutcome::Allowed, vec![])
+        };
 
-            outcome = AllowedOutcome::Allowed;
-            continue;
+        algorithm.merge(&mut outcome, policy_result);
+        if algorithm.is_final(&outcome) {
+            break;
         }
-
-        partials.push(result.get_partial());
     }
 
-    AllowedResult::new(outcome, partials)
+    outcome
 }
 
 pub trait Role: Into<Value> {
@@ -49,7 +70,7 @@ pub trait Role: Into<Value> {
     {
         let mut policies = vec![];
         let linked_policies = self.linked_policies();
-        for policy in linked_policies {
+        for policy in linked_policies.active_at(unix_now()) {
             policies.push(policy);
         }
 
@@ -1,53 +1,122 @@
-use crate::identity::role::Role;
+use crate::identity::role::{allowed_with, Role};
+use crate::policy::allowed_result::AllowedResult;
+use crate::policy::combining_algorithm::CombiningAlgorithm;
+use crate::policy::condition::Attributes;
 use crate::policy::policy::{CompletePolicy, ToJson};
-use num_traits::cast::AsPrimitive;
+use crate::policy::validity::unix_now;
+use std::fmt::{Debug, Display};
 
 pub trait Subject: Role + ToJson {
     /// Returns the inline policy associate with the subject.
     fn get_inline_policy(&self) -> Option<&CompletePolicy>;
-}
 
-pub(crate) struct SubjectIterator<'a, T: Subject> {
-    current: isize,
-    total: isize,
+    /// Returns the permission boundary of the subject, if any.
+    fn get_boundary(&self) -> Option<&CompletePolicy> {
+        Option::None
+    }
 
-    subject: &'a T,
-    linked_policies: &'a [CompletePolicy],
+    /// Restricts the given result to what is allowed by the subject
+    /// permission boundary. Results of subjects without boundary
+    /// are returned untouched.
+    fn apply_boundary<T, S>(&self, result: AllowedResult, attributes: &Attributes, action: Option<T>, resource: Option<S>) -> AllowedResult
+    where
+        T: ToString + Display,
+        S: ToString + Display + Debug,
+    {
+        let mut result = result;
+        if let Some(boundary) = self.get_boundary() {
+            result.intersect(allowed_with(std::iter::once(boundary), action, resource, CombiningAlgorithm::default(), attributes));
+        }
+
+        result
+    }
+
+    /// Restricts the permissions granted by the subject to its boundary, for
+    /// subjects contributing to the outcome of another one (e.g. the groups of
+    /// an identity): unlike `apply_boundary`, a result granting nothing, or a
+    /// grant not covered by the boundary, never turns into a deny.
+    fn apply_boundary_to_grants<T, S>(&self, result: AllowedResult, attributes: &Attributes, action: Option<T>, resource: Option<S>) -> AllowedResult
+    where
+        T: ToString + Display,
+        S: ToString + Display + Debug,
+    {
+        let mut result = result;
+        if let Some(boundary) = self.get_boundary() {
+            result.intersect_grants(allowed_with(std::iter::once(boundary), action, resource, CombiningAlgorithm::default(), attributes));
+        }
+
+        result
+    }
+
+    /// Evaluates the subject policies (inline policy first) combining
+    /// their results with the given algorithm. The attributes are
+    /// the ones of the subject the request is evaluated for.
+    fn allowed_with<T, S>(&self, algorithm: CombiningAlgorithm, attributes: &Attributes, action: Option<T>, resource: Option<S>) -> AllowedResult
+    where
+        Self: Sized,
+        T: ToString + Display,
+        S: ToString + Display + Debug,
+    {
+        allowed_with(SubjectIterator::new(self), action, resource, algorithm, attributes)
+    }
 }
 
-impl<'a, T: Subject> SubjectIterator<'a, T> {
-    pub(crate) fn new(subject: &'a T) -> Self {
-        let linked_policies = subject.linked_policies();
-        let linked_policies = linked_policies.into_iter().as_slice();
+/// Iterates over the inline policy (if any) and then over
+/// the linked policies whose link is currently effective.
+pub(crate) struct SubjectIterator<'a> {
+    inline_policy: Option<&'a CompletePolicy>,
+    linked_policies: std::vec::IntoIter<&'a CompletePolicy>,
+}
 
-        let total: usize = linked_policies.len();
+impl<'a> SubjectIterator<'a> {
+    pub(crate) fn new<T: Subject>(subject: &'a T) -> Self {
+        let linked_policies: Vec<&'a CompletePolicy> = subject
+            .linked_policies()
+            .active_at(unix_now())
+            .collect();
 
         SubjectIterator {
-            current: -1,
-            total: total.as_(),
-            linked_policies,
-            subject,
+            inline_policy: subject.get_inline_policy(),
+            linked_policies: linked_policies.into_iter(),
         }
     }
 }
 
-impl<'a, T: Subject> Iterator for SubjectIterator<'a, T> {
+impl<'a> Iterator for SubjectIterator<'a> {
     type Item = &'a CompletePolicy;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.current >= self.total {
-            return Option::None;
-        }
+        self.inline_policy.take().or_else(|| self.linked_policies.next())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::identity::identity::Identity;
+    use crate::identity::subject::{Subject, SubjectIterator};
+    use crate::policy::allowed_result::AllowedOutcome;
+    use crate::policy::combining_algorithm::CombiningAlgorithm;
+    use crate::policy::condition::Attributes;
+    use crate::policy::policy_set::PolicySetTrait;
+    use crate::policy::{PolicyEffect, PolicyVersion};
+    use crate::zephir_policy;
+
+    #[test]
+    fn every_linked_policy_should_be_evaluated_without_inline_policy() {
+        let identity = Identity::new("IdentityWithoutInlinePolicy", Option::None)
+            .add_policy(zephir_policy!("AllowAll", PolicyVersion::Version1, PolicyEffect::Allow, vec!["*"]).unwrap())
+            .add_policy(zephir_policy!("DenyDelete", PolicyVersion::Version1, PolicyEffect::Deny, vec!["delete_*"]).unwrap())
+            .add_policy(zephir_policy!("DenyPut", PolicyVersion::Version1, PolicyEffect::Deny, vec!["put_*"]).unwrap());
 
-        let element = match self.current {
-            -1 => self.subject.get_inline_policy().or_else(|| {
-                self.current += 1;
-                self.next()
-            }),
-            _ => self.linked_policies.get(self.current.unsigned_abs())
-        };
+        let ids: Vec<&String> = SubjectIterator::new(&identity).map(|p| &p.id).collect();
+        assert_eq!(ids, vec!["AllowAll", "DenyDelete", "DenyPut"]);
 
-        self.current += 1;
-        element
+        let result = identity.allowed_with::<&str, String>(
+            CombiningAlgorithm::DenyOverrides,
+            &Attributes::new(),
+            Option::Some("delete_item"),
+            Option::None,
+        );
+        assert_eq!(result.outcome(), AllowedOutcome::Denied);
     }
 }
@@ -4,9 +4,13 @@
 pub mod cache;
 mod compiler;
 pub mod err;
+pub mod file_store;
 pub mod identity;
+pub mod lint;
 pub mod policy;
 pub mod storage;
+pub mod tenant;
 pub mod utils;
+pub mod webhook;
 
 pub use utils::glob_to_regex;
@@ -0,0 +1,475 @@
+use crate::err::Error;
+use crate::identity::group::Group;
+use crate::identity::identity::Identity;
+use crate::policy::combining_algorithm::CombiningAlgorithm;
+use crate::policy::diff::PolicyDiff;
+use crate::policy::policy::{CompletePolicy, MatchablePolicy, ToJson};
+use crate::policy::policy_set::PolicySet;
+use crate::policy::PolicyEffect;
+use crate::utils::glob_to_regex;
+use serde_json::{Map, Value};
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+/// A policy attached to the subject being analyzed, along with its owner.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PolicyRef {
+    /// "identity" or "group".
+    pub owner_type: String,
+    pub owner: String,
+    pub id: String,
+    pub inline: bool,
+}
+
+impl ToJson for PolicyRef {
+    fn to_json(&self) -> Map<String, Value> {
+        let mut map = Map::new();
+        map.insert(String::from("owner_type"), Value::from(self.owner_type.as_str()));
+        map.insert(String::from("owner"), Value::from(self.owner.as_str()));
+        map.insert(String::from("id"), Value::from(self.id.as_str()));
+        map.insert(String::from("inline"), Value::from(self.inline));
+
+        map
+    }
+}
+
+impl Display for PolicyRef {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.inline {
+            write!(f, "inline policy of {} \"{}\"", self.owner_type, self.owner)
+        } else {
+            write!(f, "policy \"{}\" of {} \"{}\"", self.id, self.owner_type, self.owner)
+        }
+    }
+}
+
+/// An issue found in the policies of a subject.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LintFinding {
+    /// Every request matched by the policy is matched by
+    /// another one with the same effect.
+    Redundant { policy: PolicyRef, subsumed_by: PolicyRef },
+
+    /// Every request allowed by the policy is denied by another one,
+    /// which wins according to the combining algorithm.
+    Shadowed { policy: PolicyRef, denied_by: PolicyRef },
+
+    /// The policy allows every action, or every action of a sensitive service.
+    BroadPattern {
+        policy: PolicyRef,
+        pattern: String,
+        service: Option<String>,
+    },
+
+    /// An inline policy identical to a linked one.
+    DuplicateInline { policy: PolicyRef, linked: PolicyRef },
+}
+
+impl LintFinding {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            LintFinding::Redundant { .. } => "redundant",
+            LintFinding::Shadowed { .. } => "shadowed",
+            LintFinding::BroadPattern { .. } => "broad_pattern",
+            LintFinding::DuplicateInline { .. } => "duplicate_inline",
+        }
+    }
+
+    pub fn policy(&self) -> &PolicyRef {
+        match self {
+            LintFinding::Redundant { policy, .. }
+            | LintFinding::Shadowed { policy, .. }
+            | LintFinding::BroadPattern { policy, .. }
+            | LintFinding::DuplicateInline { policy, .. } => policy,
+        }
+    }
+}
+
+impl ToJson for LintFinding {
+    fn to_json(&self) -> Map<String, Value> {
+        let mut map = Map::new();
+        map.insert(String::from("kind"), Value::from(self.kind()));
+        map.insert(String::from("policy"), Value::Object(self.policy().to_json()));
+        match self {
+            LintFinding::Redundant { subsumed_by, .. } => {
+                map.insert(String::from("subsumed_by"), Value::Object(subsumed_by.to_json()));
+            }
+            LintFinding::Shadowed { denied_by, .. } => {
+                map.insert(String::from("denied_by"), Value::Object(denied_by.to_json()));
+            }
+            LintFinding::BroadPattern { pattern, service, .. } => {
+                map.insert(String::from("pattern"), Value::from(pattern.as_str()));
+                map.insert(String::from("service"), Value::from(service.clone()));
+            }
+            LintFinding::DuplicateInline { linked, .. } => {
+                map.insert(String::from("linked"), Value::Object(linked.to_json()));
+            }
+        }
+        map.insert(String::from("message"), Value::from(self.to_string()));
+
+        map
+    }
+}
+
+impl Display for LintFinding {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            LintFinding::Redundant { policy, subsumed_by } => {
+                write!(f, "{} is redundant: {} matches everything it does", policy, subsumed_by)
+            }
+            LintFinding::Shadowed { policy, denied_by } => {
+                write!(f, "{} is shadowed: {} denies everything it allows", policy, denied_by)
+            }
+            LintFinding::BroadPattern { policy, pattern, service: Option::None } => {
+                write!(f, "{} allows every action through \"{}\"", policy, pattern)
+            }
+            LintFinding::BroadPattern { policy, pattern, service: Option::Some(service) } => {
+                write!(f, "{} allows every action of the sensitive service \"{}\" through \"{}\"", policy, service, pattern)
+            }
+            LintFinding::DuplicateInline { policy, linked } => {
+                write!(f, "{} duplicates {}", policy, linked)
+            }
+        }
+    }
+}
+
+/// A policy attached to the subject, as seen by the linter.
+struct Attached<'a> {
+    reference: PolicyRef,
+    policy: &'a CompletePolicy,
+
+    /// Whether the policy always applies: a link effective
+    /// in a time window only could not subsume another policy.
+    unbounded: bool,
+}
+
+fn attach<'a>(
+    result: &mut Vec<Attached<'a>>,
+    owner_type: &str,
+    owner: &str,
+    inline_policy: &'a Option<CompletePolicy>,
+    linked_policies: &'a PolicySet<CompletePolicy>,
+) {
+    let reference = |id: &str, inline: bool| PolicyRef {
+        owner_type: owner_type.to_string(),
+        owner: owner.to_string(),
+        id: id.to_string(),
+        inline,
+    };
+
+    if let Some(policy) = inline_policy {
+        result.push(Attached {
+            reference: reference(&policy.id, true),
+            policy,
+            unbounded: true,
+        });
+    }
+
+    for policy in linked_policies {
+        let bounded = matches!(linked_policies.get_link(&policy.id), Some(l) if l.validity.is_bounded());
+        result.push(Attached {
+            reference: reference(&policy.id, false),
+            policy,
+            unbounded: !bounded,
+        });
+    }
+}
+
+/// Gets the resources of the policy: a policy without resources matches all of them.
+fn resource_patterns(policy: &CompletePolicy) -> Vec<String> {
+    let resources = policy.get_resources();
+    if resources.is_empty() {
+        vec![String::from("*")]
+    } else {
+        resources.to_vec()
+    }
+}
+
+/// Checks whether each of the inner patterns is included into one of the outer ones.
+fn patterns_include(outer: &[String], inner: &[String]) -> bool {
+    inner
+        .iter()
+        .all(|i| outer.iter().any(|o| glob_to_regex::includes(o, i)))
+}
+
+/// Checks whether the outer policy matches every request the inner one does.
+/// The check is conservative, as `glob_to_regex::includes` is.
+fn subsumes(outer: &Attached, inner: &Attached) -> bool {
+    let conditions_hold = match outer.policy.get_subject_conditions() {
+        Option::None => true,
+        Option::Some(conditions) => {
+            inner.policy.get_subject_conditions().map(|c| c.to_value()) == Option::Some(conditions.to_value())
+        }
+    };
+
+    outer.unbounded
+        && conditions_hold
+        && patterns_include(outer.policy.get_actions(), inner.policy.get_actions())
+        && patterns_include(&resource_patterns(outer.policy), &resource_patterns(inner.policy))
+}
+
+/// Whether the deny policy at the given position in evaluation order
+/// wins over the allow one matching the same requests.
+fn deny_wins(algorithm: CombiningAlgorithm, deny: usize, allow: usize) -> bool {
+    match algorithm {
+        CombiningAlgorithm::DenyOverrides => true,
+        CombiningAlgorithm::FirstApplicable => deny < allow,
+        CombiningAlgorithm::PermitOverrides | CombiningAlgorithm::DenyUnlessPermit => false,
+    }
+}
+
+/// Reports the redundant, shadowed, overly broad and duplicated
+/// policies among the ones attached to a subject.
+///
+/// Shadowing depends on the combining algorithm the policies are evaluated
+/// with: the policies are analyzed in evaluation order (the identity ones,
+/// then the ones of its groups, inline policies first).
+#[derive(Clone, Debug, Default)]
+pub struct Linter {
+    /// Services (the part of the actions before the colon) whose
+    /// actions should not be granted all at once (e.g. "iam").
+    sensitive_services: Vec<String>,
+}
+
+impl Linter {
+    pub fn new<S: ToString>(sensitive_services: Vec<S>) -> Self {
+        Linter {
+            sensitive_services: sensitive_services.iter().map(ToString::to_string).collect(),
+        }
+    }
+
+    /// Reads the sensitive services from the LINT_SENSITIVE_SERVICES
+    /// env var, as a comma separated list.
+    pub fn from_env() -> Result<Self, Error> {
+        let services = std::env::var("LINT_SENSITIVE_SERVICES").unwrap_or_default();
+        Ok(Self::new(
+            services
+                .split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .collect(),
+        ))
+    }
+
+    /// Analyzes the policies of an identity along with the ones of its groups.
+    pub fn lint_identity(&self, identity: &Identity, groups: &[Group], algorithm: CombiningAlgorithm) -> Vec<LintFinding> {
+        let mut attached = vec![];
+        attach(&mut attached, "identity", &identity.id, &identity.inline_policy, &identity.linked_policies);
+        for group in groups {
+            attach(&mut attached, "group", &group.name, &group.inline_policy, &group.linked_policies);
+        }
+
+        self.lint(&attached, algorithm)
+    }
+
+    /// Analyzes the policies of a group along with the ones of the groups it inherits.
+    pub fn lint_group(&self, group: &Group, ancestors: &[Group], algorithm: CombiningAlgorithm) -> Vec<LintFinding> {
+        let mut attached = vec![];
+        for group in std::iter::once(group).chain(ancestors) {
+            attach(&mut attached, "group", &group.name, &group.inline_policy, &group.linked_policies);
+        }
+
+        self.lint(&attached, algorithm)
+    }
+
+    fn broad_patterns(&self, attached: &Attached, findings: &mut Vec<LintFinding>) {
+        if attached.policy.effect != PolicyEffect::Allow {
+            return;
+        }
+
+        for pattern in attached.policy.get_actions() {
+            let finding = |service: Option<&String>| LintFinding::BroadPattern {
+                policy: attached.reference.clone(),
+                pattern: pattern.clone(),
+                service: service.cloned(),
+            };
+
+            if pattern == "*" {
+                findings.push(finding(Option::None));
+                continue;
+            }
+
+            for service in &self.sensitive_services {
+                if glob_to_regex::includes(pattern, &format!("{}:*", service)) {
+                    findings.push(finding(Option::Some(service)));
+                }
+            }
+        }
+    }
+
+    fn lint(&self, attached: &[Attached], algorithm: CombiningAlgorithm) -> Vec<LintFinding> {
+        let mut findings = vec![];
+        for (i, a) in attached.iter().enumerate() {
+            self.broad_patterns(a, &mut findings);
+
+            for (j, b) in attached.iter().enumerate() {
+                if i == j {
+                    continue;
+                }
+
+                if a.reference.inline && !b.reference.inline && PolicyDiff::between(b.policy, a.policy).is_empty() {
+                    findings.push(LintFinding::DuplicateInline {
+                        policy: a.reference.clone(),
+                        linked: b.reference.clone(),
+                    });
+                    continue;
+                }
+
+                // Already reported as a duplicate the other way around.
+                if b.reference.inline && !a.reference.inline && PolicyDiff::between(a.policy, b.policy).is_empty() {
+                    continue;
+                }
+
+                if !subsumes(b, a) {
+                    continue;
+                }
+
+                if a.policy.effect == b.policy.effect {
+                    // Of two equivalent policies, only the latter is redundant.
+                    if j < i || !subsumes(a, b) {
+                        findings.push(LintFinding::Redundant {
+                            policy: a.reference.clone(),
+                            subsumed_by: b.reference.clone(),
+                        });
+                    }
+                } else if a.policy.effect == PolicyEffect::Allow && deny_wins(algorithm, j, i) {
+                    findings.push(LintFinding::Shadowed {
+                        policy: a.reference.clone(),
+                        denied_by: b.reference.clone(),
+                    });
+                }
+            }
+        }
+
+        findings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::identity::group::Group;
+    use crate::identity::identity::Identity;
+    use crate::lint::{LintFinding, Linter};
+    use crate::policy::combining_algorithm::CombiningAlgorithm;
+    use crate::policy::policy::CompletePolicy;
+    use crate::policy::policy_set::{PolicyLink, PolicySetTrait};
+    use crate::policy::validity::Validity;
+    use crate::policy::{PolicyEffect, PolicyVersion};
+
+    fn policy(id: &str, effect: PolicyEffect, actions: Vec<&str>, resources: Vec<&str>) -> CompletePolicy {
+        CompletePolicy::new(String::from(id), PolicyVersion::Version1, effect, actions, resources).unwrap()
+    }
+
+    fn kinds(findings: &[LintFinding]) -> Vec<(&'static str, String)> {
+        findings.iter().map(|f| (f.kind(), f.policy().id.clone())).collect()
+    }
+
+    #[test]
+    fn linter_should_report_redundant_and_shadowed_policies() {
+        let identity = Identity::new("alice", Option::None)
+            .add_policy(policy("ReadAll", PolicyEffect::Allow, vec!["storage:Get*"], vec!["urn:bucket:*"]))
+            .add_policy(policy("ReadReports", PolicyEffect::Allow, vec!["storage:GetObject"], vec!["urn:bucket:reports:*"]))
+            .add_policy(policy("Deploy", PolicyEffect::Allow, vec!["deploy:staging"], vec![]));
+        let group = Group::new("contractors", Option::None)
+            .add_policy(policy("NoDeploy", PolicyEffect::Deny, vec!["deploy:*"], vec![]));
+
+        let findings = Linter::default().lint_identity(&identity, &[group], CombiningAlgorithm::default());
+        assert_eq!(
+            kinds(&findings),
+            vec![
+                ("redundant", String::from("ReadReports")),
+                ("shadowed", String::from("Deploy")),
+            ]
+        );
+        assert_eq!(
+            findings[1].to_string(),
+            r#"policy "Deploy" of identity "alice" is shadowed: policy "NoDeploy" of group "contractors" denies everything it allows"#
+        );
+    }
+
+    #[test]
+    fn shadowing_should_depend_on_the_combining_algorithm() {
+        let identity = Identity::new("alice", Option::None)
+            .add_policy(policy("Deploy", PolicyEffect::Allow, vec!["deploy:staging"], vec![]));
+        let group = Group::new("contractors", Option::None)
+            .add_policy(policy("NoDeploy", PolicyEffect::Deny, vec!["deploy:*"], vec![]));
+
+        let shadowed = |identity: &Identity, group: &Group, algorithm| {
+            kinds(&Linter::default().lint_identity(identity, std::slice::from_ref(group), algorithm))
+                .into_iter()
+                .filter(|(kind, _)| *kind == "shadowed")
+                .map(|(_, id)| id)
+                .collect::<Vec<String>>()
+        };
+
+        assert_eq!(shadowed(&identity, &group, CombiningAlgorithm::DenyOverrides), vec![String::from("Deploy")]);
+        assert!(shadowed(&identity, &group, CombiningAlgorithm::FirstApplicable).is_empty());
+        assert!(shadowed(&identity, &group, CombiningAlgorithm::PermitOverrides).is_empty());
+        assert!(shadowed(&identity, &group, CombiningAlgorithm::DenyUnlessPermit).is_empty());
+
+        // The deny is evaluated first: it applies before the allow does.
+        let identity = Identity::new("bob", Option::None)
+            .add_policy(policy("NoDeploy", PolicyEffect::Deny, vec!["deploy:*"], vec![]));
+        let group = Group::new("developers", Option::None)
+            .add_policy(policy("Deploy", PolicyEffect::Allow, vec!["deploy:staging"], vec![]));
+
+        assert_eq!(shadowed(&identity, &group, CombiningAlgorithm::FirstApplicable), vec![String::from("Deploy")]);
+        assert!(shadowed(&identity, &group, CombiningAlgorithm::PermitOverrides).is_empty());
+    }
+
+    #[test]
+    fn policies_without_resources_should_match_every_resource() {
+        let identity = Identity::new("alice", Option::None)
+            .add_policy(policy("Deploy", PolicyEffect::Allow, vec!["deploy:staging"], vec![]))
+            .add_policy(policy("DeployAnywhere", PolicyEffect::Allow, vec!["deploy:staging"], vec!["*"]));
+        let group = Group::new("contractors", Option::None)
+            .add_policy(policy("NoStaging", PolicyEffect::Deny, vec!["deploy:*"], vec!["urn:env:staging"]))
+            .add_policy(policy("ReadStaging", PolicyEffect::Allow, vec!["deploy:staging"], vec!["urn:env:staging"]));
+
+        let findings = Linter::default().lint_identity(&identity, &[group], CombiningAlgorithm::default());
+        assert_eq!(
+            kinds(&findings),
+            vec![
+                ("redundant", String::from("DeployAnywhere")),
+                ("redundant", String::from("ReadStaging")),
+                ("redundant", String::from("ReadStaging")),
+                ("shadowed", String::from("ReadStaging")),
+            ]
+        );
+    }
+
+    #[test]
+    fn linter_should_not_rely_on_time_bounded_links() {
+        let identity = Identity::new("alice", Option::None)
+            .add_policy(policy("Deploy", PolicyEffect::Allow, vec!["deploy:staging"], vec![]))
+            .add_policy_link(
+                policy("Freeze", PolicyEffect::Deny, vec!["deploy:*"], vec![]),
+                PolicyLink::new(0, Validity::new(Option::Some(1), Option::Some(2))),
+            );
+
+        assert!(Linter::default().lint_identity(&identity, &[], CombiningAlgorithm::default()).is_empty());
+    }
+
+    #[test]
+    fn linter_should_report_broad_patterns_and_duplicated_inline_policies() {
+        let linked = policy("Admin", PolicyEffect::Allow, vec!["iam:*", "*"], vec![]);
+        let identity = Identity::new("alice", Option::Some(policy("__inline__", PolicyEffect::Allow, vec!["*", "iam:*"], vec![])))
+            .add_policy(linked);
+
+        let findings = Linter::new(vec!["iam", "kms"]).lint_identity(&identity, &[], CombiningAlgorithm::default());
+        assert_eq!(
+            kinds(&findings),
+            vec![
+                ("broad_pattern", String::from("__inline__")),
+                ("broad_pattern", String::from("__inline__")),
+                ("duplicate_inline", String::from("__inline__")),
+                ("broad_pattern", String::from("Admin")),
+                ("broad_pattern", String::from("Admin")),
+            ]
+        );
+        assert_eq!(
+            findings[1].to_string(),
+            r#"inline policy of identity "alice" allows every action of the sensitive service "iam" through "iam:*""#
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use crate::policy::combining_algorithm::CombiningAlgorithm;
 use crate::policy::policy::{PartialPolicy, ToJson};
 use crate::policy::PolicyEffect;
 use serde_json::{Map, Value};
@@ -11,8 +12,8 @@ pub enum AllowedOutcome {
 
 #[derive(Debug)]
 pub struct AllowedResult {
-    outcome: AllowedOutcome,
-    partials: Vec<PartialPolicy>,
+    pub(super) outcome: AllowedOutcome,
+    pub(super) partials: Vec<PartialPolicy>,
 }
 
 impl AllowedResult {
@@ -51,30 +52,82 @@ impl AllowedResult {
         }
     }
 
+    /// Merges the other result into this one, using the
+    /// default (deny overrides) combining algorithm.
     pub fn merge(&mut self, other: Self) {
-        if other.outcome == AllowedOutcome::Denied {
+        CombiningAlgorithm::DenyOverrides.merge(self, other)
+    }
+
+    /// Restricts this result to what is also allowed by the other one
+    /// (e.g. the result of a permission boundary or a session policy).
+    ///
+    /// If both results are conditional, the allow partials are intersected
+    /// pairwise, while the deny partials of both are retained.
+    pub fn intersect(&mut self, other: Self) {
+        let outcomes = (self.outcome(), other.outcome());
+        if outcomes.0 == AllowedOutcome::Denied || outcomes.1 == AllowedOutcome::Denied {
             self.outcome = AllowedOutcome::Denied;
             self.partials = vec![];
+            return;
         }
 
-        if self.outcome == AllowedOutcome::Denied {
+        if outcomes == (AllowedOutcome::Abstain, AllowedOutcome::Abstain) {
+            let (allows, mut denies): (Vec<PartialPolicy>, Vec<PartialPolicy>) = self
+                .partials
+                .drain(..)
+                .partition(|p| p.effect == PolicyEffect::Allow);
+            let (other_allows, mut other_denies): (Vec<PartialPolicy>, Vec<PartialPolicy>) = other
+                .partials
+                .into_iter()
+                .partition(|p| p.effect == PolicyEffect::Allow);
+
+            let mut partials: Vec<PartialPolicy> = allows
+                .iter()
+                .flat_map(|a| other_allows.iter().filter_map(move |b| a.intersect(b)))
+                .collect();
+
+            if partials.is_empty() {
+                self.outcome = AllowedOutcome::Denied;
+                return;
+            }
+
+            partials.append(&mut denies);
+            partials.append(&mut other_denies);
+            self.partials = partials;
             return;
         }
 
-        if other.outcome == AllowedOutcome::Allowed {
-            self.outcome = AllowedOutcome::Allowed;
+        if outcomes.1 == AllowedOutcome::Abstain {
+            self.outcome = AllowedOutcome::Abstain;
         }
 
         for p in other.partials {
             self.partials.push(p);
         }
+    }
 
-        if self.outcome == AllowedOutcome::Allowed {
-            self.partials = self
-                .partials
-                .drain(..)
-                .filter(|p| p.effect == PolicyEffect::Deny)
-                .collect();
+    /// Restricts the permissions granted by this result to what is also allowed
+    /// by the other one, keeping the denies of this result: a result granting
+    /// nothing is left untouched, and an allow not covered by the other result
+    /// is dropped (ABSTAIN) instead of being turned into a deny.
+    pub fn intersect_grants(&mut self, other: Self) {
+        let grants = self.outcome == AllowedOutcome::Allowed
+            || (self.outcome == AllowedOutcome::Abstain && self.partials.iter().any(|p| p.effect == PolicyEffect::Allow));
+        if !grants {
+            return;
+        }
+
+        let denies: Vec<PartialPolicy> = self
+            .partials
+            .iter()
+            .filter(|p| p.effect == PolicyEffect::Deny)
+            .cloned()
+            .collect();
+
+        self.intersect(other);
+        if self.outcome() == AllowedOutcome::Denied {
+            self.outcome = AllowedOutcome::Abstain;
+            self.partials = denies;
         }
     }
 }
@@ -100,7 +153,7 @@ impl ToJson for AllowedResult {
 #[cfg(test)]
 mod tests {
     use crate::policy::allowed_result::{AllowedOutcome, AllowedResult};
-    use crate::policy::policy::{PartialPolicy, ToJson};
+use crate::policy::policy::{PartialPolicy, ToJson};
     use crate::policy::PolicyEffect;
     use serde_json::{Map, Value};
 
@@ -213,4 +266,79 @@ mod tests {
         assert_eq!(ar.outcome(), AllowedOutcome::Allowed);
         assert_eq!(ar.to_json(), json);
     }
+
+    #[test]
+    fn intersect_should_restrict_the_outcome() {
+        let mut deny = PartialPolicy::default();
+        deny.effect = PolicyEffect::Deny;
+
+        let mut ar = AllowedResult::new(AllowedOutcome::Allowed, vec![deny.clone()]);
+        ar.intersect(AllowedResult::new(AllowedOutcome::Allowed, vec![]));
+        assert_eq!(ar.outcome(), AllowedOutcome::Allowed);
+        assert_eq!(ar.partials.len(), 1);
+
+        ar.intersect(AllowedResult::new(
+            AllowedOutcome::Abstain,
+            vec![PartialPolicy::default()],
+        ));
+        assert_eq!(ar.outcome(), AllowedOutcome::Abstain);
+        assert_eq!(ar.partials.len(), 2);
+
+        ar.intersect(AllowedResult::new(AllowedOutcome::Allowed, vec![]));
+        assert_eq!(ar.outcome(), AllowedOutcome::Abstain);
+
+        ar.intersect(AllowedResult::new(AllowedOutcome::Denied, vec![]));
+        assert_eq!(ar.outcome(), AllowedOutcome::Denied);
+        assert_eq!(ar.partials.len(), 0);
+
+        let mut ar = AllowedResult::new(AllowedOutcome::Allowed, vec![]);
+        ar.intersect(AllowedResult::new(AllowedOutcome::Abstain, vec![]));
+        assert_eq!(ar.outcome(), AllowedOutcome::Denied);
+    }
+
+    #[test]
+    fn intersect_should_intersect_conditional_results() {
+        let partial = |effect: PolicyEffect, resources: Vec<&str>| {
+            let mut p = PartialPolicy::default();
+            p.effect = effect;
+            p.resources = Option::Some(resources.into_iter().map(String::from).collect());
+            p
+        };
+
+        let mut ar = AllowedResult::new(
+            AllowedOutcome::Abstain,
+            vec![
+                partial(PolicyEffect::Allow, vec!["urn:resource:*"]),
+                partial(PolicyEffect::Deny, vec!["urn:resource:secret"]),
+            ],
+        );
+
+        ar.intersect(AllowedResult::new(
+            AllowedOutcome::Abstain,
+            vec![
+                partial(PolicyEffect::Allow, vec!["urn:resource:public:*", "urn:other:*"]),
+                partial(PolicyEffect::Allow, vec!["urn:another:*"]),
+            ],
+        ));
+
+        let mut json = Map::new();
+        json.insert(String::from("outcome"), Value::from("ABSTAIN"));
+        json.insert(
+            String::from("partials"),
+            Value::from(vec![
+                partial(PolicyEffect::Allow, vec!["urn:resource:public:*"]),
+                partial(PolicyEffect::Deny, vec!["urn:resource:secret"]),
+            ]),
+        );
+
+        assert_eq!(ar.outcome(), AllowedOutcome::Abstain);
+        assert_eq!(ar.to_json(), json);
+
+        ar.intersect(AllowedResult::new(
+            AllowedOutcome::Abstain,
+            vec![partial(PolicyEffect::Allow, vec!["urn:another:*"])],
+        ));
+        assert_eq!(ar.outcome(), AllowedOutcome::Denied);
+        assert_eq!(ar.partials.len(), 0);
+    }
 }
@@ -0,0 +1,291 @@
+use crate::err::Error;
+use crate::policy::allowed_result::{AllowedOutcome, AllowedResult};
+use crate::policy::PolicyEffect;
+use std::convert::TryFrom;
+
+/// The strategy used to combine the results of the single policies
+/// (and of the subjects an identity inherits policies from)
+/// into the final decision.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CombiningAlgorithm {
+    /// A matching deny policy always wins. Conditional results are returned
+    /// as ABSTAIN along with the partial policies (default).
+    DenyOverrides,
+
+    /// A matching allow policy always wins. Deny partials are discarded
+    /// as they could not override an allow.
+    PermitOverrides,
+
+    /// The first fully matching policy (in the evaluation order) wins.
+    /// Partial policies evaluated before it are returned as conditions.
+    FirstApplicable,
+
+    /// Access is allowed only if an allow policy fully matches, otherwise
+    /// it is denied: this algorithm never returns ABSTAIN.
+    DenyUnlessPermit,
+}
+
+impl Default for CombiningAlgorithm {
+    fn default() -> Self {
+        CombiningAlgorithm::DenyOverrides
+    }
+}
+
+impl TryFrom<&str> for CombiningAlgorithm {
+    type Error = Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "deny-overrides" => Ok(CombiningAlgorithm::DenyOverrides),
+            "permit-overrides" => Ok(CombiningAlgorithm::PermitOverrides),
+            "first-applicable" => Ok(CombiningAlgorithm::FirstApplicable),
+            "deny-unless-permit" => Ok(CombiningAlgorithm::DenyUnlessPermit),
+            _ => Err(Error::unknown_combining_algorithm(value)),
+        }
+    }
+}
+
+impl CombiningAlgorithm {
+    /// Reads the default algorithm from the COMBINING_ALGORITHM env var.
+    /// Deny overrides is returned if the variable is not set or empty.
+    pub fn from_env() -> Result<Self, Error> {
+        let name = std::env::var("COMBINING_ALGORITHM").unwrap_or_default();
+        if name.is_empty() {
+            return Ok(Self::default());
+        }
+
+        Self::try_from(name.as_str())
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CombiningAlgorithm::DenyOverrides => "deny-overrides",
+            CombiningAlgorithm::PermitOverrides => "permit-overrides",
+            CombiningAlgorithm::FirstApplicable => "first-applicable",
+            CombiningAlgorithm::DenyUnlessPermit => "deny-unless-permit",
+        }
+    }
+
+    /// Combines the next result (in evaluation order) into the given one.
+    pub fn merge(&self, result: &mut AllowedResult, other: AllowedResult) {
+        match self {
+            CombiningAlgorithm::DenyOverrides => deny_overrides(result, other),
+            CombiningAlgorithm::PermitOverrides => permit_overrides(result, other),
+            CombiningAlgorithm::FirstApplicable => first_applicable(result, other),
+            CombiningAlgorithm::DenyUnlessPermit => deny_unless_permit(result, other),
+        }
+    }
+
+    /// Whether the given result cannot be changed anymore by
+    /// merging other results: further evaluation can be skipped.
+    pub fn is_final(&self, result: &AllowedResult) -> bool {
+        match self {
+            CombiningAlgorithm::DenyOverrides => result.outcome == AllowedOutcome::Denied,
+            CombiningAlgorithm::PermitOverrides | CombiningAlgorithm::DenyUnlessPermit => {
+                result.outcome == AllowedOutcome::Allowed
+            }
+            CombiningAlgorithm::FirstApplicable => result.outcome != AllowedOutcome::Abstain,
+        }
+    }
+}
+
+fn deny_overrides(result: &mut AllowedResult, other: AllowedResult) {
+    if other.outcome == AllowedOutcome::Denied {
+        result.outcome = AllowedOutcome::Denied;
+        result.partials = vec![];
+    }
+
+    if result.outcome == AllowedOutcome::Denied {
+        return;
+    }
+
+    if other.outcome == AllowedOutcome::Allowed {
+        result.outcome = AllowedOutcome::Allowed;
+    }
+
+    result.partials.extend(other.partials);
+    if result.outcome == AllowedOutcome::Allowed {
+        result.partials.retain(|p| p.effect == PolicyEffect::Deny);
+    }
+}
+
+fn permit_overrides(result: &mut AllowedResult, other: AllowedResult) {
+    if result.outcome == AllowedOutcome::Allowed || other.outcome == AllowedOutcome::Allowed {
+        result.outcome = AllowedOutcome::Allowed;
+        result.partials = vec![];
+        return;
+    }
+
+    let denied = result.outcome == AllowedOutcome::Denied || other.outcome == AllowedOutcome::Denied;
+    result.partials.extend(other.partials);
+    result.partials.retain(|p| p.effect == PolicyEffect::Allow);
+
+    result.outcome = if denied && result.partials.is_empty() {
+        AllowedOutcome::Denied
+    } else {
+        AllowedOutcome::Abstain
+    };
+}
+
+fn first_applicable(result: &mut AllowedResult, other: AllowedResult) {
+    if result.outcome != AllowedOutcome::Abstain {
+        return;
+    }
+
+    match other.outcome {
+        AllowedOutcome::Allowed => {
+            result.outcome = AllowedOutcome::Allowed;
+            result.partials.retain(|p| p.effect == PolicyEffect::Deny);
+            result.partials.extend(other.partials);
+        }
+        AllowedOutcome::Denied => {
+            if !result.partials.iter().any(|p| p.effect == PolicyEffect::Allow) {
+                result.outcome = AllowedOutcome::Denied;
+                result.partials = vec![];
+            }
+        }
+        AllowedOutcome::Abstain => result.partials.extend(other.partials),
+    }
+}
+
+fn deny_unless_permit(result: &mut AllowedResult, other: AllowedResult) {
+    result.outcome = if result.outcome == AllowedOutcome::Allowed || other.outcome == AllowedOutcome::Allowed {
+        AllowedOutcome::Allowed
+    } else {
+        AllowedOutcome::Denied
+    };
+
+    result.partials = vec![];
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::identity::role::allowed_with;
+    use crate::policy::allowed_result::AllowedOutcome;
+    use crate::policy::combining_algorithm::CombiningAlgorithm;
+    use crate::policy::condition::Attributes;
+    use crate::policy::policy::CompletePolicy;
+    use crate::policy::{PolicyEffect, PolicyVersion};
+    use crate::zephir_policy;
+    use std::convert::TryFrom;
+
+    const ACTION: &str = "test:GetResource";
+
+    fn policy(case: &str) -> CompletePolicy {
+        let (effect, resources) = match case {
+            "A" => (PolicyEffect::Allow, vec!["*"]),
+            "D" => (PolicyEffect::Deny, vec!["*"]),
+            "pA" => (PolicyEffect::Allow, vec!["urn:resource:allowed"]),
+            "pD" => (PolicyEffect::Deny, vec!["urn:resource:denied"]),
+            _ => unreachable!(),
+        };
+
+        zephir_policy!(case, PolicyVersion::Version1, effect, vec![ACTION], resources).unwrap()
+    }
+
+    /// Evaluates the policies in order (A: allow, D: deny, pA/pD: partially matching)
+    /// checking the outcome and the number of returned partials.
+    fn assert_matrix(algorithm: CombiningAlgorithm, matrix: Vec<(Vec<&str>, AllowedOutcome, usize)>) {
+        for (cases, outcome, partials) in matrix {
+            let policies: Vec<CompletePolicy> = cases.iter().map(|c| policy(c)).collect();
+            let result = allowed_with(policies.iter(), Option::Some(ACTION), Option::None::<String>, algorithm, &Attributes::new());
+
+            assert_eq!(result.outcome(), outcome, "{:?} with {:?}", algorithm, cases);
+            assert_eq!(result.get_partials().len(), partials, "{:?} with {:?}", algorithm, cases);
+        }
+    }
+
+    #[test]
+    fn algorithms_could_be_parsed() {
+        for algorithm in vec![
+            CombiningAlgorithm::DenyOverrides,
+            CombiningAlgorithm::PermitOverrides,
+            CombiningAlgorithm::FirstApplicable,
+            CombiningAlgorithm::DenyUnlessPermit,
+        ] {
+            assert_eq!(CombiningAlgorithm::try_from(algorithm.name()).unwrap(), algorithm);
+        }
+
+        assert!(CombiningAlgorithm::try_from("only-one-applicable").is_err());
+    }
+
+    #[test]
+    fn deny_overrides_matrix() {
+        assert_matrix(
+            CombiningAlgorithm::DenyOverrides,
+            vec![
+                (vec![], AllowedOutcome::Denied, 0),
+                (vec!["A"], AllowedOutcome::Allowed, 0),
+                (vec!["D"], AllowedOutcome::Denied, 0),
+                (vec!["A", "D"], AllowedOutcome::Denied, 0),
+                (vec!["D", "A"], AllowedOutcome::Denied, 0),
+                (vec!["pA"], AllowedOutcome::Abstain, 1),
+                (vec!["pA", "D"], AllowedOutcome::Denied, 0),
+                (vec!["D", "pA"], AllowedOutcome::Denied, 0),
+                (vec!["pD", "A"], AllowedOutcome::Allowed, 1),
+                (vec!["A", "pD"], AllowedOutcome::Allowed, 1),
+                (vec!["pA", "pD"], AllowedOutcome::Abstain, 2),
+            ],
+        );
+    }
+
+    #[test]
+    fn permit_overrides_matrix() {
+        assert_matrix(
+            CombiningAlgorithm::PermitOverrides,
+            vec![
+                (vec![], AllowedOutcome::Denied, 0),
+                (vec!["A"], AllowedOutcome::Allowed, 0),
+                (vec!["D"], AllowedOutcome::Denied, 0),
+                (vec!["A", "D"], AllowedOutcome::Allowed, 0),
+                (vec!["D", "A"], AllowedOutcome::Allowed, 0),
+                (vec!["pA"], AllowedOutcome::Abstain, 1),
+                (vec!["pA", "D"], AllowedOutcome::Abstain, 1),
+                (vec!["D", "pA"], AllowedOutcome::Abstain, 1),
+                (vec!["pD", "A"], AllowedOutcome::Allowed, 0),
+                (vec!["A", "pD"], AllowedOutcome::Allowed, 0),
+                (vec!["pA", "pD"], AllowedOutcome::Abstain, 1),
+            ],
+        );
+    }
+
+    #[test]
+    fn first_applicable_matrix() {
+        assert_matrix(
+            CombiningAlgorithm::FirstApplicable,
+            vec![
+                (vec![], AllowedOutcome::Denied, 0),
+                (vec!["A"], AllowedOutcome::Allowed, 0),
+                (vec!["D"], AllowedOutcome::Denied, 0),
+                (vec!["A", "D"], AllowedOutcome::Allowed, 0),
+                (vec!["D", "A"], AllowedOutcome::Denied, 0),
+                (vec!["pA"], AllowedOutcome::Abstain, 1),
+                (vec!["pA", "D"], AllowedOutcome::Abstain, 1),
+                (vec!["D", "pA"], AllowedOutcome::Denied, 0),
+                (vec!["pD", "A"], AllowedOutcome::Allowed, 1),
+                (vec!["A", "pD"], AllowedOutcome::Allowed, 0),
+                (vec!["pA", "pD"], AllowedOutcome::Abstain, 2),
+            ],
+        );
+    }
+
+    #[test]
+    fn deny_unless_permit_matrix() {
+        assert_matrix(
+            CombiningAlgorithm::DenyUnlessPermit,
+            vec![
+                (vec![], AllowedOutcome::Denied, 0),
+                (vec!["A"], AllowedOutcome::Allowed, 0),
+                (vec!["D"], AllowedOutcome::Denied, 0),
+                (vec!["A", "D"], AllowedOutcome::Allowed, 0),
+                (vec!["D", "A"], AllowedOutcome::Allowed, 0),
+                (vec!["pA"], AllowedOutcome::Denied, 0),
+                (vec!["pA", "D"], AllowedOutcome::Denied, 0),
+                (vec!["D", "pA"], AllowedOutcome::Denied, 0),
+                (vec!["pD", "A"], AllowedOutcome::Allowed, 0),
+                (vec!["A", "pD"], AllowedOutcome::Allowed, 0),
+                (vec!["pA", "pD"], AllowedOutcome::Denied, 0),
+            ],
+        );
+    }
+}
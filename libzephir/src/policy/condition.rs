@@ -0,0 +1,128 @@
+use crate::utils::glob_to_regex;
+use pcre2::bytes::Regex;
+use serde_json::{Map, Value};
+
+/// Arbitrary typed attributes of a subject (department, clearance, ...).
+pub type Attributes = Map<String, Value>;
+
+#[derive(Clone, Debug)]
+struct AttributeCondition {
+    name: String,
+    expected: Value,
+    globs: Vec<Regex>,
+}
+
+/// Conditions on the attributes of the subject a request is evaluated for.
+///
+/// Every listed attribute must match. String values are globs matched
+/// against the whole attribute value (numbers and booleans are matched
+/// through their string representation), arrays list the accepted
+/// alternatives and any other value must be equal to the attribute.
+/// Multi-valued (array) attributes match if any of their values does.
+#[derive(Clone, Debug, Default)]
+pub struct SubjectConditions {
+    conditions: Vec<AttributeCondition>,
+}
+
+impl SubjectConditions {
+    pub fn new(attributes: Map<String, Value>) -> Self {
+        let conditions = attributes
+            .into_iter()
+            .map(|(name, expected)| {
+                let globs = match &expected {
+                    Value::String(glob) => vec![glob_to_regex::anchored(glob)],
+                    Value::Array(values) => values
+                        .iter()
+                        .filter_map(|v| v.as_str())
+                        .map(glob_to_regex::anchored)
+                        .collect(),
+                    _ => vec![],
+                };
+
+                AttributeCondition { name, expected, globs }
+            })
+            .collect();
+
+        SubjectConditions { conditions }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.conditions.is_empty()
+    }
+
+    /// Checks the given subject attributes against the conditions.
+    pub fn matches(&self, attributes: &Attributes) -> bool {
+        self.conditions.iter().all(|c| match attributes.get(&c.name) {
+            Option::None | Option::Some(Value::Null) => false,
+            Option::Some(Value::Array(values)) => values.iter().any(|v| c.matches(v)),
+            Option::Some(value) => c.matches(value),
+        })
+    }
+
+    pub fn to_value(&self) -> Value {
+        Value::Object(
+            self.conditions
+                .iter()
+                .map(|c| (c.name.clone(), c.expected.clone()))
+                .collect(),
+        )
+    }
+}
+
+impl AttributeCondition {
+    fn matches(&self, value: &Value) -> bool {
+        let as_string = match value {
+            Value::String(s) => Option::Some(s.clone()),
+            Value::Number(n) => Option::Some(n.to_string()),
+            Value::Bool(b) => Option::Some(b.to_string()),
+            _ => Option::None,
+        };
+
+        if let Some(s) = as_string {
+            if self.globs.iter().any(|g| g.is_match(s.as_bytes()).unwrap_or(false)) {
+                return true;
+            }
+        }
+
+        match &self.expected {
+            Value::String(_) => false,
+            Value::Array(alternatives) => alternatives.iter().any(|a| !a.is_string() && a == value),
+            expected => expected == value,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::policy::condition::{Attributes, SubjectConditions};
+    use serde_json::{json, Value};
+
+    fn attributes(value: Value) -> Attributes {
+        value.as_object().unwrap().clone()
+    }
+
+    #[test]
+    fn conditions_should_match_values_and_globs() {
+        let conditions = SubjectConditions::new(attributes(json!({
+            "department": "fin*",
+            "clearance": [3, 4, "5"],
+            "contractor": false,
+        })));
+
+        assert!(conditions.matches(&attributes(json!({ "department": "finance", "clearance": 3, "contractor": false }))));
+        assert!(conditions.matches(&attributes(json!({ "department": "fintech", "clearance": 5, "contractor": false }))));
+        assert!(!conditions.matches(&attributes(json!({ "department": "it-finance", "clearance": 3, "contractor": false }))));
+        assert!(!conditions.matches(&attributes(json!({ "department": "finance", "clearance": 2, "contractor": false }))));
+        assert!(!conditions.matches(&attributes(json!({ "department": "finance", "clearance": 3, "contractor": true }))));
+        assert!(!conditions.matches(&attributes(json!({ "department": "finance", "clearance": 3 }))));
+    }
+
+    #[test]
+    fn multi_valued_attributes_should_match_any_value() {
+        let conditions = SubjectConditions::new(attributes(json!({ "tenant": "acme" })));
+
+        assert!(conditions.matches(&attributes(json!({ "tenant": ["globex", "acme"] }))));
+        assert!(!conditions.matches(&attributes(json!({ "tenant": ["globex"] }))));
+        assert_eq!(conditions.to_value(), json!({ "tenant": "acme" }));
+    }
+}
@@ -0,0 +1,296 @@
+use crate::policy::policy::{CompletePolicy, MatchablePolicy, ToJson};
+use crate::policy::PolicyEffect;
+use crate::utils::glob_to_regex;
+use serde_json::{Map, Value};
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+/// A removed pattern along with the added one which
+/// matches a superset (or a subset) of what it matched.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PatternChange {
+    pub from: String,
+    pub to: String,
+}
+
+impl ToJson for PatternChange {
+    fn to_json(&self) -> Map<String, Value> {
+        let mut map = Map::new();
+        map.insert(String::from("from"), Value::from(self.from.as_str()));
+        map.insert(String::from("to"), Value::from(self.to.as_str()));
+
+        map
+    }
+}
+
+/// The differences between two sets of action (or resource) patterns.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PatternsDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+
+    /// Removed patterns included into an added one.
+    pub broadened: Vec<PatternChange>,
+
+    /// Removed patterns including an added one.
+    pub narrowed: Vec<PatternChange>,
+}
+
+impl PatternsDiff {
+    /// Compares two sets of patterns.
+    ///
+    /// Containment is checked as the policy intersection does (see
+    /// `glob_to_regex::includes`): a pair of patterns which is not
+    /// reported as broadened or narrowed could still be related.
+    pub fn between(from: &[String], to: &[String]) -> Self {
+        let added: Vec<String> = to.iter().filter(|p| !from.contains(p)).cloned().collect();
+        let removed: Vec<String> = from.iter().filter(|p| !to.contains(p)).cloned().collect();
+
+        let mut broadened = vec![];
+        let mut narrowed = vec![];
+        for old in &removed {
+            for new in &added {
+                let change = PatternChange {
+                    from: old.clone(),
+                    to: new.clone(),
+                };
+
+                match (glob_to_regex::includes(new, old), glob_to_regex::includes(old, new)) {
+                    (true, false) => broadened.push(change),
+                    (false, true) => narrowed.push(change),
+                    _ => (),
+                }
+            }
+        }
+
+        PatternsDiff {
+            added,
+            removed,
+            broadened,
+            narrowed,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+impl ToJson for PatternsDiff {
+    fn to_json(&self) -> Map<String, Value> {
+        let changes = |changes: &[PatternChange]| -> Value {
+            Value::Array(changes.iter().map(|c| Value::Object(c.to_json())).collect())
+        };
+
+        let mut map = Map::new();
+        map.insert(String::from("added"), Value::from(self.added.clone()));
+        map.insert(String::from("removed"), Value::from(self.removed.clone()));
+        map.insert(String::from("broadened"), changes(&self.broadened));
+        map.insert(String::from("narrowed"), changes(&self.narrowed));
+
+        map
+    }
+}
+
+/// The semantic differences between two versions of a policy: unlike a
+/// textual diff, reordering the patterns is not a change, and the patterns
+/// replaced by broader or narrower ones are reported as such.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PolicyDiff {
+    pub id: String,
+
+    /// The old and new versions, if changed.
+    pub version: Option<(i32, i32)>,
+
+    /// The old and new effects, if changed.
+    pub effect: Option<(PolicyEffect, PolicyEffect)>,
+    pub actions: PatternsDiff,
+    pub resources: PatternsDiff,
+
+    /// The old and new subject conditions (null if none), if changed.
+    pub subject: Option<(Value, Value)>,
+}
+
+fn subject_of(policy: &CompletePolicy) -> Value {
+    policy
+        .get_subject_conditions()
+        .map_or(Value::Null, |c| c.to_value())
+}
+
+impl PolicyDiff {
+    pub fn between(from: &CompletePolicy, to: &CompletePolicy) -> Self {
+        let versions: (i32, i32) = ((&from.version).into(), (&to.version).into());
+        let subjects = (subject_of(from), subject_of(to));
+
+        PolicyDiff {
+            id: to.id.clone(),
+            version: Option::Some(versions).filter(|(a, b)| a != b),
+            effect: Option::Some((from.effect, to.effect)).filter(|(a, b)| a != b),
+            actions: PatternsDiff::between(from.get_actions(), to.get_actions()),
+            resources: PatternsDiff::between(from.get_resources(), to.get_resources()),
+            subject: Option::Some(subjects).filter(|(a, b)| a != b),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.version.is_none()
+            && self.effect.is_none()
+            && self.actions.is_empty()
+            && self.resources.is_empty()
+            && self.subject.is_none()
+    }
+}
+
+fn change_to_value(from: Value, to: Value) -> Value {
+    let mut map = Map::new();
+    map.insert(String::from("from"), from);
+    map.insert(String::from("to"), to);
+
+    Value::Object(map)
+}
+
+impl ToJson for PolicyDiff {
+    fn to_json(&self) -> Map<String, Value> {
+        let mut map = Map::new();
+        map.insert(String::from("id"), Value::from(self.id.as_str()));
+        map.insert(String::from("changed"), Value::from(!self.is_empty()));
+        map.insert(
+            String::from("version"),
+            self.version.map_or(Value::Null, |(a, b)| change_to_value(Value::from(a), Value::from(b))),
+        );
+        map.insert(
+            String::from("effect"),
+            self.effect.map_or(Value::Null, |(a, b)| change_to_value(Value::from(&a), Value::from(&b))),
+        );
+        map.insert(String::from("actions"), Value::Object(self.actions.to_json()));
+        map.insert(String::from("resources"), Value::Object(self.resources.to_json()));
+        map.insert(
+            String::from("subject"),
+            self.subject.clone().map_or(Value::Null, |(a, b)| change_to_value(a, b)),
+        );
+
+        map
+    }
+}
+
+fn fmt_patterns(f: &mut Formatter<'_>, name: &str, diff: &PatternsDiff) -> fmt::Result {
+    if diff.is_empty() {
+        return Ok(());
+    }
+
+    writeln!(f, "  {}:", name)?;
+    for pattern in &diff.added {
+        writeln!(f, "    + {}", pattern)?;
+    }
+    for pattern in &diff.removed {
+        writeln!(f, "    - {}", pattern)?;
+    }
+    for change in &diff.broadened {
+        writeln!(f, "    broader: {} -> {}", change.from, change.to)?;
+    }
+    for change in &diff.narrowed {
+        writeln!(f, "    narrower: {} -> {}", change.from, change.to)?;
+    }
+
+    Ok(())
+}
+
+fn effect_name(effect: PolicyEffect) -> &'static str {
+    match effect {
+        PolicyEffect::Allow => "ALLOW",
+        PolicyEffect::Deny => "DENY",
+    }
+}
+
+/// Renders the diff for humans, one change per line.
+impl Display for PolicyDiff {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "policy {}", self.id)?;
+        if self.is_empty() {
+            return writeln!(f, "  no changes");
+        }
+
+        if let Some((from, to)) = self.version {
+            writeln!(f, "  version: {} -> {}", from, to)?;
+        }
+        if let Some((from, to)) = self.effect {
+            writeln!(f, "  effect: {} -> {}", effect_name(from), effect_name(to))?;
+        }
+
+        fmt_patterns(f, "actions", &self.actions)?;
+        fmt_patterns(f, "resources", &self.resources)?;
+
+        if let Some((from, to)) = &self.subject {
+            writeln!(f, "  subject: {} -> {}", from, to)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::policy::diff::{PatternChange, PatternsDiff, PolicyDiff};
+    use crate::policy::policy::CompletePolicy;
+    use crate::policy::{PolicyEffect, PolicyVersion};
+
+    fn policy(effect: PolicyEffect, actions: Vec<&str>, resources: Vec<&str>) -> CompletePolicy {
+        CompletePolicy::new(String::from("TestPolicy"), PolicyVersion::Version1, effect, actions, resources).unwrap()
+    }
+
+    fn strings(values: Vec<&str>) -> Vec<String> {
+        values.into_iter().map(String::from).collect()
+    }
+
+    #[test]
+    fn patterns_diff_should_detect_broader_and_narrower_patterns() {
+        let diff = PatternsDiff::between(
+            &strings(vec!["get_item", "urn:resource:*", "list_*"]),
+            &strings(vec!["list_*", "get_*", "urn:resource:foo:*"]),
+        );
+
+        assert_eq!(diff.added, strings(vec!["get_*", "urn:resource:foo:*"]));
+        assert_eq!(diff.removed, strings(vec!["get_item", "urn:resource:*"]));
+        assert_eq!(
+            diff.broadened,
+            vec![PatternChange {
+                from: String::from("get_item"),
+                to: String::from("get_*")
+            }]
+        );
+        assert_eq!(
+            diff.narrowed,
+            vec![PatternChange {
+                from: String::from("urn:resource:*"),
+                to: String::from("urn:resource:foo:*")
+            }]
+        );
+    }
+
+    #[test]
+    fn policy_diff_should_ignore_the_order_of_the_patterns() {
+        let from = policy(PolicyEffect::Allow, vec!["get_*", "list_*"], vec!["urn:a", "urn:b"]);
+        let to = policy(PolicyEffect::Allow, vec!["list_*", "get_*"], vec!["urn:b", "urn:a"]);
+
+        let diff = PolicyDiff::between(&from, &to);
+        assert!(diff.is_empty());
+        assert_eq!(diff.to_string(), "policy TestPolicy\n  no changes\n");
+    }
+
+    #[test]
+    fn policy_diff_should_report_the_changes() {
+        let from = policy(PolicyEffect::Allow, vec!["get_*"], vec!["urn:resource:*"]);
+        let to = policy(PolicyEffect::Deny, vec!["get_*", "put_*"], vec![]);
+
+        let diff = PolicyDiff::between(&from, &to);
+        assert!(!diff.is_empty());
+        assert_eq!(diff.version, Option::None);
+        assert_eq!(diff.effect, Option::Some((PolicyEffect::Allow, PolicyEffect::Deny)));
+        assert_eq!(diff.actions.added, strings(vec!["put_*"]));
+        assert_eq!(diff.resources.broadened.len(), 1);
+        assert_eq!(
+            diff.to_string(),
+            "policy TestPolicy\n  effect: ALLOW -> DENY\n  actions:\n    + put_*\n  resources:\n    + *\n    - urn:resource:*\n    broader: urn:resource:* -> *\n"
+        );
+    }
+}
@@ -0,0 +1,130 @@
+use crate::identity::role::allowed_with;
+use crate::policy::allowed_result::AllowedResult;
+use crate::policy::combining_algorithm::CombiningAlgorithm;
+use crate::policy::condition::Attributes;
+use crate::policy::policy::CompletePolicy;
+use crate::policy::{PolicyEffect, PolicyVersion};
+use std::fmt::{Debug, Display};
+
+/// Organization-level policies applied to every subject, regardless
+/// of the policies linked to it.
+///
+/// Deny guardrails deny the matching requests outright, while allow
+/// guardrails (if any) restrict the set of the requests which could
+/// be allowed by the subject policies.
+#[derive(Clone, Debug, Default)]
+pub struct Guardrails {
+    policies: Vec<CompletePolicy>,
+}
+
+impl Guardrails {
+    pub fn new(policies: Vec<CompletePolicy>) -> Self {
+        Guardrails { policies }
+    }
+
+    pub fn get_policies(&self) -> &Vec<CompletePolicy> {
+        &self.policies
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.policies.is_empty()
+    }
+
+    /// Evaluates the guardrails.
+    ///
+    /// The returned result must be intersected with the subject one:
+    /// when no allow guardrail is defined, all the requests
+    /// not denied by a deny guardrail are allowed.
+    pub fn allowed<T, S>(&self, action: Option<T>, resource: Option<S>) -> AllowedResult
+    where
+        T: ToString + Display,
+        S: ToString + Display + Debug,
+    {
+        self.allowed_for(&Attributes::new(), action, resource)
+    }
+
+    /// Evaluates the guardrails for a subject with the given attributes.
+    pub fn allowed_for<T, S>(&self, attributes: &Attributes, action: Option<T>, resource: Option<S>) -> AllowedResult
+    where
+        T: ToString + Display,
+        S: ToString + Display + Debug,
+    {
+        let algorithm = CombiningAlgorithm::default();
+        let restricts = self.policies.iter().any(|p| p.effect == PolicyEffect::Allow);
+        if restricts {
+            return allowed_with(self.policies.iter(), action, resource, algorithm, attributes);
+        }
+
+        let allow_all = CompletePolicy::new(
+            "__guardrail_allow_all__".to_string(),
+            PolicyVersion::Version1,
+            PolicyEffect::Allow,
+            vec!["*"],
+            Vec::<String>::new(),
+        )
+        .unwrap();
+
+        allowed_with(
+            std::iter::once(&allow_all).chain(self.policies.iter()),
+            action,
+            resource,
+            algorithm,
+            attributes,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::policy::allowed_result::AllowedOutcome;
+    use crate::policy::guardrails::Guardrails;
+    use crate::policy::{PolicyEffect, PolicyVersion};
+    use crate::zephir_policy;
+
+    #[test]
+    fn empty_guardrails_should_allow_everything() {
+        let guardrails = Guardrails::default();
+        let result = guardrails.allowed::<&str, String>(Option::Some("core:GetVersion"), Option::None);
+
+        assert_eq!(result.outcome(), AllowedOutcome::Allowed);
+    }
+
+    #[test]
+    fn deny_guardrails_should_deny_outright() {
+        let guardrails = Guardrails::new(vec![zephir_policy!(
+            "DenyDelete",
+            PolicyVersion::Version1,
+            PolicyEffect::Deny,
+            vec!["*:Delete*"],
+            vec!["urn:resource:prod:*"]
+        )
+        .unwrap()]);
+
+        let result = guardrails.allowed(Option::Some("test:DeleteResource"), Option::Some("urn:resource:prod:id"));
+        assert_eq!(result.outcome(), AllowedOutcome::Denied);
+
+        let result = guardrails.allowed(Option::Some("test:DeleteResource"), Option::Some("urn:resource:dev:id"));
+        assert_eq!(result.outcome(), AllowedOutcome::Allowed);
+
+        let result = guardrails.allowed::<&str, String>(Option::Some("test:DeleteResource"), Option::None);
+        assert_eq!(result.outcome(), AllowedOutcome::Allowed);
+        assert_eq!(result.get_partials().len(), 1);
+    }
+
+    #[test]
+    fn allow_guardrails_should_restrict_the_allowed_set() {
+        let guardrails = Guardrails::new(vec![zephir_policy!(
+            "AllowTest",
+            PolicyVersion::Version1,
+            PolicyEffect::Allow,
+            vec!["test:*"]
+        )
+        .unwrap()]);
+
+        let result = guardrails.allowed::<&str, String>(Option::Some("test:GetResource"), Option::None);
+        assert_eq!(result.outcome(), AllowedOutcome::Allowed);
+
+        let result = guardrails.allowed::<&str, String>(Option::Some("core:GetVersion"), Option::None);
+        assert_eq!(result.outcome(), AllowedOutcome::Denied);
+    }
+}
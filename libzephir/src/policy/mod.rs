@@ -4,9 +4,15 @@ use serde_json::{Number, Value};
 use std::convert::TryFrom;
 
 pub mod allowed_result;
+pub mod combining_algorithm;
+pub mod condition;
+pub mod diff;
+pub mod guardrails;
 pub mod match_result;
 pub mod policy;
 pub mod policy_set;
+pub mod resource_policy;
+pub mod validity;
 
 /// Get a new policy object
 pub fn policy_new<A, R>(
@@ -1,8 +1,11 @@
 use crate::compiler::compiled_policy::CompiledPolicy;
 use crate::compiler::compiler::Compiler;
 use crate::err::Error;
+use crate::policy::condition::{Attributes, SubjectConditions};
 use crate::policy::match_result::MatchResult;
 use crate::policy::{PolicyEffect, PolicyVersion};
+use crate::tenant::{scoped_id, DEFAULT_TENANT};
+use crate::utils::glob_to_regex;
 use serde_json::{Map, Value};
 use std::fmt::Debug;
 
@@ -54,6 +57,16 @@ pub trait MatchablePolicy: Policy {
         T: ToString,
         S: ToString + Debug;
 
+    /// Calculate if this policy is matching for a subject with the given attributes.
+    /// Policies without subject conditions ignore the attributes.
+    fn matching_subject<T, S>(&self, action: Option<T>, resource: Option<S>, _attributes: &Attributes) -> MatchResult
+    where
+        T: ToString,
+        S: ToString + Debug,
+    {
+        self.matching(action, resource)
+    }
+
     /// Gets the action of the policy.
     fn get_actions(&self) -> &[String];
 
@@ -94,6 +107,56 @@ impl PartialPolicy {
         self.actions = Option::None;
         self.resources = Option::None;
     }
+
+    /// Builds a partial policy matching only what is matched by both
+    /// this and the other partial policy.
+    ///
+    /// # Returns
+    ///
+    /// None if the intersection is empty or cannot be represented
+    pub(crate) fn intersect(&self, other: &PartialPolicy) -> Option<PartialPolicy> {
+        Option::Some(PartialPolicy {
+            version: self.version.clone(),
+            effect: self.effect,
+            actions: intersect_globs(self.actions.as_ref(), other.actions.as_ref())?,
+            resources: intersect_globs(self.resources.as_ref(), other.resources.as_ref())?,
+        })
+    }
+}
+
+/// Intersects two sets of globs, where None means "no restriction".
+/// Only the globs included into a glob of the other set are retained.
+fn intersect_globs(a: Option<&Vec<String>>, b: Option<&Vec<String>>) -> Option<Option<Vec<String>>> {
+    let (a, b) = match (a, b) {
+        (Option::None, Option::None) => return Option::Some(Option::None),
+        (Option::Some(globs), Option::None) | (Option::None, Option::Some(globs)) => {
+            return Option::Some(Option::Some(globs.clone()))
+        }
+        (Option::Some(a), Option::Some(b)) => (a, b),
+    };
+
+    let mut result: Vec<String> = vec![];
+    for outer in a {
+        for inner in b {
+            let glob = if glob_to_regex::includes(outer, inner) {
+                inner
+            } else if glob_to_regex::includes(inner, outer) {
+                outer
+            } else {
+                continue;
+            };
+
+            if !result.contains(glob) {
+                result.push(glob.clone());
+            }
+        }
+    }
+
+    if result.is_empty() {
+        Option::None
+    } else {
+        Option::Some(Option::Some(result))
+    }
 }
 
 impl Into<Value> for PartialPolicy {
@@ -140,6 +203,7 @@ pub struct CompletePolicy {
     pub effect: PolicyEffect,
     actions: Vec<String>,
     resources: Vec<String>,
+    subject_conditions: Option<SubjectConditions>,
 
     compiled_policy: CompiledPolicy,
 }
@@ -153,6 +217,56 @@ impl CompletePolicy {
         actions: Vec<A>,
         resources: Vec<R>,
     ) -> Result<CompletePolicy, Error>
+    where
+        A: ToString,
+        R: ToString,
+    {
+        Self::new_in_tenant(DEFAULT_TENANT, id, version, effect, actions, resources)
+    }
+
+    /// Get a new policy object belonging to the given tenant.
+    /// The tenant only scopes the tracking of the compiled policy.
+    pub fn new_in_tenant<A, R>(
+        tenant: &str,
+        id: String,
+        version: PolicyVersion,
+        effect: PolicyEffect,
+        actions: Vec<A>,
+        resources: Vec<R>,
+    ) -> Result<CompletePolicy, Error>
+    where
+        A: ToString,
+        R: ToString,
+    {
+        let tracking_id = if id.is_empty() { String::new() } else { scoped_id(tenant, &id) };
+        Self::build(tracking_id, id, version, effect, actions, resources)
+    }
+
+    /// Get a new policy object whose compiled version is not tracked.
+    /// Used for transient copies of the policies (e.g. the simulated ones),
+    /// which must not take the place of the stored policies with the same id.
+    pub(crate) fn new_untracked<A, R>(
+        id: String,
+        version: PolicyVersion,
+        effect: PolicyEffect,
+        actions: Vec<A>,
+        resources: Vec<R>,
+    ) -> Result<CompletePolicy, Error>
+    where
+        A: ToString,
+        R: ToString,
+    {
+        Self::build(String::new(), id, version, effect, actions, resources)
+    }
+
+    fn build<A, R>(
+        tracking_id: String,
+        id: String,
+        version: PolicyVersion,
+        effect: PolicyEffect,
+        actions: Vec<A>,
+        resources: Vec<R>,
+    ) -> Result<CompletePolicy, Error>
     where
         A: ToString,
         R: ToString,
@@ -168,7 +282,7 @@ impl CompletePolicy {
         };
 
         let actions: Vec<String> = actions.into_iter().map(|s| s.to_string()).collect();
-        let compiled_policy = Compiler::get_instance().compile(&id, &actions, &resources);
+        let compiled_policy = Compiler::get_instance().compile(&tracking_id, &actions, &resources);
 
         Ok(CompletePolicy {
             id,
@@ -176,9 +290,25 @@ impl CompletePolicy {
             effect,
             actions,
             resources,
+            subject_conditions: Option::None,
             compiled_policy,
         })
     }
+
+    /// Restricts the policy to the subjects whose attributes match the conditions.
+    pub fn with_subject_conditions(mut self, conditions: SubjectConditions) -> Self {
+        self.subject_conditions = if conditions.is_empty() {
+            Option::None
+        } else {
+            Option::Some(conditions)
+        };
+
+        self
+    }
+
+    pub fn get_subject_conditions(&self) -> Option<&SubjectConditions> {
+        self.subject_conditions.as_ref()
+    }
 }
 
 impl Policy for CompletePolicy {
@@ -213,6 +343,10 @@ impl ToJson for CompletePolicy {
             Value::from(self.resources.as_slice()),
         );
 
+        if let Some(conditions) = &self.subject_conditions {
+            result.insert(String::from("subject"), conditions.to_value());
+        }
+
         result
     }
 }
@@ -223,6 +357,14 @@ impl MatchablePolicy for CompletePolicy {
     }
 
     fn matching<T, S>(&self, action: Option<T>, resource: Option<S>) -> MatchResult
+    where
+        T: ToString,
+        S: ToString + Debug,
+    {
+        self.matching_subject(action, resource, &Attributes::new())
+    }
+
+    fn matching_subject<T, S>(&self, action: Option<T>, resource: Option<S>, attributes: &Attributes) -> MatchResult
     where
         T: ToString,
         S: ToString + Debug,
@@ -245,7 +387,10 @@ impl MatchablePolicy for CompletePolicy {
             }
         }
 
-        // @todo: Conditions
+        if let Some(conditions) = &self.subject_conditions {
+            result.update_conditions(conditions.matches(attributes));
+            result._update(self);
+        }
 
         result
     }
@@ -1,4 +1,6 @@
 use crate::policy::policy::{CompletePolicy, Policy};
+use crate::policy::validity::Validity;
+use serde_json::{Map, Value};
 use std::cmp::Ordering;
 use std::slice::Iter;
 
@@ -8,8 +10,9 @@ impl PolicySetHelper {
     pub(crate) fn link_policy(
         policy_set: PolicySet<CompletePolicy>,
         policy: CompletePolicy,
+        link: PolicyLink,
     ) -> PolicySet<CompletePolicy> {
-        policy_set.add_policy(policy)
+        policy_set.add_policy_link(policy, link)
     }
 
     pub(crate) fn unlink_policy<S: ToString>(
@@ -20,9 +23,28 @@ impl PolicySetHelper {
     }
 }
 
-#[derive(Debug)]
+/// The attributes of the link between a subject and a policy.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PolicyLink {
+    /// Lower values are evaluated first.
+    pub priority: i32,
+    pub validity: Validity,
+}
+
+impl PolicyLink {
+    pub fn new(priority: i32, validity: Validity) -> Self {
+        PolicyLink { priority, validity }
+    }
+}
+
+/// An ordered set of policies.
+///
+/// Policies are kept sorted by priority (lower values first), policies
+/// with the same priority are kept in insertion order.
+#[derive(Clone, Debug)]
 pub struct PolicySet<T: Policy> {
     policies: Vec<T>,
+    links: Vec<PolicyLink>,
 }
 
 impl<T: Policy> Default for PolicySet<T> {
@@ -33,7 +55,10 @@ impl<T: Policy> Default for PolicySet<T> {
 
 impl<T: Policy> PolicySet<T> {
     pub fn new() -> Self {
-        PolicySet { policies: vec![] }
+        PolicySet {
+            policies: vec![],
+            links: vec![],
+        }
     }
 
     pub fn len(&self) -> usize {
@@ -44,34 +69,101 @@ impl<T: Policy> PolicySet<T> {
         self.policies.is_empty()
     }
 
-    fn insert_if_missing(policies: &mut Vec<T>, policy: T) {
-        match policies
-            .iter_mut()
-            .find(|ref p| p.id().cmp(policy.id()) == Ordering::Equal)
+    /// Gets the link to the given policy, if present in the set.
+    pub fn get_link<S: ToString>(&self, id: S) -> Option<&PolicyLink> {
+        let id = id.to_string();
+        self.policies
+            .iter()
+            .position(|p| p.id().cmp(&id) == Ordering::Equal)
+            .map(|idx| &self.links[idx])
+    }
+
+    /// Gets the priority of the given policy, if present in the set.
+    pub fn get_priority<S: ToString>(&self, id: S) -> Option<i32> {
+        self.get_link(id).map(|l| l.priority)
+    }
+
+    /// Iterates over the policies whose link is effective at the given time.
+    pub fn active_at(&self, timestamp: i64) -> impl Iterator<Item = &T> {
+        self.policies
+            .iter()
+            .zip(self.links.iter())
+            .filter(move |(_, l)| l.validity.is_active_at(timestamp))
+            .map(|(p, _)| p)
+    }
+
+    /// Serializes the priorities of the policies, keyed by policy id.
+    pub fn priorities_to_value(&self) -> Value {
+        let mut map = Map::new();
+        for (policy, link) in self.policies.iter().zip(self.links.iter()) {
+            map.insert(policy.id().to_string(), Value::from(link.priority));
+        }
+
+        Value::Object(map)
+    }
+
+    /// Serializes the validity of the time-bounded links, keyed by policy id.
+    pub fn validity_to_value(&self) -> Value {
+        let mut map = Map::new();
+        for (policy, link) in self.policies.iter().zip(self.links.iter()) {
+            if link.validity.is_bounded() {
+                map.insert(policy.id().to_string(), link.validity.to_value());
+            }
+        }
+
+        Value::Object(map)
+    }
+
+    fn insert_if_missing(&mut self, policy: T, link: PolicyLink) {
+        if self
+            .policies
+            .iter()
+            .any(|p| p.id().cmp(policy.id()) == Ordering::Equal)
         {
-            Some(_) => { }
-            None => policies.push(policy),
+            return;
         }
+
+        let idx = self
+            .links
+            .iter()
+            .position(|l| l.priority > link.priority)
+            .unwrap_or(self.policies.len());
+
+        self.policies.insert(idx, policy);
+        self.links.insert(idx, link);
     }
 }
 
-pub trait PolicySetTrait<T: Policy> {
-    fn add_policy(self, policy: T) -> Self;
+pub trait PolicySetTrait<T: Policy>: Sized {
+    /// Adds a policy with the default (unbounded, 0 priority) link.
+    fn add_policy(self, policy: T) -> Self {
+        self.add_policy_link(policy, PolicyLink::default())
+    }
+
+    fn add_policy_with_priority(self, policy: T, priority: i32) -> Self {
+        self.add_policy_link(policy, PolicyLink::new(priority, Validity::default()))
+    }
+
+    fn add_policy_link(self, policy: T, link: PolicyLink) -> Self;
     fn remove_policy<S: ToString>(self, id: S) -> Self;
 }
 
 impl<T: Policy> PolicySetTrait<T> for PolicySet<T> {
-    fn add_policy(mut self, policy: T) -> Self {
-        Self::insert_if_missing(self.policies.as_mut(), policy);
+    fn add_policy_link(mut self, policy: T, link: PolicyLink) -> Self {
+        self.insert_if_missing(policy, link);
         self
     }
 
     fn remove_policy<S: ToString>(mut self, id: S) -> Self {
-        self.policies = self
+        let id = id.to_string();
+        if let Some(idx) = self
             .policies
-            .into_iter()
-            .filter(|p| p.id().cmp(&id.to_string()) != Ordering::Equal)
-            .collect();
+            .iter()
+            .position(|p| p.id().cmp(&id) == Ordering::Equal)
+        {
+            self.policies.remove(idx);
+            self.links.remove(idx);
+        }
 
         self
     }
@@ -89,7 +181,8 @@ impl<'a, T: Policy> IntoIterator for &'a PolicySet<T> {
 #[cfg(test)]
 mod tests {
     use crate::policy::policy::CompletePolicy;
-    use crate::policy::policy_set::{PolicySet, PolicySetTrait};
+    use crate::policy::policy_set::{PolicyLink, PolicySet, PolicySetTrait};
+    use crate::policy::validity::Validity;
     use crate::policy::{PolicyEffect, PolicyVersion};
     use crate::zephir_policy;
 
@@ -161,4 +254,40 @@ mod tests {
         assert_eq!(policies.len(), 2);
         assert_eq!(policies, vec!["p1", "p3"]);
     }
+
+    #[test]
+    fn policies_should_be_ordered_by_priority() {
+        let policy = |id: &str| zephir_policy!(id, PolicyVersion::Version1, PolicyEffect::Allow, vec!["action"]).unwrap();
+        let ps: PolicySet<CompletePolicy> = PolicySet::new()
+            .add_policy_with_priority(policy("p1"), 10)
+            .add_policy(policy("p2"))
+            .add_policy_with_priority(policy("p3"), -5)
+            .add_policy_with_priority(policy("p4"), 10)
+            .add_policy_with_priority(policy("p2"), -10);
+
+        let policies: Vec<&str> = (&ps).into_iter().map(|p| p.id.as_str()).collect();
+        assert_eq!(policies, vec!["p3", "p2", "p1", "p4"]);
+        assert_eq!(ps.get_priority("p2"), Option::Some(0));
+        assert_eq!(ps.get_priority("p4"), Option::Some(10));
+
+        let ps = ps.remove_policy("p1");
+        let policies: Vec<&str> = (&ps).into_iter().map(|p| p.id.as_str()).collect();
+        assert_eq!(policies, vec!["p3", "p2", "p4"]);
+        assert_eq!(ps.get_priority("p1"), Option::None);
+    }
+
+    #[test]
+    fn only_effective_links_should_be_active() {
+        let policy = |id: &str| zephir_policy!(id, PolicyVersion::Version1, PolicyEffect::Allow, vec!["action"]).unwrap();
+        let ps: PolicySet<CompletePolicy> = PolicySet::new()
+            .add_policy(policy("p1"))
+            .add_policy_link(policy("p2"), PolicyLink::new(0, Validity::new(Option::None, Option::Some(100))))
+            .add_policy_link(policy("p3"), PolicyLink::new(0, Validity::new(Option::Some(50), Option::None)));
+
+        let active = |ts: i64| ps.active_at(ts).map(|p| p.id.as_str()).collect::<Vec<&str>>();
+        assert_eq!(active(10), vec!["p1", "p2"]);
+        assert_eq!(active(60), vec!["p1", "p2", "p3"]);
+        assert_eq!(active(100), vec!["p1", "p3"]);
+        assert_eq!(ps.validity_to_value().as_object().unwrap().len(), 2);
+    }
 }
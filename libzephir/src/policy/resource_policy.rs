@@ -0,0 +1,229 @@
+use crate::identity::role::allowed_with;
+use crate::policy::allowed_result::AllowedResult;
+use crate::policy::combining_algorithm::CombiningAlgorithm;
+use crate::policy::condition::Attributes;
+use crate::policy::policy::{CompletePolicy, ToJson};
+use crate::utils::glob_to_regex;
+use pcre2::bytes::Regex;
+use serde_json::{Map, Value};
+use std::fmt::{Debug, Display};
+
+/// The principal a request is evaluated for: an identity (along
+/// with the groups it belongs to) or a role assumed in a session.
+#[derive(Clone, Debug, Default)]
+pub struct Principal<'a> {
+    identity: Option<&'a str>,
+    groups: Vec<&'a str>,
+    role: Option<&'a str>,
+}
+
+impl<'a> Principal<'a> {
+    pub fn identity(id: &'a str, groups: Vec<&'a str>) -> Self {
+        Principal {
+            identity: Option::Some(id),
+            groups,
+            role: Option::None,
+        }
+    }
+
+    pub fn role(id: &'a str) -> Self {
+        Principal {
+            identity: Option::None,
+            groups: vec![],
+            role: Option::Some(id),
+        }
+    }
+}
+
+/// Names the principals a resource policy applies to.
+/// Every entry is an id or a glob.
+#[derive(Clone, Debug, Default)]
+pub struct Principals {
+    identities: Vec<String>,
+    groups: Vec<String>,
+    roles: Vec<String>,
+
+    compiled: [Vec<Regex>; 3],
+}
+
+impl Principals {
+    pub fn new(identities: Vec<String>, groups: Vec<String>, roles: Vec<String>) -> Self {
+        let compile = |globs: &[String]| globs.iter().map(|g| glob_to_regex::anchored(g)).collect();
+        let compiled = [compile(&identities), compile(&groups), compile(&roles)];
+
+        Principals {
+            identities,
+            groups,
+            roles,
+            compiled,
+        }
+    }
+
+    pub fn get_identities(&self) -> &Vec<String> {
+        &self.identities
+    }
+
+    pub fn get_groups(&self) -> &Vec<String> {
+        &self.groups
+    }
+
+    pub fn get_roles(&self) -> &Vec<String> {
+        &self.roles
+    }
+
+    /// Whether the given principal (or one of its groups) is named.
+    pub fn matches(&self, principal: &Principal) -> bool {
+        let any_match = |globs: &Vec<Regex>, id: &str| globs.iter().any(|g| g.is_match(id.as_bytes()).unwrap_or(false));
+        let [identities, groups, roles] = &self.compiled;
+
+        matches!(principal.identity, Some(id) if any_match(identities, id))
+            || principal.groups.iter().any(|g| any_match(groups, g))
+            || matches!(principal.role, Some(id) if any_match(roles, id))
+    }
+
+    pub fn to_value(&self) -> Value {
+        let mut map = Map::new();
+        map.insert(String::from("identities"), Value::from(self.identities.clone()));
+        map.insert(String::from("groups"), Value::from(self.groups.clone()));
+        map.insert(String::from("roles"), Value::from(self.roles.clone()));
+
+        Value::Object(map)
+    }
+}
+
+/// A policy attached to the resources matching its resource patterns,
+/// granting (or denying) access to the listed principals.
+#[derive(Clone, Debug)]
+pub struct ResourcePolicy {
+    policy: CompletePolicy,
+    principals: Principals,
+}
+
+impl ResourcePolicy {
+    pub fn new(policy: CompletePolicy, principals: Principals) -> Self {
+        ResourcePolicy { policy, principals }
+    }
+
+    pub fn get_policy(&self) -> &CompletePolicy {
+        &self.policy
+    }
+
+    pub fn get_principals(&self) -> &Principals {
+        &self.principals
+    }
+}
+
+impl ToJson for ResourcePolicy {
+    fn to_json(&self) -> Map<String, Value> {
+        let mut map = self.policy.to_json();
+        map.insert(String::from("principal"), self.principals.to_value());
+
+        map
+    }
+}
+
+/// All the resource policies, evaluated along with the subject policies.
+#[derive(Clone, Debug, Default)]
+pub struct ResourcePolicies {
+    policies: Vec<ResourcePolicy>,
+}
+
+impl ResourcePolicies {
+    pub fn new(policies: Vec<ResourcePolicy>) -> Self {
+        ResourcePolicies { policies }
+    }
+
+    pub fn get_policies(&self) -> &Vec<ResourcePolicy> {
+        &self.policies
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.policies.is_empty()
+    }
+
+    /// Evaluates the resource policies naming the given principal.
+    ///
+    /// The result must be merged into the subject one: if no resource
+    /// policy matches, an empty abstain result is returned, which does
+    /// not change the outcome when merged.
+    pub fn allowed_for<T, S>(
+        &self,
+        principal: &Principal,
+        attributes: &Attributes,
+        algorithm: CombiningAlgorithm,
+        action: Option<T>,
+        resource: Option<S>,
+    ) -> AllowedResult
+    where
+        T: ToString + Display,
+        S: ToString + Display + Debug,
+    {
+        let policies = self
+            .policies
+            .iter()
+            .filter(|p| p.principals.matches(principal))
+            .map(|p| &p.policy);
+
+        allowed_with(policies, action, resource, algorithm, attributes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::identity::role::allowed;
+    use crate::policy::allowed_result::AllowedOutcome;
+    use crate::policy::combining_algorithm::CombiningAlgorithm;
+    use crate::policy::condition::Attributes;
+    use crate::policy::resource_policy::{Principal, Principals, ResourcePolicies, ResourcePolicy};
+    use crate::policy::{PolicyEffect, PolicyVersion};
+    use crate::zephir_policy;
+
+    fn principals(identities: Vec<&str>, groups: Vec<&str>, roles: Vec<&str>) -> Principals {
+        let to_vec = |v: Vec<&str>| v.into_iter().map(String::from).collect();
+        Principals::new(to_vec(identities), to_vec(groups), to_vec(roles))
+    }
+
+    #[test]
+    fn principals_should_match_ids_and_globs() {
+        let p = principals(vec!["alice"], vec!["finance-*"], vec!["deployer"]);
+
+        assert!(p.matches(&Principal::identity("alice", vec![])));
+        assert!(!p.matches(&Principal::identity("bob", vec!["sales"])));
+        assert!(p.matches(&Principal::identity("bob", vec!["sales", "finance-emea"])));
+        assert!(p.matches(&Principal::role("deployer")));
+        assert!(!p.matches(&Principal::role("alice")));
+    }
+
+    #[test]
+    fn resource_policies_should_be_merged_with_subject_policies() {
+        let policies = ResourcePolicies::new(vec![
+            ResourcePolicy::new(
+                zephir_policy!("BucketReaders", PolicyVersion::Version1, PolicyEffect::Allow, vec!["storage:Get*"], vec!["urn:bucket:reports:*"]).unwrap(),
+                principals(vec![], vec!["finance"], vec![]),
+            ),
+            ResourcePolicy::new(
+                zephir_policy!("BucketDenyBob", PolicyVersion::Version1, PolicyEffect::Deny, vec!["*"], vec!["urn:bucket:reports:*"]).unwrap(),
+                principals(vec!["bob"], vec![], vec![]),
+            ),
+        ]);
+
+        let identity_policy = zephir_policy!("IdentityPolicy", PolicyVersion::Version1, PolicyEffect::Allow, vec!["storage:*"]).unwrap();
+        let algorithm = CombiningAlgorithm::DenyOverrides;
+        let action = Option::Some("storage:GetObject");
+        let resource = Option::Some("urn:bucket:reports:q1");
+
+        let result = policies.allowed_for(&Principal::identity("alice", vec!["finance"]), &Attributes::new(), algorithm, action, resource);
+        assert_eq!(result.outcome(), AllowedOutcome::Allowed);
+
+        let mut result = allowed(std::iter::once(&identity_policy), action, resource);
+        algorithm.merge(&mut result, policies.allowed_for(&Principal::identity("bob", vec!["finance"]), &Attributes::new(), algorithm, action, resource));
+        assert_eq!(result.outcome(), AllowedOutcome::Denied);
+
+        let mut result = allowed(std::iter::once(&identity_policy), action, resource);
+        algorithm.merge(&mut result, policies.allowed_for(&Principal::identity("carol", vec![]), &Attributes::new(), algorithm, action, resource));
+        assert_eq!(result.outcome(), AllowedOutcome::Allowed);
+
+        let result = policies.allowed_for(&Principal::identity("carol", vec![]), &Attributes::new(), algorithm, action, resource);
+        assert_eq!(result.outcome(), AllowedOutcome::Denied);
+    }
+}
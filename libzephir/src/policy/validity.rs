@@ -0,0 +1,72 @@
+use serde_json::{Map, Value};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Gets the current time as a unix timestamp.
+pub fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+/// The time window (as unix timestamps) a policy link or a
+/// group membership is effective in. Unbounded if not specified.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Validity {
+    pub not_before: Option<i64>,
+    pub not_after: Option<i64>,
+}
+
+impl Validity {
+    pub fn new(not_before: Option<i64>, not_after: Option<i64>) -> Self {
+        Validity { not_before, not_after }
+    }
+
+    pub fn is_bounded(&self) -> bool {
+        self.not_before.is_some() || self.not_after.is_some()
+    }
+
+    /// Whether the link is effective at the given time.
+    pub fn is_active_at(&self, timestamp: i64) -> bool {
+        !matches!(self.not_before, Some(t) if t > timestamp) && !self.is_expired_at(timestamp)
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.is_active_at(unix_now())
+    }
+
+    /// Whether the link is expired at the given time (and will never be effective again).
+    pub fn is_expired_at(&self, timestamp: i64) -> bool {
+        matches!(self.not_after, Some(t) if t <= timestamp)
+    }
+
+    pub fn to_value(&self) -> Value {
+        let mut map = Map::new();
+        map.insert(String::from("not_before"), Value::from(self.not_before));
+        map.insert(String::from("not_after"), Value::from(self.not_after));
+
+        Value::Object(map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::policy::validity::Validity;
+
+    #[test]
+    fn validity_should_check_the_time_window() {
+        let unbounded = Validity::default();
+        assert!(!unbounded.is_bounded());
+        assert!(unbounded.is_active_at(0));
+        assert!(!unbounded.is_expired_at(i64::MAX));
+
+        let validity = Validity::new(Option::Some(100), Option::Some(200));
+        assert!(validity.is_bounded());
+        assert!(!validity.is_active_at(99));
+        assert!(validity.is_active_at(100));
+        assert!(validity.is_active_at(199));
+        assert!(!validity.is_active_at(200));
+        assert!(!validity.is_expired_at(199));
+        assert!(validity.is_expired_at(200));
+    }
+}
@@ -0,0 +1,134 @@
+use crate::err::Error;
+use crate::storage::types::DbChange;
+use crate::storage::{Change, ChangeOperation, StorageManager};
+use crate::webhook::event_name;
+use serde_json::Map;
+use sqlx::{PgConnection, Postgres, Transaction};
+use std::time::{Duration, Instant};
+
+/// Key of the advisory lock serializing the writes to the change log.
+const CHANGE_LOG_LOCK: i64 = 0x7a65_7068_6972;
+
+/// How often the change log is checked while waiting for new changes.
+const CHANGES_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+impl From<DbChange> for Change {
+    fn from(value: DbChange) -> Self {
+        Change {
+            seq: value.seq,
+            tenant: value.tenant_id,
+            entity_type: value.entity_type,
+            entity_id: value.entity_id,
+            operation: value.operation,
+            changed_at: value.changed_at,
+        }
+    }
+}
+
+impl StorageManager {
+    /// Records a write into the change log, as part of the given transaction,
+    /// and notifies the webhooks subscribed to the corresponding event.
+    ///
+    /// Consumers read the changes following the last sequence number they have
+    /// seen: the transactions recording a change are serialized (until they
+    /// commit or roll back), so that sequence numbers become visible in order.
+    ///
+    /// # Returns
+    ///
+    /// The sequence number of the change
+    pub(super) async fn _record_change(
+        &self,
+        entity_type: &str,
+        entity_id: &str,
+        operation: ChangeOperation,
+        transaction: &mut Transaction<'_, Postgres>,
+    ) -> Result<i64, Error> {
+        self._lock_change_log(&mut *transaction).await?;
+
+        let (seq,) = sqlx::query_as::<_, (i64,)>(
+            r#"
+            INSERT INTO change_log (tenant_id, entity_type, entity_id, operation)
+            VALUES ($1, $2, $3, $4)
+            RETURNING seq
+        "#,
+        )
+        .bind(&self.tenant)
+        .bind(entity_type)
+        .bind(entity_id)
+        .bind(operation.as_str())
+        .fetch_one(&mut *transaction)
+        .await?;
+
+        let event = event_name(entity_type, operation);
+        self._notify_webhooks(&event, entity_type, entity_id, seq, Map::new(), &mut *transaction)
+            .await?;
+
+        Ok(seq)
+    }
+
+    /// Waits for the other writers to commit or roll back, and prevents them
+    /// from committing until the end of the transaction: as every write records
+    /// a change, the data read afterwards cannot change meanwhile.
+    pub(super) async fn _lock_change_log(&self, transaction: &mut Transaction<'_, Postgres>) -> Result<(), Error> {
+        sqlx::query("SELECT pg_advisory_xact_lock($1)")
+            .bind(CHANGE_LOG_LOCK)
+            .execute(transaction)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Finds the changes of the tenant following the given sequence number, oldest first.
+    pub async fn find_changes(&self, since: i64, limit: i64) -> Result<Vec<Change>, Error> {
+        Ok(sqlx::query_as::<_, DbChange>(
+            r#"
+            SELECT seq, tenant_id, entity_type, entity_id, operation,
+                   extract(epoch FROM changed_at)::bigint AS changed_at
+            FROM change_log
+            WHERE tenant_id = $1 AND seq > $2
+            ORDER BY seq
+            LIMIT $3
+        "#,
+        )
+        .bind(&self.tenant)
+        .bind(since)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(Change::from)
+        .collect())
+    }
+
+    /// Waits (up to the given timeout) for changes following the given sequence number.
+    /// Returns immediately if there are already some.
+    ///
+    /// # Returns
+    ///
+    /// The changes, or an empty vector if the timeout elapsed
+    pub async fn wait_for_changes(&self, since: i64, limit: i64, timeout: Duration) -> Result<Vec<Change>, Error> {
+        let started_at = Instant::now();
+        loop {
+            let changes = self.find_changes(since, limit).await?;
+            if !changes.is_empty() || started_at.elapsed() >= timeout {
+                return Ok(changes);
+            }
+
+            async_std::task::sleep(CHANGES_POLL_INTERVAL.min(timeout.saturating_sub(started_at.elapsed()))).await;
+        }
+    }
+
+    /// Gets the sequence number of the last change of the tenant (0 if none).
+    pub async fn last_change_seq(&self) -> Result<i64, Error> {
+        self._last_change_seq(&mut *self.pool.acquire().await?).await
+    }
+
+    pub(super) async fn _last_change_seq(&self, conn: &mut PgConnection) -> Result<i64, Error> {
+        let (seq,) = sqlx::query_as::<_, (Option<i64>,)>("SELECT max(seq) FROM change_log WHERE tenant_id = $1")
+            .bind(&self.tenant)
+            .fetch_one(conn)
+            .await?;
+
+        Ok(seq.unwrap_or_default())
+    }
+}
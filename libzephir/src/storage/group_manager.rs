@@ -1,164 +1,368 @@
+use crate::cache::invalidation::InvalidationEvent;
+use crate::cache::membership_rule_cache::MEMBERSHIP_RULE_CACHE;
+use crate::cache::subject_cache::SUBJECT_CACHE;
 use crate::err::Error;
-use crate::identity::group::Group;
+use crate::identity::group::{find_cycle, Group};
 use crate::identity::identity::Identity;
 use crate::identity::role::Role;
 use crate::identity::subject::Subject;
-use crate::policy::policy::CompletePolicy;
-use crate::policy::policy_set::PolicySetTrait;
-use crate::storage::types::{DbIdentity, DbPolicy};
-use crate::storage::StorageManager;
+use crate::identity::membership_rule::MembershipRule;
+use crate::policy::condition::Attributes;
+use crate::storage::types::DbNestedGroup;
+use crate::storage::{ChangeOperation, GroupLoading, StorageManager};
+use serde_json::{Map, Value};
+use sqlx::types::Json;
+use sqlx::{Postgres, Transaction};
 use std::convert::TryFrom;
+use std::collections::{HashMap, HashSet};
+use log::warn;
 
 impl StorageManager {
+    /// Finds all the groups the given identity belongs to, directly
+    /// or through nested groups.
+    ///
+    /// Groups are loaded without their members: they should only
+    /// be used to evaluate the identity permissions.
     pub async fn find_groups_for_identity(&self, target: &Identity) -> Result<Vec<Group>, Error> {
-        let groups = sqlx::query_as::<_, DbIdentity>(r#"
-            SELECT id, policy_id
-            FROM "group"
-            INNER JOIN group_identity ON "group".id = group_identity.group_id AND group_identity.identity_id = $1
-        "#)
-            .bind(&target.id)
-            .fetch_all(&self.pool)
-            .await?;
-
-        let mut result = vec![];
-        for g in groups {
-            result.push(self._load_group(&g).await?);
+        if let Some(groups) = SUBJECT_CACHE.get_groups(&self.tenant, &target.id) {
+            return Ok(groups);
         }
 
-        Ok(result)
+        let generation = SUBJECT_CACHE.generation();
+        let loaded = self
+            ._load_subjects(&[], &[], Option::Some(&target.id), &mut *self.pool.acquire().await?)
+            .await?;
+
+        SUBJECT_CACHE.insert_groups(&self.tenant, &target.id, &loaded.groups, loaded.memberships_change_at, generation);
+        Ok(loaded.groups)
     }
 
-    pub async fn find_group<S>(&self, id: S) -> Result<Option<Group>, Error>
-    where
-        S: ToString,
-    {
-        let group = sqlx::query_as::<_, DbIdentity>(
-            r#"
-            SELECT id, policy_id
-            FROM group
-            WHERE id = $1
-        "#,
+    /// Loads the membership rules of the dynamic groups of the tenant.
+    ///
+    /// Rules are validated when saved: a stored rule which cannot be
+    /// parsed anymore is skipped, rather than failing every evaluation.
+    async fn _find_membership_rules(&self) -> Result<Vec<(String, MembershipRule)>, Error> {
+        if let Some(rules) = MEMBERSHIP_RULE_CACHE.get(&self.tenant) {
+            return Ok(rules);
+        }
+
+        let generation = MEMBERSHIP_RULE_CACHE.generation();
+        let rows = sqlx::query_as::<_, (String, String)>(
+            r#"SELECT id, membership_rule FROM "group" WHERE tenant_id = $1 AND membership_rule IS NOT NULL"#,
         )
-        .bind(id.to_string())
-        .fetch_optional(&self.pool)
+        .bind(&self.tenant)
+        .fetch_all(&self.pool)
         .await?;
 
-        if group.is_none() {
-            return Ok(Option::None);
+        let mut rules = vec![];
+        for (id, rule) in rows {
+            match MembershipRule::try_from(rule.as_str()) {
+                Ok(rule) => rules.push((id, rule)),
+                Err(e) => warn!(r#"Skipping the membership rule of group "{}" of tenant "{}": {}"#, id, self.tenant, e),
+            }
         }
 
-        Ok(Some(self._load_group(group.as_ref().unwrap()).await?))
+        MEMBERSHIP_RULE_CACHE.insert(&self.tenant, &rules, generation);
+        Ok(rules)
     }
 
-    async fn _load_group(&self, group: &DbIdentity) -> Result<Group, Error> {
-        let inline_policy = if group.policy_id.is_some() {
-            self.find_policy(group.policy_id.as_ref().unwrap()).await?
-        } else {
-            Option::None
-        };
+    /// Finds the dynamic groups whose membership rule
+    /// matches the attributes of the given identity.
+    pub(super) async fn _find_dynamic_groups_of(&self, identity_id: &str) -> Result<Vec<String>, Error> {
+        let rules = self._find_membership_rules().await?;
+        if rules.is_empty() {
+            return Ok(vec![]);
+        }
 
-        let mut group = Group::new(group.id.to_string(), inline_policy);
-        let policies = sqlx::query_as::<_, DbPolicy>(
-            r#"
-            SELECT id, version, effect, actions, resources
-            FROM policy
-            INNER JOIN group_policy ip ON ip.policy_id = policy.id AND ip.group_id = $1
-        "#,
+        let attributes = sqlx::query_as::<_, (Json<Attributes>,)>(
+            "SELECT attributes FROM identity WHERE tenant_id = $1 AND id = $2",
         )
-        .bind(&group.name)
-        .fetch_all(&self.pool)
+        .bind(&self.tenant)
+        .bind(identity_id)
+        .fetch_optional(&self.pool)
         .await?;
 
-        for db_policy in policies {
-            group = group.add_policy(CompletePolicy::try_from(db_policy)?);
-        }
+        Ok(match attributes {
+            Option::None => vec![],
+            Option::Some((attributes,)) => rules
+                .into_iter()
+                .filter(|(_, rule)| rule.matches(&attributes))
+                .map(|(id, _)| id)
+                .collect(),
+        })
+    }
 
-        let identities: Vec<DbIdentity> = sqlx::query_as::<_, DbIdentity>(
-            r#"
-            SELECT id, policy_id
-            FROM identity
-            INNER JOIN group_identity gi ON gi.identity_id = identity.id AND gi.group_id = $1
-        "#,
+    /// Lists the ids of the identities currently matching the given membership rule.
+    pub async fn find_identities_matching(&self, rule: &MembershipRule) -> Result<Vec<String>, Error> {
+        let identities = sqlx::query_as::<_, (String, Json<Attributes>)>(
+            "SELECT id, attributes FROM identity WHERE tenant_id = $1 ORDER BY id",
         )
-        .bind(&group.name)
+        .bind(&self.tenant)
         .fetch_all(&self.pool)
         .await?;
 
-        for i in identities {
-            group = group.add_identity(self.find_identity(i.id).await?.unwrap());
-        }
+        Ok(identities
+            .into_iter()
+            .filter(|(_, attributes)| rule.matches(attributes))
+            .map(|(id, _)| id)
+            .collect())
+    }
 
-        Ok(group)
+    pub async fn find_group<S>(&self, id: S) -> Result<Option<Group>, Error>
+    where
+        S: ToString,
+    {
+        self.find_group_with(id, GroupLoading::Full).await
     }
 
-    pub async fn save_group(&self, g: &Group) -> Result<(), Error> {
-        let embedded_policy = g.get_inline_policy();
+    /// Finds a group, loading its members only if requested.
+    pub async fn find_group_with<S>(&self, id: S, loading: GroupLoading) -> Result<Option<Group>, Error>
+    where
+        S: ToString,
+    {
+        let mut conn = self.pool.acquire().await?;
+        let groups = self
+            ._load_subjects(&[], &[id.to_string()], Option::None, &mut conn)
+            .await?
+            .groups;
+
+        let groups = match loading {
+            GroupLoading::Full => self._load_members(groups, &mut conn).await?,
+            GroupLoading::PoliciesOnly => groups,
+        };
+
+        Ok(groups.into_iter().next())
+    }
 
+    pub async fn save_group(&self, g: &Group) -> Result<(), Error> {
         let mut transaction = self.pool.begin().await?;
-        if embedded_policy.is_some() {
-            self._save_policy(embedded_policy.unwrap(), &mut transaction)
+        let previous_members = sqlx::query_as::<_, (String,)>(
+            "SELECT identity_id FROM group_identity WHERE tenant_id = $1 AND group_id = $2",
+        )
+        .bind(&self.tenant)
+        .bind(&g.name)
+        .fetch_all(&mut transaction)
+        .await?
+        .into_iter()
+        .map(|(id,)| id)
+        .collect::<HashSet<_>>();
+
+        let events = self._save_group(g, &mut transaction).await?;
+        let seq = self
+            ._record_change("group", &g.name, ChangeOperation::Upsert, &mut transaction)
+            .await?;
+
+        let members = (&g.identities).into_iter().map(|i| i.id.clone()).collect::<HashSet<_>>();
+        let mut membership_changes = members
+            .difference(&previous_members)
+            .map(|id| ("group.member_added", id))
+            .chain(previous_members.difference(&members).map(|id| ("group.member_removed", id)))
+            .collect::<Vec<_>>();
+        membership_changes.sort();
+
+        for (event, identity_id) in membership_changes {
+            let mut data = Map::new();
+            data.insert(String::from("identity"), Value::from(identity_id.as_str()));
+            self._notify_webhooks(event, "group", &g.name, seq, data, &mut transaction)
                 .await?;
+        }
+
+        transaction.commit().await?;
+        self.invalidator.publish(&events).await;
+
+        Ok(())
+    }
+
+    /// Saves a group and its members into the given transaction.
+    ///
+    /// # Returns
+    ///
+    /// The invalidation events to be published once the transaction is committed
+    pub(super) async fn _save_group(
+        &self,
+        g: &Group,
+        transaction: &mut Transaction<'_, Postgres>,
+    ) -> Result<Vec<InvalidationEvent>, Error> {
+        let embedded_policy = g.get_inline_policy();
+        let previous_rule = sqlx::query_as::<_, (Option<String>,)>(
+            r#"SELECT membership_rule FROM "group" WHERE tenant_id = $1 AND id = $2"#,
+        )
+        .bind(&self.tenant)
+        .bind(&g.name)
+        .fetch_optional(&mut *transaction)
+        .await?
+        .and_then(|(rule,)| rule);
+
+        let embedded_policy_id = if let Some(embedded_policy) = embedded_policy {
+            self._save_policy(embedded_policy, &mut *transaction).await?;
+            embedded_policy.id.clone()
         } else {
             let policy_id = "__embedded_policy_group_".to_owned() + g.name.as_str() + "__";
-            sqlx::query("DELETE FROM policy WHERE id = $1")
-                .bind(policy_id)
-                .execute(&mut transaction)
+            sqlx::query("DELETE FROM policy WHERE tenant_id = $1 AND id = $2")
+                .bind(&self.tenant)
+                .bind(&policy_id)
+                .execute(&mut *transaction)
                 .await?;
-        }
+            policy_id
+        };
 
         sqlx::query(
             r#"
-            INSERT INTO group(id, policy_id)
-            VALUES ($1, $2)
-            ON CONFLICT (id) DO UPDATE SET policy_id = $2
+            INSERT INTO "group"(tenant_id, id, policy_id, boundary_policy_id, attributes, membership_rule)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (tenant_id, id) DO UPDATE SET policy_id = $3, boundary_policy_id = $4, attributes = $5, membership_rule = $6
         "#,
         )
+        .bind(&self.tenant)
         .bind(&g.name)
         .bind(if embedded_policy.is_none() {
             Option::None
         } else {
             Option::Some(&embedded_policy.unwrap().id)
         })
-        .execute(&mut transaction)
+        .bind(g.boundary.as_ref().map(|p| &p.id))
+        .bind(Value::Object(g.attributes.clone()))
+        .bind(g.membership_rule.as_ref().map(|r| r.as_str()))
+        .execute(&mut *transaction)
         .await?;
 
-        sqlx::query("DELETE FROM group_policy WHERE group_id = $1")
+        sqlx::query("DELETE FROM group_policy WHERE tenant_id = $1 AND group_id = $2")
+            .bind(&self.tenant)
             .bind(&g.name)
-            .execute(&mut transaction)
+            .execute(&mut *transaction)
             .await?;
 
-        for p in g.linked_policies() {
+        let linked_policies = g.linked_policies();
+        for p in linked_policies {
+            let link = linked_policies.get_link(&p.id).copied().unwrap_or_default();
             sqlx::query(
                 r#"
-                INSERT INTO group_policy (group_id, policy_id)
-                VALUES ($1, $2)
+                INSERT INTO group_policy (tenant_id, group_id, policy_id, priority, not_before, not_after)
+                VALUES ($1, $2, $3, $4, to_timestamp($5), to_timestamp($6))
             "#,
             )
+            .bind(&self.tenant)
             .bind(&g.name)
             .bind(&p.id)
-            .execute(&mut transaction)
+            .bind(link.priority)
+            .bind(link.validity.not_before)
+            .bind(link.validity.not_after)
+            .execute(&mut *transaction)
             .await?;
         }
 
-        sqlx::query("DELETE FROM group_identity WHERE group_id = $1")
+        self._save_nested_groups(g, &mut *transaction).await?;
+
+        sqlx::query("DELETE FROM group_identity WHERE tenant_id = $1 AND group_id = $2")
+            .bind(&self.tenant)
             .bind(&g.name)
-            .execute(&mut transaction)
+            .execute(&mut *transaction)
             .await?;
 
         for i in &g.identities {
+            let validity = g.identities.get_validity(&i.id);
             sqlx::query(
                 r#"
-                INSERT INTO group_identity (group_id, identity_id)
-                VALUES ($1, $2)
+                INSERT INTO group_identity (tenant_id, group_id, identity_id, not_before, not_after)
+                VALUES ($1, $2, $3, to_timestamp($4), to_timestamp($5))
             "#,
             )
+            .bind(&self.tenant)
             .bind(&g.name)
             .bind(&i.id)
-            .execute(&mut transaction)
+            .bind(validity.not_before)
+            .bind(validity.not_after)
+            .execute(&mut *transaction)
             .await?;
         }
 
-        transaction.commit().await?;
+        // Old members depend on the group event, new members (and the
+        // members of the nested groups) must reload the groups they belong to.
+        let mut events = vec![
+            InvalidationEvent::Policy(self.scoped(&embedded_policy_id)),
+            InvalidationEvent::Group(self.scoped(&g.name)),
+        ];
+        for i in &g.identities {
+            events.push(InvalidationEvent::Identity(self.scoped(&i.id)));
+        }
+        for nested in &g.groups {
+            events.push(InvalidationEvent::Group(self.scoped(nested)));
+        }
+
+        // Any identity could have joined or left a dynamic group.
+        if previous_rule.as_deref() != g.membership_rule.as_ref().map(|r| r.as_str()) {
+            events.push(InvalidationEvent::DynamicGroups);
+        }
+
+        Ok(events)
+    }
+
+    /// Replaces the groups nested into the given one, refusing
+    /// to save the memberships if they would introduce a cycle.
+    async fn _save_nested_groups(
+        &self,
+        g: &Group,
+        transaction: &mut Transaction<'_, Postgres>,
+    ) -> Result<(), Error> {
+        // Prevents concurrent transactions from creating a cycle
+        // by saving memberships which are valid on their own.
+        sqlx::query("LOCK TABLE group_group IN SHARE ROW EXCLUSIVE MODE")
+            .execute(&mut *transaction)
+            .await?;
+
+        let nested_groups = sqlx::query_as::<_, DbNestedGroup>(
+            r#"
+            WITH RECURSIVE edge AS (
+                SELECT group_id, member_group_id
+                FROM group_group
+                WHERE tenant_id = $3 AND group_id = ANY($1) AND group_id <> $2
+                UNION
+                SELECT gg.group_id, gg.member_group_id
+                FROM group_group gg
+                INNER JOIN edge e ON gg.group_id = e.member_group_id
+                WHERE gg.tenant_id = $3 AND gg.group_id <> $2
+            )
+            SELECT group_id, member_group_id FROM edge
+        "#,
+        )
+        .bind(&g.groups)
+        .bind(&g.name)
+        .bind(&self.tenant)
+        .fetch_all(&mut *transaction)
+        .await?;
+
+        let mut edges: HashMap<String, Vec<String>> = HashMap::new();
+        edges.insert(g.name.clone(), g.groups.clone());
+        for nested in nested_groups {
+            edges
+                .entry(nested.group_id)
+                .or_default()
+                .push(nested.member_group_id);
+        }
+
+        if let Some(cycle) = find_cycle(&edges, &g.name) {
+            return Err(Error::group_cycle(cycle));
+        }
+
+        sqlx::query("DELETE FROM group_group WHERE tenant_id = $1 AND group_id = $2")
+            .bind(&self.tenant)
+            .bind(&g.name)
+            .execute(&mut *transaction)
+            .await?;
+
+        for nested in &g.groups {
+            sqlx::query(
+                r#"
+                INSERT INTO group_group (tenant_id, group_id, member_group_id)
+                VALUES ($1, $2, $3)
+            "#,
+            )
+            .bind(&self.tenant)
+            .bind(&g.name)
+            .bind(nested)
+            .execute(&mut *transaction)
+            .await?;
+        }
 
         Ok(())
     }
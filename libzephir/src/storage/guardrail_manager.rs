@@ -0,0 +1,138 @@
+use crate::cache::tenant_cache::GUARDRAIL_CACHE;
+use crate::cache::invalidation::InvalidationEvent;
+use crate::err::Error;
+use crate::policy::guardrails::Guardrails;
+use crate::policy::policy::{CompletePolicy, MatchablePolicy};
+use crate::storage::types::DbPolicy;
+use crate::storage::{ChangeOperation, StorageManager};
+use serde_json::Value;
+use sqlx::{Postgres, Transaction};
+use std::convert::TryFrom;
+
+impl StorageManager {
+    /// Loads all the guardrails of the tenant.
+    pub async fn find_guardrails(&self) -> Result<Guardrails, Error> {
+        if let Some(guardrails) = GUARDRAIL_CACHE.get(&self.tenant) {
+            return Ok(guardrails);
+        }
+
+        let generation = GUARDRAIL_CACHE.generation();
+        let policies = sqlx::query_as::<_, DbPolicy>(
+            r#"
+            SELECT tenant_id, id, version, effect, actions, resources, conditions
+            FROM guardrail
+            WHERE tenant_id = $1
+            ORDER BY id
+        "#,
+        )
+        .bind(&self.tenant)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut result = vec![];
+        for policy in policies {
+            result.push(CompletePolicy::try_from(policy)?);
+        }
+
+        let guardrails = Guardrails::new(result);
+        GUARDRAIL_CACHE.insert(&self.tenant, &guardrails, generation);
+
+        Ok(guardrails)
+    }
+
+    pub async fn find_guardrail<S>(&self, id: S) -> Result<Option<CompletePolicy>, Error>
+    where
+        S: ToString,
+    {
+        let policy = sqlx::query_as::<_, DbPolicy>(
+            r#"
+            SELECT tenant_id, id, version, effect, actions, resources, conditions
+            FROM guardrail
+            WHERE tenant_id = $1 AND id = $2
+        "#,
+        )
+        .bind(&self.tenant)
+        .bind(id.to_string())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(match policy {
+            Option::None => Option::None,
+            Option::Some(policy) => Option::Some(CompletePolicy::try_from(policy)?),
+        })
+    }
+
+    pub async fn save_guardrail(&self, p: &CompletePolicy) -> Result<(), Error> {
+        let mut transaction = self.pool.begin().await?;
+        self._save_guardrail(p, &mut transaction).await?;
+        self._record_change("guardrail", &p.id, ChangeOperation::Upsert, &mut transaction)
+            .await?;
+
+        transaction.commit().await?;
+        self.invalidator
+            .publish(&[InvalidationEvent::Guardrails(self.tenant.clone())])
+            .await;
+
+        Ok(())
+    }
+
+    pub(super) async fn _save_guardrail(
+        &self,
+        p: &CompletePolicy,
+        transaction: &mut Transaction<'_, Postgres>,
+    ) -> Result<(), Error> {
+        let version: i32 = (&p.version).into();
+        let effect: bool = (&p.effect).into();
+
+        sqlx::query(
+            r#"
+            INSERT INTO guardrail(tenant_id, id, version, effect, actions, resources, conditions)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            ON CONFLICT (tenant_id, id)
+            DO UPDATE SET version = $3, effect = $4, actions = $5, resources = $6, conditions = $7
+        "#,
+        )
+        .bind(&self.tenant)
+        .bind(&p.id)
+        .bind(version)
+        .bind(effect)
+        .bind(Value::from(p.get_actions()))
+        .bind(Value::from(p.get_resources()))
+        .bind(p.get_subject_conditions().map(|c| c.to_value()))
+        .execute(transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Deletes a guardrail.
+    ///
+    /// # Returns
+    ///
+    /// Whether the guardrail existed
+    pub async fn delete_guardrail<S>(&self, id: S) -> Result<bool, Error>
+    where
+        S: ToString,
+    {
+        let id = id.to_string();
+        let mut transaction = self.pool.begin().await?;
+        let result = sqlx::query("DELETE FROM guardrail WHERE tenant_id = $1 AND id = $2")
+            .bind(&self.tenant)
+            .bind(&id)
+            .execute(&mut transaction)
+            .await?;
+
+        if result.rows_affected() > 0 {
+            self._record_change("guardrail", &id, ChangeOperation::Delete, &mut transaction)
+                .await?;
+        }
+
+        transaction.commit().await?;
+
+        self.invalidator
+            .publish(&[InvalidationEvent::Guardrails(self.tenant.clone())])
+            .await;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
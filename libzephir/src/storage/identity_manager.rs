@@ -1,108 +1,118 @@
+use crate::cache::invalidation::InvalidationEvent;
+use crate::cache::subject_cache::SUBJECT_CACHE;
 use crate::err::Error;
 use crate::identity::identity::Identity;
 use crate::identity::role::Role;
-use crate::policy::policy::CompletePolicy;
-use crate::policy::policy_set::PolicySetTrait;
-use crate::storage::types::{DbIdentity, DbPolicy};
-use crate::storage::StorageManager;
-use std::convert::TryFrom;
+use crate::storage::{ChangeOperation, StorageManager};
+use serde_json::Value;
+use sqlx::{Postgres, Transaction};
 
 impl StorageManager {
     pub async fn find_identity<S>(&self, id: S) -> Result<Option<Identity>, Error>
     where
         S: ToString,
     {
-        let identity = sqlx::query_as::<_, DbIdentity>(
-            r#"
-            SELECT id, policy_id
-            FROM identity
-            WHERE id = $1
-        "#,
-        )
-        .bind(id.to_string())
-        .fetch_optional(&self.pool)
-        .await?;
-
-        if identity.is_none() {
-            return Ok(Option::None);
+        if let Some(identity) = SUBJECT_CACHE.get_identity(&self.tenant, &id.to_string()) {
+            return Ok(Option::Some(identity));
         }
 
-        let identity = identity.unwrap();
-        let inline_policy = if identity.policy_id.is_some() {
-            self.find_policy(identity.policy_id.unwrap()).await?
-        } else {
-            Option::None
-        };
-
-        let mut identity = Identity::new(identity.id, inline_policy);
-        let policies = sqlx::query_as::<_, DbPolicy>(
-            r#"
-            SELECT id, version, effect, actions, resources
-            FROM policy
-            INNER JOIN identity_policy ip ON ip.policy_id = policy.id AND ip.identity_id = $1
-        "#,
-        )
-        .bind(id.to_string())
-        .fetch_all(&self.pool)
-        .await?;
+        let generation = SUBJECT_CACHE.generation();
+        let identity = self
+            ._load_subjects(&[id.to_string()], &[], Option::None, &mut *self.pool.acquire().await?)
+            .await?
+            .identities
+            .pop();
 
-        for db_policy in policies {
-            identity = identity.add_policy(CompletePolicy::try_from(db_policy)?);
+        if let Some(identity) = &identity {
+            SUBJECT_CACHE.insert_identity(&self.tenant, identity, generation);
         }
 
-        Ok(Option::Some(identity))
+        Ok(identity)
     }
 
     pub async fn save_identity(&self, i: &Identity) -> Result<(), Error> {
-        let embedded_policy = i.inline_policy.as_ref();
-
         let mut transaction = self.pool.begin().await?;
-        if embedded_policy.is_some() {
-            self._save_policy(embedded_policy.unwrap(), &mut transaction)
-                .await?;
+        let events = self._save_identity(i, &mut transaction).await?;
+        self._record_change("identity", &i.id, ChangeOperation::Upsert, &mut transaction)
+            .await?;
+
+        transaction.commit().await?;
+        self.invalidator.publish(&events).await;
+
+        Ok(())
+    }
+
+    /// Saves an identity into the given transaction.
+    ///
+    /// # Returns
+    ///
+    /// The invalidation events to be published once the transaction is committed
+    pub(super) async fn _save_identity(
+        &self,
+        i: &Identity,
+        transaction: &mut Transaction<'_, Postgres>,
+    ) -> Result<Vec<InvalidationEvent>, Error> {
+        let embedded_policy = i.inline_policy.as_ref();
+        let embedded_policy_id = if let Some(embedded_policy) = embedded_policy {
+            self._save_policy(embedded_policy, &mut *transaction).await?;
+            embedded_policy.id.clone()
         } else {
             let policy_id = "__embedded_policy_identity_".to_owned() + i.id.as_str() + "__";
-            sqlx::query("DELETE FROM policy WHERE id = $1")
-                .bind(policy_id)
-                .execute(&mut transaction)
+            sqlx::query("DELETE FROM policy WHERE tenant_id = $1 AND id = $2")
+                .bind(&self.tenant)
+                .bind(&policy_id)
+                .execute(&mut *transaction)
                 .await?;
-        }
+            policy_id
+        };
 
         sqlx::query(
             r#"
-            INSERT INTO identity(id, policy_id)
-            VALUES ($1, $2)
-            ON CONFLICT (id) DO UPDATE SET policy_id = $2
+            INSERT INTO identity(tenant_id, id, policy_id, boundary_policy_id, attributes)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (tenant_id, id) DO UPDATE SET policy_id = $3, boundary_policy_id = $4, attributes = $5
         "#,
         )
+        .bind(&self.tenant)
         .bind(&i.id)
         .bind(if embedded_policy.is_none() {
             Option::None
         } else {
             Option::Some(&embedded_policy.unwrap().id)
         })
-        .execute(&mut transaction)
+        .bind(i.boundary.as_ref().map(|p| &p.id))
+        .bind(Value::Object(i.attributes.clone()))
+        .execute(&mut *transaction)
         .await?;
 
-        sqlx::query("DELETE FROM identity_policy WHERE identity_id = $1")
+        sqlx::query("DELETE FROM identity_policy WHERE tenant_id = $1 AND identity_id = $2")
+            .bind(&self.tenant)
             .bind(&i.id)
-            .execute(&mut transaction)
+            .execute(&mut *transaction)
             .await?;
 
-        for p in i.linked_policies() {
+        let linked_policies = i.linked_policies();
+        for p in linked_policies {
+            let link = linked_policies.get_link(&p.id).copied().unwrap_or_default();
             sqlx::query(
                 r#"
-                INSERT INTO identity_policy (identity_id, policy_id)
-                VALUES ($1, $2)
+                INSERT INTO identity_policy (tenant_id, identity_id, policy_id, priority, not_before, not_after)
+                VALUES ($1, $2, $3, $4, to_timestamp($5), to_timestamp($6))
             "#,
             )
+            .bind(&self.tenant)
             .bind(&i.id)
             .bind(&p.id)
-            .execute(&mut transaction)
+            .bind(link.priority)
+            .bind(link.validity.not_before)
+            .bind(link.validity.not_after)
+            .execute(&mut *transaction)
             .await?;
         }
 
-        transaction.commit().await?;
-        Ok(())
+        Ok(vec![
+            InvalidationEvent::Policy(self.scoped(&embedded_policy_id)),
+            InvalidationEvent::Identity(self.scoped(&i.id)),
+        ])
     }
 }
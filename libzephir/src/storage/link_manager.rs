@@ -0,0 +1,129 @@
+use crate::cache::get_env_duration;
+use crate::cache::invalidation::InvalidationEvent;
+use crate::err::Error;
+use crate::storage::{ChangeOperation, ExpiredLinks, StorageManager};
+use crate::tenant::scoped_id;
+use log::{info, warn};
+use std::collections::BTreeSet;
+
+impl StorageManager {
+    /// Periodically removes the expired links of all the tenants, every
+    /// EXPIRED_LINKS_CLEANUP_INTERVAL seconds. Does nothing if the variable is not set or zero.
+    pub fn schedule_expired_links_cleanup(&self) {
+        let interval = match get_env_duration("EXPIRED_LINKS_CLEANUP_INTERVAL") {
+            Option::None => return,
+            Option::Some(interval) => interval,
+        };
+
+        let storage = self.clone();
+        async_std::task::spawn(async move {
+            loop {
+                async_std::task::sleep(interval).await;
+                match storage._remove_expired_links(Option::None).await {
+                    Ok(removed) if !removed.is_empty() => info!("Removed {} expired links", removed.len()),
+                    Ok(_) => {}
+                    Err(e) => warn!("Expired links cleanup failed: {}", e),
+                }
+            }
+        });
+    }
+
+    /// Deletes the expired policy links, group memberships and role sessions of the tenant.
+    ///
+    /// Expired links and sessions are already ignored when evaluating the subjects: this
+    /// keeps the storage clean and reports what has been removed. A change is
+    /// recorded for every identity and group losing a link, so that the change
+    /// feed, the webhooks and the replicas see the removal.
+    pub async fn remove_expired_links(&self) -> Result<ExpiredLinks, Error> {
+        self._remove_expired_links(Option::Some(&self.tenant)).await
+    }
+
+    /// Deletes the expired links of the given tenant, or of all the tenants if None.
+    async fn _remove_expired_links(&self, tenant: Option<&str>) -> Result<ExpiredLinks, Error> {
+        let mut transaction = self.pool.begin().await?;
+        let identity_policies = sqlx::query_as::<_, (String, String, String)>(
+            r#"
+            DELETE FROM identity_policy
+            WHERE not_after <= now() AND ($1::text IS NULL OR tenant_id = $1)
+            RETURNING tenant_id, identity_id, policy_id
+        "#,
+        )
+        .bind(tenant)
+        .fetch_all(&mut transaction)
+        .await?;
+
+        let group_policies = sqlx::query_as::<_, (String, String, String)>(
+            r#"
+            DELETE FROM group_policy
+            WHERE not_after <= now() AND ($1::text IS NULL OR tenant_id = $1)
+            RETURNING tenant_id, group_id, policy_id
+        "#,
+        )
+        .bind(tenant)
+        .fetch_all(&mut transaction)
+        .await?;
+
+        let group_identities = sqlx::query_as::<_, (String, String, String)>(
+            r#"
+            DELETE FROM group_identity
+            WHERE not_after <= now() AND ($1::text IS NULL OR tenant_id = $1)
+            RETURNING tenant_id, group_id, identity_id
+        "#,
+        )
+        .bind(tenant)
+        .fetch_all(&mut transaction)
+        .await?;
+
+        // Sessions are neither cached nor exported: no change is recorded for them.
+        let role_sessions = sqlx::query_as::<_, (String, String, String)>(
+            r#"
+            DELETE FROM role_session
+            WHERE expires_at <= now() AND ($1::text IS NULL OR tenant_id = $1)
+            RETURNING tenant_id, identity_id, id
+        "#,
+        )
+        .bind(tenant)
+        .fetch_all(&mut transaction)
+        .await?;
+
+        let changed: BTreeSet<(&str, &str, &str)> = identity_policies
+            .iter()
+            .map(|(tenant, identity, _)| (tenant.as_str(), "identity", identity.as_str()))
+            .chain(
+                group_policies
+                    .iter()
+                    .chain(group_identities.iter())
+                    .map(|(tenant, group, _)| (tenant.as_str(), "group", group.as_str())),
+            )
+            .collect();
+
+        for (tenant, entity_type, id) in &changed {
+            self.for_tenant(tenant)
+                ._record_change(entity_type, id, ChangeOperation::Upsert, &mut transaction)
+                .await?;
+        }
+
+        transaction.commit().await?;
+
+        let events: Vec<InvalidationEvent> = changed
+            .iter()
+            .map(|(tenant, entity_type, id)| match *entity_type {
+                "identity" => InvalidationEvent::Identity(scoped_id(tenant, id)),
+                _ => InvalidationEvent::Group(scoped_id(tenant, id)),
+            })
+            .collect();
+
+        let removed = ExpiredLinks {
+            identity_policies,
+            group_policies,
+            group_identities,
+            role_sessions,
+        };
+
+        if !events.is_empty() {
+            self.invalidator.publish(&events).await;
+        }
+
+        Ok(removed)
+    }
+}
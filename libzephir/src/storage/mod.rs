@@ -1,17 +1,492 @@
+mod change_manager;
 mod group_manager;
+mod guardrail_manager;
 mod identity_manager;
+mod link_manager;
 mod policy_manager;
+mod resource_policy_manager;
+mod role_manager;
+mod subject_manager;
+mod tenant_manager;
 mod types;
+mod webhook_manager;
 
+use crate::cache::invalidation::Invalidator;
+use crate::err::{Error, ErrorKind};
+use crate::identity::assumable_role::AssumableRole;
+use crate::identity::group::Group;
+use crate::identity::identity::Identity;
+use crate::policy::policy::{CompletePolicy, ToJson};
+use crate::policy::resource_policy::ResourcePolicy;
+use crate::tenant::{scoped_id, DEFAULT_TENANT};
+use serde_json::{Map, Value};
 use sqlx::{Pool, Postgres};
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
 
+/// How much of a group should be loaded from the storage.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GroupLoading {
+    /// Loads the group policies and its members.
+    Full,
+
+    /// Loads only the group policies, enough to evaluate the group.
+    PoliciesOnly,
+}
+
+/// The expired policy links, group memberships and role sessions removed
+/// by a cleanup, as (tenant, subject id, policy, identity or session id) triples.
+#[derive(Clone, Debug, Default)]
+pub struct ExpiredLinks {
+    pub identity_policies: Vec<(String, String, String)>,
+    pub group_policies: Vec<(String, String, String)>,
+    pub group_identities: Vec<(String, String, String)>,
+    pub role_sessions: Vec<(String, String, String)>,
+}
+
+impl ExpiredLinks {
+    pub fn len(&self) -> usize {
+        self.identity_policies.len()
+            + self.group_policies.len()
+            + self.group_identities.len()
+            + self.role_sessions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+fn links_to_value(links: &[(String, String, String)], owner: &str, target: &str) -> Value {
+    Value::from(
+        links
+            .iter()
+            .map(|(tenant, o, t)| {
+                let mut map = Map::new();
+                map.insert(String::from("tenant"), Value::from(tenant.as_str()));
+                map.insert(owner.to_string(), Value::from(o.as_str()));
+                map.insert(target.to_string(), Value::from(t.as_str()));
+
+                Value::Object(map)
+            })
+            .collect::<Vec<Value>>(),
+    )
+}
+
+impl ToJson for ExpiredLinks {
+    fn to_json(&self) -> Map<String, Value> {
+        let mut map = Map::new();
+        map.insert(
+            String::from("identity_policies"),
+            links_to_value(&self.identity_policies, "identity", "policy"),
+        );
+        map.insert(
+            String::from("group_policies"),
+            links_to_value(&self.group_policies, "group", "policy"),
+        );
+        map.insert(
+            String::from("group_identities"),
+            links_to_value(&self.group_identities, "group", "identity"),
+        );
+        map.insert(
+            String::from("role_sessions"),
+            links_to_value(&self.role_sessions, "identity", "session"),
+        );
+        map.insert(String::from("removed"), Value::from(self.len()));
+
+        map
+    }
+}
+
+/// The version of the export format, increased on any incompatible change.
+pub const EXPORT_VERSION: i32 = 1;
+
+/// The sections of an export, one for each kind of entity.
+pub const EXPORT_SECTIONS: &[&str] = &[
+    "policies",
+    "identities",
+    "groups",
+    "roles",
+    "guardrails",
+    "resource_policies",
+];
+
+/// All the entities of a tenant, as exported by `StorageManager::export_tenant`.
+#[derive(Clone, Default)]
+pub struct TenantExport {
+    pub tenant: String,
+    pub policies: Vec<CompletePolicy>,
+    pub identities: Vec<Identity>,
+    pub groups: Vec<Group>,
+    pub roles: Vec<AssumableRole>,
+    pub guardrails: Vec<CompletePolicy>,
+    pub resource_policies: Vec<ResourcePolicy>,
+}
+
+fn to_values<T: ToJson>(entities: &[T]) -> Value {
+    Value::from(
+        entities
+            .iter()
+            .map(|e| Value::Object(e.to_json()))
+            .collect::<Vec<Value>>(),
+    )
+}
+
+fn group_members_to_value(group: &Group) -> Map<String, Value> {
+    let mut map = group.to_json();
+    let identities = group
+        .get_identities()
+        .iter()
+        .map(|i| {
+            let validity = group.identities.get_validity(&i.id);
+            let mut member = Map::new();
+            member.insert(String::from("id"), Value::from(i.id.as_str()));
+            member.insert(String::from("not_before"), Value::from(validity.not_before));
+            member.insert(String::from("not_after"), Value::from(validity.not_after));
+
+            Value::Object(member)
+        })
+        .collect::<Vec<Value>>();
+
+    map.insert(String::from("identities"), Value::from(identities));
+    map.insert(String::from("groups"), Value::from(group.get_groups().clone()));
+
+    map
+}
+
+impl ToJson for TenantExport {
+    fn to_json(&self) -> Map<String, Value> {
+        let mut map = Map::new();
+        map.insert(String::from("version"), Value::from(EXPORT_VERSION));
+        map.insert(String::from("tenant"), Value::from(self.tenant.as_str()));
+        map.insert(String::from("policies"), to_values(&self.policies));
+        map.insert(String::from("identities"), to_values(&self.identities));
+        map.insert(
+            String::from("groups"),
+            Value::from(
+                self.groups
+                    .iter()
+                    .map(|g| Value::Object(group_members_to_value(g)))
+                    .collect::<Vec<Value>>(),
+            ),
+        );
+        map.insert(String::from("roles"), to_values(&self.roles));
+        map.insert(String::from("guardrails"), to_values(&self.guardrails));
+        map.insert(String::from("resource_policies"), to_values(&self.resource_policies));
+
+        map
+    }
+}
+
+/// The kind of write recorded in the change log.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChangeOperation {
+    Upsert,
+    Delete,
+}
+
+impl ChangeOperation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeOperation::Upsert => "upsert",
+            ChangeOperation::Delete => "delete",
+        }
+    }
+}
+
+/// A write recorded in the change log.
+///
+/// Entity types are "policy", "identity", "group", "role", "guardrail",
+/// "resource_policy" and "tenant" (when a whole tenant is deleted).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Change {
+    pub seq: i64,
+    pub tenant: String,
+    pub entity_type: String,
+    pub entity_id: String,
+    pub operation: String,
+    pub changed_at: i64,
+}
+
+impl ToJson for Change {
+    fn to_json(&self) -> Map<String, Value> {
+        let mut map = Map::new();
+        map.insert(String::from("seq"), Value::from(self.seq));
+        map.insert(String::from("tenant"), Value::from(self.tenant.as_str()));
+        map.insert(String::from("entity_type"), Value::from(self.entity_type.as_str()));
+        map.insert(String::from("entity_id"), Value::from(self.entity_id.as_str()));
+        map.insert(String::from("operation"), Value::from(self.operation.as_str()));
+        map.insert(String::from("changed_at"), Value::from(self.changed_at));
+
+        map
+    }
+}
+
+/// A saved version of a policy.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PolicyRevision {
+    pub revision: i32,
+    pub created_at: i64,
+}
+
+impl ToJson for PolicyRevision {
+    fn to_json(&self) -> Map<String, Value> {
+        let mut map = Map::new();
+        map.insert(String::from("revision"), Value::from(self.revision));
+        map.insert(String::from("created_at"), Value::from(self.created_at));
+
+        map
+    }
+}
+
+/// How an import is applied to the entities already in the tenant.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImportMode {
+    /// Creates or updates the imported entities, keeping the others.
+    Merge,
+
+    /// Deletes all the entities of the tenant before importing.
+    Replace,
+
+    /// Only computes the differences, without saving anything.
+    DryRun,
+}
+
+impl TryFrom<&str> for ImportMode {
+    type Error = Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "merge" => Ok(ImportMode::Merge),
+            "replace" => Ok(ImportMode::Replace),
+            "dry-run" => Ok(ImportMode::DryRun),
+            _ => Err(Error::new(
+                ErrorKind::InvalidSnapshotError,
+                format!(r#"Unknown import mode "{}""#, value),
+            )),
+        }
+    }
+}
+
+impl ImportMode {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ImportMode::Merge => "merge",
+            ImportMode::Replace => "replace",
+            ImportMode::DryRun => "dry-run",
+        }
+    }
+}
+
+/// Sorts the arrays contained in a value, so that entities
+/// differing only in the order of their lists compare equal.
+fn normalize(value: Value) -> Value {
+    match value {
+        Value::Array(values) => {
+            let mut values: Vec<Value> = values.into_iter().map(normalize).collect();
+            values.sort_by_cached_key(|v| v.to_string());
+            Value::Array(values)
+        }
+        Value::Object(map) => Value::Object(map.into_iter().map(|(k, v)| (k, normalize(v))).collect()),
+        value => value,
+    }
+}
+
+/// The ids of the entities of an export section, along with their normalized representation.
+fn section_entities(export: &Map<String, Value>, section: &str) -> BTreeMap<String, Value> {
+    export
+        .get(section)
+        .and_then(|v| v.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|entity| {
+            let id = entity.get("id")?.as_str()?.to_string();
+            Some((id, normalize(entity.clone())))
+        })
+        .collect()
+}
+
+/// The changes made (or, on dry runs, to be made) by an import,
+/// as the ids of the affected entities for each export section.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImportReport {
+    pub mode: ImportMode,
+    pub created: BTreeMap<String, Vec<String>>,
+    pub updated: BTreeMap<String, Vec<String>>,
+    pub deleted: BTreeMap<String, Vec<String>>,
+    pub unchanged: usize,
+}
+
+impl ImportReport {
+    /// Compares the entities currently in the tenant with the imported ones.
+    ///
+    /// Entities missing from the import are reported as deleted unless merging.
+    /// Dry runs report them too, as they would be deleted by a replace.
+    pub fn diff(mode: ImportMode, current: &TenantExport, imported: &TenantExport) -> Self {
+        let current = current.to_json();
+        let imported = imported.to_json();
+        let mut report = ImportReport {
+            mode,
+            created: BTreeMap::new(),
+            updated: BTreeMap::new(),
+            deleted: BTreeMap::new(),
+            unchanged: 0,
+        };
+
+        for section in EXPORT_SECTIONS {
+            let before = section_entities(&current, section);
+            let after = section_entities(&imported, section);
+
+            let mut created = vec![];
+            let mut updated = vec![];
+            for (id, entity) in &after {
+                match before.get(id) {
+                    Option::None => created.push(id.clone()),
+                    Option::Some(previous) if previous != entity => updated.push(id.clone()),
+                    Option::Some(_) => report.unchanged += 1,
+                }
+            }
+
+            let deleted: Vec<String> = if mode == ImportMode::Merge {
+                vec![]
+            } else {
+                before.keys().filter(|id| !after.contains_key(*id)).cloned().collect()
+            };
+
+            for (changes, ids) in vec![
+                (&mut report.created, created),
+                (&mut report.updated, updated),
+                (&mut report.deleted, deleted),
+            ] {
+                if !ids.is_empty() {
+                    changes.insert(section.to_string(), ids);
+                }
+            }
+        }
+
+        report
+    }
+}
+
+impl ToJson for ImportReport {
+    fn to_json(&self) -> Map<String, Value> {
+        let changes_to_value = |changes: &BTreeMap<String, Vec<String>>| {
+            Value::Object(changes.iter().map(|(k, v)| (k.clone(), Value::from(v.clone()))).collect())
+        };
+
+        let mut map = Map::new();
+        map.insert(String::from("mode"), Value::from(self.mode.as_str()));
+        map.insert(String::from("applied"), Value::from(self.mode != ImportMode::DryRun));
+        map.insert(String::from("created"), changes_to_value(&self.created));
+        map.insert(String::from("updated"), changes_to_value(&self.updated));
+        map.insert(String::from("deleted"), changes_to_value(&self.deleted));
+        map.insert(String::from("unchanged"), Value::from(self.unchanged));
+
+        map
+    }
+}
+
+/// Loads and saves the entities of a single tenant: every query is
+/// restricted to the tenant the manager has been scoped to.
 #[derive(Clone)]
 pub struct StorageManager {
     pool: Pool<Postgres>,
+    invalidator: Invalidator,
+    tenant: String,
 }
 
 impl StorageManager {
+    /// Creates a storage manager scoped to the default tenant.
     pub fn new(pool: Pool<Postgres>) -> Self {
-        StorageManager { pool }
+        let invalidator = Invalidator::from_env(pool.clone());
+        StorageManager {
+            pool,
+            invalidator,
+            tenant: DEFAULT_TENANT.to_string(),
+        }
+    }
+
+    /// Gets a storage manager sharing the same pool and
+    /// invalidator, scoped to the given tenant.
+    pub fn for_tenant<S: ToString>(&self, tenant: S) -> Self {
+        StorageManager {
+            pool: self.pool.clone(),
+            invalidator: self.invalidator.clone(),
+            tenant: tenant.to_string(),
+        }
+    }
+
+    pub fn get_tenant(&self) -> &str {
+        self.tenant.as_str()
+    }
+
+    /// Scopes an entity id to the tenant of this manager.
+    fn scoped(&self, id: &str) -> String {
+        scoped_id(&self.tenant, id)
+    }
+
+    /// Gets the invalidator used to broadcast the changes
+    /// to the other zephir instances.
+    pub fn get_invalidator(&self) -> &Invalidator {
+        &self.invalidator
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::file_store::parse_export;
+    use crate::storage::{ImportMode, ImportReport};
+    use std::collections::BTreeMap;
+
+    fn changes(entries: &[(&str, &[&str])]) -> BTreeMap<String, Vec<String>> {
+        entries
+            .iter()
+            .map(|(section, ids)| (section.to_string(), ids.iter().map(|id| id.to_string()).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn import_report_should_list_the_changes() {
+        let current = parse_export(
+            br#"{
+                "version": 1,
+                "policies": [
+                    { "id": "ReadOnly", "effect": "ALLOW", "actions": ["core:Get*", "core:List*"] },
+                    { "id": "Legacy", "effect": "DENY", "actions": ["*"] }
+                ],
+                "identities": [{ "id": "alice", "linked_policies": ["ReadOnly"] }],
+                "groups": [{ "id": "admins", "identities": ["alice"] }]
+            }"#,
+            "acme",
+        )
+        .unwrap();
+
+        let imported = parse_export(
+            br#"{
+                "version": 1,
+                "policies": [
+                    { "id": "ReadOnly", "effect": "ALLOW", "actions": ["core:List*", "core:Get*"] },
+                    { "id": "Admin", "effect": "ALLOW", "actions": ["*"] }
+                ],
+                "identities": [{ "id": "alice", "linked_policies": ["Admin"] }],
+                "groups": [{ "id": "admins", "identities": [{ "id": "alice", "not_before": null, "not_after": null }] }]
+            }"#,
+            "acme",
+        )
+        .unwrap();
+
+        assert_eq!(imported.groups[0].get_identities().len(), 1);
+
+        let report = ImportReport::diff(ImportMode::Merge, &current, &imported);
+        assert_eq!(report.created, changes(&[("policies", &["Admin"])]));
+        assert_eq!(report.updated, changes(&[("identities", &["alice"])]));
+        assert!(report.deleted.is_empty());
+        assert_eq!(report.unchanged, 2);
+
+        let report = ImportReport::diff(ImportMode::Replace, &current, &imported);
+        assert_eq!(report.deleted, changes(&[("policies", &["Legacy"])]));
+
+        assert!(parse_export(br#"{ "version": 2 }"#, "acme").is_err());
+        assert!(parse_export(br#"{ "identities": [{ "id": "bob", "linked_policies": ["Unknown"] }] }"#, "acme").is_err());
     }
 }
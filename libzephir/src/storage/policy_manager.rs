@@ -1,9 +1,11 @@
-use crate::compiler::compiler::cache;
+use crate::cache::invalidation::InvalidationEvent;
 use crate::err::Error;
+use crate::policy::condition::SubjectConditions;
+use crate::policy::diff::PolicyDiff;
 use crate::policy::policy::{CompletePolicy, MatchablePolicy};
 use crate::policy::{PolicyEffect, PolicyVersion};
-use crate::storage::types::DbPolicy;
-use crate::storage::StorageManager;
+use crate::storage::types::{DbPolicy, DbPolicyRevision};
+use crate::storage::{ChangeOperation, PolicyRevision, StorageManager};
 use serde_json::Value;
 use sqlx::{Postgres, Transaction};
 use std::convert::TryFrom;
@@ -15,11 +17,12 @@ impl StorageManager {
     {
         let policy = sqlx::query_as::<_, DbPolicy>(
             r#"
-            SELECT id, version, effect, actions, resources
+            SELECT tenant_id, id, version, effect, actions, resources, conditions
             FROM policy
-            WHERE id = $1
+            WHERE tenant_id = $1 AND id = $2
         "#,
         )
+        .bind(&self.tenant)
         .bind(id.to_string())
         .fetch_optional(&self.pool)
         .await?;
@@ -33,8 +36,14 @@ impl StorageManager {
     pub async fn save_policy(&self, p: &CompletePolicy) -> Result<(), Error> {
         let mut transaction = self.pool.begin().await?;
         self._save_policy(p, &mut transaction).await?;
+        self._record_change("policy", &p.id, ChangeOperation::Upsert, &mut transaction)
+            .await?;
 
         transaction.commit().await?;
+        self.invalidator
+            .publish(&[InvalidationEvent::Policy(self.scoped(&p.id))])
+            .await;
+
         Ok(())
     }
 
@@ -49,30 +58,135 @@ impl StorageManager {
 
         sqlx::query(
             r#"
-            INSERT INTO policy(id, version, effect, actions, resources)
-            VALUES ($1, $2, $3, $4, $5)
-            ON CONFLICT (id)
-            DO UPDATE SET version = $2, effect = $3, actions = $4, resources = $5
+            INSERT INTO policy(tenant_id, id, version, effect, actions, resources, conditions)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            ON CONFLICT (tenant_id, id)
+            DO UPDATE SET version = $3, effect = $4, actions = $5, resources = $6, conditions = $7
         "#,
         )
+        .bind(&self.tenant)
         .bind(id)
         .bind(version)
         .bind(effect)
         .bind(Value::from(p.get_actions()))
         .bind(Value::from(p.get_resources()))
-        .execute(transaction)
+        .bind(p.get_subject_conditions().map(|c| c.to_value()))
+        .execute(&mut *transaction)
+        .await?;
+
+        self._save_policy_revision(p, transaction).await
+    }
+
+    /// Records the policy as a new revision, unless it is the same as the last one.
+    async fn _save_policy_revision(
+        &self,
+        p: &CompletePolicy,
+        transaction: &mut Transaction<'_, Postgres>,
+    ) -> Result<(), Error> {
+        // The policy row is locked by the upsert: concurrent
+        // saves of the same policy cannot pick the same number.
+        let (last,): (Option<i32>,) = sqlx::query_as(
+            "SELECT MAX(revision) FROM policy_revision WHERE tenant_id = $1 AND policy_id = $2",
+        )
+        .bind(&self.tenant)
+        .bind(&p.id)
+        .fetch_one(&mut *transaction)
+        .await?;
+
+        if let Some(last) = last {
+            let previous = sqlx::query_as::<_, DbPolicy>(
+                r#"
+                SELECT tenant_id, policy_id AS id, version, effect, actions, resources, conditions
+                FROM policy_revision
+                WHERE tenant_id = $1 AND policy_id = $2 AND revision = $3
+            "#,
+            )
+            .bind(&self.tenant)
+            .bind(&p.id)
+            .bind(last)
+            .fetch_one(&mut *transaction)
+            .await?;
+
+            // Reordering the patterns is not worth a revision.
+            if PolicyDiff::between(&CompletePolicy::try_from(previous)?, p).is_empty() {
+                return Ok(());
+            }
+        }
+
+        let version: i32 = (&p.version).into();
+        let effect: bool = (&p.effect).into();
+        let revision = last.unwrap_or(0) + 1;
+
+        sqlx::query(
+            r#"
+            INSERT INTO policy_revision (tenant_id, policy_id, revision, version, effect, actions, resources, conditions)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        "#,
+        )
+        .bind(&self.tenant)
+        .bind(&p.id)
+        .bind(revision)
+        .bind(version)
+        .bind(effect)
+        .bind(Value::from(p.get_actions()))
+        .bind(Value::from(p.get_resources()))
+        .bind(p.get_subject_conditions().map(|c| c.to_value()))
+        .execute(&mut *transaction)
         .await?;
 
-        cache::flush_policy(&p.id);
         Ok(())
     }
+
+    /// Lists the saved revisions of a policy, oldest first.
+    pub async fn find_policy_revisions<S: ToString>(&self, id: S) -> Result<Vec<PolicyRevision>, Error> {
+        Ok(sqlx::query_as::<_, DbPolicyRevision>(
+            r#"
+            SELECT revision, extract(epoch FROM created_at)::bigint AS created_at
+            FROM policy_revision
+            WHERE tenant_id = $1 AND policy_id = $2
+            ORDER BY revision
+        "#,
+        )
+        .bind(&self.tenant)
+        .bind(id.to_string())
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|r| PolicyRevision {
+            revision: r.revision,
+            created_at: r.created_at,
+        })
+        .collect())
+    }
+
+    /// Finds the policy as it was saved in the given revision.
+    pub async fn find_policy_revision<S: ToString>(&self, id: S, revision: i32) -> Result<Option<CompletePolicy>, Error> {
+        let policy = sqlx::query_as::<_, DbPolicy>(
+            r#"
+            SELECT tenant_id, policy_id AS id, version, effect, actions, resources, conditions
+            FROM policy_revision
+            WHERE tenant_id = $1 AND policy_id = $2 AND revision = $3
+        "#,
+        )
+        .bind(&self.tenant)
+        .bind(id.to_string())
+        .bind(revision)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(match policy {
+            Option::None => Option::None,
+            Option::Some(policy) => Option::Some(CompletePolicy::try_from(policy)?),
+        })
+    }
 }
 
 impl TryFrom<DbPolicy> for CompletePolicy {
     type Error = Error;
 
     fn try_from(value: DbPolicy) -> Result<Self, Self::Error> {
-        CompletePolicy::new(
+        let policy = CompletePolicy::new_in_tenant(
+            &value.tenant_id,
             value.id,
             PolicyVersion::try_from(value.version)?,
             if value.effect {
@@ -82,6 +196,11 @@ impl TryFrom<DbPolicy> for CompletePolicy {
             },
             value.actions.to_vec(),
             value.resources.to_vec(),
-        )
+        )?;
+
+        Ok(match value.conditions {
+            Option::None => policy,
+            Option::Some(conditions) => policy.with_subject_conditions(SubjectConditions::new(conditions.0)),
+        })
     }
 }
@@ -0,0 +1,156 @@
+use crate::cache::invalidation::InvalidationEvent;
+use crate::cache::tenant_cache::RESOURCE_POLICY_CACHE;
+use crate::err::Error;
+use crate::policy::policy::{CompletePolicy, MatchablePolicy};
+use crate::policy::resource_policy::{ResourcePolicies, ResourcePolicy};
+use crate::storage::types::DbResourcePolicy;
+use crate::storage::{ChangeOperation, StorageManager};
+use serde_json::Value;
+use sqlx::{Postgres, Transaction};
+use std::convert::TryFrom;
+
+impl TryFrom<DbResourcePolicy> for ResourcePolicy {
+    type Error = Error;
+
+    fn try_from(value: DbResourcePolicy) -> Result<Self, Self::Error> {
+        let (policy, principals) = value.into_parts();
+        Ok(ResourcePolicy::new(CompletePolicy::try_from(policy)?, principals))
+    }
+}
+
+impl StorageManager {
+    /// Loads all the resource policies of the tenant.
+    pub async fn find_resource_policies(&self) -> Result<ResourcePolicies, Error> {
+        if let Some(policies) = RESOURCE_POLICY_CACHE.get(&self.tenant) {
+            return Ok(policies);
+        }
+
+        let generation = RESOURCE_POLICY_CACHE.generation();
+        let policies = sqlx::query_as::<_, DbResourcePolicy>(
+            r#"
+            SELECT tenant_id, id, version, effect, actions, resources, conditions,
+                   principal_identities, principal_groups, principal_roles
+            FROM resource_policy
+            WHERE tenant_id = $1
+            ORDER BY id
+        "#,
+        )
+        .bind(&self.tenant)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut result = vec![];
+        for policy in policies {
+            result.push(ResourcePolicy::try_from(policy)?);
+        }
+
+        let policies = ResourcePolicies::new(result);
+        RESOURCE_POLICY_CACHE.insert(&self.tenant, &policies, generation);
+
+        Ok(policies)
+    }
+
+    pub async fn find_resource_policy<S>(&self, id: S) -> Result<Option<ResourcePolicy>, Error>
+    where
+        S: ToString,
+    {
+        let policy = sqlx::query_as::<_, DbResourcePolicy>(
+            r#"
+            SELECT tenant_id, id, version, effect, actions, resources, conditions,
+                   principal_identities, principal_groups, principal_roles
+            FROM resource_policy
+            WHERE tenant_id = $1 AND id = $2
+        "#,
+        )
+        .bind(&self.tenant)
+        .bind(id.to_string())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(match policy {
+            Option::None => Option::None,
+            Option::Some(policy) => Option::Some(ResourcePolicy::try_from(policy)?),
+        })
+    }
+
+    pub async fn save_resource_policy(&self, p: &ResourcePolicy) -> Result<(), Error> {
+        let mut transaction = self.pool.begin().await?;
+        self._save_resource_policy(p, &mut transaction).await?;
+        self._record_change("resource_policy", &p.get_policy().id, ChangeOperation::Upsert, &mut transaction)
+            .await?;
+
+        transaction.commit().await?;
+        self.invalidator
+            .publish(&[InvalidationEvent::ResourcePolicies(self.tenant.clone())])
+            .await;
+
+        Ok(())
+    }
+
+    pub(super) async fn _save_resource_policy(
+        &self,
+        p: &ResourcePolicy,
+        transaction: &mut Transaction<'_, Postgres>,
+    ) -> Result<(), Error> {
+        let policy = p.get_policy();
+        let principals = p.get_principals();
+        let version: i32 = (&policy.version).into();
+        let effect: bool = (&policy.effect).into();
+
+        sqlx::query(
+            r#"
+            INSERT INTO resource_policy(tenant_id, id, version, effect, actions, resources, conditions,
+                                        principal_identities, principal_groups, principal_roles)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+            ON CONFLICT (tenant_id, id)
+            DO UPDATE SET version = $3, effect = $4, actions = $5, resources = $6, conditions = $7,
+                          principal_identities = $8, principal_groups = $9, principal_roles = $10
+        "#,
+        )
+        .bind(&self.tenant)
+        .bind(&policy.id)
+        .bind(version)
+        .bind(effect)
+        .bind(Value::from(policy.get_actions()))
+        .bind(Value::from(policy.get_resources()))
+        .bind(policy.get_subject_conditions().map(|c| c.to_value()))
+        .bind(Value::from(principals.get_identities().clone()))
+        .bind(Value::from(principals.get_groups().clone()))
+        .bind(Value::from(principals.get_roles().clone()))
+        .execute(transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Deletes a resource policy.
+    ///
+    /// # Returns
+    ///
+    /// Whether the resource policy existed
+    pub async fn delete_resource_policy<S>(&self, id: S) -> Result<bool, Error>
+    where
+        S: ToString,
+    {
+        let id = id.to_string();
+        let mut transaction = self.pool.begin().await?;
+        let result = sqlx::query("DELETE FROM resource_policy WHERE tenant_id = $1 AND id = $2")
+            .bind(&self.tenant)
+            .bind(&id)
+            .execute(&mut transaction)
+            .await?;
+
+        if result.rows_affected() > 0 {
+            self._record_change("resource_policy", &id, ChangeOperation::Delete, &mut transaction)
+                .await?;
+        }
+
+        transaction.commit().await?;
+
+        self.invalidator
+            .publish(&[InvalidationEvent::ResourcePolicies(self.tenant.clone())])
+            .await;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
@@ -0,0 +1,256 @@
+use crate::cache::invalidation::InvalidationEvent;
+use crate::err::Error;
+use crate::identity::assumable_role::{AssumableRole, RoleSession, TrustPolicy};
+use crate::identity::identity::Identity;
+use crate::identity::role::Role;
+use crate::identity::subject::Subject;
+use crate::policy::policy::{CompletePolicy, MatchablePolicy};
+use crate::policy::policy_set::PolicySetTrait;
+use crate::storage::types::{DbPolicy, DbRole, DbRoleSession};
+use crate::storage::{ChangeOperation, StorageManager};
+use serde_json::Value;
+use sqlx::{PgConnection, Postgres, Transaction};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::time::Duration;
+
+const SESSION_COLUMNS: &str = r#"
+    tenant_id, id, role_id, identity_id,
+    policy_version AS version, policy_effect AS effect,
+    policy_actions AS actions, policy_resources AS resources,
+    policy_conditions AS conditions,
+    extract(epoch FROM expires_at)::bigint AS expires_at
+"#;
+
+impl StorageManager {
+    pub async fn find_role<S>(&self, id: S) -> Result<Option<AssumableRole>, Error>
+    where
+        S: ToString,
+    {
+        self._find_role(&id.to_string(), &mut *self.pool.acquire().await?).await
+    }
+
+    pub(super) async fn _find_role(&self, id: &str, conn: &mut PgConnection) -> Result<Option<AssumableRole>, Error> {
+        let role = sqlx::query_as::<_, DbRole>(
+            r#"
+            SELECT id, policy_id, trusted_identities, trusted_groups
+            FROM role
+            WHERE tenant_id = $1 AND id = $2
+        "#,
+        )
+        .bind(&self.tenant)
+        .bind(id)
+        .fetch_optional(&mut *conn)
+        .await?;
+
+        let role = match role {
+            Option::None => return Ok(Option::None),
+            Option::Some(role) => role,
+        };
+
+        let policies = sqlx::query_as::<_, DbPolicy>(
+            r#"
+            SELECT tenant_id, id, version, effect, actions, resources, conditions
+            FROM policy
+            WHERE tenant_id = $1
+              AND (id = $2 OR id IN (SELECT policy_id FROM role_policy WHERE tenant_id = $1 AND role_id = $3))
+        "#,
+        )
+        .bind(&self.tenant)
+        .bind(&role.policy_id)
+        .bind(id)
+        .fetch_all(&mut *conn)
+        .await?;
+
+        let priorities: HashMap<String, i32> = sqlx::query_as::<_, (String, i32)>(
+            "SELECT policy_id, priority FROM role_policy WHERE tenant_id = $1 AND role_id = $2",
+        )
+        .bind(&self.tenant)
+        .bind(id)
+        .fetch_all(&mut *conn)
+        .await?
+        .into_iter()
+        .collect();
+
+        let trust_policy = TrustPolicy {
+            identities: role.trusted_identities.to_vec(),
+            groups: role.trusted_groups.to_vec(),
+        };
+
+        let mut inline_policy = Option::None;
+        let mut linked_policies = vec![];
+        for policy in policies {
+            let policy = CompletePolicy::try_from(policy)?;
+            if Option::Some(&policy.id) == role.policy_id.as_ref() {
+                inline_policy = Option::Some(policy);
+            } else {
+                linked_policies.push(policy);
+            }
+        }
+
+        let mut role = AssumableRole::new(role.id, inline_policy, trust_policy);
+        for policy in linked_policies {
+            let priority = priorities.get(&policy.id).copied().unwrap_or_default();
+            role = role.add_policy_with_priority(policy, priority);
+        }
+
+        Ok(Option::Some(role))
+    }
+
+    pub async fn save_role(&self, r: &AssumableRole) -> Result<(), Error> {
+        let mut transaction = self.pool.begin().await?;
+        let events = self._save_role(r, &mut transaction).await?;
+        self._record_change("role", &r.id, ChangeOperation::Upsert, &mut transaction)
+            .await?;
+
+        transaction.commit().await?;
+        self.invalidator.publish(&events).await;
+
+        Ok(())
+    }
+
+    /// Saves a role into the given transaction.
+    ///
+    /// # Returns
+    ///
+    /// The invalidation events to be published once the transaction is committed
+    pub(super) async fn _save_role(
+        &self,
+        r: &AssumableRole,
+        transaction: &mut Transaction<'_, Postgres>,
+    ) -> Result<Vec<InvalidationEvent>, Error> {
+        let embedded_policy = r.get_inline_policy();
+        let embedded_policy_id = if let Some(embedded_policy) = embedded_policy {
+            self._save_policy(embedded_policy, &mut *transaction).await?;
+            embedded_policy.id.clone()
+        } else {
+            let policy_id = "__embedded_policy_role_".to_owned() + r.id.as_str() + "__";
+            sqlx::query("DELETE FROM policy WHERE tenant_id = $1 AND id = $2")
+                .bind(&self.tenant)
+                .bind(&policy_id)
+                .execute(&mut *transaction)
+                .await?;
+            policy_id
+        };
+
+        sqlx::query(
+            r#"
+            INSERT INTO role(tenant_id, id, policy_id, trusted_identities, trusted_groups)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (tenant_id, id) DO UPDATE SET policy_id = $3, trusted_identities = $4, trusted_groups = $5
+        "#,
+        )
+        .bind(&self.tenant)
+        .bind(&r.id)
+        .bind(embedded_policy.map(|p| &p.id))
+        .bind(Value::from(r.trust_policy.identities.clone()))
+        .bind(Value::from(r.trust_policy.groups.clone()))
+        .execute(&mut *transaction)
+        .await?;
+
+        sqlx::query("DELETE FROM role_policy WHERE tenant_id = $1 AND role_id = $2")
+            .bind(&self.tenant)
+            .bind(&r.id)
+            .execute(&mut *transaction)
+            .await?;
+
+        let linked_policies = r.linked_policies();
+        for p in linked_policies {
+            sqlx::query(
+                r#"
+                INSERT INTO role_policy (tenant_id, role_id, policy_id, priority)
+                VALUES ($1, $2, $3, $4)
+            "#,
+            )
+            .bind(&self.tenant)
+            .bind(&r.id)
+            .bind(&p.id)
+            .bind(linked_policies.get_priority(&p.id).unwrap_or_default())
+            .execute(&mut *transaction)
+            .await?;
+        }
+
+        Ok(vec![InvalidationEvent::Policy(self.scoped(&embedded_policy_id))])
+    }
+
+    /// Opens a session for the given identity assuming the given role.
+    ///
+    /// The caller is responsible for checking the role trust policy
+    /// before opening the session.
+    pub async fn create_role_session(
+        &self,
+        role: &AssumableRole,
+        identity: &Identity,
+        session_policy: Option<&CompletePolicy>,
+        duration: Duration,
+    ) -> Result<RoleSession, Error> {
+        let query = format!(
+            r#"
+            INSERT INTO role_session (tenant_id, role_id, identity_id, policy_version, policy_effect, policy_actions, policy_resources, policy_conditions, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, now() + make_interval(secs => $9))
+            RETURNING {}
+        "#,
+            SESSION_COLUMNS
+        );
+
+        let session = sqlx::query_as::<_, DbRoleSession>(query.as_str())
+            .bind(&self.tenant)
+            .bind(&role.id)
+            .bind(&identity.id)
+            .bind(session_policy.map(|p| i32::from(&p.version)))
+            .bind(session_policy.map(|p| bool::from(&p.effect)))
+            .bind(session_policy.map(|p| Value::from(p.get_actions())))
+            .bind(session_policy.map(|p| Value::from(p.get_resources())))
+            .bind(session_policy.and_then(|p| p.get_subject_conditions()).map(|c| c.to_value()))
+            .bind(duration.as_secs_f64())
+            .fetch_one(&self.pool)
+            .await?;
+
+        RoleSession::try_from(session)
+    }
+
+    /// Finds a role session. Expired sessions are not returned.
+    pub async fn find_role_session<S>(&self, id: S) -> Result<Option<RoleSession>, Error>
+    where
+        S: ToString,
+    {
+        let query = format!(
+            r#"
+            SELECT {}
+            FROM role_session
+            WHERE tenant_id = $1 AND id = $2 AND expires_at > now()
+        "#,
+            SESSION_COLUMNS
+        );
+
+        let session = sqlx::query_as::<_, DbRoleSession>(query.as_str())
+            .bind(&self.tenant)
+            .bind(id.to_string())
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(match session {
+            Option::None => Option::None,
+            Option::Some(session) => Option::Some(RoleSession::try_from(session)?),
+        })
+    }
+}
+
+impl TryFrom<DbRoleSession> for RoleSession {
+    type Error = Error;
+
+    fn try_from(mut value: DbRoleSession) -> Result<Self, Self::Error> {
+        let policy = match value.take_policy() {
+            Option::None => Option::None,
+            Option::Some(policy) => Option::Some(CompletePolicy::try_from(policy)?),
+        };
+
+        Ok(RoleSession {
+            id: value.id,
+            role_id: value.role_id,
+            identity_id: value.identity_id,
+            policy,
+            expires_at: value.expires_at,
+        })
+    }
+}
@@ -0,0 +1,361 @@
+use crate::cache::subject_cache::SUBJECT_CACHE;
+use crate::err::Error;
+use crate::identity::group::Group;
+use crate::identity::identity::Identity;
+use crate::identity::membership_rule::MembershipRule;
+use crate::policy::condition::Attributes;
+use crate::policy::policy::CompletePolicy;
+use crate::policy::policy_set::{PolicyLink, PolicySetTrait};
+use crate::policy::validity::Validity;
+use crate::storage::types::{DbAncestor, DbMembership, DbNestedGroup, DbSubjectPolicy};
+use crate::storage::StorageManager;
+use sqlx::PgConnection;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+/// Loads the requested identities and groups along with their inline,
+/// boundary and linked policies, one row per (subject, policy) pair.
+/// Linked policies are returned ordered by their link priority.
+///
+/// The groups of an identity are loaded transitively, following the
+/// nested groups memberships: each of them is returned along with the
+/// shortest path through which the identity inherits it.
+/// Only the currently effective identity memberships are followed, while
+/// the next time one of them starts or ends is returned on every row.
+/// The dynamic groups the identity matches the rule of are passed in
+/// and followed as its direct memberships.
+///
+/// Every entity is loaded from the given tenant only.
+///
+/// $1: identity ids, $2: group ids, $3: identity whose groups should be loaded,
+/// $4: dynamic groups the identity belongs to, $5: tenant.
+const SUBJECTS_QUERY: &str = r#"
+    WITH RECURSIVE membership AS (
+        SELECT group_id, ARRAY[group_id]::text[] AS path
+        FROM group_identity
+        WHERE tenant_id = $5 AND identity_id = $3
+          AND (not_before IS NULL OR not_before <= now())
+          AND (not_after IS NULL OR not_after > now())
+        UNION ALL
+        SELECT id, ARRAY[id]::text[]
+        FROM "group"
+        WHERE tenant_id = $5 AND id = ANY($4)
+        UNION ALL
+        SELECT gg.group_id, m.path || gg.group_id::text
+        FROM group_group gg
+        INNER JOIN membership m ON gg.member_group_id = m.group_id
+        WHERE gg.tenant_id = $5 AND NOT gg.group_id = ANY(m.path)
+    ), membership_change AS (
+        SELECT min(t) AS changes_at
+        FROM group_identity gi, unnest(ARRAY[gi.not_before, gi.not_after]) t
+        WHERE gi.tenant_id = $5 AND gi.identity_id = $3 AND t > now()
+    ), closure AS (
+        SELECT DISTINCT ON (group_id) group_id, path
+        FROM membership
+        ORDER BY group_id, cardinality(path)
+    ), subject AS (
+        SELECT 'identity' AS owner_type, id AS owner_id, policy_id, boundary_policy_id, NULL::text[] AS inheritance_path, attributes,
+               NULL::text AS membership_rule
+        FROM identity
+        WHERE tenant_id = $5 AND id = ANY($1)
+        UNION ALL
+        SELECT 'group', g.id, g.policy_id, g.boundary_policy_id, c.path, g.attributes, g.membership_rule
+        FROM "group" g
+        LEFT JOIN closure c ON c.group_id = g.id
+        WHERE g.tenant_id = $5 AND (g.id = ANY($2) OR c.group_id IS NOT NULL)
+    ), link AS (
+        SELECT owner_type, owner_id, 'inline' AS link_type, policy_id, 0 AS priority,
+               NULL::timestamptz AS not_before, NULL::timestamptz AS not_after
+        FROM subject
+        WHERE policy_id IS NOT NULL
+        UNION ALL
+        SELECT owner_type, owner_id, 'boundary', boundary_policy_id, 0, NULL, NULL
+        FROM subject
+        WHERE boundary_policy_id IS NOT NULL
+        UNION ALL
+        SELECT s.owner_type, s.owner_id, 'linked', ip.policy_id, ip.priority, ip.not_before, ip.not_after
+        FROM subject s
+        INNER JOIN identity_policy ip ON s.owner_type = 'identity' AND ip.tenant_id = $5 AND ip.identity_id = s.owner_id
+        UNION ALL
+        SELECT s.owner_type, s.owner_id, 'linked', gp.policy_id, gp.priority, gp.not_before, gp.not_after
+        FROM subject s
+        INNER JOIN group_policy gp ON s.owner_type = 'group' AND gp.tenant_id = $5 AND gp.group_id = s.owner_id
+    )
+    SELECT s.owner_type, s.owner_id, s.inheritance_path, s.attributes, s.membership_rule, l.link_type, l.priority,
+           extract(epoch FROM l.not_before)::bigint AS not_before,
+           extract(epoch FROM l.not_after)::bigint AS not_after,
+           extract(epoch FROM (SELECT changes_at FROM membership_change))::bigint AS memberships_change_at,
+           p.tenant_id, p.id, p.version, p.effect, p.actions, p.resources, p.conditions
+    FROM subject s
+    LEFT JOIN link l ON l.owner_type = s.owner_type AND l.owner_id = s.owner_id
+    LEFT JOIN policy p ON p.tenant_id = $5 AND p.id = l.policy_id
+    ORDER BY cardinality(s.inheritance_path) NULLS FIRST, s.owner_id, l.priority, l.policy_id
+"#;
+
+/// Loads all the groups the given group is (transitively) nested into,
+/// along with the shortest path through which it inherits them.
+///
+/// $1: group id, $2: tenant.
+const ANCESTORS_QUERY: &str = r#"
+    WITH RECURSIVE ancestor AS (
+        SELECT group_id, ARRAY[group_id]::text[] AS path
+        FROM group_group
+        WHERE tenant_id = $2 AND member_group_id = $1
+        UNION ALL
+        SELECT gg.group_id, a.path || gg.group_id::text
+        FROM group_group gg
+        INNER JOIN ancestor a ON gg.member_group_id = a.group_id
+        WHERE gg.tenant_id = $2 AND NOT gg.group_id = ANY(a.path) AND gg.group_id <> $1
+    )
+    SELECT DISTINCT ON (group_id) group_id, path
+    FROM ancestor
+    ORDER BY group_id, cardinality(path)
+"#;
+
+#[derive(Default)]
+pub(super) struct LoadedSubjects {
+    pub(super) identities: Vec<Identity>,
+    pub(super) groups: Vec<Group>,
+
+    /// Next time (as unix timestamp) the loaded groups memberships will change.
+    pub(super) memberships_change_at: Option<i64>,
+}
+
+struct SubjectRows {
+    owner_type: String,
+    owner_id: String,
+    inheritance_path: Option<Vec<String>>,
+    attributes: Attributes,
+    membership_rule: Option<String>,
+
+    inline_policy: Option<CompletePolicy>,
+    boundary: Option<CompletePolicy>,
+    linked_policies: Vec<(CompletePolicy, PolicyLink)>,
+}
+
+impl StorageManager {
+    /// Loads an identity and all the groups it belongs to in a single round trip.
+    ///
+    /// Groups are loaded without their members, as they are only needed
+    /// to evaluate the identity permissions.
+    pub async fn find_subject<S>(&self, id: S) -> Result<Option<(Identity, Vec<Group>)>, Error>
+    where
+        S: ToString,
+    {
+        let id = id.to_string();
+        if let (Some(identity), Some(groups)) =
+            (SUBJECT_CACHE.get_identity(&self.tenant, &id), SUBJECT_CACHE.get_groups(&self.tenant, &id))
+        {
+            return Ok(Option::Some((identity, groups)));
+        }
+
+        let generation = SUBJECT_CACHE.generation();
+        let mut loaded = self
+            ._load_subjects(std::slice::from_ref(&id), &[], Option::Some(&id), &mut *self.pool.acquire().await?)
+            .await?;
+
+        let identity = match loaded.identities.pop() {
+            Option::None => return Ok(Option::None),
+            Option::Some(identity) => identity,
+        };
+
+        SUBJECT_CACHE.insert_identity(&self.tenant, &identity, generation);
+        SUBJECT_CACHE.insert_groups(&self.tenant, &id, &loaded.groups, loaded.memberships_change_at, generation);
+
+        Ok(Option::Some((identity, loaded.groups)))
+    }
+
+    /// Loads identities and groups (without members) with their policies
+    /// executing a single query.
+    pub(super) async fn _load_subjects(
+        &self,
+        identity_ids: &[String],
+        group_ids: &[String],
+        groups_of: Option<&str>,
+        conn: &mut PgConnection,
+    ) -> Result<LoadedSubjects, Error> {
+        let dynamic_groups = match groups_of {
+            Option::None => vec![],
+            Option::Some(identity_id) => self._find_dynamic_groups_of(identity_id).await?,
+        };
+
+        let rows = sqlx::query_as::<_, DbSubjectPolicy>(SUBJECTS_QUERY)
+            .bind(identity_ids)
+            .bind(group_ids)
+            .bind(groups_of)
+            .bind(&dynamic_groups)
+            .bind(&self.tenant)
+            .fetch_all(&mut *conn)
+            .await?;
+
+        let mut result = LoadedSubjects::default();
+        let mut subjects: Vec<SubjectRows> = vec![];
+        let mut positions = HashMap::new();
+        for mut row in rows {
+            result.memberships_change_at = row.memberships_change_at;
+
+            let key = (row.owner_type.clone(), row.owner_id.clone());
+            let position = *positions.entry(key).or_insert_with(|| {
+                subjects.push(SubjectRows {
+                    owner_type: row.owner_type.clone(),
+                    owner_id: row.owner_id.clone(),
+                    inheritance_path: row.inheritance_path.clone(),
+                    attributes: row.attributes.0.clone(),
+                    membership_rule: row.membership_rule.clone(),
+                    inline_policy: Option::None,
+                    boundary: Option::None,
+                    linked_policies: vec![],
+                });
+
+                subjects.len() - 1
+            });
+
+            if let Some(policy) = row.take_policy() {
+                let policy = CompletePolicy::try_from(policy)?;
+                let subject = &mut subjects[position];
+                match row.link_type.as_deref() {
+                    Option::Some("inline") => subject.inline_policy = Option::Some(policy),
+                    Option::Some("boundary") => subject.boundary = Option::Some(policy),
+                    _ => subject.linked_policies.push((
+                        policy,
+                        PolicyLink::new(
+                            row.priority.unwrap_or_default(),
+                            Validity::new(row.not_before, row.not_after),
+                        ),
+                    )),
+                }
+            }
+        }
+
+        for subject in subjects {
+            if subject.owner_type == "identity" {
+                let mut identity = Identity::new(subject.owner_id, subject.inline_policy)
+                    .set_attributes(subject.attributes);
+                identity.boundary = subject.boundary;
+                for (policy, link) in subject.linked_policies {
+                    identity = identity.add_policy_link(policy, link);
+                }
+
+                result.identities.push(identity);
+            } else {
+                let mut group = Group::new(subject.owner_id, subject.inline_policy)
+                    .with_inheritance_path(subject.inheritance_path.unwrap_or_default())
+                    .set_attributes(subject.attributes);
+                group.boundary = subject.boundary;
+                if let Some(rule) = subject.membership_rule {
+                    group = group.set_membership_rule(MembershipRule::try_from(rule.as_str())?);
+                }
+
+                for (policy, link) in subject.linked_policies {
+                    group = group.add_policy_link(policy, link);
+                }
+
+                result.groups.push(group);
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Finds all the groups the given group is nested into, directly or not.
+    ///
+    /// Groups are loaded without their members, ordered by the
+    /// length of their inheritance path.
+    pub async fn find_group_ancestors<S>(&self, id: S) -> Result<Vec<Group>, Error>
+    where
+        S: ToString,
+    {
+        let ancestors = sqlx::query_as::<_, DbAncestor>(ANCESTORS_QUERY)
+            .bind(id.to_string())
+            .bind(&self.tenant)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let group_ids: Vec<String> = ancestors.iter().map(|a| a.group_id.clone()).collect();
+        let mut paths: HashMap<String, Vec<String>> = ancestors
+            .into_iter()
+            .map(|a| (a.group_id, a.path))
+            .collect();
+
+        let mut groups: Vec<Group> = self
+            ._load_subjects(&[], &group_ids, Option::None, &mut *self.pool.acquire().await?)
+            .await?
+            .groups
+            .into_iter()
+            .map(|g| {
+                let path = paths.remove(&g.name).unwrap_or_default();
+                g.with_inheritance_path(path)
+            })
+            .collect();
+
+        groups.sort_by(|a, b| {
+            a.inheritance_path
+                .len()
+                .cmp(&b.inheritance_path.len())
+                .then_with(|| a.name.cmp(&b.name))
+        });
+
+        Ok(groups)
+    }
+
+    /// Loads the members (identities and nested groups) of the given groups.
+    /// Executes a fixed number of queries, whatever the number of groups and members is.
+    pub(super) async fn _load_members(&self, groups: Vec<Group>, conn: &mut PgConnection) -> Result<Vec<Group>, Error> {
+        let group_ids: Vec<String> = groups.iter().map(|g| g.name.clone()).collect();
+        let memberships = sqlx::query_as::<_, DbMembership>(
+            r#"
+            SELECT group_id, identity_id,
+                   extract(epoch FROM not_before)::bigint AS not_before,
+                   extract(epoch FROM not_after)::bigint AS not_after
+            FROM group_identity
+            WHERE tenant_id = $1 AND group_id = ANY($2)
+        "#,
+        )
+        .bind(&self.tenant)
+        .bind(&group_ids)
+        .fetch_all(&mut *conn)
+        .await?;
+
+        let nested_groups = sqlx::query_as::<_, DbNestedGroup>(
+            r#"
+            SELECT group_id, member_group_id
+            FROM group_group
+            WHERE tenant_id = $1 AND group_id = ANY($2)
+        "#,
+        )
+        .bind(&self.tenant)
+        .bind(&group_ids)
+        .fetch_all(&mut *conn)
+        .await?;
+
+        let identity_ids: Vec<String> = memberships.iter().map(|m| m.identity_id.clone()).collect();
+        let identities: HashMap<String, Identity> = self
+            ._load_subjects(&identity_ids, &[], Option::None, conn)
+            .await?
+            .identities
+            .into_iter()
+            .map(|i| (i.id.clone(), i))
+            .collect();
+
+        let mut result = vec![];
+        for mut group in groups {
+            let group_id = group.name.clone();
+            for membership in memberships.iter().filter(|m| m.group_id == group_id) {
+                if let Some(identity) = identities.get(&membership.identity_id) {
+                    group = group.add_identity_with_validity(
+                        identity.clone(),
+                        Validity::new(membership.not_before, membership.not_after),
+                    );
+                }
+            }
+
+            for nested in nested_groups.iter().filter(|n| n.group_id == group_id) {
+                group = group.add_group(&nested.member_group_id);
+            }
+
+            result.push(group);
+        }
+
+        Ok(result)
+    }
+}
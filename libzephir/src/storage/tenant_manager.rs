@@ -0,0 +1,290 @@
+use crate::cache::invalidation::InvalidationEvent;
+use crate::err::Error;
+use crate::identity::group::Group;
+use crate::policy::policy::CompletePolicy;
+use crate::policy::resource_policy::ResourcePolicy;
+use crate::storage::types::{DbPolicy, DbResourcePolicy};
+use crate::storage::{ChangeOperation, ImportMode, ImportReport, StorageManager, TenantExport};
+use sqlx::{PgConnection, Postgres, Transaction};
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+
+/// The tables holding the tenant entities, in an order
+/// respecting the foreign keys between them.
+const TENANT_TABLES: &[&str] = &[
+    "role_session",
+    "role_policy",
+    "group_group",
+    "group_identity",
+    "group_policy",
+    "identity_policy",
+    "identity",
+    r#""group""#,
+    "role",
+    "guardrail",
+    "resource_policy",
+    "policy",
+];
+
+impl StorageManager {
+    async fn _find_ids(&self, table: &str, conn: &mut PgConnection) -> Result<Vec<String>, Error> {
+        let query = format!("SELECT id FROM {} WHERE tenant_id = $1 ORDER BY id", table);
+        Ok(sqlx::query_as::<_, (String,)>(query.as_str())
+            .bind(&self.tenant)
+            .fetch_all(conn)
+            .await?
+            .into_iter()
+            .map(|(id,)| id)
+            .collect())
+    }
+
+    /// Loads all the entities of the tenant, from a consistent snapshot of the storage.
+    ///
+    /// Inline policies are exported along with their subject only.
+    pub async fn export_tenant(&self) -> Result<TenantExport, Error> {
+        Ok(self.export_tenant_with_last_change().await?.1)
+    }
+
+    /// Loads all the entities of the tenant along with the sequence number
+    /// of the last change they include, both read from the same snapshot.
+    pub async fn export_tenant_with_last_change(&self) -> Result<(i64, TenantExport), Error> {
+        let mut transaction = self.pool.begin().await?;
+        sqlx::query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ, READ ONLY")
+            .execute(&mut transaction)
+            .await?;
+
+        let seq = self._last_change_seq(&mut transaction).await?;
+        let export = self._export_tenant(&mut transaction).await?;
+        transaction.commit().await?;
+
+        Ok((seq, export))
+    }
+
+    async fn _export_tenant(&self, conn: &mut PgConnection) -> Result<TenantExport, Error> {
+        let mut policies = vec![];
+        for policy in sqlx::query_as::<_, DbPolicy>(
+            r#"
+            SELECT tenant_id, id, version, effect, actions, resources, conditions
+            FROM policy
+            WHERE tenant_id = $1 AND id NOT LIKE '\_\_embedded\_policy\_%'
+            ORDER BY id
+        "#,
+        )
+        .bind(&self.tenant)
+        .fetch_all(&mut *conn)
+        .await?
+        {
+            policies.push(CompletePolicy::try_from(policy)?);
+        }
+
+        let identity_ids = self._find_ids("identity", conn).await?;
+        let identities = self._load_subjects(&identity_ids, &[], Option::None, conn).await?.identities;
+
+        let group_ids = self._find_ids(r#""group""#, conn).await?;
+        let groups = self._load_subjects(&[], &group_ids, Option::None, conn).await?.groups;
+        let groups = self._load_members(groups, conn).await?;
+
+        let mut roles = vec![];
+        for id in self._find_ids("role", conn).await? {
+            if let Some(role) = self._find_role(&id, conn).await? {
+                roles.push(role);
+            }
+        }
+
+        let mut resource_policies = vec![];
+        for policy in sqlx::query_as::<_, DbResourcePolicy>(
+            r#"
+            SELECT tenant_id, id, version, effect, actions, resources, conditions,
+                   principal_identities, principal_groups, principal_roles
+            FROM resource_policy
+            WHERE tenant_id = $1
+            ORDER BY id
+        "#,
+        )
+        .bind(&self.tenant)
+        .fetch_all(&mut *conn)
+        .await?
+        {
+            resource_policies.push(ResourcePolicy::try_from(policy)?);
+        }
+
+        let mut guardrails = vec![];
+        for policy in sqlx::query_as::<_, DbPolicy>(
+            r#"
+            SELECT tenant_id, id, version, effect, actions, resources, conditions
+            FROM guardrail
+            WHERE tenant_id = $1
+            ORDER BY id
+        "#,
+        )
+        .bind(&self.tenant)
+        .fetch_all(&mut *conn)
+        .await?
+        {
+            guardrails.push(CompletePolicy::try_from(policy)?);
+        }
+
+        Ok(TenantExport {
+            tenant: self.tenant.clone(),
+            policies,
+            identities,
+            groups,
+            roles,
+            guardrails,
+            resource_policies,
+        })
+    }
+
+    /// Deletes all the entities of the tenant.
+    ///
+    /// # Returns
+    ///
+    /// The number of deleted rows
+    pub async fn delete_tenant(&self) -> Result<u64, Error> {
+        let mut transaction = self.pool.begin().await?;
+        let deleted = self._delete_tenant(&mut transaction).await?;
+        let tenant = self.tenant.clone();
+        self._record_change("tenant", &tenant, ChangeOperation::Delete, &mut transaction)
+            .await?;
+
+        // Webhooks and policy revisions are not part of the tenant data
+        // set (imports keep them): they only go away with the tenant itself.
+        for table in &["webhook", "policy_revision"] {
+            let query = format!("DELETE FROM {} WHERE tenant_id = $1", table);
+            sqlx::query(query.as_str())
+                .bind(&self.tenant)
+                .execute(&mut transaction)
+                .await?;
+        }
+
+        transaction.commit().await?;
+
+        // Cached entries are scoped to their tenant, but there is
+        // no cheap way to enumerate them: flush everything.
+        self.invalidator.publish(&[InvalidationEvent::All]).await;
+
+        Ok(deleted)
+    }
+
+    async fn _delete_tenant(&self, transaction: &mut Transaction<'_, Postgres>) -> Result<u64, Error> {
+        let mut deleted = 0;
+        for table in TENANT_TABLES {
+            let query = format!("DELETE FROM {} WHERE tenant_id = $1", table);
+            deleted += sqlx::query(query.as_str())
+                .bind(&self.tenant)
+                .execute(&mut *transaction)
+                .await?
+                .rows_affected();
+        }
+
+        Ok(deleted)
+    }
+
+    /// Imports the given entities into the tenant, in a single transaction.
+    ///
+    /// The imported entities are expected to be consistent with each other
+    /// (see `file_store::parse_export`). Role sessions are not exported,
+    /// and are lost when replacing the tenant.
+    ///
+    /// # Returns
+    ///
+    /// The differences between the current and the imported entities
+    pub async fn import_tenant(&self, imported: &TenantExport, mode: ImportMode) -> Result<ImportReport, Error> {
+        // The current entities are read once the other writers are done,
+        // so that the report describes exactly what is applied.
+        let mut transaction = self.pool.begin().await?;
+        self._lock_change_log(&mut transaction).await?;
+        let report = ImportReport::diff(mode, &self._export_tenant(&mut transaction).await?, imported);
+        if mode == ImportMode::DryRun {
+            transaction.rollback().await?;
+            return Ok(report);
+        }
+
+        if mode == ImportMode::Replace {
+            self._delete_tenant(&mut transaction).await?;
+        }
+
+        for policy in &imported.policies {
+            self._save_policy(policy, &mut transaction).await?;
+        }
+
+        for identity in &imported.identities {
+            self._save_identity(identity, &mut transaction).await?;
+        }
+
+        for group in members_first(&imported.groups) {
+            self._save_group(group, &mut transaction).await?;
+        }
+
+        for role in &imported.roles {
+            self._save_role(role, &mut transaction).await?;
+        }
+
+        for guardrail in &imported.guardrails {
+            self._save_guardrail(guardrail, &mut transaction).await?;
+        }
+
+        for resource_policy in &imported.resource_policies {
+            self._save_resource_policy(resource_policy, &mut transaction).await?;
+        }
+
+        // Unchanged entities are saved again, but only the actual changes are recorded.
+        for (changes, operation) in vec![
+            (&report.created, ChangeOperation::Upsert),
+            (&report.updated, ChangeOperation::Upsert),
+            (&report.deleted, ChangeOperation::Delete),
+        ] {
+            for (section, ids) in changes {
+                for id in ids {
+                    self._record_change(entity_type(section), id, operation, &mut transaction)
+                        .await?;
+                }
+            }
+        }
+
+        transaction.commit().await?;
+        self.invalidator.publish(&[InvalidationEvent::All]).await;
+
+        Ok(report)
+    }
+}
+
+/// The type of the entities in an export section, as recorded in the change log.
+fn entity_type(section: &str) -> &str {
+    match section {
+        "policies" => "policy",
+        "identities" => "identity",
+        "groups" => "group",
+        "roles" => "role",
+        "guardrails" => "guardrail",
+        "resource_policies" => "resource_policy",
+        _ => section,
+    }
+}
+
+/// Sorts the groups so that every group comes after the groups
+/// nested into it, which must exist before the membership is saved.
+fn members_first(groups: &[Group]) -> Vec<&Group> {
+    fn visit<'a>(group: &'a Group, groups: &HashMap<&str, &'a Group>, visited: &mut HashSet<&'a str>, result: &mut Vec<&'a Group>) {
+        if !visited.insert(group.name.as_str()) {
+            return;
+        }
+
+        for nested in group.get_groups() {
+            if let Some(nested) = groups.get(nested.as_str()) {
+                visit(nested, groups, visited, result);
+            }
+        }
+
+        result.push(group);
+    }
+
+    let by_name: HashMap<&str, &Group> = groups.iter().map(|g| (g.name.as_str(), g)).collect();
+    let mut visited = HashSet::new();
+    let mut result = vec![];
+    for group in groups {
+        visit(group, &by_name, &mut visited, &mut result);
+    }
+
+    result
+}
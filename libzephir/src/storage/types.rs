@@ -1,16 +1,193 @@
+use crate::policy::condition::Attributes;
+use crate::policy::resource_policy::Principals;
 use sqlx::types::Json;
 
-#[derive(sqlx::FromRow)]
-pub(super) struct DbIdentity {
+#[derive(sqlx::Type, sqlx::FromRow)]
+pub(super) struct DbPolicy {
+    pub(super) tenant_id: String,
     pub(super) id: String,
-    pub(super) policy_id: Option<String>,
+    pub(super) version: i32,
+    pub(super) effect: bool,
+    pub(super) actions: Json<Vec<String>>,
+    pub(super) resources: Json<Vec<String>>,
+    #[sqlx(default)]
+    pub(super) conditions: Option<Json<Attributes>>,
 }
 
-#[derive(sqlx::Type, sqlx::FromRow)]
-pub(super) struct DbPolicy {
+/// A policy attached to a subject, as loaded by the subjects loader.
+/// Policy fields are NULL if the subject has no policy at all.
+#[derive(sqlx::FromRow)]
+pub(super) struct DbSubjectPolicy {
+    pub(super) owner_type: String,
+    pub(super) owner_id: String,
+    pub(super) inheritance_path: Option<Vec<String>>,
+    pub(super) attributes: Json<Attributes>,
+    pub(super) membership_rule: Option<String>,
+    pub(super) link_type: Option<String>,
+    pub(super) priority: Option<i32>,
+    pub(super) not_before: Option<i64>,
+    pub(super) not_after: Option<i64>,
+    pub(super) memberships_change_at: Option<i64>,
+    pub(super) tenant_id: Option<String>,
+    pub(super) id: Option<String>,
+    pub(super) version: Option<i32>,
+    pub(super) effect: Option<bool>,
+    pub(super) actions: Option<Json<Vec<String>>>,
+    pub(super) resources: Option<Json<Vec<String>>>,
+    pub(super) conditions: Option<Json<Attributes>>,
+}
+
+impl DbSubjectPolicy {
+    pub(super) fn take_policy(&mut self) -> Option<DbPolicy> {
+        Some(DbPolicy {
+            tenant_id: self.tenant_id.take()?,
+            id: self.id.take()?,
+            version: self.version.take()?,
+            effect: self.effect.take()?,
+            actions: self.actions.take()?,
+            resources: self.resources.take()?,
+            conditions: self.conditions.take(),
+        })
+    }
+}
+
+#[derive(sqlx::FromRow)]
+pub(super) struct DbMembership {
+    pub(super) group_id: String,
+    pub(super) identity_id: String,
+    pub(super) not_before: Option<i64>,
+    pub(super) not_after: Option<i64>,
+}
+
+#[derive(sqlx::FromRow)]
+pub(super) struct DbNestedGroup {
+    pub(super) group_id: String,
+    pub(super) member_group_id: String,
+}
+
+#[derive(sqlx::FromRow)]
+pub(super) struct DbAncestor {
+    pub(super) group_id: String,
+    pub(super) path: Vec<String>,
+}
+
+#[derive(sqlx::FromRow)]
+pub(super) struct DbResourcePolicy {
+    pub(super) tenant_id: String,
     pub(super) id: String,
     pub(super) version: i32,
     pub(super) effect: bool,
     pub(super) actions: Json<Vec<String>>,
     pub(super) resources: Json<Vec<String>>,
+    pub(super) conditions: Option<Json<Attributes>>,
+    pub(super) principal_identities: Json<Vec<String>>,
+    pub(super) principal_groups: Json<Vec<String>>,
+    pub(super) principal_roles: Json<Vec<String>>,
+}
+
+impl DbResourcePolicy {
+    pub(super) fn into_parts(self) -> (DbPolicy, Principals) {
+        let principals = Principals::new(
+            self.principal_identities.to_vec(),
+            self.principal_groups.to_vec(),
+            self.principal_roles.to_vec(),
+        );
+
+        let policy = DbPolicy {
+            tenant_id: self.tenant_id,
+            id: self.id,
+            version: self.version,
+            effect: self.effect,
+            actions: self.actions,
+            resources: self.resources,
+            conditions: self.conditions,
+        };
+
+        (policy, principals)
+    }
+}
+
+#[derive(sqlx::FromRow)]
+pub(super) struct DbRole {
+    pub(super) id: String,
+    pub(super) policy_id: Option<String>,
+    pub(super) trusted_identities: Json<Vec<String>>,
+    pub(super) trusted_groups: Json<Vec<String>>,
+}
+
+/// A role session. Policy fields are NULL if the session has no session policy.
+#[derive(sqlx::FromRow)]
+pub(super) struct DbRoleSession {
+    pub(super) tenant_id: String,
+    pub(super) id: String,
+    pub(super) role_id: String,
+    pub(super) identity_id: String,
+    pub(super) version: Option<i32>,
+    pub(super) effect: Option<bool>,
+    pub(super) actions: Option<Json<Vec<String>>>,
+    pub(super) resources: Option<Json<Vec<String>>>,
+    pub(super) conditions: Option<Json<Attributes>>,
+    pub(super) expires_at: i64,
+}
+
+impl DbRoleSession {
+    pub(super) fn take_policy(&mut self) -> Option<DbPolicy> {
+        Some(DbPolicy {
+            tenant_id: self.tenant_id.clone(),
+            id: "__session_policy_".to_owned() + self.id.as_str() + "__",
+            version: self.version.take()?,
+            effect: self.effect.take()?,
+            actions: self.actions.take()?,
+            resources: self.resources.take()?,
+            conditions: self.conditions.take(),
+        })
+    }
+}
+
+#[derive(sqlx::FromRow)]
+pub(super) struct DbChange {
+    pub(super) seq: i64,
+    pub(super) tenant_id: String,
+    pub(super) entity_type: String,
+    pub(super) entity_id: String,
+    pub(super) operation: String,
+    pub(super) changed_at: i64,
+}
+
+#[derive(sqlx::FromRow)]
+pub(super) struct DbWebhook {
+    pub(super) id: String,
+    pub(super) url: String,
+    pub(super) events: Json<Vec<String>>,
+    pub(super) secret: String,
+    pub(super) enabled: bool,
+}
+
+#[derive(sqlx::FromRow)]
+pub(super) struct DbDelivery {
+    pub(super) id: i64,
+    pub(super) tenant_id: String,
+    pub(super) webhook_id: String,
+    pub(super) url: String,
+    pub(super) secret: String,
+    pub(super) event: String,
+    pub(super) payload: Json<serde_json::Value>,
+    pub(super) attempts: i32,
+}
+
+#[derive(sqlx::FromRow)]
+pub(super) struct DbDeadLetter {
+    pub(super) id: i64,
+    pub(super) webhook_id: String,
+    pub(super) event: String,
+    pub(super) payload: Json<serde_json::Value>,
+    pub(super) attempts: i32,
+    pub(super) last_error: Option<String>,
+    pub(super) failed_at: i64,
+}
+
+#[derive(sqlx::FromRow)]
+pub(super) struct DbPolicyRevision {
+    pub(super) revision: i32,
+    pub(super) created_at: i64,
 }
@@ -0,0 +1,293 @@
+use crate::err::Error;
+use crate::policy::validity::unix_now;
+use crate::storage::types::{DbDeadLetter, DbDelivery, DbWebhook};
+use crate::storage::StorageManager;
+use crate::webhook::{retry_delay, DeadLetter, Delivery, Webhook};
+use serde_json::{Map, Value};
+use sqlx::types::Json;
+use sqlx::{Postgres, Transaction};
+use std::time::Duration;
+
+impl From<DbWebhook> for Webhook {
+    fn from(value: DbWebhook) -> Self {
+        Webhook {
+            id: value.id,
+            url: value.url,
+            events: value.events.0,
+            secret: value.secret,
+            enabled: value.enabled,
+        }
+    }
+}
+
+impl From<DbDelivery> for Delivery {
+    fn from(value: DbDelivery) -> Self {
+        Delivery {
+            id: value.id,
+            tenant: value.tenant_id,
+            webhook_id: value.webhook_id,
+            url: value.url,
+            secret: value.secret,
+            event: value.event,
+            payload: value.payload.0,
+            attempts: value.attempts,
+        }
+    }
+}
+
+impl From<DbDeadLetter> for DeadLetter {
+    fn from(value: DbDeadLetter) -> Self {
+        DeadLetter {
+            id: value.id,
+            webhook_id: value.webhook_id,
+            event: value.event,
+            payload: value.payload.0,
+            attempts: value.attempts,
+            last_error: value.last_error,
+            failed_at: value.failed_at,
+        }
+    }
+}
+
+impl StorageManager {
+    pub async fn find_webhooks(&self) -> Result<Vec<Webhook>, Error> {
+        Ok(sqlx::query_as::<_, DbWebhook>("SELECT id, url, events, secret, enabled FROM webhook WHERE tenant_id = $1 ORDER BY id")
+            .bind(&self.tenant)
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .map(Webhook::from)
+            .collect())
+    }
+
+    pub async fn find_webhook<S: ToString>(&self, id: S) -> Result<Option<Webhook>, Error> {
+        Ok(sqlx::query_as::<_, DbWebhook>("SELECT id, url, events, secret, enabled FROM webhook WHERE tenant_id = $1 AND id = $2")
+            .bind(&self.tenant)
+            .bind(id.to_string())
+            .fetch_optional(&self.pool)
+            .await?
+            .map(Webhook::from))
+    }
+
+    pub async fn save_webhook(&self, w: &Webhook) -> Result<(), Error> {
+        w.validate()?;
+        sqlx::query(
+            r#"
+            INSERT INTO webhook (tenant_id, id, url, events, secret, enabled)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (tenant_id, id) DO UPDATE SET url = $3, events = $4, secret = $5, enabled = $6
+        "#,
+        )
+        .bind(&self.tenant)
+        .bind(&w.id)
+        .bind(&w.url)
+        .bind(Json(&w.events))
+        .bind(&w.secret)
+        .bind(w.enabled)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Deletes a webhook, along with its pending deliveries and dead letters.
+    pub async fn delete_webhook<S: ToString>(&self, id: S) -> Result<bool, Error> {
+        let result = sqlx::query("DELETE FROM webhook WHERE tenant_id = $1 AND id = $2")
+            .bind(&self.tenant)
+            .bind(id.to_string())
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Queues a notification of the given event for every webhook of the tenant
+    /// subscribed to it, as part of the transaction making the change.
+    ///
+    /// The payload holds the event, the changed entity, the sequence number
+    /// of the change in the change log and any additional data.
+    pub(super) async fn _notify_webhooks(
+        &self,
+        event: &str,
+        entity_type: &str,
+        entity_id: &str,
+        seq: i64,
+        data: Map<String, Value>,
+        transaction: &mut Transaction<'_, Postgres>,
+    ) -> Result<(), Error> {
+        let webhooks = sqlx::query_as::<_, DbWebhook>(
+            "SELECT id, url, events, secret, enabled FROM webhook WHERE tenant_id = $1 AND enabled",
+        )
+        .bind(&self.tenant)
+        .fetch_all(&mut *transaction)
+        .await?;
+
+        let mut payload = Map::new();
+        payload.insert(String::from("event"), Value::from(event));
+        payload.insert(String::from("tenant"), Value::from(self.tenant.as_str()));
+        payload.insert(String::from("entity_type"), Value::from(entity_type));
+        payload.insert(String::from("entity_id"), Value::from(entity_id));
+        payload.insert(String::from("seq"), Value::from(seq));
+        payload.insert(String::from("occurred_at"), Value::from(unix_now()));
+        payload.insert(String::from("data"), Value::Object(data));
+        let payload = Value::Object(payload);
+
+        for webhook in webhooks.into_iter().map(Webhook::from) {
+            if !webhook.matches(event) {
+                continue;
+            }
+
+            sqlx::query("INSERT INTO webhook_delivery (tenant_id, webhook_id, event, payload) VALUES ($1, $2, $3, $4)")
+                .bind(&self.tenant)
+                .bind(&webhook.id)
+                .bind(event)
+                .bind(&payload)
+                .execute(&mut *transaction)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Claims the deliveries due to be sent, of any tenant.
+    ///
+    /// Claimed deliveries are postponed by the given lease, so that other
+    /// instances do not send them meanwhile: if the instance dies before
+    /// completing them, they are sent again once the lease expires.
+    pub async fn claim_deliveries(&self, limit: i64, lease: Duration) -> Result<Vec<Delivery>, Error> {
+        Ok(sqlx::query_as::<_, DbDelivery>(
+            r#"
+            WITH claimed AS (
+                UPDATE webhook_delivery SET next_attempt_at = now() + make_interval(secs => $2)
+                WHERE id IN (
+                    SELECT id FROM webhook_delivery
+                    WHERE next_attempt_at <= now()
+                    ORDER BY id
+                    LIMIT $1
+                    FOR UPDATE SKIP LOCKED
+                )
+                RETURNING *
+            )
+            SELECT c.id, c.tenant_id, c.webhook_id, w.url, w.secret, c.event, c.payload, c.attempts
+            FROM claimed c
+            INNER JOIN webhook w ON w.tenant_id = c.tenant_id AND w.id = c.webhook_id
+            ORDER BY c.id
+        "#,
+        )
+        .bind(limit)
+        .bind(lease.as_secs_f64())
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(Delivery::from)
+        .collect())
+    }
+
+    /// Removes a successfully sent delivery.
+    pub async fn complete_delivery(&self, delivery: &Delivery) -> Result<(), Error> {
+        sqlx::query("DELETE FROM webhook_delivery WHERE id = $1")
+            .bind(delivery.id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Records a failed attempt, scheduling the next one with an exponential
+    /// backoff or moving the delivery to the dead letters after too many failures.
+    ///
+    /// # Returns
+    ///
+    /// Whether the delivery has been moved to the dead letters
+    pub async fn fail_delivery(&self, delivery: &Delivery, error: &str) -> Result<bool, Error> {
+        let attempts = delivery.attempts + 1;
+        if let Some(delay) = retry_delay(attempts) {
+            sqlx::query(
+                r#"
+                UPDATE webhook_delivery
+                SET attempts = $2, last_error = $3, next_attempt_at = now() + make_interval(secs => $4)
+                WHERE id = $1
+            "#,
+            )
+            .bind(delivery.id)
+            .bind(attempts)
+            .bind(error)
+            .bind(delay.as_secs_f64())
+            .execute(&self.pool)
+            .await?;
+
+            return Ok(false);
+        }
+
+        let mut transaction = self.pool.begin().await?;
+        sqlx::query(
+            r#"
+            INSERT INTO webhook_dead_letter (id, tenant_id, webhook_id, event, payload, attempts, last_error)
+            SELECT id, tenant_id, webhook_id, event, payload, $2, $3 FROM webhook_delivery WHERE id = $1
+        "#,
+        )
+        .bind(delivery.id)
+        .bind(attempts)
+        .bind(error)
+        .execute(&mut transaction)
+        .await?;
+
+        sqlx::query("DELETE FROM webhook_delivery WHERE id = $1")
+            .bind(delivery.id)
+            .execute(&mut transaction)
+            .await?;
+
+        transaction.commit().await?;
+        Ok(true)
+    }
+
+    pub async fn find_dead_letters<S: ToString>(&self, webhook_id: S) -> Result<Vec<DeadLetter>, Error> {
+        Ok(sqlx::query_as::<_, DbDeadLetter>(
+            r#"
+            SELECT id, webhook_id, event, payload, attempts, last_error,
+                   extract(epoch FROM failed_at)::bigint AS failed_at
+            FROM webhook_dead_letter
+            WHERE tenant_id = $1 AND webhook_id = $2
+            ORDER BY id
+        "#,
+        )
+        .bind(&self.tenant)
+        .bind(webhook_id.to_string())
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(DeadLetter::from)
+        .collect())
+    }
+
+    /// Queues the dead letters of the given webhook again, resetting their attempts.
+    ///
+    /// # Returns
+    ///
+    /// The number of requeued deliveries
+    pub async fn redeliver_dead_letters<S: ToString>(&self, webhook_id: S) -> Result<u64, Error> {
+        let webhook_id = webhook_id.to_string();
+        let mut transaction = self.pool.begin().await?;
+        let requeued = sqlx::query(
+            r#"
+            INSERT INTO webhook_delivery (id, tenant_id, webhook_id, event, payload)
+            SELECT id, tenant_id, webhook_id, event, payload FROM webhook_dead_letter
+            WHERE tenant_id = $1 AND webhook_id = $2
+        "#,
+        )
+        .bind(&self.tenant)
+        .bind(&webhook_id)
+        .execute(&mut transaction)
+        .await?
+        .rows_affected();
+
+        sqlx::query("DELETE FROM webhook_dead_letter WHERE tenant_id = $1 AND webhook_id = $2")
+            .bind(&self.tenant)
+            .bind(&webhook_id)
+            .execute(&mut transaction)
+            .await?;
+
+        transaction.commit().await?;
+        Ok(requeued)
+    }
+}
@@ -0,0 +1,47 @@
+use crate::err::Error;
+
+/// The tenant the entities created without an explicit tenant belong to.
+pub const DEFAULT_TENANT: &str = "default";
+
+/// Checks that the given string is a valid tenant id: a non-empty
+/// string (up to 255 characters) of letters, digits, '-', '_' and '.'.
+pub fn validate_tenant(tenant: &str) -> Result<(), Error> {
+    let valid = !tenant.is_empty()
+        && tenant.len() <= 255
+        && tenant
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.');
+
+    if valid {
+        Ok(())
+    } else {
+        Err(Error::invalid_tenant(tenant))
+    }
+}
+
+/// Scopes an entity id to the given tenant.
+///
+/// Used to build cache keys and invalidation events: tenant ids
+/// cannot contain slashes, so the scoped id is never ambiguous.
+pub fn scoped_id(tenant: &str, id: &str) -> String {
+    format!("{}/{}", tenant, id)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tenant::{scoped_id, validate_tenant};
+
+    #[test]
+    fn scoped_ids_should_include_the_tenant() {
+        assert_eq!(scoped_id("acme", "users/alice"), "acme/users/alice");
+        assert_ne!(scoped_id("acme", "alice"), scoped_id("default", "alice"));
+    }
+
+    #[test]
+    fn tenant_ids_should_be_validated() {
+        assert!(validate_tenant("acme-corp_1.eu").is_ok());
+        for tenant in vec!["", "acme/corp", "acme:corp", "acme corp"] {
+            assert!(validate_tenant(tenant).is_err(), "{}", tenant);
+        }
+    }
+}
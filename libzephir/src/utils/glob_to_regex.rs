@@ -12,6 +12,24 @@ pub fn from_string(glob: String) -> Regex {
             .unwrap();
     }
 
+    RegexBuilder::new()
+        .jit_if_available(true)
+        .build(pattern(&glob).as_str())
+        .unwrap()
+}
+
+/// Builds a regex matching the whole string against the glob,
+/// as opposed to `from_str` which looks for the glob inside the string.
+pub fn anchored(glob: &str) -> Regex {
+    let pattern = if glob == "*" { ".*".to_string() } else { pattern(glob) };
+
+    RegexBuilder::new()
+        .jit_if_available(true)
+        .build(format!("^(?:{})$", pattern).as_str())
+        .unwrap()
+}
+
+fn pattern(glob: &str) -> String {
     let mut escaping = false;
     let mut in_curlies = 0;
     let mut regex = String::new();
@@ -69,15 +87,68 @@ pub fn from_string(glob: String) -> Regex {
         escaping = false;
     }
 
-    RegexBuilder::new()
-        .jit_if_available(true)
-        .build(regex.as_str())
-        .unwrap()
+    regex
+}
+
+/// Splits a glob into the literal parts every matching string must contain.
+/// Parts inside curly braces (alternatives) are skipped, as well as the colon
+/// before a `**` (which `pattern` turns into `.*` along with the colon).
+fn literal_parts(glob: &str) -> Vec<String> {
+    let mut parts = vec![];
+    let mut current = String::new();
+    let mut in_curlies = 0;
+    let mut chars = glob.chars();
+
+    while let Some(car) = chars.next() {
+        match car {
+            ':' if chars.clone().take(2).eq("**".chars()) => {}
+            '{' => in_curlies += 1,
+            '}' if in_curlies > 0 => in_curlies -= 1,
+            '\\' | '*' | '?' => {
+                if car == '\\' {
+                    chars.next();
+                }
+            }
+            _ if in_curlies == 0 => {
+                current.push(car);
+                continue;
+            }
+            _ => continue,
+        }
+
+        if !current.is_empty() {
+            parts.push(std::mem::take(&mut current));
+        }
+    }
+
+    if !current.is_empty() {
+        parts.push(current);
+    }
+
+    parts
+}
+
+/// Checks whether every string matched by the inner glob
+/// is also matched by the outer one.
+///
+/// As globs are matched anywhere in the subject string, this is true if
+/// the outer glob matches one of the literal parts of the inner one.
+/// The check is conservative: false could be returned for globs
+/// which actually include each other.
+pub fn includes(outer: &str, inner: &str) -> bool {
+    if outer == "*" || outer == inner {
+        return true;
+    }
+
+    let regex = from_str(outer);
+    literal_parts(inner)
+        .iter()
+        .any(|part| regex.is_match(part.as_bytes()).unwrap_or(false))
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::utils::glob_to_regex::{from_str, from_string};
+    use crate::utils::glob_to_regex::{from_str, from_string, includes};
 
     #[test]
     fn from_string_should_return_match_all_regex() {
@@ -103,4 +174,19 @@ mod tests {
             "foo_(bar|foo)\\.[^:]*"
         );
     }
+
+    #[test]
+    fn includes_should_check_inclusion() {
+        assert!(includes("*", "urn:resource:*"));
+        assert!(includes("urn:resource:*", "urn:resource:*"));
+        assert!(includes("urn:resource:*", "urn:resource:foo:*"));
+        assert!(includes("urn:resource:**", "urn:resource:foo:bar"));
+        assert!(!includes("urn:resource:foo:*", "urn:resource:*"));
+        assert!(!includes("urn:resource:{foo,bar}", "urn:resource:*"));
+        assert!(!includes("urn:resource:foo", "urn:resource:{foo,bar}"));
+        assert!(!includes("urn:other:*", "urn:resource:*"));
+        assert!(!includes("a:", "a:**"));
+        assert!(includes("a", "a:**"));
+        assert!(includes("urn:resource:**", "urn:resource:foo:**"));
+    }
 }
@@ -0,0 +1,226 @@
+use crate::err::Error;
+use crate::policy::policy::ToJson;
+use crate::storage::ChangeOperation;
+use crate::utils::glob_to_regex;
+use hmac::{Hmac, Mac, NewMac};
+use serde_json::{Map, Value};
+use sha2::Sha256;
+use std::time::Duration;
+
+/// The events a webhook could subscribe to.
+pub const EVENTS: &[&str] = &[
+    "policy.updated",
+    "identity.updated",
+    "group.updated",
+    "group.member_added",
+    "group.member_removed",
+    "role.updated",
+    "guardrail.updated",
+    "guardrail.deleted",
+    "resource_policy.updated",
+    "resource_policy.deleted",
+    "tenant.deleted",
+];
+
+/// The number of failed attempts after which a delivery is moved to the dead letters.
+pub const MAX_DELIVERY_ATTEMPTS: i32 = 8;
+
+const BACKOFF_BASE: Duration = Duration::from_secs(5);
+const BACKOFF_MAX: Duration = Duration::from_secs(3600);
+
+/// Gets the name of the event notifying a change of the given entity type.
+pub fn event_name(entity_type: &str, operation: ChangeOperation) -> String {
+    match operation {
+        ChangeOperation::Upsert => format!("{}.updated", entity_type),
+        ChangeOperation::Delete => format!("{}.deleted", entity_type),
+    }
+}
+
+/// Signs a payload with the webhook secret (HMAC-SHA256).
+///
+/// # Returns
+///
+/// The signature, formatted as "sha256=<hex digest>"
+pub fn sign(secret: &str, payload: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_varkey(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(payload);
+
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Gets the delay before retrying a delivery which failed the given number of times,
+/// doubling at every attempt (5 seconds after the first failure, up to an hour).
+pub fn backoff(attempts: i32) -> Duration {
+    let exponent = attempts.clamp(1, 16) as u32 - 1;
+    (BACKOFF_BASE * 2u32.pow(exponent)).min(BACKOFF_MAX)
+}
+
+/// Gets the delay before the next attempt of a delivery which failed
+/// the given number of times, or None if it must be moved to the dead letters.
+pub fn retry_delay(attempts: i32) -> Option<Duration> {
+    if attempts < MAX_DELIVERY_ATTEMPTS {
+        Option::Some(backoff(attempts))
+    } else {
+        Option::None
+    }
+}
+
+/// Generates a random secret for a webhook registered without one.
+pub fn generate_secret() -> String {
+    hex::encode(rand::random::<[u8; 32]>())
+}
+
+/// An URL notified (through a signed POST request) of the changes
+/// to the authorization data matching its event filters.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Webhook {
+    pub id: String,
+    pub url: String,
+
+    /// Glob patterns of the notified events (e.g. "group.*").
+    pub events: Vec<String>,
+    pub secret: String,
+    pub enabled: bool,
+}
+
+impl Webhook {
+    /// Checks that the url is an http(s) one, and that every
+    /// event filter matches at least one of the known events.
+    pub fn validate(&self) -> Result<(), Error> {
+        if !self.url.starts_with("http://") && !self.url.starts_with("https://") {
+            return Err(Error::invalid_webhook(format!("\"{}\" is not an http(s) url", self.url)));
+        }
+
+        if self.events.is_empty() {
+            return Err(Error::invalid_webhook("no event to notify"));
+        }
+
+        for filter in &self.events {
+            if !EVENTS.iter().any(|event| matches(filter, event)) {
+                return Err(Error::invalid_webhook(format!("unknown event \"{}\"", filter)));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Whether the webhook must be notified of the given event.
+    pub fn matches(&self, event: &str) -> bool {
+        self.enabled && self.events.iter().any(|filter| matches(filter, event))
+    }
+}
+
+fn matches(filter: &str, event: &str) -> bool {
+    glob_to_regex::anchored(filter)
+        .is_match(event.as_bytes())
+        .unwrap_or(false)
+}
+
+/// The secret is never exposed once the webhook has been created.
+impl ToJson for Webhook {
+    fn to_json(&self) -> Map<String, Value> {
+        let mut map = Map::new();
+        map.insert(String::from("id"), Value::from(self.id.as_str()));
+        map.insert(String::from("url"), Value::from(self.url.as_str()));
+        map.insert(String::from("events"), Value::from(self.events.clone()));
+        map.insert(String::from("enabled"), Value::from(self.enabled));
+
+        map
+    }
+}
+
+/// A notification waiting to be sent to a webhook.
+#[derive(Clone, Debug)]
+pub struct Delivery {
+    pub id: i64,
+    pub tenant: String,
+    pub webhook_id: String,
+    pub url: String,
+    pub secret: String,
+    pub event: String,
+    pub payload: Value,
+
+    /// The number of failed attempts so far.
+    pub attempts: i32,
+}
+
+/// A notification which could not be delivered after `MAX_DELIVERY_ATTEMPTS` attempts.
+#[derive(Clone, Debug)]
+pub struct DeadLetter {
+    pub id: i64,
+    pub webhook_id: String,
+    pub event: String,
+    pub payload: Value,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub failed_at: i64,
+}
+
+impl ToJson for DeadLetter {
+    fn to_json(&self) -> Map<String, Value> {
+        let mut map = Map::new();
+        map.insert(String::from("id"), Value::from(self.id));
+        map.insert(String::from("webhook_id"), Value::from(self.webhook_id.as_str()));
+        map.insert(String::from("event"), Value::from(self.event.as_str()));
+        map.insert(String::from("payload"), self.payload.clone());
+        map.insert(String::from("attempts"), Value::from(self.attempts));
+        map.insert(String::from("last_error"), Value::from(self.last_error.clone()));
+        map.insert(String::from("failed_at"), Value::from(self.failed_at));
+
+        map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::ChangeOperation;
+    use crate::webhook::{backoff, event_name, retry_delay, sign, Webhook, MAX_DELIVERY_ATTEMPTS};
+    use std::time::Duration;
+
+    fn webhook(events: Vec<&str>) -> Webhook {
+        Webhook {
+            id: String::from("audit"),
+            url: String::from("http://localhost:8080/hook"),
+            events: events.into_iter().map(String::from).collect(),
+            secret: String::from("secret"),
+            enabled: true,
+        }
+    }
+
+    #[test]
+    fn webhooks_should_match_the_event_filters() {
+        let hook = webhook(vec!["group.*", "policy.updated"]);
+        assert!(hook.validate().is_ok());
+        assert!(hook.matches("group.member_added"));
+        assert!(hook.matches(&event_name("policy", ChangeOperation::Upsert)));
+        assert!(!hook.matches("identity.updated"));
+        assert!(!Webhook { enabled: false, ..hook }.matches("group.updated"));
+
+        assert!(webhook(vec!["*"]).matches("tenant.deleted"));
+        assert!(webhook(vec!["polcy.updated"]).validate().is_err());
+        assert!(webhook(vec![]).validate().is_err());
+        assert!(Webhook { url: String::from("ftp://host"), ..webhook(vec!["*"]) }.validate().is_err());
+    }
+
+    #[test]
+    fn payloads_should_be_signed() {
+        // Reference value computed with `openssl dgst -sha256 -hmac secret`.
+        assert_eq!(
+            sign("secret", b"{}"),
+            "sha256=77325902caca812dc259733aacd046b73817372c777b8d95b402647474516e13"
+        );
+        assert_ne!(sign("secret", b"{}"), sign("other", b"{}"));
+    }
+
+    #[test]
+    fn retries_should_back_off_exponentially() {
+        assert_eq!(backoff(1), Duration::from_secs(5));
+        assert_eq!(backoff(2), Duration::from_secs(10));
+        assert_eq!(backoff(4), Duration::from_secs(40));
+        assert_eq!(backoff(20), Duration::from_secs(3600));
+
+        assert_eq!(retry_delay(1), Option::Some(Duration::from_secs(5)));
+        assert_eq!(retry_delay(MAX_DELIVERY_ATTEMPTS - 1), Option::Some(backoff(MAX_DELIVERY_ATTEMPTS - 1)));
+        assert_eq!(retry_delay(MAX_DELIVERY_ATTEMPTS), Option::None);
+    }
+}
@@ -0,0 +1,75 @@
+use libzephir::err::{Error, ErrorKind};
+use libzephir::file_store::bundle::{generate_keys, parse_public_key, parse_secret_key, Bundle};
+use libzephir::tenant::DEFAULT_TENANT;
+
+const USAGE: &str = "Usage:
+    rzephir bundle build <output> <secret key file> [tenant]
+    rzephir bundle verify <bundle> <public key file>
+    rzephir bundle keygen <name>";
+
+fn usage() -> Error {
+    Error::new(ErrorKind::UnknownError, USAGE)
+}
+
+fn read_key(path: &str) -> Result<String, Error> {
+    Ok(std::fs::read_to_string(path)?)
+}
+
+/// Builds a bundle of all the entities of a tenant, read from the database.
+async fn build(output: &str, secret_key: &str, tenant: &str) -> Result<(), Error> {
+    let keypair = parse_secret_key(&read_key(secret_key)?)?;
+    let export = crate::connect_storage(tenant).await?.export_tenant().await?;
+    let bundle = Bundle::from_export(&export)?;
+    std::fs::write(output, bundle.write(&keypair)?)?;
+
+    println!(
+        "Bundle of tenant \"{}\" written to {} ({} policies, {} identities, {} groups, {} roles)",
+        tenant,
+        output,
+        export.policies.len(),
+        export.identities.len(),
+        export.groups.len(),
+        export.roles.len()
+    );
+
+    Ok(())
+}
+
+/// Checks the signature and the content hashes of a bundle.
+fn verify(path: &str, public_key: &str) -> Result<(), Error> {
+    let public_key = parse_public_key(&read_key(public_key)?)?;
+    let bundle = Bundle::read(&std::fs::read(path)?, &public_key)?;
+    let manifest = bundle.get_manifest();
+
+    println!("Bundle {} is valid", path);
+    println!("  tenant: {}", manifest.tenant);
+    println!("  created at: {}", manifest.created_at);
+    for (file, hash) in &manifest.files {
+        println!("  {}: sha256:{}", file, hash);
+    }
+
+    Ok(())
+}
+
+/// Generates a new signing key, writing the secret and
+/// public keys to "<name>.key" and "<name>.pub".
+fn keygen(name: &str) -> Result<(), Error> {
+    let (secret, public) = generate_keys();
+    std::fs::write(format!("{}.key", name), secret)?;
+    std::fs::write(format!("{}.pub", name), public)?;
+
+    println!("Keys written to {}.key and {}.pub", name, name);
+    Ok(())
+}
+
+/// Runs the bundle subcommand with the given arguments.
+pub(crate) async fn run(args: &[String]) -> Result<(), Error> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args.as_slice() {
+        ["build", output, secret_key] => build(output, secret_key, DEFAULT_TENANT).await,
+        ["build", output, secret_key, tenant] => build(output, secret_key, tenant).await,
+        ["verify", path, public_key] => verify(path, public_key),
+        ["keygen", name] => keygen(name),
+        _ => Err(usage()),
+    }
+}
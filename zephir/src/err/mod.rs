@@ -3,7 +3,7 @@ use derive_more::{Display, From};
 use sqlx::error::Error as DatabaseError;
 use libzephir::policy::allowed_result::AllowedResult;
 use libzephir::policy::policy::ToJson;
-use libzephir::err::Error as LibError;
+use libzephir::err::{Error as LibError, ErrorKind};
 use serde_json::{Map, Value};
 use validator::ValidationErrors;
 
@@ -34,6 +34,20 @@ impl ResponseError for ZephirError {
             ZephirError::AllowedError => {
                 HttpResponse::Forbidden().json(AllowedResult::denied().to_value())
             }
+            ZephirError::ServerError(ref err)
+                if err.kind() == ErrorKind::GroupCycleError
+                    || err.kind() == ErrorKind::UnknownCombiningAlgorithmError
+                    || err.kind() == ErrorKind::InvalidMembershipRuleError
+                    || err.kind() == ErrorKind::InvalidTenantError
+                    || err.kind() == ErrorKind::InvalidSnapshotError
+                    || err.kind() == ErrorKind::InvalidWebhookError =>
+            {
+                let mut map = Map::new();
+                map.insert("status_code".to_string(), Value::from(400));
+                map.insert("error".to_string(), Value::from(err.to_string()));
+
+                HttpResponse::BadRequest().json(map)
+            }
             ZephirError::ServerError(ref err) => {
                 let mut map = Map::new();
                 map.insert("status_code".to_string(), Value::from(500));
@@ -1,23 +1,53 @@
 use actix_web::{post, web, HttpResponse};
 use libzephir::storage::StorageManager;
+use crate::tenant::TenantStorage;
 use crate::err::ZephirError;
+use crate::handlers::group::inherited_groups_to_value;
 use log::{Level, debug, log_enabled, trace};
-use libzephir::identity::role::Role;
-use libzephir::policy::allowed_result::AllowedOutcome;
+use libzephir::file_store::{FileStore, Snapshot};
+use libzephir::identity::group::Group;
+use libzephir::identity::identity::Identity;
+use libzephir::identity::subject::Subject;
+use libzephir::policy::allowed_result::{AllowedOutcome, AllowedResult};
+use libzephir::policy::combining_algorithm::CombiningAlgorithm;
+use libzephir::policy::condition::Attributes;
 use libzephir::policy::policy::ToJson;
+use libzephir::policy::resource_policy::{Principal, ResourcePolicies};
 use serde::Deserialize;
+use serde_json::Value;
+use std::convert::TryFrom;
 
 #[derive(Deserialize)]
 pub struct AllowedInfo {
     subject: String,
     action: String,
     resource: Option<String>,
+    session: Option<String>,
+    combining_algorithm: Option<String>,
+}
+
+impl AllowedInfo {
+    fn algorithm(&self, default_algorithm: &CombiningAlgorithm) -> Result<CombiningAlgorithm, ZephirError> {
+        Ok(match self.combining_algorithm.as_ref() {
+            Option::None => *default_algorithm,
+            Option::Some(name) => CombiningAlgorithm::try_from(name.as_str())?,
+        })
+    }
 }
 
 #[post("/allowed")]
-pub(crate) async fn allowed_action(info: web::Json<AllowedInfo>, storage: web::Data<StorageManager>) -> Result<HttpResponse, ZephirError> {
-    let storage = storage.get_ref();
-    let identity = storage.find_identity(&info.subject)
+pub(crate) async fn allowed_action(
+    info: web::Json<AllowedInfo>,
+    storage: TenantStorage,
+    default_algorithm: web::Data<CombiningAlgorithm>,
+) -> Result<HttpResponse, ZephirError> {
+    let storage: &StorageManager = &storage;
+    let algorithm = info.algorithm(default_algorithm.get_ref())?;
+
+    let action = Option::Some(&info.action);
+    let resource = info.resource.as_ref();
+
+    let (identity, groups) = storage.find_subject(&info.subject)
         .await?
         .ok_or_else(|| {
             trace!(r#"Identity "{}" not found. Denying access..."#, info.subject.as_str());
@@ -28,42 +58,174 @@ pub(crate) async fn allowed_action(info: web::Json<AllowedInfo>, storage: web::D
         trace!(r#"Identity "{}" successfull loaded -> {:#?}"#, info.subject.as_str(), identity);
     }
 
+    let attributes = identity.effective_attributes(&groups);
+    let guardrails = storage.find_guardrails().await?.allowed_for(&attributes, action, resource);
+    if guardrails.outcome() == AllowedOutcome::Denied {
+        trace!(r#"Guardrails denied access. Returning deny result."#);
+        return Ok(HttpResponse::Forbidden().json(guardrails.to_value()));
+    }
+
+    if let Some(session) = info.session.as_ref() {
+        return allowed_in_session(&info, session, algorithm, &attributes, guardrails, storage).await;
+    }
+
+    let resource_policies = storage.find_resource_policies().await?;
+    Ok(evaluate(&info, &identity, &groups, &attributes, &resource_policies, guardrails, algorithm))
+}
+
+/// Evaluates the request against the policies loaded from the
+/// policy files, when zephir runs without a database.
+///
+/// Role sessions are stored in the database only: requests
+/// made in a session are always denied.
+#[post("/allowed")]
+pub(crate) async fn allowed_action_from_files(
+    info: web::Json<AllowedInfo>,
+    store: web::Data<FileStore>,
+    default_algorithm: web::Data<CombiningAlgorithm>,
+) -> Result<HttpResponse, ZephirError> {
+    let algorithm = info.algorithm(default_algorithm.get_ref())?;
+    let snapshot = store.snapshot();
+
     let action = Option::Some(&info.action);
     let resource = info.resource.as_ref();
 
-    let mut result = identity.allowed(action, resource);
-    match result.outcome() {
-        AllowedOutcome::Denied => {
-            trace!(r#"Identity policies denied access. Returning deny result."#);
-            Ok(HttpResponse::Forbidden().json(result.to_value()))
-        }
-        _ => {
-            trace!(r#"Identity policies {} access. Now evaluating groups policies..."#,
-                match result.outcome() {
-                    AllowedOutcome::Allowed => "allowed",
-                    AllowedOutcome::Abstain => "conditional allow",
-                    _ => ""
-                }
-            );
-
-            let groups = storage.find_groups_for_identity(&identity).await?;
-            for g in groups {
-                result.merge(g.allowed(action, resource));
-            }
+    if info.session.is_some() {
+        trace!(r#"Sessions are not available without a database. Denying access..."#);
+        return Err(ZephirError::AllowedError);
+    }
+
+    let (identity, groups) = snapshot.find_subject(&info.subject)
+        .ok_or_else(|| {
+            trace!(r#"Identity "{}" not found. Denying access..."#, info.subject.as_str());
+            ZephirError::AllowedError
+        })?;
+
+    let attributes = identity.effective_attributes(&groups);
+    let guardrails = snapshot.get_guardrails().allowed_for(&attributes, action, resource);
+    if guardrails.outcome() == AllowedOutcome::Denied {
+        trace!(r#"Guardrails denied access. Returning deny result."#);
+        return Ok(HttpResponse::Forbidden().json(guardrails.to_value()));
+    }
+
+    Ok(evaluate(&info, &identity, &groups, &attributes, snapshot.get_resource_policies(), guardrails, algorithm))
+}
+
+/// Evaluates the request against the identity, its groups and the
+/// resource policies naming them, restricted by the identity boundary
+/// and by the (already evaluated) guardrails.
+fn evaluate(info: &AllowedInfo, identity: &Identity, groups: &[Group], attributes: &Attributes, resource_policies: &ResourcePolicies, guardrails: AllowedResult, algorithm: CombiningAlgorithm) -> HttpResponse {
+    let action = Option::Some(&info.action);
+    let resource = info.resource.as_ref();
+
+    let mut result = decide(identity, groups, attributes, resource_policies, algorithm, action, resource);
+    result.intersect(guardrails);
 
-            let mut builder = if result.outcome() == AllowedOutcome::Denied { HttpResponse::Forbidden() } else { HttpResponse::Ok() };
-            debug!(
-                r#"{} access for action "{}" on resource {}"#,
-                match result.outcome() {
-                    AllowedOutcome::Allowed => "Allowed",
-                    AllowedOutcome::Abstain => "Conditional allowed",
-                    AllowedOutcome::Denied => "Denied",
-                },
-                action.unwrap(),
-                resource.unwrap_or(&"NULL".to_string())
-            );
-
-            Ok(builder.json(result.to_value()))
+    let mut builder = if result.outcome() == AllowedOutcome::Denied { HttpResponse::Forbidden() } else { HttpResponse::Ok() };
+    debug!(
+        r#"{} access for action "{}" on resource {}"#,
+        match result.outcome() {
+            AllowedOutcome::Allowed => "Allowed",
+            AllowedOutcome::Abstain => "Conditional allowed",
+            AllowedOutcome::Denied => "Denied",
+        },
+        action.unwrap(),
+        resource.unwrap_or(&"NULL".to_string())
+    );
+
+    let mut json = result.to_json();
+    json.insert(String::from("groups"), inherited_groups_to_value(groups));
+
+    builder.json(json)
+}
+
+/// Computes the outcome of the request, as described in `evaluate`,
+/// before restricting it by the guardrails.
+fn decide(identity: &Identity, groups: &[Group], attributes: &Attributes, resource_policies: &ResourcePolicies, algorithm: CombiningAlgorithm, action: Option<&String>, resource: Option<&String>) -> AllowedResult {
+    let mut result = identity.allowed_with(algorithm, attributes, action, resource);
+    if algorithm.is_final(&result) {
+        trace!(r#"Identity policies decided the outcome ({}). Skipping groups evaluation."#, algorithm.name());
+    } else {
+        trace!(r#"Identity policies did not decide the outcome ({}). Now evaluating groups policies..."#, algorithm.name());
+
+        for g in groups {
+            algorithm.merge(&mut result, g.apply_boundary_to_grants(g.allowed_with(algorithm, attributes, action, resource), attributes, action, resource));
+            if algorithm.is_final(&result) {
+                break;
+            }
         }
     }
+
+    if !algorithm.is_final(&result) {
+        trace!(r#"Now evaluating resource policies..."#);
+
+        let principal = Principal::identity(identity.get_id().as_str(), groups.iter().map(|g| g.get_name().as_str()).collect());
+        algorithm.merge(&mut result, resource_policies.allowed_for(&principal, attributes, algorithm, action, resource));
+    }
+
+    identity.apply_boundary(result, attributes, action, resource)
+}
+
+/// Evaluates a request (outside of any session) against the entities of a
+/// snapshot, as `allowed_action_from_files` does.
+/// Requests made by unknown subjects are denied.
+pub(crate) fn decide_in_snapshot(snapshot: &Snapshot, subject: &str, action: &String, resource: Option<&String>, algorithm: CombiningAlgorithm) -> AllowedResult {
+    let (identity, groups) = match snapshot.find_subject(subject) {
+        Option::None => return AllowedResult::denied(),
+        Option::Some(subject) => subject,
+    };
+
+    let action = Option::Some(action);
+    let attributes = identity.effective_attributes(&groups);
+    let guardrails = snapshot.get_guardrails().allowed_for(&attributes, action, resource);
+    if guardrails.outcome() == AllowedOutcome::Denied {
+        return guardrails;
+    }
+
+    let mut result = decide(&identity, &groups, &attributes, snapshot.get_resource_policies(), algorithm, action, resource);
+    result.intersect(guardrails);
+
+    result
+}
+
+/// Evaluates the request as the role assumed in the given session (along
+/// with the resource policies naming the role), restricted by the session
+/// policy and by the guardrails.
+async fn allowed_in_session(info: &AllowedInfo, session_id: &str, algorithm: CombiningAlgorithm, attributes: &Attributes, guardrails: AllowedResult, storage: &StorageManager) -> Result<HttpResponse, ZephirError> {
+    let session = storage.find_role_session(session_id)
+        .await?
+        .filter(|s| s.get_identity_id() == &info.subject)
+        .ok_or_else(|| {
+            trace!(r#"Session "{}" not found, expired or not owned by "{}". Denying access..."#, session_id, info.subject.as_str());
+            ZephirError::AllowedError
+        })?;
+
+    let role = storage.find_role(session.get_role_id())
+        .await?
+        .ok_or(ZephirError::AllowedError)?;
+
+    let action = Option::Some(&info.action);
+    let resource = info.resource.as_ref();
+
+    let mut result = role.allowed_in_session(algorithm, attributes, &storage.find_resource_policies().await?, session.get_policy(), action, resource);
+    result.intersect(guardrails);
+    debug!(
+        r#"{} access for action "{}" on resource {} in session "{}" (role "{}")"#,
+        match result.outcome() {
+            AllowedOutcome::Allowed => "Allowed",
+            AllowedOutcome::Abstain => "Conditional allowed",
+            AllowedOutcome::Denied => "Denied",
+        },
+        info.action,
+        resource.unwrap_or(&"NULL".to_string()),
+        session_id,
+        role.get_id()
+    );
+
+    let mut builder = if result.outcome() == AllowedOutcome::Denied { HttpResponse::Forbidden() } else { HttpResponse::Ok() };
+    let mut json = result.to_json();
+    json.insert(String::from("role"), Value::from(role.get_id().as_str()));
+    json.insert(String::from("session"), Value::from(session.get_id().as_str()));
+
+    Ok(builder.json(json))
 }
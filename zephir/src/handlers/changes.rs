@@ -0,0 +1,173 @@
+use crate::err::ZephirError;
+use crate::tenant::TenantStorage;
+use actix_web::http::header;
+use actix_web::web::Bytes;
+use actix_web::{get, web, HttpRequest, HttpResponse};
+use libzephir::err::Error;
+use libzephir::policy::policy::ToJson;
+use libzephir::storage::Change;
+use log::warn;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::time::Duration;
+
+const DEFAULT_TIMEOUT: u64 = 30;
+const MAX_TIMEOUT: u64 = 60;
+const DEFAULT_LIMIT: i64 = 100;
+const MAX_LIMIT: i64 = 1000;
+
+/// Interval between the keep-alive comments sent on an idle event stream.
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+#[derive(Default, Deserialize)]
+pub(crate) struct ChangesQuery {
+    since: Option<i64>,
+    timeout: Option<u64>,
+    limit: Option<i64>,
+}
+
+impl ChangesQuery {
+    fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout.unwrap_or(DEFAULT_TIMEOUT).min(MAX_TIMEOUT))
+    }
+
+    fn limit(&self) -> i64 {
+        self.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT)
+    }
+}
+
+/// The sequence number to be sent back to get the changes following the given ones.
+fn next_seq(changes: &[Change], since: i64) -> i64 {
+    changes.last().map_or(since, |c| c.seq)
+}
+
+/// Long-polls the changes following the given sequence number: the response
+/// is sent as soon as there are some, or when the timeout (in seconds) elapses.
+/// Clients send back the returned `next` sequence number on the following call.
+#[get("/changes")]
+pub(crate) async fn get_changes(
+    storage: TenantStorage,
+    query: web::Query<ChangesQuery>,
+) -> Result<HttpResponse, ZephirError> {
+    let since = query.since.unwrap_or_default();
+    let changes = storage.wait_for_changes(since, query.limit(), query.timeout()).await?;
+    let next = next_seq(&changes, since);
+
+    let mut json = Map::new();
+    json.insert(
+        String::from("changes"),
+        Value::Array(changes.iter().map(|c| Value::Object(c.to_json())).collect()),
+    );
+    json.insert(String::from("next"), Value::from(next));
+
+    Ok(HttpResponse::Ok().json(json))
+}
+
+/// Formats the changes as server-sent events, using the sequence numbers as event ids.
+fn events(changes: &[Change]) -> Result<Bytes, Error> {
+    let mut body = String::new();
+    for change in changes {
+        let data = serde_json::to_string(&change.to_json())?;
+        body.push_str(&format!("id: {}\nevent: change\ndata: {}\n\n", change.seq, data));
+    }
+
+    Ok(Bytes::from(body))
+}
+
+/// Streams the changes as server-sent events.
+///
+/// The stream starts after the sequence number in the `Last-Event-ID` header
+/// when a client reconnects (browsers reconnect to the same URL), or after the
+/// `since` one. Otherwise, only the changes made after the connection are sent.
+#[get("/changes/stream")]
+pub(crate) async fn stream_changes(
+    req: HttpRequest,
+    storage: TenantStorage,
+    query: web::Query<ChangesQuery>,
+) -> Result<HttpResponse, ZephirError> {
+    let last_event_id = req
+        .headers()
+        .get("Last-Event-ID")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<i64>().ok());
+
+    let since = match last_event_id.or(query.since) {
+        Option::Some(since) => since,
+        Option::None => storage.last_change_seq().await?,
+    };
+
+    let stream = futures::stream::unfold((storage, since), |(storage, since)| async move {
+        let changes = match storage.wait_for_changes(since, MAX_LIMIT, KEEP_ALIVE_INTERVAL).await {
+            Ok(changes) => changes,
+            Err(e) => {
+                warn!("Change stream of tenant \"{}\" closed: {}", storage.get_tenant(), e);
+                return Option::None;
+            }
+        };
+
+        if changes.is_empty() {
+            return Option::Some((Ok(Bytes::from_static(b": keep-alive\n\n")), (storage, since)));
+        }
+
+        let next = next_seq(&changes, since);
+        Option::Some((events(&changes).map_err(ZephirError::from), (storage, next)))
+    });
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .header(header::CACHE_CONTROL, "no-cache")
+        .streaming(Box::pin(stream)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handlers::changes::{events, next_seq, ChangesQuery, MAX_LIMIT, MAX_TIMEOUT};
+    use libzephir::storage::Change;
+    use std::time::Duration;
+
+    fn change(seq: i64) -> Change {
+        Change {
+            seq,
+            tenant: String::from("default"),
+            entity_type: String::from("policy"),
+            entity_id: String::from("ReadPolicy"),
+            operation: String::from("upsert"),
+            changed_at: 1700000000,
+        }
+    }
+
+    #[test]
+    fn next_should_be_the_last_sequence_number() {
+        assert_eq!(next_seq(&[change(4), change(7)], 3), 7);
+        assert_eq!(next_seq(&[], 3), 3);
+    }
+
+    #[test]
+    fn limit_and_timeout_should_be_clamped() {
+        let query = ChangesQuery::default();
+        assert_eq!(query.limit(), 100);
+        assert_eq!(query.timeout(), Duration::from_secs(30));
+
+        let query = ChangesQuery { since: Option::None, timeout: Option::Some(3600), limit: Option::Some(1_000_000) };
+        assert_eq!(query.limit(), MAX_LIMIT);
+        assert_eq!(query.timeout(), Duration::from_secs(MAX_TIMEOUT));
+
+        let query = ChangesQuery { since: Option::None, timeout: Option::Some(0), limit: Option::Some(-5) };
+        assert_eq!(query.limit(), 1);
+        assert_eq!(query.timeout(), Duration::from_secs(0));
+    }
+
+    #[test]
+    fn changes_should_be_framed_as_server_sent_events() {
+        let body = events(&[change(4), change(7)]).unwrap();
+        let body = std::str::from_utf8(&body).unwrap();
+
+        let frames: Vec<&str> = body.split_terminator("\n\n").collect();
+        assert_eq!(frames.len(), 2);
+        assert!(frames[0].starts_with("id: 4\nevent: change\ndata: {"));
+        assert!(frames[1].starts_with("id: 7\nevent: change\ndata: {"));
+        assert!(frames[1].contains(r#""seq":7"#));
+        assert!(!frames[1].trim_start_matches("id: 7\nevent: change\ndata: ").contains('\n'));
+        assert!(body.ends_with("\n\n"));
+    }
+}
@@ -2,21 +2,36 @@ use actix_web::{get, patch, post, web, HttpResponse};
 use serde::{Deserialize, Deserializer};
 use serde::de::Unexpected;
 use actix_web_validator::Validate;
-use crate::handlers::policy::UpsertPolicyRequest;
-use libzephir::storage::StorageManager;
+use crate::handlers::policy::{LinkedPolicyRequest, UpsertPolicyRequest};
+use crate::tenant::TenantStorage;
+use libzephir::storage::GroupLoading;
 use crate::err::ZephirError;
+use libzephir::policy::condition::Attributes;
 use libzephir::policy::policy::{CompletePolicy, ToJson};
 use std::convert::TryFrom;
 use libzephir::policy::policy_set::PolicySetTrait;
 use libzephir::identity::group::Group;
+use libzephir::identity::membership_rule::MembershipRule;
+use libzephir::policy::validity::Validity;
+use serde_json::{Map, Value};
 
 #[derive(Debug, Deserialize, Validate)]
 pub(crate) struct UpsertGroupRequest {
     #[validate(length(min = 1, message = "The value is too short"))]
     id: String,
-    linked_policies: Vec<String>,
+    linked_policies: Vec<LinkedPolicyRequest>,
     #[validate]
     inline_policy: Option<UpsertPolicyRequest>,
+    boundary_policy: Option<String>,
+    #[serde(default)]
+    attributes: Attributes,
+    membership_rule: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub(crate) struct PreviewMembershipRuleRequest {
+    #[validate(length(min = 1, message = "The value is too short"))]
+    membership_rule: String,
 }
 
 type StringType<'a> = &'a str;
@@ -42,10 +57,35 @@ impl Deserialize<'de> for PatchOperation {
 pub(crate) struct PatchGroupIdentitiesRequest {
     operation: PatchOperation,
     identity: String,
+    not_before: Option<i64>,
+    not_after: Option<i64>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub(crate) struct PatchGroupGroupsRequest {
+    operation: PatchOperation,
+    group: String,
+}
+
+/// Serializes the groups a subject inherits from, along with
+/// the path through which each of them is inherited.
+pub(crate) fn inherited_groups_to_value(groups: &[Group]) -> Value {
+    Value::from(
+        groups
+            .iter()
+            .map(|g| {
+                let mut map = Map::new();
+                map.insert(String::from("id"), Value::from(g.get_name().as_str()));
+                map.insert(String::from("inheritance_path"), Value::from(g.get_inheritance_path().clone()));
+
+                Value::Object(map)
+            })
+            .collect::<Vec<Value>>()
+    )
 }
 
 #[post("/groups")]
-pub(crate) async fn upsert_group(info: web::Json<UpsertGroupRequest>, storage: web::Data<StorageManager>) -> Result<HttpResponse, ZephirError> {
+pub(crate) async fn upsert_group(info: web::Json<UpsertGroupRequest>, storage: TenantStorage) -> Result<HttpResponse, ZephirError> {
     info.validate()?;
     let inline_policy = match info.0.inline_policy {
         Option::None => Option::None,
@@ -54,11 +94,34 @@ pub(crate) async fn upsert_group(info: web::Json<UpsertGroupRequest>, storage: w
         }
     };
 
-    let mut group = Group::new(info.0.id, inline_policy);
+    let mut group = Group::new(info.0.id, inline_policy).set_attributes(info.0.attributes);
+
+    // Members and nested groups are only changed through the PATCH endpoints.
+    if let Some(current) = storage.find_group(group.get_name()).await? {
+        for identity in current.get_identities() {
+            let validity = current.get_membership_validity(identity.get_id());
+            group = group.add_identity_with_validity(identity.clone(), validity);
+        }
+        for nested in current.get_groups() {
+            group = group.add_group(nested);
+        }
+    }
+
+    if let Some(ref rule) = info.0.membership_rule {
+        group = group.set_membership_rule(MembershipRule::try_from(rule.as_str())?);
+    }
+
     for ref p in info.0.linked_policies {
+        match storage.find_policy(p.get_id()).await? {
+            Option::None => return Ok(HttpResponse::BadRequest().json(format!("Policy {} does not exist", p.get_id()))),
+            Option::Some(policy) => group = group.add_policy_link(policy, p.get_link())
+        };
+    }
+
+    if let Some(ref p) = info.0.boundary_policy {
         match storage.find_policy(p).await? {
             Option::None => return Ok(HttpResponse::BadRequest().json(format!("Policy {} does not exist", p))),
-            Option::Some(policy) => group = group.add_policy(policy)
+            Option::Some(policy) => group = group.set_boundary(policy)
         };
     }
 
@@ -66,17 +129,36 @@ pub(crate) async fn upsert_group(info: web::Json<UpsertGroupRequest>, storage: w
     Ok(HttpResponse::Ok().json(group.to_json()))
 }
 
+#[post("/groups/membership-rule/preview")]
+pub(crate) async fn preview_membership_rule(info: web::Json<PreviewMembershipRuleRequest>, storage: TenantStorage) -> Result<HttpResponse, ZephirError> {
+    info.validate()?;
+    let rule = MembershipRule::try_from(info.membership_rule.as_str())?;
+    let identities = storage.find_identities_matching(&rule).await?;
+
+    let mut json = Map::new();
+    json.insert(String::from("membership_rule"), Value::from(rule.as_str()));
+    json.insert(String::from("identities"), Value::from(identities));
+
+    Ok(HttpResponse::Ok().json(json))
+}
+
 #[get("/group/{id}")]
-pub(crate) async fn get_group(web::Path(id): web::Path<String>, storage: web::Data<StorageManager>) -> Result<HttpResponse, ZephirError> {
-    let result = storage.find_group(id).await?;
+pub(crate) async fn get_group(web::Path(id): web::Path<String>, storage: TenantStorage) -> Result<HttpResponse, ZephirError> {
+    let result = storage.find_group_with(id, GroupLoading::PoliciesOnly).await?;
     match result {
         Option::None => Err(ZephirError::NotFound),
-        Option::Some(group) => Ok(HttpResponse::Ok().json(group.to_json()))
+        Option::Some(group) => {
+            let ancestors = storage.find_group_ancestors(group.get_name()).await?;
+            let mut json = group.to_json();
+            json.insert(String::from("inherited_groups"), inherited_groups_to_value(&ancestors));
+
+            Ok(HttpResponse::Ok().json(json))
+        }
     }
 }
 
 #[get("/group/{id}/identities")]
-pub(crate) async fn get_group_identities(web::Path(id): web::Path<String>, storage: web::Data<StorageManager>) -> Result<HttpResponse, ZephirError> {
+pub(crate) async fn get_group_identities(web::Path(id): web::Path<String>, storage: TenantStorage) -> Result<HttpResponse, ZephirError> {
     let result = storage.find_group(id).await?;
     match result {
         Option::None => Err(ZephirError::NotFound),
@@ -92,7 +174,7 @@ pub(crate) async fn get_group_identities(web::Path(id): web::Path<String>, stora
 }
 
 #[patch("/group/{id}/identities")]
-pub(crate) async fn patch_group_identities(info: web::Json<PatchGroupIdentitiesRequest>, web::Path(id): web::Path<String>, storage: web::Data<StorageManager>) -> Result<HttpResponse, ZephirError> {
+pub(crate) async fn patch_group_identities(info: web::Json<PatchGroupIdentitiesRequest>, web::Path(id): web::Path<String>, storage: TenantStorage) -> Result<HttpResponse, ZephirError> {
     let result = storage.find_group(id).await?;
     match result {
         Option::None => Err(ZephirError::NotFound),
@@ -102,7 +184,9 @@ pub(crate) async fn patch_group_identities(info: web::Json<PatchGroupIdentitiesR
                     match storage.find_identity(&info.identity).await? {
                         Option::None => Err(ZephirError::NotFound),
                         Option::Some(identity) => {
-                            group = group.add_identity(identity);
+                            group = group
+                                .remove_identity(&info.identity)
+                                .add_identity_with_validity(identity, Validity::new(info.not_before, info.not_after));
                             Ok(storage.save_group(&group).await?)
                         }
                     }
@@ -117,3 +201,39 @@ pub(crate) async fn patch_group_identities(info: web::Json<PatchGroupIdentitiesR
         }
     }
 }
+
+#[get("/group/{id}/groups")]
+pub(crate) async fn get_group_groups(web::Path(id): web::Path<String>, storage: TenantStorage) -> Result<HttpResponse, ZephirError> {
+    let result = storage.find_group(id).await?;
+    match result {
+        Option::None => Err(ZephirError::NotFound),
+        Option::Some(group) => Ok(HttpResponse::Ok().json(group.get_groups()))
+    }
+}
+
+#[patch("/group/{id}/groups")]
+pub(crate) async fn patch_group_groups(info: web::Json<PatchGroupGroupsRequest>, web::Path(id): web::Path<String>, storage: TenantStorage) -> Result<HttpResponse, ZephirError> {
+    let result = storage.find_group(id).await?;
+    match result {
+        Option::None => Err(ZephirError::NotFound),
+        Option::Some(mut group) => {
+            match info.operation {
+                PatchOperation::Add => {
+                    match storage.find_group_with(&info.group, GroupLoading::PoliciesOnly).await? {
+                        Option::None => Err(ZephirError::NotFound),
+                        Option::Some(nested) => {
+                            group = group.add_group(nested.get_name());
+                            Ok(storage.save_group(&group).await?)
+                        }
+                    }
+                },
+                PatchOperation::Remove => {
+                    group = group.remove_group(&info.group);
+                    Ok(storage.save_group(&group).await?)
+                }
+            }?;
+
+            Ok(HttpResponse::NoContent().finish())
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use actix_web::{delete, get, post, web, HttpResponse};
+use actix_web_validator::Validate;
+use crate::handlers::policy::UpsertPolicyRequest;
+use crate::tenant::TenantStorage;
+use crate::err::ZephirError;
+use libzephir::policy::policy::{CompletePolicy, ToJson};
+use serde_json::Value;
+use std::convert::TryFrom;
+
+#[get("/guardrails")]
+pub(crate) async fn get_guardrails(storage: TenantStorage) -> Result<HttpResponse, ZephirError> {
+    let guardrails = storage.find_guardrails().await?;
+    Ok(HttpResponse::Ok().json(
+        guardrails.get_policies()
+            .iter()
+            .map(|p| p.to_value())
+            .collect::<Vec<Value>>()
+    ))
+}
+
+#[post("/guardrails")]
+pub(crate) async fn upsert_guardrail(info: web::Json<UpsertPolicyRequest>, storage: TenantStorage) -> Result<HttpResponse, ZephirError> {
+    info.validate()?;
+    let id = info.get_id().clone();
+    let mut policy = CompletePolicy::try_from(info.0)?;
+    policy.id = id;
+
+    storage.save_guardrail(&policy).await?;
+    Ok(HttpResponse::Ok().json(policy.to_json()))
+}
+
+#[get("/guardrail/{id}")]
+pub(crate) async fn get_guardrail(web::Path(id): web::Path<String>, storage: TenantStorage) -> Result<HttpResponse, ZephirError> {
+    let result = storage.find_guardrail(id).await?;
+    match result {
+        Option::None => Err(ZephirError::NotFound),
+        Option::Some(policy) => Ok(HttpResponse::Ok().json(policy.to_json()))
+    }
+}
+
+#[delete("/guardrail/{id}")]
+pub(crate) async fn delete_guardrail(web::Path(id): web::Path<String>, storage: TenantStorage) -> Result<HttpResponse, ZephirError> {
+    if !storage.delete_guardrail(id).await? {
+        return Err(ZephirError::NotFound);
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -1,9 +1,10 @@
 use actix_web::{get, post, web, HttpResponse};
 use serde::Deserialize;
 use actix_web_validator::Validate;
-use crate::handlers::policy::UpsertPolicyRequest;
-use libzephir::storage::StorageManager;
+use crate::handlers::policy::{LinkedPolicyRequest, UpsertPolicyRequest};
+use crate::tenant::TenantStorage;
 use crate::err::ZephirError;
+use libzephir::policy::condition::Attributes;
 use libzephir::policy::policy::{CompletePolicy, ToJson};
 use std::convert::TryFrom;
 use libzephir::identity::identity::Identity;
@@ -13,13 +14,16 @@ use libzephir::policy::policy_set::PolicySetTrait;
 pub(crate) struct UpsertIdentityRequest {
     #[validate(length(min = 1, message = "The value is too short"))]
     id: String,
-    linked_policies: Vec<String>,
+    linked_policies: Vec<LinkedPolicyRequest>,
     #[validate]
     inline_policy: Option<UpsertPolicyRequest>,
+    boundary_policy: Option<String>,
+    #[serde(default)]
+    attributes: Attributes,
 }
 
 #[post("/identities")]
-pub(crate) async fn upsert_identity(info: web::Json<UpsertIdentityRequest>, storage: web::Data<StorageManager>) -> Result<HttpResponse, ZephirError> {
+pub(crate) async fn upsert_identity(info: web::Json<UpsertIdentityRequest>, storage: TenantStorage) -> Result<HttpResponse, ZephirError> {
     info.validate()?;
     let inline_policy = match info.0.inline_policy {
         Option::None => Option::None,
@@ -28,11 +32,18 @@ pub(crate) async fn upsert_identity(info: web::Json<UpsertIdentityRequest>, stor
         }
     };
 
-    let mut identity = Identity::new(info.0.id, inline_policy);
+    let mut identity = Identity::new(info.0.id, inline_policy).set_attributes(info.0.attributes);
     for ref p in info.0.linked_policies {
+        match storage.find_policy(p.get_id()).await? {
+            Option::None => return Ok(HttpResponse::BadRequest().json(format!("Policy {} does not exist", p.get_id()))),
+            Option::Some(policy) => identity = identity.add_policy_link(policy, p.get_link())
+        };
+    }
+
+    if let Some(ref p) = info.0.boundary_policy {
         match storage.find_policy(p).await? {
             Option::None => return Ok(HttpResponse::BadRequest().json(format!("Policy {} does not exist", p))),
-            Option::Some(policy) => identity = identity.add_policy(policy)
+            Option::Some(policy) => identity = identity.set_boundary(policy)
         };
     }
 
@@ -41,7 +52,7 @@ pub(crate) async fn upsert_identity(info: web::Json<UpsertIdentityRequest>, stor
 }
 
 #[get("/identity/{id}")]
-pub(crate) async fn get_identity(web::Path(id): web::Path<String>, storage: web::Data<StorageManager>) -> Result<HttpResponse, ZephirError> {
+pub(crate) async fn get_identity(web::Path(id): web::Path<String>, storage: TenantStorage) -> Result<HttpResponse, ZephirError> {
     let result = storage.find_identity(id).await?;
     match result {
         Option::None => Err(ZephirError::NotFound),
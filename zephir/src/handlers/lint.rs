@@ -0,0 +1,35 @@
+use actix_web::{get, web, HttpResponse};
+use crate::err::ZephirError;
+use crate::tenant::TenantStorage;
+use libzephir::lint::{LintFinding, Linter};
+use libzephir::policy::combining_algorithm::CombiningAlgorithm;
+use libzephir::policy::policy::ToJson;
+use serde_json::{Map, Value};
+
+fn findings_to_json(findings: &[LintFinding]) -> Map<String, Value> {
+    let mut json = Map::new();
+    json.insert(String::from("count"), Value::from(findings.len()));
+    json.insert(
+        String::from("findings"),
+        Value::Array(findings.iter().map(|f| Value::Object(f.to_json())).collect()),
+    );
+
+    json
+}
+
+/// Reports the redundant, shadowed and overly broad policies
+/// of an identity, including the ones of its groups.
+#[get("/lint/identity/{id}")]
+pub(crate) async fn lint_identity(web::Path(id): web::Path<String>, storage: TenantStorage, linter: web::Data<Linter>, algorithm: web::Data<CombiningAlgorithm>) -> Result<HttpResponse, ZephirError> {
+    let (identity, groups) = storage.find_subject(&id).await?.ok_or(ZephirError::NotFound)?;
+    Ok(HttpResponse::Ok().json(findings_to_json(&linter.lint_identity(&identity, &groups, *algorithm.get_ref()))))
+}
+
+/// Reports the redundant, shadowed and overly broad policies
+/// of a group, including the ones of the groups it inherits.
+#[get("/lint/group/{id}")]
+pub(crate) async fn lint_group(web::Path(id): web::Path<String>, storage: TenantStorage, linter: web::Data<Linter>, algorithm: web::Data<CombiningAlgorithm>) -> Result<HttpResponse, ZephirError> {
+    let group = storage.find_group(&id).await?.ok_or(ZephirError::NotFound)?;
+    let ancestors = storage.find_group_ancestors(&id).await?;
+    Ok(HttpResponse::Ok().json(findings_to_json(&linter.lint_group(&group, &ancestors, *algorithm.get_ref()))))
+}
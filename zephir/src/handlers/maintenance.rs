@@ -0,0 +1,10 @@
+use crate::err::ZephirError;
+use actix_web::{post, HttpResponse};
+use libzephir::policy::policy::ToJson;
+use crate::tenant::TenantStorage;
+
+#[post("/_maintenance/expired-links")]
+pub(crate) async fn remove_expired_links(storage: TenantStorage) -> Result<HttpResponse, ZephirError> {
+    let removed = storage.remove_expired_links().await?;
+    Ok(HttpResponse::Ok().json(removed.to_json()))
+}
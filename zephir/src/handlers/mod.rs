@@ -1,24 +1,90 @@
 mod allowed;
+mod changes;
 mod group;
+mod guardrail;
 mod identity;
+mod lint;
+mod maintenance;
 mod policy;
+mod replication;
+mod resource_policy;
+mod role;
+mod simulate;
 mod status;
+mod tenant;
+mod webhook;
 
+pub(crate) use status::get_cache_status;
+pub(crate) use status::get_files_status;
 pub(crate) use status::get_status;
 
 // Allowed
 pub(crate) use allowed::allowed_action;
+pub(crate) use allowed::allowed_action_from_files;
+
+// Changes
+pub(crate) use changes::get_changes;
+pub(crate) use changes::stream_changes;
 
 // Group
 pub(crate) use group::get_group;
+pub(crate) use group::get_group_groups;
 pub(crate) use group::get_group_identities;
+pub(crate) use group::patch_group_groups;
 pub(crate) use group::patch_group_identities;
+pub(crate) use group::preview_membership_rule;
 pub(crate) use group::upsert_group;
 
+// Guardrail
+pub(crate) use guardrail::delete_guardrail;
+pub(crate) use guardrail::get_guardrail;
+pub(crate) use guardrail::get_guardrails;
+pub(crate) use guardrail::upsert_guardrail;
+
 // Identity
 pub(crate) use identity::get_identity;
 pub(crate) use identity::upsert_identity;
 
+// Lint
+pub(crate) use lint::lint_group;
+pub(crate) use lint::lint_identity;
+
+// Maintenance
+pub(crate) use maintenance::remove_expired_links;
+
 // Policy
 pub(crate) use policy::get_policy;
+pub(crate) use policy::get_policy_diff;
+pub(crate) use policy::get_policy_revisions;
 pub(crate) use policy::upsert_policy;
+
+// Replication
+pub(crate) use replication::get_replication_snapshot;
+
+// Resource policy
+pub(crate) use resource_policy::delete_resource_policy;
+pub(crate) use resource_policy::get_resource_policies;
+pub(crate) use resource_policy::get_resource_policy;
+pub(crate) use resource_policy::upsert_resource_policy;
+
+// Role
+pub(crate) use role::assume_role;
+pub(crate) use role::get_role;
+pub(crate) use role::upsert_role;
+
+// Simulation
+pub(crate) use simulate::simulate_changes;
+
+// Tenant
+pub(crate) use tenant::delete_tenant;
+pub(crate) use tenant::export_data;
+pub(crate) use tenant::export_tenant;
+pub(crate) use tenant::import_data;
+
+// Webhook
+pub(crate) use webhook::delete_webhook;
+pub(crate) use webhook::get_webhook;
+pub(crate) use webhook::get_webhook_dead_letters;
+pub(crate) use webhook::get_webhooks;
+pub(crate) use webhook::redeliver_webhook_dead_letters;
+pub(crate) use webhook::upsert_webhook;
@@ -1,19 +1,30 @@
 use actix_web::{get, post, web, HttpResponse};
 use serde::Deserialize;
-use libzephir::storage::StorageManager;
+use crate::tenant::TenantStorage;
 use actix_web_validator::Validate;
 use regex::Regex;
 use crate::err::ZephirError;
+use libzephir::policy::condition::{Attributes, SubjectConditions};
 use libzephir::policy::policy::{CompletePolicy, ToJson};
 use libzephir::policy::{PolicyVersion, PolicyEffect};
+use libzephir::policy::policy_set::PolicyLink;
+use libzephir::policy::validity::Validity;
 use std::convert::TryFrom;
 use libzephir::err::Error;
+use libzephir::policy::diff::PolicyDiff;
+use serde_json::Value;
 
 lazy_static! {
     static ref RE_VALID_ID: Regex = Regex::new(r"^[A-Za-z][A-Za-z0-9_\-.]*$").unwrap();
     static ref RE_EFFECT: Regex = Regex::new(r"^(ALLOW|DENY)$").unwrap();
 }
 
+#[derive(Debug, Deserialize)]
+pub(crate) struct DiffQuery {
+    from: Option<i32>,
+    to: Option<i32>,
+}
+
 #[derive(Debug, Deserialize, Validate)]
 pub(crate) struct EmbeddedPolicyRequest {
     #[validate(range(min = 1, max = 1, message = "Invalid version."))]
@@ -38,6 +49,48 @@ pub(crate) struct UpsertPolicyRequest {
     actions: Vec<String>,
     #[validate(length(min = 1, message = "The value is too short"))]
     resources: Option<Vec<String>>,
+    /// Attributes the subject must have for the policy to apply (values or globs).
+    subject: Option<Attributes>,
+}
+
+impl UpsertPolicyRequest {
+    pub(crate) fn get_id(&self) -> &String {
+        &self.id
+    }
+}
+
+/// A policy linked to a subject: either the bare policy id or an object
+/// carrying the link priority (lower values are evaluated first) and
+/// the time window (unix timestamps) the link is effective in.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum LinkedPolicyRequest {
+    Id(String),
+    Link {
+        id: String,
+        #[serde(default)]
+        priority: i32,
+        not_before: Option<i64>,
+        not_after: Option<i64>,
+    },
+}
+
+impl LinkedPolicyRequest {
+    pub(crate) fn get_id(&self) -> &String {
+        match self {
+            LinkedPolicyRequest::Id(id) => id,
+            LinkedPolicyRequest::Link { id, .. } => id,
+        }
+    }
+
+    pub(crate) fn get_link(&self) -> PolicyLink {
+        match self {
+            LinkedPolicyRequest::Id(_) => PolicyLink::default(),
+            LinkedPolicyRequest::Link { priority, not_before, not_after, .. } => {
+                PolicyLink::new(*priority, Validity::new(*not_before, *not_after))
+            }
+        }
+    }
 }
 
 impl TryFrom<EmbeddedPolicyRequest> for CompletePolicy {
@@ -58,18 +111,23 @@ impl TryFrom<UpsertPolicyRequest> for CompletePolicy {
     type Error = Error;
 
     fn try_from(value: UpsertPolicyRequest) -> Result<Self, Self::Error> {
-        CompletePolicy::new(
+        let policy = CompletePolicy::new(
             "".to_string(),
             PolicyVersion::try_from(value.version)?,
             PolicyEffect::try_from(&value.effect)?,
             value.actions,
             value.resources.unwrap_or_else(|| vec![])
-        )
+        )?;
+
+        Ok(match value.subject {
+            Option::None => policy,
+            Option::Some(subject) => policy.with_subject_conditions(SubjectConditions::new(subject)),
+        })
     }
 }
 
 #[post("/policies")]
-pub(crate) async fn upsert_policy(info: web::Json<UpsertPolicyRequest>, storage: web::Data<StorageManager>) -> Result<HttpResponse, ZephirError> {
+pub(crate) async fn upsert_policy(info: web::Json<UpsertPolicyRequest>, storage: TenantStorage) -> Result<HttpResponse, ZephirError> {
     info.validate()?;
     let policy = CompletePolicy::try_from(info.0)?;
 
@@ -78,10 +136,51 @@ pub(crate) async fn upsert_policy(info: web::Json<UpsertPolicyRequest>, storage:
 }
 
 #[get("/policy/{id}")]
-pub(crate) async fn get_policy(web::Path(id): web::Path<String>, storage: web::Data<StorageManager>) -> Result<HttpResponse, ZephirError> {
+pub(crate) async fn get_policy(web::Path(id): web::Path<String>, storage: TenantStorage) -> Result<HttpResponse, ZephirError> {
     let result = storage.find_policy(id).await?;
     match result {
         Option::None => Err(ZephirError::NotFound),
         Option::Some(policy) => Ok(HttpResponse::Ok().json(policy.to_json()))
     }
 }
+
+#[get("/policy/{id}/revisions")]
+pub(crate) async fn get_policy_revisions(web::Path(id): web::Path<String>, storage: TenantStorage) -> Result<HttpResponse, ZephirError> {
+    let revisions = storage.find_policy_revisions(id).await?;
+    if revisions.is_empty() {
+        return Err(ZephirError::NotFound);
+    }
+
+    Ok(HttpResponse::Ok().json(
+        revisions
+            .iter()
+            .map(|r| Value::Object(r.to_json()))
+            .collect::<Vec<Value>>()
+    ))
+}
+
+/// Compares two revisions of a policy: by default the
+/// latest one and the one before it.
+#[get("/policy/{id}/diff")]
+pub(crate) async fn get_policy_diff(web::Path(id): web::Path<String>, query: web::Query<DiffQuery>, storage: TenantStorage) -> Result<HttpResponse, ZephirError> {
+    let to = match query.to {
+        Option::Some(to) => to,
+        Option::None => match storage.find_policy_revisions(&id).await?.last() {
+            Option::Some(last) => last.revision,
+            Option::None => return Err(ZephirError::NotFound),
+        },
+    };
+    let from = query.from.unwrap_or(to - 1);
+
+    let from_policy = storage.find_policy_revision(&id, from).await?;
+    let to_policy = storage.find_policy_revision(&id, to).await?;
+    match (from_policy, to_policy) {
+        (Option::Some(from_policy), Option::Some(to_policy)) => {
+            let mut json = PolicyDiff::between(&from_policy, &to_policy).to_json();
+            json.insert(String::from("from_revision"), Value::from(from));
+            json.insert(String::from("to_revision"), Value::from(to));
+            Ok(HttpResponse::Ok().json(json))
+        }
+        _ => Err(ZephirError::NotFound),
+    }
+}
@@ -0,0 +1,40 @@
+use crate::err::ZephirError;
+use crate::tenant::TenantStorage;
+use actix_web::http::header;
+use actix_web::{get, HttpRequest, HttpResponse};
+use libzephir::err::Error;
+use libzephir::policy::policy::ToJson;
+use serde_json::Value;
+
+/// Serves the whole data set of the tenant to the replicas.
+///
+/// The snapshot is tagged with the sequence number of the last change it
+/// includes (both are read from the same storage snapshot): replicas send back
+/// the tag they hold, and get a 304 response (at the cost of a single query) if
+/// nothing changed. The removal of the expired links is recorded as a change too,
+/// so replicas get the cleanup done by the primary on their next pull.
+#[get("/replication/snapshot")]
+pub(crate) async fn get_replication_snapshot(
+    req: HttpRequest,
+    storage: TenantStorage,
+) -> Result<HttpResponse, ZephirError> {
+    let etag = |seq: i64| format!(r#""{}""#, seq);
+    let current = etag(storage.last_change_seq().await?);
+
+    let unchanged = matches!(
+        req.headers().get(header::IF_NONE_MATCH).and_then(|v| v.to_str().ok()),
+        Some(tag) if tag == current
+    );
+
+    if unchanged {
+        return Ok(HttpResponse::NotModified().header(header::ETAG, current).finish());
+    }
+
+    let (seq, export) = storage.export_tenant_with_last_change().await?;
+    let body = serde_json::to_vec(&Value::Object(export.to_json())).map_err(Error::from)?;
+
+    Ok(HttpResponse::Ok()
+        .header(header::ETAG, etag(seq))
+        .content_type("application/json")
+        .body(body))
+}
@@ -0,0 +1,70 @@
+use actix_web::{delete, get, post, web, HttpResponse};
+use actix_web_validator::Validate;
+use serde::Deserialize;
+use crate::handlers::policy::UpsertPolicyRequest;
+use crate::tenant::TenantStorage;
+use crate::err::ZephirError;
+use libzephir::policy::policy::{CompletePolicy, ToJson};
+use libzephir::policy::resource_policy::{Principals, ResourcePolicy};
+use serde_json::Value;
+use std::convert::TryFrom;
+
+#[derive(Debug, Default, Deserialize)]
+pub(crate) struct PrincipalRequest {
+    #[serde(default)]
+    identities: Vec<String>,
+    #[serde(default)]
+    groups: Vec<String>,
+    #[serde(default)]
+    roles: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub(crate) struct UpsertResourcePolicyRequest {
+    #[serde(flatten)]
+    #[validate]
+    policy: UpsertPolicyRequest,
+    principal: PrincipalRequest,
+}
+
+#[get("/resource-policies")]
+pub(crate) async fn get_resource_policies(storage: TenantStorage) -> Result<HttpResponse, ZephirError> {
+    let policies = storage.find_resource_policies().await?;
+    Ok(HttpResponse::Ok().json(
+        policies.get_policies()
+            .iter()
+            .map(|p| p.to_value())
+            .collect::<Vec<Value>>()
+    ))
+}
+
+#[post("/resource-policies")]
+pub(crate) async fn upsert_resource_policy(info: web::Json<UpsertResourcePolicyRequest>, storage: TenantStorage) -> Result<HttpResponse, ZephirError> {
+    info.validate()?;
+    let id = info.policy.get_id().clone();
+    let principal = info.0.principal;
+    let mut policy = CompletePolicy::try_from(info.0.policy)?;
+    policy.id = id;
+
+    let policy = ResourcePolicy::new(policy, Principals::new(principal.identities, principal.groups, principal.roles));
+    storage.save_resource_policy(&policy).await?;
+    Ok(HttpResponse::Ok().json(policy.to_json()))
+}
+
+#[get("/resource-policy/{id}")]
+pub(crate) async fn get_resource_policy(web::Path(id): web::Path<String>, storage: TenantStorage) -> Result<HttpResponse, ZephirError> {
+    let result = storage.find_resource_policy(id).await?;
+    match result {
+        Option::None => Err(ZephirError::NotFound),
+        Option::Some(policy) => Ok(HttpResponse::Ok().json(policy.to_json()))
+    }
+}
+
+#[delete("/resource-policy/{id}")]
+pub(crate) async fn delete_resource_policy(web::Path(id): web::Path<String>, storage: TenantStorage) -> Result<HttpResponse, ZephirError> {
+    if !storage.delete_resource_policy(id).await? {
+        return Err(ZephirError::NotFound);
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -0,0 +1,103 @@
+use actix_web::{get, post, web, HttpResponse};
+use serde::Deserialize;
+use actix_web_validator::Validate;
+use crate::handlers::policy::{EmbeddedPolicyRequest, LinkedPolicyRequest, UpsertPolicyRequest};
+use crate::tenant::TenantStorage;
+use crate::err::ZephirError;
+use libzephir::identity::assumable_role::{AssumableRole, TrustPolicy};
+use libzephir::policy::policy::{CompletePolicy, ToJson};
+use libzephir::policy::policy_set::PolicySetTrait;
+use log::trace;
+use std::convert::TryFrom;
+use std::time::Duration;
+
+const DEFAULT_SESSION_DURATION: u64 = 3600;
+
+#[derive(Debug, Default, Deserialize)]
+pub(crate) struct TrustPolicyRequest {
+    #[serde(default)]
+    identities: Vec<String>,
+    #[serde(default)]
+    groups: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub(crate) struct UpsertRoleRequest {
+    #[validate(length(min = 1, message = "The value is too short"))]
+    id: String,
+    linked_policies: Vec<LinkedPolicyRequest>,
+    #[validate]
+    inline_policy: Option<UpsertPolicyRequest>,
+    #[serde(default)]
+    trust_policy: TrustPolicyRequest,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub(crate) struct AssumeRoleRequest {
+    #[validate(length(min = 1, message = "The value is too short"))]
+    identity: String,
+    #[validate]
+    session_policy: Option<EmbeddedPolicyRequest>,
+    #[validate(range(min = 60, max = 43200, message = "Invalid duration."))]
+    duration: Option<u64>,
+}
+
+#[post("/roles")]
+pub(crate) async fn upsert_role(info: web::Json<UpsertRoleRequest>, storage: TenantStorage) -> Result<HttpResponse, ZephirError> {
+    info.validate()?;
+    let inline_policy = match info.0.inline_policy {
+        Option::None => Option::None,
+        Option::Some(req_policy) => {
+            Option::Some(CompletePolicy::try_from(req_policy)?)
+        }
+    };
+
+    let trust_policy = TrustPolicy {
+        identities: info.0.trust_policy.identities,
+        groups: info.0.trust_policy.groups,
+    };
+
+    let mut role = AssumableRole::new(info.0.id, inline_policy, trust_policy);
+    for ref p in info.0.linked_policies {
+        match storage.find_policy(p.get_id()).await? {
+            Option::None => return Ok(HttpResponse::BadRequest().json(format!("Policy {} does not exist", p.get_id()))),
+            Option::Some(policy) => role = role.add_policy_link(policy, p.get_link())
+        };
+    }
+
+    storage.save_role(&role).await?;
+    Ok(HttpResponse::Ok().json(role.to_json()))
+}
+
+#[get("/role/{id}")]
+pub(crate) async fn get_role(web::Path(id): web::Path<String>, storage: TenantStorage) -> Result<HttpResponse, ZephirError> {
+    let result = storage.find_role(id).await?;
+    match result {
+        Option::None => Err(ZephirError::NotFound),
+        Option::Some(role) => Ok(HttpResponse::Ok().json(role.to_json()))
+    }
+}
+
+#[post("/role/{id}/sessions")]
+pub(crate) async fn assume_role(info: web::Json<AssumeRoleRequest>, web::Path(id): web::Path<String>, storage: TenantStorage) -> Result<HttpResponse, ZephirError> {
+    info.validate()?;
+    let role = storage.find_role(id).await?.ok_or(ZephirError::NotFound)?;
+    let (identity, groups) = storage.find_subject(&info.identity).await?.ok_or(ZephirError::NotFound)?;
+
+    if !role.get_trust_policy().trusts(&identity, &groups) {
+        trace!(r#"Identity "{}" is not trusted by role "{}". Denying access..."#, identity.get_id(), role.get_id());
+        return Err(ZephirError::AllowedError);
+    }
+
+    let session_policy = match info.0.session_policy {
+        Option::None => Option::None,
+        Option::Some(req_policy) => {
+            Option::Some(CompletePolicy::try_from(req_policy)?)
+        }
+    };
+
+    let duration = Duration::from_secs(info.0.duration.unwrap_or(DEFAULT_SESSION_DURATION));
+    let session = storage.create_role_session(&role, &identity, session_policy.as_ref(), duration).await?;
+
+    Ok(HttpResponse::Ok().json(session.to_json()))
+}
@@ -0,0 +1,82 @@
+use actix_web::{post, web, HttpResponse};
+use crate::err::ZephirError;
+use crate::handlers::allowed::decide_in_snapshot;
+use crate::tenant::TenantStorage;
+use libzephir::err::Error;
+use libzephir::file_store::simulation::{simulate, ProposedChanges};
+use libzephir::policy::allowed_result::AllowedResult;
+use libzephir::policy::combining_algorithm::CombiningAlgorithm;
+use libzephir::policy::policy::ToJson;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::convert::TryFrom;
+
+/// The maximum number of probes evaluated by a single simulation.
+const MAX_PROBES: usize = 1000;
+
+/// A request to be evaluated against both the current and the proposed data.
+#[derive(Deserialize)]
+pub(crate) struct Probe {
+    subject: String,
+    action: String,
+    resource: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct SimulateRequest {
+    #[serde(default)]
+    changes: ProposedChanges,
+    probes: Vec<Probe>,
+    combining_algorithm: Option<String>,
+}
+
+fn outcome(result: &AllowedResult) -> Value {
+    result.to_json().remove("outcome").unwrap_or(Value::Null)
+}
+
+/// Evaluates the probes against the current data of the tenant and against
+/// the data as it would be after the proposed changes, reporting the decisions
+/// which would flip. Nothing is saved: both data sets are held in memory.
+#[post("/simulate")]
+pub(crate) async fn simulate_changes(
+    info: web::Json<SimulateRequest>,
+    storage: TenantStorage,
+    default_algorithm: web::Data<CombiningAlgorithm>,
+) -> Result<HttpResponse, ZephirError> {
+    let algorithm = match info.combining_algorithm.as_ref() {
+        Option::None => *default_algorithm.get_ref(),
+        Option::Some(name) => CombiningAlgorithm::try_from(name.as_str())?,
+    };
+
+    if info.probes.len() > MAX_PROBES {
+        return Err(Error::invalid_snapshot(format!("too many probes (at most {} allowed)", MAX_PROBES)).into());
+    }
+
+    let (current, proposed) = simulate(&storage.export_tenant().await?, &info.changes)?;
+
+    let mut flipped = 0;
+    let mut results = vec![];
+    for probe in &info.probes {
+        let resource = probe.resource.as_ref();
+        let before = outcome(&decide_in_snapshot(&current, &probe.subject, &probe.action, resource, algorithm));
+        let after = outcome(&decide_in_snapshot(&proposed, &probe.subject, &probe.action, resource, algorithm));
+        if before != after {
+            flipped += 1;
+        }
+
+        let mut json = Map::new();
+        json.insert(String::from("subject"), Value::from(probe.subject.as_str()));
+        json.insert(String::from("action"), Value::from(probe.action.as_str()));
+        json.insert(String::from("resource"), Value::from(probe.resource.clone()));
+        json.insert(String::from("flipped"), Value::from(before != after));
+        json.insert(String::from("current"), before);
+        json.insert(String::from("proposed"), after);
+        results.push(Value::Object(json));
+    }
+
+    let mut json = Map::new();
+    json.insert(String::from("flipped"), Value::from(flipped));
+    json.insert(String::from("results"), Value::Array(results));
+
+    Ok(HttpResponse::Ok().json(json))
+}
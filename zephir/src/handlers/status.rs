@@ -1,12 +1,50 @@
 use crate::err::ZephirError;
+use crate::replica::ReplicationState;
 use actix_web::{get, web, HttpResponse};
-use serde_json::Value;
+use libzephir::cache;
+use serde_json::{Map, Value};
 use sqlx::PgPool;
 
+/// Checks the database connection. Replicas report their replication status too.
 #[get("/_status")]
-pub(crate) async fn get_status(db_pool: web::Data<PgPool>) -> Result<HttpResponse, ZephirError> {
+pub(crate) async fn get_status(
+    db_pool: web::Data<PgPool>,
+    replication: Option<web::Data<ReplicationState>>,
+) -> Result<HttpResponse, ZephirError> {
     let pool = db_pool.get_ref();
     sqlx::query("SELECT 1").fetch_one(pool).await?;
 
+    Ok(match replication {
+        Option::None => HttpResponse::Ok().json(Value::from("OK")),
+        Option::Some(replication) => {
+            let mut map = Map::new();
+            map.insert("status".to_string(), Value::from("OK"));
+            map.insert("replication".to_string(), Value::Object(replication.to_json()));
+
+            HttpResponse::Ok().json(map)
+        }
+    })
+}
+
+/// Status endpoint used when serving the policy files: the
+/// current snapshot is valid, as invalid ones are never loaded.
+#[get("/_status")]
+pub(crate) async fn get_files_status() -> Result<HttpResponse, ZephirError> {
     Ok(HttpResponse::Ok().json(Value::from("OK")))
 }
+
+#[get("/_status/cache")]
+pub(crate) async fn get_cache_status() -> Result<HttpResponse, ZephirError> {
+    let stats = cache::stats();
+
+    let mut map = Map::new();
+    map.insert("backend".to_string(), Value::from(cache::backend_name()));
+    map.insert("hits".to_string(), Value::from(stats.hits));
+    map.insert("misses".to_string(), Value::from(stats.misses));
+    map.insert("evictions".to_string(), Value::from(stats.evictions));
+    map.insert("expirations".to_string(), Value::from(stats.expirations));
+    map.insert("entries".to_string(), Value::from(stats.entries));
+    map.insert("capacity".to_string(), Value::from(stats.capacity));
+
+    Ok(HttpResponse::Ok().json(map))
+}
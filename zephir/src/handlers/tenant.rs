@@ -0,0 +1,57 @@
+use crate::err::ZephirError;
+use crate::tenant::TenantStorage;
+use actix_web::{delete, get, post, web, HttpResponse};
+use libzephir::file_store::parse_export;
+use libzephir::policy::policy::ToJson;
+use libzephir::storage::ImportMode;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::convert::TryFrom;
+
+#[derive(Deserialize)]
+pub(crate) struct ImportQuery {
+    mode: Option<String>,
+}
+
+async fn export(storage: TenantStorage) -> Result<HttpResponse, ZephirError> {
+    let export = storage.export_tenant().await?;
+    Ok(HttpResponse::Ok().json(export.to_json()))
+}
+
+#[get("/tenant/export")]
+pub(crate) async fn export_tenant(storage: TenantStorage) -> Result<HttpResponse, ZephirError> {
+    export(storage).await
+}
+
+/// Exports the whole data set of the tenant as a versioned document,
+/// which could be fed back to `POST /import`.
+#[get("/export")]
+pub(crate) async fn export_data(storage: TenantStorage) -> Result<HttpResponse, ZephirError> {
+    export(storage).await
+}
+
+/// Imports a document produced by `GET /export`, merging it with the
+/// current data set (the default), replacing it or only reporting the changes.
+#[post("/import")]
+pub(crate) async fn import_data(
+    storage: TenantStorage,
+    query: web::Query<ImportQuery>,
+    body: web::Bytes,
+) -> Result<HttpResponse, ZephirError> {
+    let mode = ImportMode::try_from(query.mode.as_deref().unwrap_or("merge"))?;
+    let imported = parse_export(&body, storage.get_tenant())?;
+    let report = storage.import_tenant(&imported, mode).await?;
+
+    Ok(HttpResponse::Ok().json(report.to_json()))
+}
+
+#[delete("/tenant")]
+pub(crate) async fn delete_tenant(storage: TenantStorage) -> Result<HttpResponse, ZephirError> {
+    let deleted = storage.delete_tenant().await?;
+
+    let mut json = Map::new();
+    json.insert(String::from("tenant"), Value::from(storage.get_tenant()));
+    json.insert(String::from("deleted"), Value::from(deleted));
+
+    Ok(HttpResponse::Ok().json(json))
+}
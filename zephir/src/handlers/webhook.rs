@@ -0,0 +1,110 @@
+use actix_web::{delete, get, post, web, HttpResponse};
+use actix_web_validator::Validate;
+use serde::Deserialize;
+use crate::tenant::TenantStorage;
+use crate::err::ZephirError;
+use libzephir::policy::policy::ToJson;
+use libzephir::webhook::{generate_secret, Webhook};
+use serde_json::{Map, Value};
+
+#[derive(Debug, Deserialize, Validate)]
+pub(crate) struct UpsertWebhookRequest {
+    #[validate(length(min = 1, message = "The value is too short"))]
+    id: String,
+    #[validate(url(message = "Invalid url."))]
+    url: String,
+    #[validate(length(min = 1, message = "The value is too short"))]
+    events: Vec<String>,
+    secret: Option<String>,
+    enabled: Option<bool>,
+}
+
+#[get("/webhooks")]
+pub(crate) async fn get_webhooks(storage: TenantStorage) -> Result<HttpResponse, ZephirError> {
+    let webhooks = storage.find_webhooks().await?;
+    Ok(HttpResponse::Ok().json(
+        webhooks
+            .iter()
+            .map(|w| Value::Object(w.to_json()))
+            .collect::<Vec<Value>>()
+    ))
+}
+
+/// Creates or updates a webhook.
+///
+/// The secret the payloads are signed with is generated if not given, and
+/// is only returned by this endpoint: updating a webhook without a secret keeps
+/// the current one.
+#[post("/webhooks")]
+pub(crate) async fn upsert_webhook(info: web::Json<UpsertWebhookRequest>, storage: TenantStorage) -> Result<HttpResponse, ZephirError> {
+    info.validate()?;
+    let current = storage.find_webhook(&info.id).await?;
+    let info = info.0;
+    let secret = match (info.secret, current) {
+        (Option::Some(secret), _) => secret,
+        (Option::None, Option::Some(current)) => current.secret,
+        (Option::None, Option::None) => generate_secret(),
+    };
+
+    let webhook = Webhook {
+        id: info.id,
+        url: info.url,
+        events: info.events,
+        secret,
+        enabled: info.enabled.unwrap_or(true),
+    };
+
+    storage.save_webhook(&webhook).await?;
+
+    let mut json = webhook.to_json();
+    json.insert(String::from("secret"), Value::from(webhook.secret));
+    Ok(HttpResponse::Ok().json(json))
+}
+
+#[get("/webhook/{id}")]
+pub(crate) async fn get_webhook(web::Path(id): web::Path<String>, storage: TenantStorage) -> Result<HttpResponse, ZephirError> {
+    let result = storage.find_webhook(id).await?;
+    match result {
+        Option::None => Err(ZephirError::NotFound),
+        Option::Some(webhook) => Ok(HttpResponse::Ok().json(webhook.to_json()))
+    }
+}
+
+#[delete("/webhook/{id}")]
+pub(crate) async fn delete_webhook(web::Path(id): web::Path<String>, storage: TenantStorage) -> Result<HttpResponse, ZephirError> {
+    if !storage.delete_webhook(id).await? {
+        return Err(ZephirError::NotFound);
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Lists the notifications which could not be delivered to the webhook.
+#[get("/webhook/{id}/dead-letters")]
+pub(crate) async fn get_webhook_dead_letters(web::Path(id): web::Path<String>, storage: TenantStorage) -> Result<HttpResponse, ZephirError> {
+    if storage.find_webhook(&id).await?.is_none() {
+        return Err(ZephirError::NotFound);
+    }
+
+    let dead_letters = storage.find_dead_letters(id).await?;
+    Ok(HttpResponse::Ok().json(
+        dead_letters
+            .iter()
+            .map(|d| Value::Object(d.to_json()))
+            .collect::<Vec<Value>>()
+    ))
+}
+
+/// Queues the dead letters of the webhook again (e.g. once the receiver has been fixed).
+#[post("/webhook/{id}/dead-letters/redeliver")]
+pub(crate) async fn redeliver_webhook_dead_letters(web::Path(id): web::Path<String>, storage: TenantStorage) -> Result<HttpResponse, ZephirError> {
+    if storage.find_webhook(&id).await?.is_none() {
+        return Err(ZephirError::NotFound);
+    }
+
+    let requeued = storage.redeliver_dead_letters(id).await?;
+
+    let mut json = Map::new();
+    json.insert(String::from("requeued"), Value::from(requeued));
+    Ok(HttpResponse::Ok().json(json))
+}
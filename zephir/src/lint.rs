@@ -0,0 +1,58 @@
+use libzephir::err::{Error, ErrorKind};
+use libzephir::lint::{LintFinding, Linter};
+use libzephir::policy::combining_algorithm::CombiningAlgorithm;
+use libzephir::tenant::DEFAULT_TENANT;
+
+const USAGE: &str = "Usage:
+    rzephir lint identity <id> [tenant]
+    rzephir lint group <id> [tenant]";
+
+fn usage() -> Error {
+    Error::new(ErrorKind::UnknownError, USAGE)
+}
+
+fn not_found(subject_type: &str, id: &str) -> Error {
+    Error::new(ErrorKind::UnknownError, format!("Unknown {} \"{}\"", subject_type, id))
+}
+
+/// Analyzes the policies of an identity or of a group, as `GET /lint/...` does,
+/// with the combining algorithm set in COMBINING_ALGORITHM,
+/// printing one finding per line. Fails if any finding is reported, so that
+/// the command could be used as a check.
+async fn lint(subject_type: &str, id: &str, tenant: &str) -> Result<(), Error> {
+    let linter = Linter::from_env()?;
+    let algorithm = CombiningAlgorithm::from_env()?;
+    let storage = crate::connect_storage(tenant).await?;
+    let findings: Vec<LintFinding> = match subject_type {
+        "identity" => {
+            let (identity, groups) = storage.find_subject(id).await?.ok_or_else(|| not_found(subject_type, id))?;
+            linter.lint_identity(&identity, &groups, algorithm)
+        }
+        "group" => {
+            let group = storage.find_group(id).await?.ok_or_else(|| not_found(subject_type, id))?;
+            linter.lint_group(&group, &storage.find_group_ancestors(id).await?, algorithm)
+        }
+        _ => return Err(usage()),
+    };
+
+    for finding in &findings {
+        println!("{}: {}", finding.kind(), finding);
+    }
+
+    if findings.is_empty() {
+        println!("No issues found");
+        Ok(())
+    } else {
+        Err(Error::new(ErrorKind::UnknownError, format!("{} issue(s) found", findings.len())))
+    }
+}
+
+/// Runs the lint subcommand with the given arguments.
+pub(crate) async fn run(args: &[String]) -> Result<(), Error> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args.as_slice() {
+        [subject_type, id] => lint(subject_type, id, DEFAULT_TENANT).await,
+        [subject_type, id, tenant] => lint(subject_type, id, tenant).await,
+        _ => Err(usage()),
+    }
+}
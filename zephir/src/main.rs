@@ -2,14 +2,35 @@
 #[macro_use]
 extern crate lazy_static;
 
+mod bundle;
 mod err;
 mod handlers;
+mod lint;
+mod policy;
+mod replica;
+mod tenant;
+mod transfer;
+mod webhook_dispatcher;
 
+use actix_web::dev::{Service, ServiceResponse};
 use actix_web::middleware::Logger;
-use actix_web::{App, HttpServer};
+use actix_web::{web, App, HttpServer};
 use sqlx::postgres::PgPoolOptions;
+use libzephir::file_store::bundle::parse_public_key;
+use libzephir::file_store::FileStore;
 use libzephir::storage::StorageManager;
 use libzephir::err::{Error, ErrorKind};
+use libzephir::lint::Linter;
+use libzephir::policy::combining_algorithm::CombiningAlgorithm;
+use libzephir::tenant::validate_tenant;
+use replica::ReplicaConfig;
+use std::future::Future;
+use std::pin::Pin;
+
+type LocalBoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + 'a>>;
+
+/// The maximum size of a raw request body, big enough for a whole data set import.
+const IMPORT_PAYLOAD_LIMIT: usize = 64 * 1024 * 1024;
 
 fn get_serve_port() -> u16 {
     let serve_port = std::env::var("SERVE_PORT");
@@ -38,10 +59,88 @@ fn get_db_connection_string() -> Result<String, Error> {
     }
 }
 
+/// Connects to the database for a command line operation on the given tenant.
+async fn connect_storage(tenant: &str) -> Result<StorageManager, Error> {
+    validate_tenant(tenant)?;
+    let pool = PgPoolOptions::new()
+        .max_connections(1)
+        .connect(get_db_connection_string()?.as_str())
+        .await?;
+
+    Ok(StorageManager::new(pool).for_tenant(tenant))
+}
+
+/// Gets the directory holding the policy files, if zephir
+/// should run without a database.
+fn get_policy_dir() -> Option<String> {
+    std::env::var("POLICY_DIR").ok().filter(|dir| !dir.is_empty())
+}
+
+/// Gets the bundle to serve the policies from, along with the
+/// key it must be signed with, if zephir should run without a database.
+fn get_policy_bundle() -> Option<(String, String)> {
+    let bundle = std::env::var("POLICY_BUNDLE").ok().filter(|path| !path.is_empty())?;
+    match std::env::var("BUNDLE_PUBLIC_KEY") {
+        Result::Ok(key) if !key.is_empty() => Some((bundle, key)),
+        _ => panic!("Bundle public key not set. Please set BUNDLE_PUBLIC_KEY env var"),
+    }
+}
+
+/// Serves the policies defined in the given file store, reloading
+/// them on change. Only the evaluation endpoints are exposed.
+async fn serve_files(store: FileStore, combining_algorithm: CombiningAlgorithm) -> std::io::Result<()> {
+    let store = std::sync::Arc::new(store);
+    store.clone().watch().unwrap();
+
+    let store = web::Data::from(store);
+    HttpServer::new(move || {
+        App::new()
+            .app_data(store.clone())
+            .data(combining_algorithm)
+            .wrap(Logger::default())
+            .service(handlers::get_files_status)
+            .service(handlers::get_cache_status)
+            .service(handlers::allowed_action_from_files)
+    })
+    .bind(("0.0.0.0", get_serve_port()))?
+    .run()
+    .await
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     env_logger::init();
 
+    let args: Vec<String> = std::env::args().collect();
+    let command = match args.get(1).map(String::as_str) {
+        Some("bundle") => Some(bundle::run(&args[2..]).await),
+        Some("export") => Some(transfer::export(&args[2..]).await),
+        Some("import") => Some(transfer::import(&args[2..]).await),
+        Some("policy") => Some(policy::run(&args[2..]).await),
+        Some("lint") => Some(lint::run(&args[2..]).await),
+        _ => None,
+    };
+
+    if let Some(result) = command {
+        if let Err(e) = result {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+
+        return Ok(());
+    }
+
+    let combining_algorithm = CombiningAlgorithm::from_env().unwrap();
+    let linter = Linter::from_env().unwrap();
+    if let Some((path, key)) = get_policy_bundle() {
+        let public_key = parse_public_key(&std::fs::read_to_string(key)?).unwrap();
+        return serve_files(FileStore::load_bundle(path, public_key).unwrap(), combining_algorithm).await;
+    }
+
+    if let Some(dir) = get_policy_dir() {
+        return serve_files(FileStore::load(dir).unwrap(), combining_algorithm).await;
+    }
+
     let pool = PgPoolOptions::new()
         .max_connections(5)
         .connect(get_db_connection_string().unwrap().as_str())
@@ -49,22 +148,86 @@ async fn main() -> std::io::Result<()> {
         .unwrap();
 
     let storage_manager = StorageManager::new(pool.clone());
+    storage_manager.get_invalidator().clone().listen();
+
+    // Replicas hold a copy of the primary data: expired links are removed
+    // by the primary (recording the change), and replicated on the next pull.
+    // Webhooks are notified by the primary only.
+    let replication = match ReplicaConfig::from_env().unwrap() {
+        Option::None => {
+            storage_manager.schedule_expired_links_cleanup();
+            webhook_dispatcher::start(storage_manager.clone());
+            Option::None
+        }
+        Option::Some(config) => Option::Some(web::Data::from(replica::start(config, storage_manager.clone()))),
+    };
 
     HttpServer::new(move || {
-        App::new()
+        let read_only = replication.is_some();
+        let mut app = App::new()
             .data(pool.clone())
             .data(storage_manager.clone())
+            .data(combining_algorithm)
+            .data(linter.clone())
+            .app_data(web::PayloadConfig::new(IMPORT_PAYLOAD_LIMIT));
+        if let Some(replication) = &replication {
+            app = app.app_data(replication.clone());
+        }
+
+        app
+            .wrap_fn(move |req, srv| -> LocalBoxFuture<'static, Result<ServiceResponse, actix_web::Error>> {
+                if read_only && replica::is_write(req.method(), req.path()) {
+                    let response = req.into_response(replica::read_only_response());
+                    Box::pin(async move { Ok(response) })
+                } else {
+                    Box::pin(srv.call(req))
+                }
+            })
             .wrap(Logger::default())
             .service(handlers::get_status)
+            .service(handlers::get_cache_status)
             .service(handlers::allowed_action)
+            .service(handlers::simulate_changes)
+            .service(handlers::get_changes)
+            .service(handlers::stream_changes)
             .service(handlers::get_group)
+            .service(handlers::get_group_groups)
             .service(handlers::get_group_identities)
+            .service(handlers::patch_group_groups)
             .service(handlers::patch_group_identities)
+            .service(handlers::preview_membership_rule)
             .service(handlers::upsert_group)
+            .service(handlers::delete_guardrail)
+            .service(handlers::get_guardrail)
+            .service(handlers::get_guardrails)
+            .service(handlers::upsert_guardrail)
+            .service(handlers::lint_group)
+            .service(handlers::lint_identity)
+            .service(handlers::remove_expired_links)
             .service(handlers::get_identity)
             .service(handlers::upsert_identity)
             .service(handlers::get_policy)
+            .service(handlers::get_policy_diff)
+            .service(handlers::get_policy_revisions)
             .service(handlers::upsert_policy)
+            .service(handlers::delete_resource_policy)
+            .service(handlers::get_resource_policies)
+            .service(handlers::get_resource_policy)
+            .service(handlers::upsert_resource_policy)
+            .service(handlers::assume_role)
+            .service(handlers::get_role)
+            .service(handlers::upsert_role)
+            .service(handlers::delete_tenant)
+            .service(handlers::export_tenant)
+            .service(handlers::export_data)
+            .service(handlers::import_data)
+            .service(handlers::get_replication_snapshot)
+            .service(handlers::delete_webhook)
+            .service(handlers::get_webhook)
+            .service(handlers::get_webhook_dead_letters)
+            .service(handlers::get_webhooks)
+            .service(handlers::redeliver_webhook_dead_letters)
+            .service(handlers::upsert_webhook)
     })
     .bind(("0.0.0.0", get_serve_port()))?
     .run()
@@ -0,0 +1,51 @@
+use libzephir::err::{Error, ErrorKind};
+use libzephir::file_store::read_policy;
+use libzephir::policy::diff::PolicyDiff;
+use libzephir::policy::policy::CompletePolicy;
+use libzephir::tenant::DEFAULT_TENANT;
+
+const USAGE: &str = "Usage:
+    rzephir policy diff <from file> <to file>
+    rzephir policy diff-revisions <id> <from revision> <to revision> [tenant]";
+
+fn usage() -> Error {
+    Error::new(ErrorKind::UnknownError, USAGE)
+}
+
+/// Compares two policy files (JSON or YAML).
+fn diff_files(from: &str, to: &str) -> Result<(), Error> {
+    print!("{}", PolicyDiff::between(&read_policy(from)?, &read_policy(to)?));
+    Ok(())
+}
+
+/// Compares two saved revisions of a policy, read from the database.
+async fn diff_revisions(id: &str, from: &str, to: &str, tenant: &str) -> Result<(), Error> {
+    let storage = crate::connect_storage(tenant).await?;
+    let mut policies: Vec<CompletePolicy> = vec![];
+    for revision in &[from, to] {
+        let number = revision.parse::<i32>().map_err(|_| usage())?;
+        match storage.find_policy_revision(id, number).await? {
+            Option::Some(policy) => policies.push(policy),
+            Option::None => {
+                return Err(Error::new(
+                    ErrorKind::UnknownError,
+                    format!("Policy \"{}\" has no revision {}", id, number),
+                ))
+            }
+        }
+    }
+
+    print!("{}", PolicyDiff::between(&policies[0], &policies[1]));
+    Ok(())
+}
+
+/// Runs the policy subcommand with the given arguments.
+pub(crate) async fn run(args: &[String]) -> Result<(), Error> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args.as_slice() {
+        ["diff", from, to] => diff_files(from, to),
+        ["diff-revisions", id, from, to] => diff_revisions(id, from, to, DEFAULT_TENANT).await,
+        ["diff-revisions", id, from, to, tenant] => diff_revisions(id, from, to, tenant).await,
+        _ => Err(usage()),
+    }
+}
@@ -0,0 +1,376 @@
+use crate::tenant::TENANT_HEADER;
+use actix_web::client::Client;
+use actix_web::http::{header, Method, StatusCode};
+use actix_web::HttpResponse;
+use libzephir::err::{Error, ErrorKind};
+use libzephir::file_store::parse_export;
+use libzephir::policy::validity::unix_now;
+use libzephir::storage::{ImportMode, StorageManager};
+use libzephir::tenant::{validate_tenant, DEFAULT_TENANT};
+use log::{info, warn};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Seconds between two pulls from the primary, if REPLICATION_INTERVAL is not set.
+const DEFAULT_INTERVAL: u64 = 10;
+
+/// The maximum time a pull could take, including the transfer of the snapshot.
+const PULL_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The requests served by a replica even if they are not GETs:
+/// they evaluate, preview or simulate, but never modify anything.
+const READ_ONLY_POSTS: &[&str] = &["/allowed", "/groups/membership-rule/preview", "/simulate"];
+
+/// Replication settings, read from the environment.
+///
+/// REPLICA_OF is the url of the primary zephir, REPLICA_TENANTS the comma
+/// separated list of the tenants to be replicated (default: the default tenant)
+/// and REPLICATION_INTERVAL the number of seconds between two pulls (default: 10).
+pub(crate) struct ReplicaConfig {
+    primary: String,
+    tenants: Vec<String>,
+    interval: Duration,
+}
+
+impl ReplicaConfig {
+    /// Reads the settings, returning None if REPLICA_OF is not set
+    /// (the instance is not a replica).
+    pub(crate) fn from_env() -> Result<Option<Self>, Error> {
+        let primary = match std::env::var("REPLICA_OF") {
+            Result::Ok(primary) if !primary.is_empty() => primary.trim_end_matches('/').to_string(),
+            _ => return Ok(Option::None),
+        };
+
+        let tenants: Vec<String> = std::env::var("REPLICA_TENANTS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|t| t.trim().to_string())
+            .filter(|t| !t.is_empty())
+            .collect();
+        let tenants = if tenants.is_empty() {
+            vec![DEFAULT_TENANT.to_string()]
+        } else {
+            tenants
+        };
+
+        for tenant in &tenants {
+            validate_tenant(tenant)?;
+        }
+
+        let interval = std::env::var("REPLICATION_INTERVAL")
+            .ok()
+            .and_then(|i| i.parse().ok())
+            .filter(|i| *i > 0)
+            .unwrap_or(DEFAULT_INTERVAL);
+
+        Ok(Option::Some(ReplicaConfig {
+            primary,
+            tenants,
+            interval: Duration::from_secs(interval),
+        }))
+    }
+}
+
+/// The replication progress of a tenant.
+#[derive(Clone, Debug, Default)]
+struct TenantReplication {
+    /// The tag of the last snapshot pulled from the primary.
+    etag: Option<String>,
+    /// When the replica was last known to hold the primary data.
+    last_sync: Option<i64>,
+    last_error: Option<String>,
+}
+
+/// The replication progress of all the replicated tenants,
+/// shared between the replication task and the status endpoint.
+pub(crate) struct ReplicationState {
+    primary: String,
+    interval: Duration,
+    tenants: Mutex<BTreeMap<String, TenantReplication>>,
+}
+
+impl ReplicationState {
+    fn new(config: &ReplicaConfig) -> Self {
+        ReplicationState {
+            primary: config.primary.clone(),
+            interval: config.interval,
+            tenants: Mutex::new(
+                config
+                    .tenants
+                    .iter()
+                    .map(|t| (t.clone(), TenantReplication::default()))
+                    .collect(),
+            ),
+        }
+    }
+
+    fn get(&self, tenant: &str) -> TenantReplication {
+        self.tenants.lock().unwrap().get(tenant).cloned().unwrap_or_default()
+    }
+
+    fn update(&self, tenant: &str, replication: TenantReplication) {
+        self.tenants.lock().unwrap().insert(tenant.to_string(), replication);
+    }
+
+    /// Describes the replication status. The lag of a tenant is the number of
+    /// seconds since its data was last known to match the primary one.
+    pub(crate) fn to_json(&self) -> Map<String, Value> {
+        let now = unix_now();
+        let tenants: Map<String, Value> = self
+            .tenants
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(tenant, replication)| {
+                let mut map = Map::new();
+                map.insert(String::from("last_sync"), Value::from(replication.last_sync));
+                map.insert(String::from("lag"), Value::from(replication.last_sync.map(|t| now - t)));
+                map.insert(String::from("last_error"), Value::from(replication.last_error.clone()));
+
+                (tenant.clone(), Value::Object(map))
+            })
+            .collect();
+
+        let mut map = Map::new();
+        map.insert(String::from("primary"), Value::from(self.primary.as_str()));
+        map.insert(String::from("interval"), Value::from(self.interval.as_secs()));
+        map.insert(String::from("tenants"), Value::Object(tenants));
+
+        map
+    }
+}
+
+/// Pulls the snapshot of a tenant from the primary, replacing
+/// the local data if it changed since the last pull.
+///
+/// # Returns
+///
+/// The tag of the pulled snapshot
+async fn pull(client: &Client, primary: &str, storage: &StorageManager, etag: Option<&str>) -> Result<Option<String>, Error> {
+    let mut request = client
+        .get(format!("{}/replication/snapshot", primary))
+        .header(TENANT_HEADER, storage.get_tenant());
+    if let Some(etag) = etag {
+        request = request.header(header::IF_NONE_MATCH, etag);
+    }
+
+    let mut response = request.send().await.map_err(|e| Error::new(ErrorKind::UnknownError, e.to_string()))?;
+    let new_etag = response
+        .headers()
+        .get(header::ETAG)
+        .and_then(|v| v.to_str().ok())
+        .map(String::from);
+
+    match response.status() {
+        StatusCode::NOT_MODIFIED => Ok(new_etag),
+        StatusCode::OK => {
+            let body = response
+                .body()
+                .limit(crate::IMPORT_PAYLOAD_LIMIT)
+                .await
+                .map_err(|e| Error::new(ErrorKind::UnknownError, e.to_string()))?;
+            let imported = parse_export(&body, storage.get_tenant())?;
+            let report = storage.import_tenant(&imported, ImportMode::Replace).await?;
+            info!(
+                "Replicated tenant \"{}\": {} created, {} updated, {} deleted",
+                storage.get_tenant(),
+                report.created.values().map(Vec::len).sum::<usize>(),
+                report.updated.values().map(Vec::len).sum::<usize>(),
+                report.deleted.values().map(Vec::len).sum::<usize>()
+            );
+
+            Ok(new_etag)
+        }
+        status => Err(Error::new(
+            ErrorKind::UnknownError,
+            format!("Primary responded with status {}", status),
+        )),
+    }
+}
+
+/// Starts pulling the replicated tenants from the primary.
+///
+/// # Returns
+///
+/// The replication state, updated after every pull
+pub(crate) fn start(config: ReplicaConfig, storage: StorageManager) -> Arc<ReplicationState> {
+    let state = Arc::new(ReplicationState::new(&config));
+    let replication = state.clone();
+
+    actix_web::rt::spawn(async move {
+        let client = Client::builder().timeout(PULL_TIMEOUT).finish();
+        loop {
+            for tenant in &config.tenants {
+                let mut progress = replication.get(tenant);
+                let started_at = unix_now();
+                match pull(&client, &config.primary, &storage.for_tenant(tenant), progress.etag.as_deref()).await {
+                    Ok(etag) => {
+                        progress.etag = etag;
+                        progress.last_sync = Option::Some(started_at);
+                        progress.last_error = Option::None;
+                    }
+                    Err(e) => {
+                        warn!("Replication of tenant \"{}\" failed: {}", tenant, e);
+                        progress.last_error = Option::Some(e.to_string());
+                    }
+                }
+
+                replication.update(tenant, progress);
+            }
+
+            actix_web::rt::time::delay_for(config.interval).await;
+        }
+    });
+
+    state
+}
+
+/// Whether the request would modify the data, and must be refused by a replica.
+pub(crate) fn is_write(method: &Method, path: &str) -> bool {
+    !(method == Method::GET || method == Method::HEAD || (method == Method::POST && READ_ONLY_POSTS.contains(&path)))
+}
+
+pub(crate) fn read_only_response() -> HttpResponse {
+    let mut map = Map::new();
+    map.insert("status_code".to_string(), Value::from(405));
+    map.insert(
+        "error".to_string(),
+        Value::from("This zephir instance is a read-only replica"),
+    );
+
+    HttpResponse::MethodNotAllowed().json(map)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::replica::{is_write, ReplicaConfig, ReplicationState, TenantReplication, DEFAULT_INTERVAL, READ_ONLY_POSTS};
+    use actix_web::http::Method;
+    use libzephir::policy::validity::unix_now;
+    use libzephir::tenant::DEFAULT_TENANT;
+    use serde_json::Value;
+    use std::time::Duration;
+
+    #[test]
+    fn only_reads_and_evaluations_should_be_served_by_replicas() {
+        assert!(!is_write(&Method::GET, "/policy/p1"));
+        assert!(!is_write(&Method::HEAD, "/identity/i1"));
+        for path in READ_ONLY_POSTS {
+            assert!(!is_write(&Method::POST, path), "{}", path);
+        }
+
+        let writes = [
+            (Method::POST, "/policies"),
+            (Method::POST, "/identities"),
+            (Method::POST, "/groups"),
+            (Method::POST, "/roles"),
+            (Method::POST, "/role/r1/sessions"),
+            (Method::POST, "/guardrails"),
+            (Method::POST, "/resource-policies"),
+            (Method::POST, "/webhooks"),
+            (Method::POST, "/webhook/w1/dead-letters/redeliver"),
+            (Method::POST, "/import"),
+            (Method::POST, "/_maintenance/expired-links"),
+            (Method::PATCH, "/group/g1/identities"),
+            (Method::PATCH, "/group/g1/groups"),
+            (Method::DELETE, "/guardrail/g1"),
+            (Method::DELETE, "/resource-policy/p1"),
+            (Method::DELETE, "/webhook/w1"),
+            (Method::DELETE, "/tenant"),
+            (Method::PUT, "/allowed"),
+            (Method::POST, "/allowed/"),
+            (Method::POST, "/simulate/x"),
+        ];
+        for (method, path) in writes.iter() {
+            assert!(is_write(method, path), "{} {}", method, path);
+        }
+    }
+
+    #[test]
+    fn replica_config_should_be_read_from_env() {
+        let clear = || {
+            std::env::remove_var("REPLICA_OF");
+            std::env::remove_var("REPLICA_TENANTS");
+            std::env::remove_var("REPLICATION_INTERVAL");
+        };
+
+        clear();
+        assert!(ReplicaConfig::from_env().unwrap().is_none());
+        std::env::set_var("REPLICA_OF", "");
+        assert!(ReplicaConfig::from_env().unwrap().is_none());
+
+        std::env::set_var("REPLICA_OF", "http://primary:8091/");
+        let config = ReplicaConfig::from_env().unwrap().unwrap();
+        assert_eq!(config.primary, "http://primary:8091");
+        assert_eq!(config.tenants, vec![DEFAULT_TENANT.to_string()]);
+        assert_eq!(config.interval, Duration::from_secs(DEFAULT_INTERVAL));
+
+        std::env::set_var("REPLICA_TENANTS", " acme, ,globex ");
+        std::env::set_var("REPLICATION_INTERVAL", "30");
+        let config = ReplicaConfig::from_env().unwrap().unwrap();
+        assert_eq!(config.tenants, vec!["acme".to_string(), "globex".to_string()]);
+        assert_eq!(config.interval, Duration::from_secs(30));
+
+        for interval in &["0", "-1", "soon"] {
+            std::env::set_var("REPLICATION_INTERVAL", interval);
+            let config = ReplicaConfig::from_env().unwrap().unwrap();
+            assert_eq!(config.interval, Duration::from_secs(DEFAULT_INTERVAL));
+        }
+
+        std::env::set_var("REPLICA_TENANTS", "acme,not a tenant!");
+        assert!(ReplicaConfig::from_env().is_err());
+
+        clear();
+    }
+
+    #[test]
+    fn replication_status_should_report_lag_and_last_error() {
+        let config = ReplicaConfig {
+            primary: String::from("http://primary:8091"),
+            tenants: vec![String::from("acme"), String::from("globex")],
+            interval: Duration::from_secs(5),
+        };
+        let state = ReplicationState::new(&config);
+
+        let status = state.to_json();
+        assert_eq!(status["primary"], "http://primary:8091");
+        assert_eq!(status["interval"], 5);
+        assert_eq!(status["tenants"]["acme"]["last_sync"], Value::Null);
+        assert_eq!(status["tenants"]["acme"]["lag"], Value::Null);
+        assert_eq!(status["tenants"]["acme"]["last_error"], Value::Null);
+
+        let last_sync = unix_now() - 42;
+        state.update(
+            "acme",
+            TenantReplication {
+                etag: Option::Some(String::from(r#""12""#)),
+                last_sync: Option::Some(last_sync),
+                last_error: Option::None,
+            },
+        );
+
+        let mut progress = state.get("acme");
+        progress.last_error = Option::Some(String::from("Primary responded with status 500"));
+        state.update("acme", progress);
+        state.update(
+            "globex",
+            TenantReplication {
+                last_error: Option::Some(String::from("connection refused")),
+                ..TenantReplication::default()
+            },
+        );
+
+        let status = state.to_json();
+        let acme = &status["tenants"]["acme"];
+        assert_eq!(acme["last_sync"], last_sync);
+        let lag = acme["lag"].as_i64().unwrap();
+        assert!((42..=44).contains(&lag), "{}", lag);
+        assert_eq!(acme["last_error"], "Primary responded with status 500");
+
+        let globex = &status["tenants"]["globex"];
+        assert_eq!(globex["last_sync"], Value::Null);
+        assert_eq!(globex["lag"], Value::Null);
+        assert_eq!(globex["last_error"], "connection refused");
+    }
+}
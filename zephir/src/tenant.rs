@@ -0,0 +1,50 @@
+use actix_web::dev::Payload;
+use actix_web::{web, FromRequest, HttpRequest};
+use crate::err::ZephirError;
+use libzephir::err::{Error, ErrorKind};
+use libzephir::storage::StorageManager;
+use libzephir::tenant::{validate_tenant, DEFAULT_TENANT};
+use std::future::{ready, Ready};
+use std::ops::Deref;
+
+/// Header selecting the tenant a request operates on.
+pub(crate) const TENANT_HEADER: &str = "X-Zephir-Tenant";
+
+/// The storage manager scoped to the tenant requested through the
+/// `X-Zephir-Tenant` header (or to the default tenant if not set).
+///
+/// Handlers must use this instead of the shared storage manager,
+/// so that they can never load or modify another tenant entities.
+pub(crate) struct TenantStorage(StorageManager);
+
+impl Deref for TenantStorage {
+    type Target = StorageManager;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+fn tenant_storage(req: &HttpRequest) -> Result<TenantStorage, ZephirError> {
+    let storage = req
+        .app_data::<web::Data<StorageManager>>()
+        .ok_or_else(|| Error::new(ErrorKind::UnknownError, "Storage manager is not configured"))?;
+
+    let tenant = match req.headers().get(TENANT_HEADER) {
+        Option::None => DEFAULT_TENANT,
+        Option::Some(value) => value.to_str().map_err(|_| Error::invalid_tenant(""))?,
+    };
+
+    validate_tenant(tenant)?;
+    Ok(TenantStorage(storage.for_tenant(tenant)))
+}
+
+impl FromRequest for TenantStorage {
+    type Error = ZephirError;
+    type Future = Ready<Result<Self, Self::Error>>;
+    type Config = ();
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(tenant_storage(req))
+    }
+}
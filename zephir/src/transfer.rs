@@ -0,0 +1,44 @@
+use libzephir::err::{Error, ErrorKind};
+use libzephir::file_store::parse_export;
+use libzephir::policy::policy::ToJson;
+use libzephir::storage::ImportMode;
+use libzephir::tenant::DEFAULT_TENANT;
+use serde_json::Value;
+use std::convert::TryFrom;
+
+const EXPORT_USAGE: &str = "Usage: rzephir export <output> [tenant]";
+const IMPORT_USAGE: &str = "Usage: rzephir import <file> [merge|replace|dry-run] [tenant]";
+
+/// Writes the whole data set of a tenant to a file, as `GET /export` does.
+pub(crate) async fn export(args: &[String]) -> Result<(), Error> {
+    let (output, tenant) = match args {
+        [output] => (output, DEFAULT_TENANT),
+        [output, tenant] => (output, tenant.as_str()),
+        _ => return Err(Error::new(ErrorKind::UnknownError, EXPORT_USAGE)),
+    };
+
+    let export = crate::connect_storage(tenant).await?.export_tenant().await?;
+    std::fs::write(output, serde_json::to_vec_pretty(&Value::Object(export.to_json()))?)?;
+
+    println!("Tenant \"{}\" exported to {}", tenant, output);
+    Ok(())
+}
+
+/// Imports a file produced by the export, as `POST /import` does,
+/// and prints the import report.
+pub(crate) async fn import(args: &[String]) -> Result<(), Error> {
+    let (file, mode, tenant) = match args {
+        [file] => (file, "merge", DEFAULT_TENANT),
+        [file, mode] => (file, mode.as_str(), DEFAULT_TENANT),
+        [file, mode, tenant] => (file, mode.as_str(), tenant.as_str()),
+        _ => return Err(Error::new(ErrorKind::UnknownError, IMPORT_USAGE)),
+    };
+
+    let mode = ImportMode::try_from(mode)?;
+    let storage = crate::connect_storage(tenant).await?;
+    let imported = parse_export(&std::fs::read(file)?, tenant)?;
+    let report = storage.import_tenant(&imported, mode).await?;
+
+    println!("{}", serde_json::to_string_pretty(&Value::Object(report.to_json()))?);
+    Ok(())
+}
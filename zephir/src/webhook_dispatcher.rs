@@ -0,0 +1,248 @@
+use actix_web::client::Client;
+use actix_web::http::header;
+use futures::future::LocalBoxFuture;
+use libzephir::err::{Error, ErrorKind};
+use libzephir::storage::StorageManager;
+use libzephir::webhook::{sign, Delivery};
+use log::{debug, warn};
+use std::time::Duration;
+
+/// Delay between two checks of the deliveries queue, when it is empty.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The maximum number of deliveries sent at once.
+const BATCH_SIZE: i64 = 50;
+
+/// The maximum time a webhook could take to respond.
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a claimed delivery is hidden from the other instances:
+/// longer than the time needed to send a whole batch.
+const CLAIM_LEASE: Duration = Duration::from_secs(60);
+
+/// Headers sent along with the payload.
+const EVENT_HEADER: &str = "X-Zephir-Event";
+const DELIVERY_HEADER: &str = "X-Zephir-Delivery";
+const SIGNATURE_HEADER: &str = "X-Zephir-Signature";
+
+/// Where the outcome of the deliveries is recorded.
+trait DeliveryQueue {
+    /// Removes a successfully sent delivery.
+    fn complete<'a>(&'a self, delivery: &'a Delivery) -> LocalBoxFuture<'a, Result<(), Error>>;
+
+    /// Records a failed attempt, returning whether the
+    /// delivery has been moved to the dead letters.
+    fn fail<'a>(&'a self, delivery: &'a Delivery, error: &'a str) -> LocalBoxFuture<'a, Result<bool, Error>>;
+}
+
+impl DeliveryQueue for StorageManager {
+    fn complete<'a>(&'a self, delivery: &'a Delivery) -> LocalBoxFuture<'a, Result<(), Error>> {
+        Box::pin(self.complete_delivery(delivery))
+    }
+
+    fn fail<'a>(&'a self, delivery: &'a Delivery, error: &'a str) -> LocalBoxFuture<'a, Result<bool, Error>> {
+        Box::pin(self.fail_delivery(delivery, error))
+    }
+}
+
+/// Posts the payload to the webhook, signing it with the webhook secret.
+/// Any response other than a 2xx one is a failure.
+async fn send(client: &Client, delivery: &Delivery) -> Result<(), Error> {
+    let body = serde_json::to_vec(&delivery.payload)?;
+    let response = client
+        .post(&delivery.url)
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(EVENT_HEADER, delivery.event.as_str())
+        .header(DELIVERY_HEADER, delivery.id.to_string())
+        .header(SIGNATURE_HEADER, sign(&delivery.secret, &body))
+        .send_body(body)
+        .await
+        .map_err(|e| Error::new(ErrorKind::UnknownError, e.to_string()))?;
+
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        Err(Error::new(
+            ErrorKind::UnknownError,
+            format!("Webhook responded with status {}", response.status()),
+        ))
+    }
+}
+
+async fn dispatch<Q: DeliveryQueue>(client: &Client, queue: &Q, delivery: Delivery) -> Result<(), Error> {
+    match send(client, &delivery).await {
+        Ok(()) => {
+            debug!("Delivered {} #{} to webhook \"{}\"", delivery.event, delivery.id, delivery.webhook_id);
+            queue.complete(&delivery).await
+        }
+        Err(e) => {
+            if queue.fail(&delivery, &e.to_string()).await? {
+                warn!(
+                    "Delivery #{} to webhook \"{}\" of tenant \"{}\" moved to the dead letters: {}",
+                    delivery.id, delivery.webhook_id, delivery.tenant, e
+                );
+            } else {
+                debug!("Delivery #{} to webhook \"{}\" failed: {}", delivery.id, delivery.webhook_id, e);
+            }
+
+            Ok(())
+        }
+    }
+}
+
+/// Starts sending the queued webhook notifications of all the tenants.
+///
+/// Deliveries are claimed from the database, so that many instances
+/// could run the dispatcher without sending the same notification twice.
+pub(crate) fn start(storage: StorageManager) {
+    actix_web::rt::spawn(async move {
+        let client = Client::builder().timeout(DELIVERY_TIMEOUT).finish();
+        loop {
+            let deliveries = match storage.claim_deliveries(BATCH_SIZE, CLAIM_LEASE).await {
+                Ok(deliveries) => deliveries,
+                Err(e) => {
+                    warn!("Cannot load the webhook deliveries: {}", e);
+                    vec![]
+                }
+            };
+
+            let full_batch = deliveries.len() as i64 == BATCH_SIZE;
+            let results = futures::future::join_all(
+                deliveries
+                    .into_iter()
+                    .map(|delivery| dispatch(&client, &storage, delivery)),
+            )
+            .await;
+
+            for result in results {
+                if let Err(e) = result {
+                    warn!("Cannot update a webhook delivery: {}", e);
+                }
+            }
+
+            if !full_batch {
+                actix_web::rt::time::delay_for(POLL_INTERVAL).await;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::webhook_dispatcher::{dispatch, DeliveryQueue, DELIVERY_HEADER, EVENT_HEADER, SIGNATURE_HEADER};
+    use actix_web::client::Client;
+    use actix_web::{test, web, App, HttpRequest, HttpResponse};
+    use futures::future::LocalBoxFuture;
+    use libzephir::err::Error;
+    use libzephir::webhook::{backoff, retry_delay, sign, Delivery, MAX_DELIVERY_ATTEMPTS};
+    use serde_json::json;
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    /// A request received by the stub: headers and body.
+    type Received = (HashMap<String, String>, Vec<u8>);
+
+    /// Keeps the deliveries in memory, scheduling the
+    /// retries as the storage does (see `retry_delay`).
+    #[derive(Default)]
+    struct MemoryQueue {
+        completed: RefCell<Vec<i64>>,
+        retries: RefCell<Vec<(i64, i32, Duration)>>,
+        dead_letters: RefCell<Vec<(i64, i32, String)>>,
+    }
+
+    impl DeliveryQueue for MemoryQueue {
+        fn complete<'a>(&'a self, delivery: &'a Delivery) -> LocalBoxFuture<'a, Result<(), Error>> {
+            self.completed.borrow_mut().push(delivery.id);
+            Box::pin(async { Ok(()) })
+        }
+
+        fn fail<'a>(&'a self, delivery: &'a Delivery, error: &'a str) -> LocalBoxFuture<'a, Result<bool, Error>> {
+            let attempts = delivery.attempts + 1;
+            let dead = match retry_delay(attempts) {
+                Option::Some(delay) => {
+                    self.retries.borrow_mut().push((delivery.id, attempts, delay));
+                    false
+                }
+                Option::None => {
+                    self.dead_letters.borrow_mut().push((delivery.id, attempts, error.to_string()));
+                    true
+                }
+            };
+
+            Box::pin(async move { Ok(dead) })
+        }
+    }
+
+    async fn receive(req: HttpRequest, body: web::Bytes, received: web::Data<Arc<Mutex<Vec<Received>>>>) -> HttpResponse {
+        let headers = req
+            .headers()
+            .iter()
+            .map(|(name, value)| (name.as_str().to_lowercase(), value.to_str().unwrap().to_string()))
+            .collect();
+        received.lock().unwrap().push((headers, body.to_vec()));
+
+        if req.path() == "/ok" {
+            HttpResponse::Ok().finish()
+        } else {
+            HttpResponse::ServiceUnavailable().finish()
+        }
+    }
+
+    fn delivery(url: String, attempts: i32) -> Delivery {
+        Delivery {
+            id: 42,
+            tenant: String::from("default"),
+            webhook_id: String::from("audit"),
+            url,
+            secret: String::from("s3cr3t"),
+            event: String::from("policy.updated"),
+            payload: json!({ "event": "policy.updated", "entity_id": "ReadPolicy" }),
+            attempts,
+        }
+    }
+
+    #[test]
+    fn deliveries_should_be_signed_and_retried() {
+        let received: Arc<Mutex<Vec<Received>>> = Arc::new(Mutex::new(vec![]));
+        let data = received.clone();
+
+        actix_web::rt::System::new("webhook-test").block_on(async move {
+            let stub = test::start(move || {
+                App::new()
+                    .data(data.clone())
+                    .default_service(web::to(receive))
+            });
+
+            let client = Client::default();
+            let queue = MemoryQueue::default();
+
+            dispatch(&client, &queue, delivery(stub.url("/ok"), 0)).await.unwrap();
+            assert_eq!(*queue.completed.borrow(), vec![42]);
+
+            let (headers, body) = received.lock().unwrap().pop().unwrap();
+            assert_eq!(serde_json::from_slice::<serde_json::Value>(&body).unwrap(), delivery(String::new(), 0).payload);
+            assert_eq!(headers[&EVENT_HEADER.to_lowercase()], "policy.updated");
+            assert_eq!(headers[&DELIVERY_HEADER.to_lowercase()], "42");
+            assert_eq!(headers[&SIGNATURE_HEADER.to_lowercase()], sign("s3cr3t", &body));
+            assert_eq!(headers["content-type"], "application/json");
+
+            for attempts in 0..MAX_DELIVERY_ATTEMPTS {
+                dispatch(&client, &queue, delivery(stub.url("/fail"), attempts)).await.unwrap();
+            }
+
+            let retries = queue.retries.borrow();
+            assert_eq!(retries.len() as i32, MAX_DELIVERY_ATTEMPTS - 1);
+            assert_eq!(retries[0], (42, 1, backoff(1)));
+            assert_eq!(retries[2], (42, 3, Duration::from_secs(20)));
+
+            let dead_letters = queue.dead_letters.borrow();
+            assert_eq!(dead_letters.len(), 1);
+            assert_eq!(dead_letters[0].1, MAX_DELIVERY_ATTEMPTS);
+            assert!(dead_letters[0].2.contains("503"), "{}", dead_letters[0].2);
+            assert_eq!(received.lock().unwrap().len() as i32, MAX_DELIVERY_ATTEMPTS);
+        });
+    }
+}